@@ -132,7 +132,9 @@ pub fn put_axis_name_settings(
     macro_rules! set_value {
         ($axis:ident, $set_func:ident, $field:ident) => {{
             if let Some(value) = received_settings.$field {
-                settings.motors().$axis().$set_func(value);
+                if let Err(e) = settings.motors().$axis().$set_func(value) {
+                    return ApiPutSettingsResponse::OutOfBounds(Json(errors.insert_get(e.into())));
+                }
             }
         }};
     }
@@ -0,0 +1,152 @@
+use super::{json_ok_or, JsonResult};
+use crate::hw::{mnemonic_from_str, Breakpoint, HwCtrl};
+use rocket::{
+    delete, get, http::Status, post, response::status, serde::json::Json, Responder, State,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ApiBreakpoint {
+    Line { line: usize },
+    Code { mnemonic: String, number: u32 },
+}
+
+impl TryFrom<ApiBreakpoint> for Breakpoint {
+    type Error = ();
+
+    fn try_from(breakpoint: ApiBreakpoint) -> Result<Self, Self::Error> {
+        match breakpoint {
+            ApiBreakpoint::Line { line } => Ok(Breakpoint::Line(line)),
+            ApiBreakpoint::Code { mnemonic, number } => Ok(Breakpoint::Code(
+                mnemonic_from_str(&mnemonic).map_err(|_| ())?,
+                number,
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ApiBreakpointOut {
+    Line { line: usize },
+    Code { mnemonic: String, number: u32 },
+}
+
+impl From<Breakpoint> for ApiBreakpointOut {
+    fn from(breakpoint: Breakpoint) -> Self {
+        match breakpoint {
+            Breakpoint::Line(line) => Self::Line { line },
+            Breakpoint::Code(mnemonic, number) => Self::Code {
+                mnemonic: format!("{mnemonic:?}"),
+                number,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Responder)]
+pub enum ApiDebugActionResponse {
+    #[response(status = 202)]
+    Accepted(()),
+    #[response(status = 405)]
+    InvalidInput(()),
+    #[response(status = 409)]
+    StateError(()),
+}
+
+#[post("/debug/breakpoint", data = "<breakpoint>")]
+pub fn post_breakpoint(
+    breakpoint: JsonResult<ApiBreakpoint>,
+    hw_ctrl: &State<HwCtrl>,
+) -> ApiDebugActionResponse {
+    let breakpoint = json_ok_or!(breakpoint, ApiDebugActionResponse::InvalidInput(()));
+    let breakpoint = match Breakpoint::try_from(breakpoint) {
+        Ok(breakpoint) => breakpoint,
+        Err(_) => return ApiDebugActionResponse::InvalidInput(()),
+    };
+    hw_ctrl.set_breakpoint(breakpoint);
+    ApiDebugActionResponse::Accepted(())
+}
+
+#[delete("/debug/breakpoint", data = "<breakpoint>")]
+pub fn delete_breakpoint(
+    breakpoint: JsonResult<ApiBreakpoint>,
+    hw_ctrl: &State<HwCtrl>,
+) -> ApiDebugActionResponse {
+    let breakpoint = json_ok_or!(breakpoint, ApiDebugActionResponse::InvalidInput(()));
+    let breakpoint = match Breakpoint::try_from(breakpoint) {
+        Ok(breakpoint) => breakpoint,
+        Err(_) => return ApiDebugActionResponse::InvalidInput(()),
+    };
+    hw_ctrl.clear_breakpoint(breakpoint);
+    ApiDebugActionResponse::Accepted(())
+}
+
+#[get("/debug/breakpoints")]
+pub fn get_breakpoints(hw_ctrl: &State<HwCtrl>) -> status::Custom<Json<Vec<ApiBreakpointOut>>> {
+    status::Custom(
+        Status::Ok,
+        Json(hw_ctrl.breakpoints().into_iter().map(Into::into).collect()),
+    )
+}
+
+fn default_step_count() -> u32 {
+    1
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiPostDebugStep {
+    #[serde(default = "default_step_count")]
+    count: u32,
+}
+
+/// Ignores breakpoints for the next `count` codes (1 if unspecified),
+/// resuming the print if it's currently paused at one
+#[post("/debug/step", data = "<params>")]
+pub fn post_step(
+    params: JsonResult<ApiPostDebugStep>,
+    hw_ctrl: &State<HwCtrl>,
+) -> ApiDebugActionResponse {
+    let params = json_ok_or!(params, ApiDebugActionResponse::InvalidInput(()));
+    match hw_ctrl.try_debug_step(params.count) {
+        Ok(()) => ApiDebugActionResponse::Accepted(()),
+        Err(_) => ApiDebugActionResponse::StateError(()),
+    }
+}
+
+#[post("/debug/continue")]
+pub fn post_continue(hw_ctrl: &State<HwCtrl>) -> ApiDebugActionResponse {
+    match hw_ctrl.try_play() {
+        Ok(()) => ApiDebugActionResponse::Accepted(()),
+        Err(_) => ApiDebugActionResponse::StateError(()),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiDebugPosition {
+    pos_x: f64,
+    pos_y: f64,
+    pos_z: f64,
+    // the file/line a breakpoint is currently holding the print in front
+    // of, if any
+    line: Option<usize>,
+    path: Option<PathBuf>,
+}
+
+#[get("/debug/position")]
+pub fn get_position(hw_ctrl: &State<HwCtrl>) -> status::Custom<Json<ApiDebugPosition>> {
+    let pos = hw_ctrl.pos_info();
+    let span = hw_ctrl.debug_span();
+    status::Custom(
+        Status::Ok,
+        Json(ApiDebugPosition {
+            pos_x: pos.x,
+            pos_y: pos.y,
+            pos_z: pos.z,
+            line: span.as_ref().map(|span| span.line()),
+            path: span.map(|span| span.path().clone()),
+        }),
+    )
+}
@@ -1,5 +1,14 @@
 use crate::api::values::{ApiError, Errors};
-use rocket::{get, http::Status, response::status, serde::json::Json, Responder, State};
+use rocket::{
+    get,
+    http::Status,
+    response::{
+        status,
+        stream::{Event, EventStream},
+    },
+    serde::json::Json,
+    Responder, Shutdown, State,
+};
 use serde::Serialize;
 
 #[derive(Serialize)]
@@ -46,3 +55,29 @@ pub fn get_id(
         .map(|e| status::Custom(Status::Ok, Json(e)))
         .ok_or(status::NotFound(()))
 }
+
+/// Pushes every newly inserted [`ApiError`] to the client as it happens,
+/// instead of the client having to poll [`get`]/[`get_last`]
+///
+/// Closes once the client disconnects or the server shuts down
+#[get("/errors/events")]
+pub fn get_events(errors: &State<Errors>, mut end: Shutdown) -> EventStream![] {
+    let subscription = errors.subscribe();
+    EventStream! {
+        loop {
+            let api_error = tokio::select! {
+                res = tokio::task::spawn_blocking({
+                    let subscription = subscription.clone();
+                    move || subscription.recv()
+                }) => match res.expect("blocking recv task panicked") {
+                    Ok(e) => e,
+                    // the Errors instance was dropped, nothing more will ever
+                    // arrive
+                    Err(_) => break,
+                },
+                _ = &mut end => break,
+            };
+            yield Event::json(&api_error);
+        }
+    }
+}
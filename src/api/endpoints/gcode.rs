@@ -1,12 +1,29 @@
 use super::{json_ok_or, JsonResult};
 use crate::{
     api::values::{ApiError, Errors},
-    hw::{GCodeError, HwCtrl, StateError, StateInfo},
+    hw::{GCodeError, HwCtrl, ResumeError, StateError, StateInfo},
+    settings::Settings,
 };
-use rocket::{get, http::Status, post, response::status, serde::json::Json, Responder, State};
-use serde::Deserialize;
-use std::io::Error as IoError;
-use std::path::PathBuf;
+use rocket::{
+    form::Form,
+    fs::TempFile,
+    get,
+    http::Status,
+    post,
+    response::{
+        status,
+        stream::{Event, EventStream},
+    },
+    serde::json::Json,
+    FromForm, Responder, Shutdown, State,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Error as IoError,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::interval;
 
 #[get("/gcode")]
 pub fn get(hw_ctrl: &State<HwCtrl>) -> status::Custom<Json<StateInfo>> {
@@ -34,7 +51,7 @@ pub struct ApiPostGCodeStartParams {
     path: PathBuf,
 }
 
-#[post("/gcode/start", data = "<params>")]
+#[post("/gcode/start", format = "json", data = "<params>", rank = 1)]
 pub fn post_start(
     params: JsonResult<ApiPostGCodeStartParams>,
     hw_ctrl: &State<HwCtrl>,
@@ -45,7 +62,55 @@ pub fn post_start(
         Ok(p) => p,
         Err(e) => return ApiGCodeActionResponse::IoError(Json(errors.insert_get(e.into()))),
     };
-    match hw_ctrl.try_print(canonical_path) {
+    start_print(canonical_path, hw_ctrl, errors)
+}
+
+#[derive(FromForm)]
+pub struct ApiPostGCodeUpload<'r> {
+    file: TempFile<'r>,
+}
+
+/// Spools an uploaded gcode file under `settings.config().gcode.upload_dir`
+/// instead of accepting a path already present on the printer's own
+/// filesystem, so a client doesn't need filesystem access to the daemon's
+/// host to start a print
+#[post(
+    "/gcode/start",
+    format = "multipart/form-data",
+    data = "<upload>",
+    rank = 2
+)]
+pub async fn post_start_upload(
+    mut upload: Form<ApiPostGCodeUpload<'_>>,
+    hw_ctrl: &State<HwCtrl>,
+    errors: &State<Errors>,
+    settings: &State<Settings>,
+) -> ApiGCodeActionResponse {
+    let upload_dir = &settings.config().gcode.upload_dir;
+    if let Err(e) = std::fs::create_dir_all(upload_dir) {
+        return ApiGCodeActionResponse::IoError(Json(errors.insert_get(e.into())));
+    }
+    let file_name = upload
+        .file
+        .raw_name()
+        .and_then(|name| name.as_str())
+        .unwrap_or("upload.gcode");
+    // timestamp-prefixed so two uploads with the same original name don't
+    // collide; only one print can be running at a time anyway, but finished
+    // jobs are left on disk for later inspection/reprinting
+    let millis_since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("error somehow occured before epoch")
+        .as_millis();
+    let path = upload_dir.join(format!("{}-{}", millis_since_epoch, file_name));
+    if let Err(e) = upload.file.persist_to(&path).await {
+        return ApiGCodeActionResponse::IoError(Json(errors.insert_get(e.into())));
+    }
+    start_print(path, hw_ctrl, errors)
+}
+
+fn start_print(path: PathBuf, hw_ctrl: &HwCtrl, errors: &Errors) -> ApiGCodeActionResponse {
+    match hw_ctrl.try_print(path) {
         Ok(()) => ApiGCodeActionResponse::Accepted(()),
         Err(e) => match e {
             e if e.is::<IoError>() => ApiGCodeActionResponse::IoError(Json(errors.insert_get(e))),
@@ -79,3 +144,54 @@ pub fn post_pause(hw_ctrl: &State<HwCtrl>) -> ApiGCodeActionResponse {
         Err(_) => ApiGCodeActionResponse::StateError(()),
     }
 }
+
+/// Resumes the print recorded in the checkpoint found on startup, if any
+#[post("/gcode/resume")]
+pub fn post_resume(hw_ctrl: &State<HwCtrl>, errors: &State<Errors>) -> ApiGCodeActionResponse {
+    match hw_ctrl.try_resume() {
+        Ok(()) => ApiGCodeActionResponse::Accepted(()),
+        Err(e @ ResumeError::Other(_)) => {
+            ApiGCodeActionResponse::OtherError(Json(errors.insert_get(e.into())))
+        }
+        Err(_) => ApiGCodeActionResponse::StateError(()),
+    }
+}
+
+#[derive(Serialize)]
+pub struct ApiGCodeStatus {
+    #[serde(flatten)]
+    state: StateInfo,
+    pos_x: f64,
+    pos_y: f64,
+    pos_z: f64,
+}
+
+/// Pushes a [`ApiGCodeStatus`] snapshot on a fixed interval, so a frontend
+/// can track a print's progress without having to poll [`get`]
+///
+/// Closes once the client disconnects or the server shuts down
+#[get("/gcode/stream")]
+pub fn get_stream(
+    hw_ctrl: &State<HwCtrl>,
+    settings: &State<Settings>,
+    mut end: Shutdown,
+) -> EventStream![] {
+    let period = Duration::from_millis(settings.config().gcode.stream_interval_millis);
+    EventStream! {
+        let mut ticks = interval(period);
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => (),
+                _ = &mut end => break,
+            }
+            let pos = hw_ctrl.pos_info();
+            let status = ApiGCodeStatus {
+                state: hw_ctrl.state_info(),
+                pos_x: pos.x,
+                pos_y: pos.y,
+                pos_z: pos.z,
+            };
+            yield Event::json(&status);
+        }
+    }
+}
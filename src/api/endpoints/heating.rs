@@ -1,21 +1,50 @@
-use super::{ApiPutSettingsResponse, JsonResult};
-use rocket::{get, http::Status, put, response::status, serde::json::Json};
+use crate::{config::PidControl, settings::Settings};
+use rocket::{get, http::Status, put, response::status, serde::json::Json, State};
 use serde::{Deserialize, Serialize};
 
+use super::{ApiPutSettingsResponse, JsonResult};
+
 #[derive(Debug, Serialize)]
-pub struct ApiGetHotendSettings {}
+pub struct ApiGetHeaterPidSettings {
+    kp: f64,
+    ki: f64,
+    kd: f64,
+    hysteresis: f64,
+    hysteresis_samples: u32,
+    runaway_duty_threshold: f64,
+    runaway_timeout_secs: u64,
+    runaway_min_rise: f64,
+}
+
+impl From<&PidControl> for ApiGetHeaterPidSettings {
+    fn from(pid: &PidControl) -> Self {
+        Self {
+            kp: pid.kp,
+            ki: pid.ki,
+            kd: pid.kd,
+            hysteresis: pid.hysteresis,
+            hysteresis_samples: pid.hysteresis_samples,
+            runaway_duty_threshold: pid.runaway_duty_threshold,
+            runaway_timeout_secs: pid.runaway_timeout_secs,
+            runaway_min_rise: pid.runaway_min_rise,
+        }
+    }
+}
+
+pub type ApiGetHotendSettings = ApiGetHeaterPidSettings;
 
 #[get("/heating/hotend/settings")]
-pub fn get_hotend_settings() -> status::Custom<Json<ApiGetHotendSettings>> {
-    status::Custom(Status::Ok, Json(ApiGetHotendSettings {}))
+pub fn get_hotend_settings(
+    settings: &State<Settings>,
+) -> status::Custom<Json<ApiGetHotendSettings>> {
+    status::Custom(Status::Ok, Json((&settings.config().hotend.pid).into()))
 }
 
-#[derive(Debug, Serialize)]
-pub struct ApiGetBedSettings {}
+pub type ApiGetBedSettings = ApiGetHeaterPidSettings;
 
 #[get("/heating/bed/settings")]
-pub fn get_bed_settings() -> status::Custom<Json<ApiGetBedSettings>> {
-    status::Custom(Status::Ok, Json(ApiGetBedSettings {}))
+pub fn get_bed_settings(settings: &State<Settings>) -> status::Custom<Json<ApiGetBedSettings>> {
+    status::Custom(Status::Ok, Json((&settings.config().bed.pid).into()))
 }
 
 #[get("/heating/chamber/settings")]
@@ -23,6 +52,10 @@ pub fn get_chamber_settings() -> status::Custom<()> {
     status::Custom(Status::NotImplemented, ())
 }
 
+// FIXME PID gains/hysteresis/runaway thresholds aren't runtime-adjustable
+// yet, only settable via config at startup; wiring them up here needs a
+// settings-overlay like `AxisMotorSettings` plus a way to push updated
+// gains into the already-running `HeaterLoop`
 #[derive(Debug, Default, Deserialize)]
 #[serde(default)]
 pub struct ApiPutHotendSettings {}
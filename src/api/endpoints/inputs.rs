@@ -0,0 +1,44 @@
+use crate::hw::{HwCtrl, InputState, InputsSnapshot};
+use rocket::{get, http::Status, response::status, serde::json::Json, State};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct ApiInputState {
+    active: bool,
+    edge_count: u64,
+}
+
+impl From<InputState> for ApiInputState {
+    fn from(state: InputState) -> Self {
+        Self {
+            active: state.active,
+            edge_count: state.edge_count,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiGetInputs {
+    x_endstop: ApiInputState,
+    y_endstop: ApiInputState,
+    z_endstop: ApiInputState,
+    estop: ApiInputState,
+    filament_runout: ApiInputState,
+}
+
+impl From<InputsSnapshot> for ApiGetInputs {
+    fn from(inputs: InputsSnapshot) -> Self {
+        Self {
+            x_endstop: inputs.x_endstop.into(),
+            y_endstop: inputs.y_endstop.into(),
+            z_endstop: inputs.z_endstop.into(),
+            estop: inputs.estop.into(),
+            filament_runout: inputs.filament_runout.into(),
+        }
+    }
+}
+
+#[get("/inputs")]
+pub fn get(hw_ctrl: &State<HwCtrl>) -> status::Custom<Json<ApiGetInputs>> {
+    status::Custom(Status::Ok, Json(hw_ctrl.inputs().into()))
+}
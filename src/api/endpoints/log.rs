@@ -0,0 +1,53 @@
+use crate::log::LogBuffer;
+use rocket::{
+    get,
+    http::Status,
+    response::{
+        status,
+        stream::{Event, EventStream},
+    },
+    serde::json::Json,
+    Shutdown, State,
+};
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct History {
+    pub lines: Vec<String>,
+}
+
+#[get("/log")]
+pub fn get_history(log: &State<LogBuffer>) -> status::Custom<Json<History>> {
+    status::Custom(
+        Status::Ok,
+        Json(History {
+            lines: log.history(),
+        }),
+    )
+}
+
+/// Pushes every freshly formatted line to the client as it's logged,
+/// instead of the client having to poll [`get_history`]
+///
+/// Closes once the client disconnects or the server shuts down
+#[get("/log/stream")]
+pub fn get_stream(log: &State<LogBuffer>, mut end: Shutdown) -> EventStream![] {
+    let subscription = log.subscribe();
+    EventStream! {
+        loop {
+            let line = tokio::select! {
+                res = tokio::task::spawn_blocking({
+                    let subscription = subscription.clone();
+                    move || subscription.recv()
+                }) => match res.expect("blocking recv task panicked") {
+                    Ok(l) => l,
+                    // the LogBuffer instance was dropped, nothing more will
+                    // ever arrive
+                    Err(_) => break,
+                },
+                _ = &mut end => break,
+            };
+            yield Event::data(line);
+        }
+    }
+}
@@ -1,7 +1,11 @@
 pub mod axis;
+pub mod debug;
 pub mod error;
 pub mod gcode;
 pub mod heating;
+pub mod inputs;
+pub mod log;
+pub mod telemetry;
 
 use crate::{
     api::values::ApiError,
@@ -29,6 +33,8 @@ pub enum ApiPutSettingsResponse {
     Ok(()),
     #[response(status = 405)]
     InvalidInput(()),
+    #[response(status = 409)]
+    OutOfBounds(Json<ApiError>),
     #[response(status = 512)]
     SavingError(Json<ApiError>),
 }
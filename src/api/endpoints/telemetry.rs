@@ -0,0 +1,56 @@
+use crate::api::telemetry::{Sample, Telemetry};
+use rocket::{
+    get,
+    http::Status,
+    response::{
+        status,
+        stream::{Event, EventStream},
+    },
+    serde::json::Json,
+    Shutdown, State,
+};
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct History {
+    pub samples: Vec<Sample>,
+}
+
+#[get("/telemetry/history?<since>")]
+pub fn get_history(
+    since: Option<u64>,
+    telemetry: &State<Telemetry>,
+) -> status::Custom<Json<History>> {
+    status::Custom(
+        Status::Ok,
+        Json(History {
+            samples: telemetry.history(since),
+        }),
+    )
+}
+
+/// Pushes every freshly taken [`Sample`] to the client as it's sampled,
+/// instead of the client having to poll [`get_history`]
+///
+/// Closes once the client disconnects or the server shuts down
+#[get("/telemetry/stream")]
+pub fn get_stream(telemetry: &State<Telemetry>, mut end: Shutdown) -> EventStream![] {
+    let subscription = telemetry.subscribe();
+    EventStream! {
+        loop {
+            let sample = tokio::select! {
+                res = tokio::task::spawn_blocking({
+                    let subscription = subscription.clone();
+                    move || subscription.recv()
+                }) => match res.expect("blocking recv task panicked") {
+                    Ok(s) => s,
+                    // the Telemetry instance was dropped, nothing more will
+                    // ever arrive
+                    Err(_) => break,
+                },
+                _ = &mut end => break,
+            };
+            yield Event::json(&sample);
+        }
+    }
+}
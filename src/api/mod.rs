@@ -1,22 +1,39 @@
 mod endpoints;
 mod error;
+pub mod mqtt;
+pub mod telemetry;
 pub mod values;
 
-use self::values::Errors;
-use crate::{hw::HwCtrl, settings::Settings};
+use self::{telemetry::Telemetry, values::Errors};
+use crate::{hw::HwCtrl, log::LogBuffer, settings::Settings};
 use anyhow::Result;
 use rocket::{catchers, config::Config as RocketConfig, routes};
 
-pub fn launch(settings: Settings, errors: Errors, hw_ctrl: HwCtrl) -> Result<()> {
+pub fn launch(
+    settings: Settings,
+    errors: Errors,
+    hw_ctrl: HwCtrl,
+    telemetry: Telemetry,
+    log_buffer: LogBuffer,
+) -> Result<()> {
     let routes_v0 = {
         use self::endpoints::*;
         routes![
             post_estop,
             gcode::get,
             gcode::post_start,
+            gcode::post_start_upload,
             gcode::post_stop,
             gcode::post_continue,
             gcode::post_pause,
+            gcode::post_resume,
+            gcode::get_stream,
+            debug::post_breakpoint,
+            debug::delete_breakpoint,
+            debug::get_breakpoints,
+            debug::post_step,
+            debug::post_continue,
+            debug::get_position,
             axis::get_position,
             axis::get_axis_name_position,
             axis::get_axis_name_settings,
@@ -31,9 +48,15 @@ pub fn launch(settings: Settings, errors: Errors, hw_ctrl: HwCtrl) -> Result<()>
             heating::put_hotend_settings,
             heating::put_bed_settings,
             heating::put_chamber_settings,
+            inputs::get,
+            telemetry::get_history,
+            telemetry::get_stream,
+            log::get_history,
+            log::get_stream,
             error::get,
             error::get_last,
             error::get_id,
+            error::get_events,
         ]
     };
     rocket::execute(
@@ -42,6 +65,8 @@ pub fn launch(settings: Settings, errors: Errors, hw_ctrl: HwCtrl) -> Result<()>
             .manage(settings)
             .manage(errors)
             .manage(hw_ctrl)
+            .manage(telemetry)
+            .manage(log_buffer)
             .mount("/v0/", routes_v0)
             .register("/", catchers![endpoints::catch_404])
             .launch(),
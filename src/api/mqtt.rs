@@ -0,0 +1,159 @@
+use crate::{
+    comms::ControlComms,
+    config::Mqtt as MqttConfig,
+    hw::{HwCtrl, StateInfo},
+    log::target,
+    APP_NAME,
+};
+use anyhow::{Context, Result};
+use crossbeam::channel::{self, Receiver, RecvTimeoutError, Sender};
+use rumqttc::{Client, MqttOptions, QoS};
+use serde::Serialize;
+use std::{
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use tracing::{debug, warn};
+
+// backoff is reset to this on every successful publish, and doubled (capped
+// at MAX_RECONNECT_BACKOFF) every time the connection has to be re-established
+const INITIAL_RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Published as JSON to `<topic_prefix>/temperature`
+#[derive(Debug, Serialize)]
+struct TemperaturePayload {
+    hotend: f64,
+    hotend_target: Option<u16>,
+    bed: f64,
+    bed_target: Option<u16>,
+}
+
+/// Published as JSON to `<topic_prefix>/status`
+///
+/// `status` mirrors [`StateInfo`]'s own `status` tag ("printing"/"paused"/
+/// "stopped") rather than reusing it directly, since a client subscribed
+/// over MQTT shouldn't have to pull in the current line/print path too just
+/// to read the motion status.
+#[derive(Debug, Serialize)]
+struct StatusPayload {
+    status: &'static str,
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+fn status_str(state: &StateInfo) -> &'static str {
+    match state {
+        StateInfo::Printing(_) => "printing",
+        StateInfo::Paused(_) => "paused",
+        StateInfo::Stopped => "stopped",
+    }
+}
+
+/// Publishes one fresh snapshot of `hw_ctrl`'s telemetry; a publish error
+/// (e.g. the connection just dropped) is left for the caller to react to by
+/// reconnecting, rather than handled here
+fn publish_snapshot(client: &Client, topic_prefix: &str, hw_ctrl: &HwCtrl) -> Result<()> {
+    let temperature = hw_ctrl.temperature_info();
+    let position = hw_ctrl.pos_info();
+    let status = status_str(&hw_ctrl.state_info());
+    client
+        .publish(
+            format!("{}/temperature", topic_prefix),
+            QoS::AtMostOnce,
+            false,
+            serde_json::to_vec(&TemperaturePayload {
+                hotend: temperature.hotend,
+                hotend_target: temperature.hotend_target,
+                bed: temperature.bed,
+                bed_target: temperature.bed_target,
+            })
+            .context("failed to encode mqtt temperature payload")?,
+        )
+        .context("failed to publish mqtt temperature payload")?;
+    client
+        .publish(
+            format!("{}/status", topic_prefix),
+            QoS::AtMostOnce,
+            false,
+            serde_json::to_vec(&StatusPayload {
+                status,
+                x: position.x,
+                y: position.y,
+                z: position.z,
+            })
+            .context("failed to encode mqtt status payload")?,
+        )
+        .context("failed to publish mqtt status payload")?;
+    Ok(())
+}
+
+/// Connects to the broker and publishes a snapshot every `publish_interval`
+/// until told to exit, reconnecting with exponential backoff whenever the
+/// connection drops (broker restart, network blip, ...) instead of giving up
+fn mqtt_loop(hw_ctrl: HwCtrl, config: MqttConfig, control_recv: Receiver<ControlComms<()>>) {
+    let interval = Duration::from_millis(config.publish_interval_millis);
+    let mut backoff = INITIAL_RECONNECT_BACKOFF;
+    'reconnect: loop {
+        let mut options = MqttOptions::new(APP_NAME, config.broker_address.clone(), config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        let (client, mut connection) = Client::new(options, 10);
+        // rumqttc only actually drives the network connection while
+        // `Connection` is polled; do that on its own thread so this one is
+        // free to publish on `interval` without also having to interleave
+        // polling for incoming eventloop notifications
+        let conn_handle = thread::Builder::new()
+            .name(String::from("mqtt-conn"))
+            .spawn(move || {
+                for notification in connection.iter() {
+                    if notification.is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("creating the mqtt connection thread failed");
+        loop {
+            match control_recv.recv_timeout(interval) {
+                Ok(ControlComms::Exit) | Err(RecvTimeoutError::Disconnected) => {
+                    drop(client);
+                    let _ = conn_handle.join();
+                    break 'reconnect;
+                }
+                // nothing else is ever sent over this channel
+                Ok(ControlComms::Msg(())) => unreachable!(),
+                Err(RecvTimeoutError::Timeout) => {
+                    match publish_snapshot(&client, &config.topic_prefix, &hw_ctrl) {
+                        Ok(()) => backoff = INITIAL_RECONNECT_BACKOFF,
+                        Err(e) => {
+                            warn!(
+                                target: target::INTERNAL,
+                                "mqtt publish failed, reconnecting: {:#}", e
+                            );
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        drop(client);
+        let _ = conn_handle.join();
+        debug!(target: target::INTERNAL, "mqtt connection lost, retrying in {:?}", backoff);
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+    }
+}
+
+/// Spawns the MQTT telemetry publisher; only called when `[mqtt]` is
+/// configured, see [`crate::config::Mqtt`]
+pub fn start(
+    hw_ctrl: HwCtrl,
+    config: MqttConfig,
+) -> Result<(JoinHandle<()>, Sender<ControlComms<()>>)> {
+    let (control_send, control_recv) = channel::unbounded();
+    let handle = thread::Builder::new()
+        .name(String::from("mqtt"))
+        .spawn(move || mqtt_loop(hw_ctrl, config, control_recv))
+        .context("Creating the mqtt thread failed")?;
+    Ok((handle, control_send))
+}
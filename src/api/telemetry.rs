@@ -0,0 +1,123 @@
+use crate::{
+    comms::ControlComms, config::Telemetry as TelemetryConfig, hw::HwCtrl, ring_buffer::RingBuffer,
+};
+use anyhow::{Context, Result};
+use crossbeam::channel::{self, Receiver, RecvTimeoutError, Sender};
+use serde::Serialize;
+use std::{
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// A single position/temperature sample
+///
+/// `uptime_millis` is measured from when the sampler thread started rather
+/// than being a wall-clock timestamp, so a UI can plot a time series without
+/// caring whether the daemon's clock is correct or what timezone it's in.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Sample {
+    pub uptime_millis: u64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub hotend: f64,
+    pub bed: f64,
+}
+
+#[derive(Debug)]
+struct InnerTelemetry {
+    // subscribers registered via Telemetry::subscribe, fed every freshly
+    // taken Sample; pruned lazily whenever we try to send and the receiver
+    // has hung up
+    subscribers: Vec<Sender<Sample>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Telemetry {
+    // lock-free so the sampler thread can record a reading without ever
+    // blocking on whatever is draining `/telemetry/history`; backs it
+    // directly, oldest first
+    ring: Arc<RingBuffer<Sample>>,
+    inner: Arc<Mutex<InnerTelemetry>>,
+}
+
+impl Telemetry {
+    fn new(max_samples: usize) -> Self {
+        Self {
+            ring: Arc::new(RingBuffer::new(max_samples)),
+            inner: Arc::new(Mutex::new(InnerTelemetry {
+                subscribers: Vec::new(),
+            })),
+        }
+    }
+
+    fn push(&self, sample: Sample) {
+        self.ring.push(sample);
+        // broadcast to every live subscriber, dropping any whose receiver
+        // has hung up so the list doesn't grow forever
+        let mut inner = self.inner.lock().unwrap();
+        inner.subscribers.retain(|sub| sub.send(sample).is_ok());
+    }
+
+    /// Registers a new live subscriber for freshly sampled telemetry
+    ///
+    /// Used by the `/telemetry/stream` SSE route; the returned [`Receiver`]
+    /// yields every [`Sample`] taken from this point on.
+    pub fn subscribe(&self) -> Receiver<Sample> {
+        let (sub_send, sub_recv) = channel::unbounded();
+        let mut inner = self.inner.lock().unwrap();
+        inner.subscribers.push(sub_send);
+        sub_recv
+    }
+
+    /// The buffered window of the most recent samples, oldest first,
+    /// optionally restricted to those taken after `since` (an
+    /// [`Sample::uptime_millis`] value a client already has)
+    pub fn history(&self, since: Option<u64>) -> Vec<Sample> {
+        let samples = self.ring.snapshot();
+        match since {
+            Some(since) => samples
+                .into_iter()
+                .filter(|s| s.uptime_millis > since)
+                .collect(),
+            None => samples,
+        }
+    }
+}
+
+pub fn start(
+    hw_ctrl: HwCtrl,
+    config: TelemetryConfig,
+) -> Result<(JoinHandle<()>, Sender<ControlComms<()>>, Telemetry)> {
+    let telemetry = Telemetry::new(config.max_samples);
+    let telemetry_clone = telemetry.clone();
+    let (control_send, control_recv) = channel::unbounded();
+    let handle = thread::Builder::new()
+        .name(String::from("telemetry"))
+        .spawn(move || {
+            let start = Instant::now();
+            let interval = Duration::from_millis(config.sample_interval_millis);
+            loop {
+                match control_recv.recv_timeout(interval) {
+                    Ok(ControlComms::Exit) | Err(RecvTimeoutError::Disconnected) => break,
+                    // nothing else is ever sent over this channel
+                    Ok(ControlComms::Msg(())) => unreachable!(),
+                    Err(RecvTimeoutError::Timeout) => {
+                        let position = hw_ctrl.pos_info();
+                        let temperature = hw_ctrl.temperature_info();
+                        telemetry.push(Sample {
+                            uptime_millis: start.elapsed().as_millis() as u64,
+                            x: position.x,
+                            y: position.y,
+                            z: position.z,
+                            hotend: temperature.hotend,
+                            bed: temperature.bed,
+                        });
+                    }
+                }
+            }
+        })
+        .context("Creating the telemetry thread failed")?;
+    Ok((handle, control_send, telemetry_clone))
+}
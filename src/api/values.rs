@@ -1,74 +1,273 @@
-use crate::comms::ControlComms;
-use anyhow::Error;
-use crossbeam::channel::Receiver;
+use crate::{
+    comms::ControlComms,
+    config::Errors as ErrorsConfig,
+    hw::{
+        DecoderError, GCodeError, GpioTripError, MotorError, MotorsError, OvertempError,
+        RunawayError, StateError,
+    },
+    settings::SettingsError,
+};
+use anyhow::{Error, Result};
+use crossbeam::channel::{self, Receiver, Sender};
 use indexmap::IndexMap;
 use log::error;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::{
     cmp,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
     sync::{Arc, Mutex, MutexGuard},
     thread::{self, JoinHandle},
     time::{SystemTime, UNIX_EPOCH},
 };
 
-#[derive(Debug)]
-struct ErrorWrap {
-    time: SystemTime,
-    error: Error,
+// name of the active, not yet rotated error log file; rotated files are
+// named "errors.log.1", "errors.log.2" and so on, 1 being the newest
+const LOG_FILE_NAME: &str = "errors.log";
+
+/// A stable, machine-readable classification of an [`ApiError`], so clients
+/// can branch on the kind of failure instead of matching on `text`
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    Driver,
+    Position,
+    GCodeMissingArgs,
+    GCodeUnknown,
+    PosOutOfBounds,
+    TempOutOfBounds,
+    SettingOutOfBounds,
+    ThermalRunaway,
+    GpioInput,
+    Io,
+    State,
+    Other,
+}
+
+/// Tries to classify `error` by downcasting it against the error types we
+/// know how to explain to an API client, falling back to [`ErrorCode::Other`]
+/// for anything else (e.g. a plain [`std::io::Error`] from somewhere
+/// unexpected, or a third-party error we don't special-case)
+fn classify(error: &Error) -> (ErrorCode, Option<Value>) {
+    if let Some(e) = error.downcast_ref::<DecoderError>() {
+        (e.code(), e.details())
+    } else if let Some(e) = error.downcast_ref::<GCodeError>() {
+        (e.code(), e.details())
+    } else if let Some(e) = error.downcast_ref::<StateError>() {
+        (e.code(), e.details())
+    } else if let Some(e) = error.downcast_ref::<MotorsError>() {
+        (e.code(), e.details())
+    } else if let Some(e) = error.downcast_ref::<MotorError>() {
+        (e.code(), e.details())
+    } else if let Some(e) = error.downcast_ref::<SettingsError>() {
+        (e.code(), e.details())
+    } else if let Some(e) = error.downcast_ref::<RunawayError>() {
+        (e.code(), e.details())
+    } else if let Some(e) = error.downcast_ref::<OvertempError>() {
+        (e.code(), e.details())
+    } else if let Some(e) = error.downcast_ref::<GpioTripError>() {
+        (e.code(), e.details())
+    } else {
+        (ErrorCode::Other, None)
+    }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiError {
     id: u64,
     time: u64,
     text: String,
+    code: ErrorCode,
+    details: Option<Value>,
 }
 
-impl From<(&u64, &ErrorWrap)> for ApiError {
-    fn from((id, wrap): (&u64, &ErrorWrap)) -> Self {
+impl ApiError {
+    fn from_error(id: u64, error: &Error) -> Self {
         // calculate unix timestamp
-        let time = wrap
-            .time
+        let time = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .expect("error somehow occured before epoch")
             .as_secs();
-        let text = format!("{}", wrap.error);
+        let text = format!("{}", error);
+        let (code, details) = classify(error);
         Self {
-            id: *id,
+            id,
             time,
             text,
+            code,
+            details,
+        }
+    }
+}
+
+/// The durable half of the error store: a rotating, append only JSON-lines
+/// log under [`ErrorsConfig::log_dir`], holding everything that got evicted
+/// from the in-memory ring so it isn't lost for good
+///
+/// This follows the file-logger-with-rotation design the Proxmox REST server
+/// uses for its worker/event logs: keep appending to one file, and once it
+/// passes a size threshold rename it out of the way and start a fresh one,
+/// keeping only a bounded number of the rotated files around.
+#[derive(Debug)]
+struct ErrorLog {
+    dir: PathBuf,
+    max_file_size: u64,
+    max_files: usize,
+    file: File,
+    file_len: u64,
+}
+
+impl ErrorLog {
+    fn open(dir: PathBuf, max_file_size: u64, max_files: usize) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOG_FILE_NAME))?;
+        let file_len = file.metadata()?.len();
+        Ok(Self {
+            dir,
+            max_file_size,
+            max_files,
+            file,
+            file_len,
+        })
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join(LOG_FILE_NAME)
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        self.dir.join(format!("{}.{}", LOG_FILE_NAME, n))
+    }
+
+    fn append(&mut self, api_error: &ApiError) -> Result<()> {
+        let mut line = serde_json::to_vec(api_error)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file_len += line.len() as u64;
+        if self.file_len >= self.max_file_size {
+            self.rotate()?;
         }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        // make room by dropping the oldest rotated file, then shift the
+        // rest up by one, oldest last
+        let oldest = self.rotated_path(self.max_files);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for n in (1..self.max_files).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(n + 1))?;
+            }
+        }
+        if self.max_files > 0 {
+            fs::rename(self.path(), self.rotated_path(1))?;
+        } else {
+            fs::remove_file(self.path())?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path())?;
+        self.file_len = 0;
+        Ok(())
+    }
+
+    /// Reads up to `max_entries` of the most recent entries back out of the
+    /// active log file, so a restart doesn't lose recent history
+    fn reload_tail(&self, max_entries: usize) -> Result<Vec<ApiError>> {
+        let path = self.path();
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let lines = BufReader::new(File::open(path)?)
+            .lines()
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let skip = lines.len().saturating_sub(max_entries);
+        lines[skip..]
+            .iter()
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct InnerErrors {
-    errors: IndexMap<u64, ErrorWrap>,
+    errors: IndexMap<u64, ApiError>,
     next_id: u64,
+    // subscribers registered via Errors::subscribe, fed every newly inserted
+    // ApiError; pruned lazily whenever we try to send and the receiver has
+    // hung up
+    subscribers: Vec<Sender<ApiError>>,
+    // in-memory ring is capped at this many entries; anything evicted is
+    // persisted to `log` first
+    max_entries: usize,
+    log: ErrorLog,
 }
 
 #[derive(Debug, Clone)]
 pub struct Errors(Arc<Mutex<InnerErrors>>);
 
 impl Errors {
-    fn new() -> Self {
-        Self(Arc::new(Mutex::new(InnerErrors::default())))
+    fn new(config: ErrorsConfig) -> Result<Self> {
+        let log = ErrorLog::open(config.log_dir, config.max_file_size, config.max_files)?;
+        let mut errors = IndexMap::new();
+        let mut next_id = 0;
+        for api_error in log.reload_tail(config.max_entries)? {
+            next_id = cmp::max(next_id, api_error.id + 1);
+            errors.insert(api_error.id, api_error);
+        }
+        Ok(Self(Arc::new(Mutex::new(InnerErrors {
+            errors,
+            next_id,
+            subscribers: Vec::new(),
+            max_entries: config.max_entries,
+            log,
+        }))))
     }
 
     fn insert_inner(&self, inner: &mut MutexGuard<InnerErrors>, error: Error) -> u64 {
         let id = inner.next_id;
-        inner.errors.insert(
-            id,
-            ErrorWrap {
-                time: SystemTime::now(),
-                error,
-            },
-        );
+        let api_error = ApiError::from_error(id, &error);
+        // evict the oldest entries over the cap, persisting each to the
+        // on-disk log before it's dropped from memory
+        while inner.max_entries > 0 && inner.errors.len() >= inner.max_entries {
+            let Some((_, evicted)) = inner.errors.shift_remove_index(0) else {
+                break;
+            };
+            if let Err(e) = inner.log.append(&evicted) {
+                error!("failed to persist evicted error to the error log: {}", e);
+            }
+        }
+        inner.errors.insert(id, api_error.clone());
         inner.next_id += 1;
+        // broadcast to every live subscriber, dropping any whose receiver
+        // has hung up so the list doesn't grow forever
+        inner
+            .subscribers
+            .retain(|sub| sub.send(api_error.clone()).is_ok());
         id
     }
 
-    // FIXME add limit to errors
+    /// Registers a new live subscriber for freshly inserted errors
+    ///
+    /// Used by the `/errors/events` SSE route; the returned [`Receiver`]
+    /// yields every [`ApiError`] inserted from this point on
+    pub fn subscribe(&self) -> Receiver<ApiError> {
+        let (sub_send, sub_recv) = channel::unbounded();
+        let mut inner = self.0.lock().unwrap();
+        inner.subscribers.push(sub_send);
+        sub_recv
+    }
+
     pub fn insert(&self, error: Error) -> u64 {
         let mut inner = self.0.lock().unwrap();
         self.insert_inner(&mut inner, error)
@@ -78,16 +277,12 @@ impl Errors {
         let mut inner = self.0.lock().unwrap();
         let id = self.insert_inner(&mut inner, error);
         // shouldn't panic, we just inserted the error and didn't open the lock
-        inner
-            .errors
-            .get(&id)
-            .map(|wrap| (&id, wrap).into())
-            .unwrap()
+        inner.errors.get(&id).cloned().unwrap()
     }
 
     pub fn get_last(&self) -> Option<ApiError> {
         let inner = self.0.lock().unwrap();
-        inner.errors.last().map(Into::into)
+        inner.errors.last().map(|(_, e)| e.clone())
     }
 
     pub fn get_page(&self, page: usize, size: usize) -> Vec<ApiError> {
@@ -102,19 +297,22 @@ impl Errors {
                 .iter()
                 .skip(start)
                 .take(len)
-                .map(Into::into)
+                .map(|(_, e)| e.clone())
                 .collect()
         }
     }
 
     pub fn get(&self, id: u64) -> Option<ApiError> {
         let inner = self.0.lock().unwrap();
-        inner.errors.get(&id).map(|wrap| (&id, wrap).into())
+        inner.errors.get(&id).cloned()
     }
 }
 
-pub fn start(error_recv: Receiver<ControlComms<Error>>) -> (JoinHandle<()>, Errors) {
-    let errors = Errors::new();
+pub fn start(
+    error_recv: Receiver<ControlComms<Error>>,
+    config: ErrorsConfig,
+) -> Result<(JoinHandle<()>, Errors)> {
+    let errors = Errors::new(config)?;
     let errors_clone = errors.clone();
     let handle = thread::spawn(move || loop {
         match error_recv.recv().unwrap() {
@@ -125,5 +323,5 @@ pub fn start(error_recv: Receiver<ControlComms<Error>>) -> (JoinHandle<()>, Erro
             ControlComms::Exit => break,
         }
     });
-    (handle, errors_clone)
+    Ok((handle, errors_clone))
 }
@@ -4,7 +4,7 @@ use figment::{
     value::{Dict, Map, Value},
     Metadata, Profile, Provider,
 };
-use std::net::IpAddr;
+use std::{net::IpAddr, path::PathBuf};
 use tracing::Level;
 
 fn parse_count_loglevel(arg: &str) -> Result<Level, String> {
@@ -38,6 +38,19 @@ pub struct Args {
     /// is used. Must be "error", "warn", "info", "debug" or "trace"
     #[clap(short, long, value_parser = parse_count_loglevel)]
     pub log_level: Option<Level>,
+    /// Overrides the path of the unix-domain command socket set in the config
+    /// file, default is taken from the config file or is /run/<APP_NAME>/command.sock
+    #[clap(short, long)]
+    pub socket: Option<PathBuf>,
+    /// Overrides the path to the startup gcode macro set in the config file
+    #[clap(long)]
+    pub startup_gcode: Option<PathBuf>,
+    /// Overrides the path to the idle gcode macro set in the config file
+    #[clap(long)]
+    pub idle_gcode: Option<PathBuf>,
+    /// Overrides the path to the cancel gcode macro set in the config file
+    #[clap(long)]
+    pub cancel_gcode: Option<PathBuf>,
 }
 
 impl Provider for Args {
@@ -46,6 +59,10 @@ impl Provider for Args {
             ["log", "level"] => String::from("-l/--log-level"),
             ["api", "port"] => String::from("-p/--port"),
             ["api", "address"] => String::from("-a/--address"),
+            ["socket", "path"] => String::from("-s/--socket"),
+            ["macros", "startup"] => String::from("--startup-gcode"),
+            ["macros", "idle"] => String::from("--idle-gcode"),
+            ["macros", "cancel"] => String::from("--cancel-gcode"),
             _ => unreachable!(),
         })
     }
@@ -69,9 +86,37 @@ impl Provider for Args {
             // might be a little cryptic
             api.insert(String::from("address"), Value::from(format!("{}", a)));
         }
+        let mut socket = Map::new();
+        if let Some(s) = &self.socket {
+            socket.insert(
+                String::from("path"),
+                Value::from(s.to_string_lossy().into_owned()),
+            );
+        }
+        let mut macros = Map::new();
+        if let Some(p) = &self.startup_gcode {
+            macros.insert(
+                String::from("startup"),
+                Value::from(p.to_string_lossy().into_owned()),
+            );
+        }
+        if let Some(p) = &self.idle_gcode {
+            macros.insert(
+                String::from("idle"),
+                Value::from(p.to_string_lossy().into_owned()),
+            );
+        }
+        if let Some(p) = &self.cancel_gcode {
+            macros.insert(
+                String::from("cancel"),
+                Value::from(p.to_string_lossy().into_owned()),
+            );
+        }
         let mut vals = Map::new();
         vals.insert(String::from("log"), Value::from(log));
         vals.insert(String::from("api"), Value::from(api));
+        vals.insert(String::from("socket"), Value::from(socket));
+        vals.insert(String::from("macros"), Value::from(macros));
         let mut map = Map::new();
         map.insert(Profile::Global, vals);
         Ok(map)
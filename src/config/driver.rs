@@ -0,0 +1,85 @@
+//! Config-time limits a stepper-driver family's firmware enforces, so
+//! [`super::motors`]'s range-checking deserializers validate a motor config
+//! against whichever backend its `driver` field names instead of assuming
+//! one family's numbers are universal
+//!
+//! Only [`NanotecDriver`] exists today, and the deserializers still query it
+//! directly rather than whatever `driver` a config actually names: doing
+//! that properly means threading the selected driver into `AxisMotor`'s and
+//! `ExtruderMotor`'s nested deserialization (serde's derive can't pass
+//! sibling-field context down on its own), which isn't worth the
+//! `DeserializeSeed` rewrite until there's a second backend to validate it
+//! against. The `driver` field is parsed and kept on [`Motors`] so that
+//! rewrite has somewhere to plug in.
+use serde::Deserialize;
+use std::ops::RangeInclusive;
+
+/// The config-time capability surface a stepper-driver family exposes
+pub trait StepperDriver {
+    /// valid range for `quickstop_ramp`
+    fn quickstop_ramp_range(&self) -> RangeInclusive<u32>;
+    /// valid range for an axis' `limit`, in mm
+    fn axis_limit_range(&self) -> RangeInclusive<u32>;
+    /// valid range for a speed, in hz
+    fn speed_range(&self) -> RangeInclusive<u32>;
+    /// valid range for an acceleration or deceleration, in hz/s
+    fn accel_decel_range(&self) -> RangeInclusive<u32>;
+    /// valid range for an acceleration or deceleration jerk
+    fn jerk_range(&self) -> RangeInclusive<u32>;
+    /// the serialport baud rates this driver's firmware accepts
+    fn baud_rates(&self) -> &'static [u32];
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NanotecDriver;
+
+impl StepperDriver for NanotecDriver {
+    fn quickstop_ramp_range(&self) -> RangeInclusive<u32> {
+        0..=3_000_000
+    }
+
+    fn axis_limit_range(&self) -> RangeInclusive<u32> {
+        1..=10_000
+    }
+
+    fn speed_range(&self) -> RangeInclusive<u32> {
+        1..=1_000_000
+    }
+
+    fn accel_decel_range(&self) -> RangeInclusive<u32> {
+        1..=3_000_000
+    }
+
+    fn jerk_range(&self) -> RangeInclusive<u32> {
+        1..=100_000_000
+    }
+
+    fn baud_rates(&self) -> &'static [u32] {
+        &[
+            110, 300, 600, 1200, 4800, 9600, 14400, 19200, 38400, 57600, 115200,
+        ]
+    }
+}
+
+/// The `driver = "..."` discriminant in `[motors]`, selecting which
+/// [`StepperDriver`] backend the rest of that section is meant to be
+/// validated against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepperDriverKind {
+    Nanotec,
+}
+
+impl Default for StepperDriverKind {
+    fn default() -> Self {
+        Self::Nanotec
+    }
+}
+
+impl StepperDriverKind {
+    pub fn driver(self) -> Box<dyn StepperDriver> {
+        match self {
+            Self::Nanotec => Box::new(NanotecDriver),
+        }
+    }
+}
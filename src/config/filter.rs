@@ -0,0 +1,29 @@
+//! Digital filtering config for a temperature sensor, applied before its
+//! reading reaches the overtemp check and the PID loop; see
+//! [`crate::hw::pi`]'s filter implementation for the actual runtime state
+
+use serde::Deserialize;
+
+/// How a `[hotend.filter]`/`[bed.filter]` table smooths out noisy
+/// thermistor/ADC readings
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum TempFilter {
+    /// a first-order exponential IIR, `y[n] = y[n-1] + alpha*(x[n] -
+    /// y[n-1])`, with `alpha` derived from `cutoff_hz` and `Pi.
+    /// check_interval`
+    Ewma { cutoff_hz: f64 },
+    /// an N-tap moving average, for stronger mains-frequency rejection at
+    /// the cost of more lag than an equivalent-cutoff EWMA
+    MovingAverage { taps: usize },
+    /// readings are used as-is
+    None,
+}
+
+impl Default for TempFilter {
+    fn default() -> Self {
+        // a low cutoff is fine since heater thermal mass is already slow to
+        // move; this just needs to reject sensor jitter near a limit
+        Self::Ewma { cutoff_hz: 2.0 }
+    }
+}
@@ -1,12 +1,16 @@
+mod driver;
+mod filter;
 mod motors;
 
 use crate::{args::Args, APP_NAME};
 use anyhow::Result;
+pub use driver::{StepperDriver, StepperDriverKind};
 use figment::{
     providers::{Format, Toml},
     Figment,
 };
-pub use motors::{AxisMotor, ExtruderMotor, Motors};
+pub use filter::TempFilter;
+pub use motors::{AxisMotor, ExtruderMotor, MotorBackendKind, Motors};
 use rocket::config::{Config as RocketConfig, Ident};
 use serde::{
     de::{Error as DeError, Unexpected, Visitor},
@@ -76,11 +80,17 @@ where
 pub struct Log {
     #[serde(deserialize_with = "deserialize_log_level")]
     pub level: Level,
+    // how many formatted log lines are kept in memory for `/log`, in
+    // addition to whatever `/log/stream` subscribers are fed live
+    pub max_lines: usize,
 }
 
 impl Default for Log {
     fn default() -> Self {
-        Self { level: Level::WARN }
+        Self {
+            level: Level::WARN,
+            max_lines: 1000,
+        }
     }
 }
 
@@ -128,11 +138,181 @@ impl From<Api> for RocketConfig {
 pub struct Pi {
     // interval in which to check the values in milliseconds
     pub check_interval: u64,
+    // whether the filament-runout sensor reads high when filament is present
+    // (true) or reads high when it has run out (false)
+    pub filament_runout_active_high: bool,
+    // how many hotend/bed temperature samples the pi thread keeps buffered
+    // for `PiCtrl::telemetry_snapshot`, one taken every `check_interval`
+    pub telemetry_samples: usize,
 }
 
 impl Default for Pi {
     fn default() -> Self {
-        Self { check_interval: 1 }
+        Self {
+            check_interval: 1,
+            filament_runout_active_high: true,
+            telemetry_samples: 300,
+        }
+    }
+}
+
+//
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GpioPin {
+    // sysfs GPIO line number, exported and read under
+    // /sys/class/gpio/gpio<pin>
+    pub pin: u32,
+    // whether the input reads low when active (true) or high when active
+    // (false)
+    pub active_low: bool,
+    // how long a level has to stay stable before it's trusted, in
+    // milliseconds; filters out switch bounce/electrical noise
+    pub debounce_millis: u64,
+}
+
+impl Default for GpioPin {
+    fn default() -> Self {
+        Self {
+            pin: 0,
+            active_low: false,
+            debounce_millis: 20,
+        }
+    }
+}
+
+// configures the optional sysfs-GPIO safety interlock: a physical e-stop
+// button, external per-axis endstops and a filament-runout switch wired
+// directly to GPIO rather than through a dedicated I/O driver like RevPi's
+// /dev/piControl0 (see hw::pi::RevPi) or the motor driver's own limit
+// switches. every input is opt-in; leaving it unset means that input isn't
+// monitored at all.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Gpio {
+    // how often every configured pin is polled, in milliseconds
+    pub poll_interval_millis: u64,
+    // physical e-stop button; a triggered edge is handled exactly like the
+    // existing e-stop input (halts the motors, stops the print)
+    pub estop: Option<GpioPin>,
+    // external limit switches; purely informational, cross-checked against
+    // the motor driver's own status after a reference run rather than acted
+    // on directly
+    pub x_endstop: Option<GpioPin>,
+    pub y_endstop: Option<GpioPin>,
+    pub z_endstop: Option<GpioPin>,
+    // filament-runout switch; a triggered edge pauses an active print
+    pub filament_runout: Option<GpioPin>,
+}
+
+impl Default for Gpio {
+    fn default() -> Self {
+        Self {
+            poll_interval_millis: 50,
+            estop: None,
+            x_endstop: None,
+            y_endstop: None,
+            z_endstop: None,
+            filament_runout: None,
+        }
+    }
+}
+
+//
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Execute {
+    // length of the executor's run-loop quantum, in microseconds; once
+    // printing, it wakes on this tick, drains and executes every action
+    // that's ready by then, and sleeps until the next tick, so bus writes
+    // are batched instead of firing the instant each action decodes
+    pub tick_micros: u64,
+}
+
+impl Default for Execute {
+    fn default() -> Self {
+        Self { tick_micros: 1000 }
+    }
+}
+
+//
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Checkpoint {
+    // where the active print's progress is periodically persisted, so it
+    // can be resumed if the daemon dies mid-print
+    pub path: PathBuf,
+    // how often the in-progress checkpoint is rewritten, in seconds
+    pub interval_secs: u64,
+}
+
+impl Default for Checkpoint {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from(format!("/var/lib/{}/checkpoint.json", APP_NAME)),
+            interval_secs: 5,
+        }
+    }
+}
+
+//
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PidControl {
+    // proportional, integral and derivative gains of the heater's PID loop
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    // anti-windup band the accumulated integral term is clamped to, so it
+    // can't keep growing while the output is already saturated
+    pub integral_limit: f64,
+    // length of the on/off window used to turn the PID loop's continuous
+    // output into a duty cycle for the boolean heater output, in
+    // milliseconds
+    pub pwm_window_millis: u64,
+    // how close to the target the measured temperature has to stay, in
+    // Celsius, to count as "reached" for a M109/M190-style wait
+    pub hysteresis: f64,
+    // how many consecutive samples have to be within `hysteresis` of the
+    // target before a wait is considered satisfied
+    pub hysteresis_samples: u32,
+    // duty cycle at or above which the heater counts as commanded near
+    // full power, for thermal-runaway detection
+    pub runaway_duty_threshold: f64,
+    // how long the heater may be commanded near full power without the
+    // temperature rising by at least `runaway_min_rise`, in seconds,
+    // before it's treated as a thermal runaway
+    pub runaway_timeout_secs: u64,
+    // minimum temperature rise, in Celsius, required over
+    // `runaway_timeout_secs` while near full power to avoid tripping
+    // runaway protection
+    pub runaway_min_rise: f64,
+    // how far above `upper_limit`, in Celsius, the measured temperature is
+    // allowed to sit before it's treated as a thermal fault regardless of
+    // commanded duty; catches a stuck-on heater or a thermistor reading
+    // room temperature faster than the duty-based runaway check would
+    pub overtemp_margin: f64,
+}
+
+impl Default for PidControl {
+    fn default() -> Self {
+        Self {
+            kp: 10.0,
+            ki: 0.2,
+            kd: 40.0,
+            integral_limit: 20.0,
+            pwm_window_millis: 2_000,
+            hysteresis: 2.0,
+            hysteresis_samples: 5,
+            runaway_duty_threshold: 0.95,
+            runaway_timeout_secs: 60,
+            runaway_min_rise: 2.0,
+            overtemp_margin: 5.0,
+        }
     }
 }
 
@@ -147,6 +327,16 @@ pub struct Hotend {
     // be reached naturally and as such might lead to problems
     // FIXME maybe add default?
     pub lower_limit: u16,
+    // how long a M109 wait for this target may take, in seconds, before
+    // it's aborted as a timeout; None waits forever
+    #[serde(default)]
+    pub wait_timeout: Option<u64>,
+    #[serde(default)]
+    pub pid: PidControl,
+    // smooths the raw thermistor reading before it reaches the overtemp
+    // check and the PID loop
+    #[serde(default)]
+    pub filter: TempFilter,
 }
 
 //
@@ -160,6 +350,329 @@ pub struct Bed {
     // be reached naturally and as such might lead to problems
     // FIXME maybe add default?
     pub lower_limit: u16,
+    // how long a M190 wait for this target may take, in seconds, before
+    // it's aborted as a timeout; None waits forever
+    #[serde(default)]
+    pub wait_timeout: Option<u64>,
+    #[serde(default)]
+    pub pid: PidControl,
+    // smooths the raw thermistor reading before it reaches the overtemp
+    // check and the PID loop
+    #[serde(default)]
+    pub filter: TempFilter,
+}
+
+//
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Socket {
+    // path of the unix-domain socket used for local control
+    pub path: PathBuf,
+}
+
+impl Default for Socket {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from(format!("/run/{}/command.sock", APP_NAME)),
+        }
+    }
+}
+
+//
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Errors {
+    // how many errors are kept in memory for quick access via the api
+    pub max_entries: usize,
+    // directory the rotated, persistent error log is written to
+    pub log_dir: PathBuf,
+    // log file is rotated once it grows past this size, in bytes
+    pub max_file_size: u64,
+    // amount of rotated log files to keep around, in addition to the active one
+    pub max_files: usize,
+}
+
+impl Default for Errors {
+    fn default() -> Self {
+        Self {
+            max_entries: 1000,
+            log_dir: PathBuf::from(format!("/var/log/{}/errors", APP_NAME)),
+            max_file_size: 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+//
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Telemetry {
+    // how often a position/temperature sample is taken, in milliseconds
+    pub sample_interval_millis: u64,
+    // how many samples are kept in memory for `/telemetry/history`, in
+    // addition to whatever `/telemetry/stream` subscribers are fed live
+    pub max_samples: usize,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self {
+            sample_interval_millis: 200,
+            max_samples: 300,
+        }
+    }
+}
+
+//
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_topic_prefix() -> String {
+    format!("{}/printer", APP_NAME)
+}
+
+fn default_mqtt_publish_interval_millis() -> u64 {
+    1000
+}
+
+/// Publishes live printer telemetry to an MQTT broker as JSON, on top of the
+/// existing [`Telemetry`] ring buffer/SSE stream, for e.g. a remote
+/// dashboard or triggering external automations on temperature/fault events
+///
+/// Absent entirely (`None` in [`Config`]) means the feature is disabled; the
+/// daemon never connects to a broker
+#[derive(Debug, Clone, Deserialize)]
+pub struct Mqtt {
+    // hostname or IP of the broker to connect to
+    pub broker_address: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    // telemetry is published as JSON under "<topic_prefix>/temperature" and
+    // "<topic_prefix>/status"
+    #[serde(default = "default_mqtt_topic_prefix")]
+    pub topic_prefix: String,
+    // how often a fresh snapshot is published, in milliseconds
+    #[serde(default = "default_mqtt_publish_interval_millis")]
+    pub publish_interval_millis: u64,
+}
+
+//
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Gcode {
+    // directory uploaded gcode files are spooled to before being printed;
+    // created on upload if it doesn't exist yet
+    pub upload_dir: PathBuf,
+    // how often `/gcode/stream` pushes a status snapshot, in milliseconds
+    pub stream_interval_millis: u64,
+}
+
+impl Default for Gcode {
+    fn default() -> Self {
+        Self {
+            upload_dir: PathBuf::from(format!("/var/lib/{}/uploads", APP_NAME)),
+            stream_interval_millis: 500,
+        }
+    }
+}
+
+//
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Decode {
+    // maximum deviation of a G2/G3 arc's chord from the true arc, in mm;
+    // controls how many linear segments an arc gets split into
+    pub arc_chord_tolerance: f64,
+    // maximum deviation of the planner's look-ahead junction speed from the
+    // ideal cornering speed, in mm; higher allows faster but less precise
+    // cornering between consecutive moves
+    pub junction_deviation: f64,
+}
+
+impl Default for Decode {
+    fn default() -> Self {
+        Self {
+            arc_chord_tolerance: 0.05,
+            junction_deviation: 0.013,
+        }
+    }
+}
+
+//
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Retraction {
+    // length the filament is pulled back by on G10, in mm
+    pub length: f64,
+    // feedrate of the G10 retract move, in mm/min
+    pub feedrate: f64,
+    // additional length un-retracted on G11 on top of `length`, in mm
+    pub extra_recover_length: f64,
+    // feedrate of the G11 recover move, in mm/min
+    pub recover_feedrate: f64,
+    // how far to raise the z axis while retracted, in mm; None disables
+    // z-hop entirely
+    pub z_hop: Option<f64>,
+}
+
+impl Default for Retraction {
+    fn default() -> Self {
+        Self {
+            length: 1.0,
+            feedrate: 2100.0,
+            extra_recover_length: 0.0,
+            recover_feedrate: 1500.0,
+            z_hop: None,
+        }
+    }
+}
+
+//
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BedMesh {
+    // corners of the work area the mesh was probed over, in mm
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+    // row-major grid of per-node Z offsets, in mm, evenly spaced between the
+    // corners above; every row must be the same length
+    pub heights: Vec<Vec<f64>>,
+}
+
+impl Default for BedMesh {
+    fn default() -> Self {
+        Self {
+            min_x: 0.0,
+            min_y: 0.0,
+            max_x: 0.0,
+            max_y: 0.0,
+            heights: vec![vec![0.0, 0.0], vec![0.0, 0.0]],
+        }
+    }
+}
+
+//
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ToolOffset {
+    // nozzle offset from tool 0's, in mm
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Tooling {
+    // per-tool nozzle offset table, indexed by tool number (index 0 is
+    // T0's offset, which is normally left zeroed); a tool beyond the
+    // configured entries defaults to no offset
+    pub offsets: Vec<ToolOffset>,
+}
+
+//
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BuildVolume {
+    // printable bed size in mm, measured from (min_x, min_y)
+    pub width: f64,
+    pub depth: f64,
+    pub min_x: f64,
+    pub min_y: f64,
+    // max printable height in mm; None leaves z unbounded by this check
+    // (the toolhead's physical travel limit still applies regardless)
+    pub max_z: Option<f64>,
+}
+
+impl Default for BuildVolume {
+    fn default() -> Self {
+        Self {
+            width: 220.0,
+            depth: 220.0,
+            min_x: 0.0,
+            min_y: 0.0,
+            max_z: None,
+        }
+    }
+}
+
+//
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Park {
+    // filament retract length/feedrate before parking, in mm / mm/min
+    pub retract_length: f64,
+    pub retract_feedrate: f64,
+    // how far to lift z before parking, in mm; clamped so it never drives
+    // the head past its home position
+    pub lift: f64,
+    pub lift_feedrate: f64,
+    // XY position to park at, in mm
+    pub x: f64,
+    pub y: f64,
+    pub travel_feedrate: f64,
+    // extra length un-retracted on unpark on top of retract_length, in mm,
+    // same idea as `Retraction::extra_recover_length`
+    pub extra_recover_length: f64,
+    pub recover_feedrate: f64,
+}
+
+impl Default for Park {
+    fn default() -> Self {
+        Self {
+            retract_length: 2.0,
+            retract_feedrate: 2100.0,
+            lift: 10.0,
+            lift_feedrate: 600.0,
+            x: 0.0,
+            y: 0.0,
+            travel_feedrate: 6000.0,
+            extra_recover_length: 0.0,
+            recover_feedrate: 1500.0,
+        }
+    }
+}
+
+//
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Macros {
+    // gcode run once, when the daemon starts up; a path to a gcode file, or
+    // the gcode itself given inline
+    pub startup: Option<String>,
+    // gcode run at the start of every print, before the first line of the
+    // print file itself; unlike `startup`, this runs once per print rather
+    // than once per daemon lifetime. Same path-or-inline format as `startup`
+    pub print_start: Option<String>,
+    // gcode run whenever the machine settles back into idle after a stop,
+    // unless `cancel` ran instead because a print was actually in progress;
+    // same path-or-inline format as `startup`
+    pub idle: Option<String>,
+    // gcode run whenever `stop` aborts a print/pause that was in progress,
+    // instead of `idle`; same path-or-inline format as `startup`
+    pub cancel: Option<String>,
+    // once the printer has sat stopped for this many seconds with nothing
+    // happening, `idle` is run again as a safety measure (e.g. disable
+    // steppers, cut heaters down to a safe temp), on top of the run it
+    // already got the instant the print stopped/finished; None disables
+    // this and only ever runs `idle` on the stop/finish itself
+    #[serde(default)]
+    pub idle_timeout: Option<u64>,
 }
 
 //
@@ -172,9 +685,39 @@ pub struct Config {
     pub log: Log,
     #[serde(default)]
     pub api: Api,
+    #[serde(default)]
+    pub socket: Socket,
+    #[serde(default)]
+    pub errors: Errors,
     pub motors: Motors,
     #[serde(default)]
     pub pi: Pi,
+    #[serde(default)]
+    pub gpio: Gpio,
+    #[serde(default)]
+    pub execute: Execute,
+    #[serde(default)]
+    pub checkpoint: Checkpoint,
+    #[serde(default)]
+    pub gcode: Gcode,
+    #[serde(default)]
+    pub decode: Decode,
+    #[serde(default)]
+    pub retraction: Retraction,
+    #[serde(default)]
+    pub bed_mesh: BedMesh,
+    #[serde(default)]
+    pub tooling: Tooling,
+    #[serde(default)]
+    pub build_volume: BuildVolume,
+    #[serde(default)]
+    pub park: Park,
+    #[serde(default)]
+    pub macros: Macros,
+    #[serde(default)]
+    pub telemetry: Telemetry,
+    #[serde(default)]
+    pub mqtt: Option<Mqtt>,
     pub hotend: Hotend,
     pub bed: Bed,
 }
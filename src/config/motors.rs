@@ -1,3 +1,4 @@
+use super::driver::{NanotecDriver, StepperDriver, StepperDriverKind};
 use crate::comms::Axis;
 use nanotec_stepper_driver::{RotationDirection, StepMode};
 use num_traits::FromPrimitive;
@@ -119,13 +120,18 @@ impl<'de> Visitor<'de> for U32LimitVisitor {
     }
 }
 
+// the deserializers below all query `NanotecDriver` directly for their
+// bounds rather than the `driver` a config names; see the module doc on
+// `super::driver` for why
+
 fn deserialize_quickstop_ramp<'de, D>(deserializer: D) -> Result<u32, D::Error>
 where
     D: Deserializer<'de>,
 {
+    let range = NanotecDriver.quickstop_ramp_range();
     deserializer.deserialize_u32(U32LimitVisitor {
-        lower: 0,
-        higher: 3_000_000,
+        lower: *range.start(),
+        higher: *range.end(),
     })
 }
 
@@ -133,9 +139,10 @@ fn deserialize_limit<'de, D>(deserializer: D) -> Result<u32, D::Error>
 where
     D: Deserializer<'de>,
 {
+    let range = NanotecDriver.axis_limit_range();
     deserializer.deserialize_u32(U32LimitVisitor {
-        lower: 1,
-        higher: 10_000,
+        lower: *range.start(),
+        higher: *range.end(),
     })
 }
 
@@ -143,9 +150,10 @@ fn deserialize_speed<'de, D>(deserializer: D) -> Result<u32, D::Error>
 where
     D: Deserializer<'de>,
 {
+    let range = NanotecDriver.speed_range();
     deserializer.deserialize_u32(U32LimitVisitor {
-        lower: 1,
-        higher: 1_000_000,
+        lower: *range.start(),
+        higher: *range.end(),
     })
 }
 
@@ -153,9 +161,10 @@ fn deserialize_accel_decel<'de, D>(deserializer: D) -> Result<u32, D::Error>
 where
     D: Deserializer<'de>,
 {
+    let range = NanotecDriver.accel_decel_range();
     deserializer.deserialize_u32(U32LimitVisitor {
-        lower: 1,
-        higher: 3_000_000,
+        lower: *range.start(),
+        higher: *range.end(),
     })
 }
 
@@ -163,13 +172,16 @@ fn deserialize_jerk<'de, D>(deserializer: D) -> Result<u32, D::Error>
 where
     D: Deserializer<'de>,
 {
+    let range = NanotecDriver.jerk_range();
     deserializer.deserialize_u32(U32LimitVisitor {
-        lower: 1,
-        higher: 100_000_000,
+        lower: *range.start(),
+        higher: *range.end(),
     })
 }
 
-struct BaudRateVisitor();
+struct BaudRateVisitor {
+    allowed: &'static [u32],
+}
 
 impl<'de> Visitor<'de> for BaudRateVisitor {
     type Value = u32;
@@ -184,26 +196,14 @@ impl<'de> Visitor<'de> for BaudRateVisitor {
     where
         E: serde::de::Error,
     {
-        let v = match v {
-            110 => v,
-            300 => v,
-            600 => v,
-            1200 => v,
-            4800 => v,
-            9600 => v,
-            14400 => v,
-            19200 => v,
-            38400 => v,
-            57600 => v,
-            115200 => v,
-            _ => {
-                return Err(serde::de::Error::invalid_value(
-                    Unexpected::Signed(v),
-                    &self,
-                ))
-            }
-        };
-        Ok(v as u32)
+        if u32::try_from(v).is_ok_and(|v| self.allowed.contains(&v)) {
+            Ok(v as u32)
+        } else {
+            Err(serde::de::Error::invalid_value(
+                Unexpected::Signed(v),
+                &self,
+            ))
+        }
     }
 }
 
@@ -211,35 +211,87 @@ fn deserialize_baudrate<'de, D>(deserializer: D) -> Result<u32, D::Error>
 where
     D: Deserializer<'de>,
 {
-    deserializer.deserialize_u32(BaudRateVisitor())
+    deserializer.deserialize_u32(BaudRateVisitor {
+        allowed: NanotecDriver.baud_rates(),
+    })
+}
+
+struct UnitIntervalVisitor;
+
+impl<'de> Visitor<'de> for UnitIntervalVisitor {
+    type Value = f64;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "a float x with 0.0 <= x <= 1.0")
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        if (0.0..=1.0).contains(&v) {
+            Ok(v)
+        } else {
+            Err(serde::de::Error::invalid_value(
+                Unexpected::Float(v),
+                &self,
+            ))
+        }
+    }
+}
+
+fn deserialize_unit_interval<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_f64(UnitIntervalVisitor)
 }
 
 //
 
 fn default_speed_limit() -> u32 {
-    1_000_000
+    *NanotecDriver.speed_range().end()
 }
 
 fn default_accel_limit() -> u32 {
-    3_000_000
+    *NanotecDriver.accel_decel_range().end()
 }
 
 fn default_decel_limit() -> u32 {
-    3_000_000
+    *NanotecDriver.accel_decel_range().end()
 }
 
 fn default_accel_jerk_limit() -> u32 {
-    100_000_000
+    *NanotecDriver.jerk_range().end()
 }
 
 fn default_decel_jerk_limit() -> u32 {
-    100_000_000
+    *NanotecDriver.jerk_range().end()
 }
 
 fn default_speed() -> u32 {
     1_000
 }
 
+// the re-approach pass of multi-probe homing needs to be noticeably gentler
+// than the initial fast approach, or the trigger point is no more repeatable
+// than a single-pass reference run
+fn default_reference_speed_slow() -> u32 {
+    default_speed() / 4
+}
+
+// mm retracted off the endstop between probes; needs to clear the switch's
+// hysteresis band, but 2mm is plenty for every axis this has shipped on
+fn default_home_backoff() -> f64 {
+    2.0
+}
+
+// one fast approach plus this many back-off/slow-re-approach passes; 1
+// already gets most of the repeatability win
+fn default_home_probes() -> u32 {
+    1
+}
+
 fn default_accel_decel() -> u32 {
     50_000
 }
@@ -248,10 +300,33 @@ fn default_jerk() -> u32 {
     100_000
 }
 
+// probing moves toward an unknown contact point rather than a limit switch,
+// so the defaults are much gentler than the reference ones: a stall reads as
+// a `PosError` well before it could bend anything
+fn default_probe_speed() -> u32 {
+    200
+}
+
+fn default_probe_accel() -> u32 {
+    5_000
+}
+
+fn default_probe_jerk() -> u32 {
+    10_000
+}
+
+fn default_probe_step() -> f64 {
+    0.1
+}
+
 fn default_baud_rate() -> u32 {
     115200
 }
 
+fn default_fault_rate() -> f64 {
+    0.0
+}
+
 //
 
 #[derive(Debug, Deserialize)]
@@ -312,6 +387,38 @@ pub struct AxisMotor {
     pub default_reference_accel: u32,
     #[serde(default = "default_jerk", deserialize_with = "deserialize_jerk")]
     pub default_reference_jerk: u32,
+    // speed of the slow re-approach pass(es) in multi-probe homing, see
+    // `NanotecMotors::reference_motor`
+    #[serde(
+        default = "default_reference_speed_slow",
+        deserialize_with = "deserialize_speed"
+    )]
+    pub reference_speed_slow: u32,
+    // mm retracted off the endstop between the fast approach and each slow
+    // re-approach
+    #[serde(default = "default_home_backoff")]
+    pub home_backoff: f64,
+    // number of back-off/slow-re-approach passes after the initial fast
+    // approach; 0 keeps the old single-pass behavior
+    #[serde(default = "default_home_probes")]
+    pub home_probes: u32,
+    // only meaningful for the z axis, see `Motors::probe_z_hotend`
+    #[serde(
+        default = "default_probe_speed",
+        deserialize_with = "deserialize_speed"
+    )]
+    pub default_probe_speed: u32,
+    #[serde(
+        default = "default_probe_accel",
+        deserialize_with = "deserialize_accel_decel"
+    )]
+    pub default_probe_accel: u32,
+    #[serde(default = "default_probe_jerk", deserialize_with = "deserialize_jerk")]
+    pub default_probe_jerk: u32,
+    // mm lowered per probing increment; the approximation error on the
+    // contact point found is bounded by this, see `Motors::probe_z_hotend`
+    #[serde(default = "default_probe_step")]
+    pub default_probe_step: f64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -360,11 +467,53 @@ pub struct ExtruderMotor {
     pub decel_jerk_limit: u32,
 }
 
+/// Which [`crate::hw::execute::motors::MotorBackend`] `Motors::new` should
+/// construct: the real `nanotec_stepper_driver`-backed one, or an in-memory
+/// simulation for running the rest of the printer (API, executor, estop
+/// thread) with no serial port attached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MotorBackendKind {
+    Nanotec,
+    Sim,
+}
+
+impl Default for MotorBackendKind {
+    fn default() -> Self {
+        Self::Nanotec
+    }
+}
+
 // can't implement default because port must not have a default since it could
 // in theory break stuff
 // same goes for motor addresses
 #[derive(Debug, Deserialize)]
 pub struct Motors {
+    // which StepperDriver backend this section's limits belong to; see the
+    // module doc on `super::driver` for why the deserializers above don't
+    // actually look at this yet
+    #[serde(default)]
+    pub driver: StepperDriverKind,
+    // which MotorBackend actually drives the motors at runtime; independent
+    // of `driver` above, which only picks the limits this section is
+    // validated against
+    #[serde(default)]
+    pub backend: MotorBackendKind,
+    // probability that a single simulated axis move reports a position
+    // error when `backend = "sim"`; see
+    // `crate::hw::execute::motors::sim::SimMotors`. Has no effect on the
+    // real backend.
+    #[serde(
+        default = "default_fault_rate",
+        deserialize_with = "deserialize_unit_interval"
+    )]
+    pub position_error_rate: f64,
+    // same as `position_error_rate`, but for simulated driver faults
+    #[serde(
+        default = "default_fault_rate",
+        deserialize_with = "deserialize_unit_interval"
+    )]
+    pub driver_error_rate: f64,
     pub port: String,
     #[serde(
         default = "default_baud_rate",
@@ -388,4 +537,9 @@ impl Motors {
             Axis::Z => &self.z,
         }
     }
+
+    /// The [`StepperDriver`] this config's `driver` field names
+    pub fn driver(&self) -> Box<dyn StepperDriver> {
+        self.driver.driver()
+    }
 }
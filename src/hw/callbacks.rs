@@ -43,8 +43,39 @@ pub trait ErrorCallback: Send {
     fn err<E: Error>(&self, err: E);
 }
 
+/// Why a print/pause ended, passed to [`StopCallback::stop`] so it can react
+/// differently (e.g. run a different gcode macro) to a deliberate abort than
+/// to the print simply running out of gcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// a `stop` request aborted a print/pause that was running, or the print
+    /// couldn't continue anyway because of an error
+    Cancelled,
+    /// the print ran out of gcode to execute on its own
+    Finished,
+}
+
 pub trait StopCallback: Send {
-    fn stop(&self);
+    fn stop(&self, reason: StopReason);
+
+    /// Called right before `stop(StopReason::Cancelled)` when the print is
+    /// ending because of a real error rather than a deliberate stop/pause,
+    /// so a callback that cares about the distinction (e.g.
+    /// [`super::execute::OutcomeCallback`]) doesn't have to infer it; the
+    /// matching `stop` call still follows as usual.
+    fn fail(&self, _err: &anyhow::Error) {}
+}
+
+/// How a print ultimately ended, as reported through
+/// [`super::execute::ExecutorCtrl::print_with_handle`]'s completion channel
+#[derive(Debug)]
+pub enum PrintOutcome {
+    /// the print ran out of gcode to execute on its own
+    Completed,
+    /// a `stop`/`pause` request aborted the print while it was running
+    Stopped,
+    /// the print aborted because of a real error, not a deliberate stop
+    Failed(anyhow::Error),
 }
 
 pub trait EStopCallback: Send {
@@ -0,0 +1,87 @@
+use anyhow::{Context, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+
+/// A snapshot of an in-progress print, persisted periodically so it can be
+/// resumed if the daemon dies mid-print
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub path: PathBuf,
+    pub line: usize,
+    pub hotend_target: Option<u16>,
+    pub bed_target: Option<u16>,
+}
+
+impl Checkpoint {
+    pub fn new(
+        path: PathBuf,
+        line: usize,
+        hotend_target: Option<u16>,
+        bed_target: Option<u16>,
+    ) -> Self {
+        Self {
+            path,
+            line,
+            hotend_target,
+            bed_target,
+        }
+    }
+
+    /// Loads a checkpoint from `checkpoint_path`, if one exists
+    ///
+    /// Tolerates a missing or empty file the same way [`Settings::new`] does
+    /// for the settings-file, since both just mean there's nothing to load.
+    ///
+    /// [`Settings::new`]: crate::settings::Settings
+    pub fn load(checkpoint_path: &Path) -> Result<Option<Self>> {
+        let contents = match fs::read_to_string(checkpoint_path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::from(e)).context("failed to read checkpoint file"),
+        };
+        if contents.trim().is_empty() {
+            warn!("checkpoint file is empty");
+            return Ok(None);
+        }
+        Ok(Some(
+            serde_json::from_str(&contents).context("failed to parse checkpoint file")?,
+        ))
+    }
+
+    /// Writes this checkpoint to `checkpoint_path`
+    ///
+    /// Writes to a temporary file first and atomically renames it into
+    /// place, so a crash mid-write can never leave a half-written,
+    /// unparseable checkpoint behind.
+    pub fn save(&self, checkpoint_path: &Path) -> Result<()> {
+        if let Some(parent) = checkpoint_path.parent() {
+            fs::create_dir_all(parent).context("failed to create checkpoint directory")?;
+        }
+        let tmp_path = checkpoint_path.with_extension("json.tmp");
+        let file = File::create(&tmp_path).context("failed to open temporary checkpoint file")?;
+        serde_json::to_writer(&file, self).context("failed to write checkpoint file")?;
+        file.sync_all()
+            .context("failed to sync temporary checkpoint file")?;
+        fs::rename(&tmp_path, checkpoint_path)
+            .context("failed to atomically replace checkpoint file")?;
+        Ok(())
+    }
+
+    /// Removes the checkpoint file, if any
+    ///
+    /// Called once a print finishes, is explicitly stopped, or is
+    /// successfully resumed, since a stale checkpoint would otherwise offer
+    /// to resume a print that no longer needs resuming.
+    pub fn clear(checkpoint_path: &Path) -> Result<()> {
+        match fs::remove_file(checkpoint_path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(Error::from(e)).context("failed to remove checkpoint file"),
+        }
+    }
+}
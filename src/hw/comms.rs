@@ -4,6 +4,16 @@ pub enum EStopComms {
     EStop,
 }
 
+/// Which control request triggered a cancellation of a blocking executor
+/// wait (`Action::Wait`/a temperature hold), so the waiter can react
+/// differently depending on why it was interrupted -- e.g. actually pause
+/// instead of aborting the print outright
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    Stop,
+    Pause,
+}
+
 #[derive(Debug, Clone)]
 pub struct OnewayPosRead {
     pub x: OnewayAtomicF64Read,
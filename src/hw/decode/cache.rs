@@ -0,0 +1,110 @@
+//! A sidecar cache of a gcode file's already-parsed [`GCode`]s, so a print
+//! that's been sliced once doesn't pay [`Parser`][super::parser::Parser]'s
+//! line-by-line cost again on every subsequent print
+//!
+//! The sidecar lives next to the gcode file itself (`foo.gcode.cache`),
+//! prefixed with a magic tag, a one-byte protocol version, and the source
+//! file's mtime at the time it was written; it's written to a temporary
+//! file and renamed into place, the same atomic-write convention the
+//! print checkpoint uses. The body is CBOR rather than JSON since it
+//! doesn't need to be hand-edited and new fields shouldn't break old
+//! readers.
+use super::parser::{CachedCode, GCode};
+use crate::log::target;
+use anyhow::{Context, Result};
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read as _, Write as _},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
+use tracing::{debug, warn};
+
+const CACHE_MAGIC: &[u8; 4] = b"3PGC";
+const CACHE_VERSION: u8 = 1;
+
+fn cache_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".cache");
+    PathBuf::from(name)
+}
+
+fn source_mtime_secs(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_secs())
+}
+
+/// Loads `path`'s cached gcode stream, if its sidecar exists, matches
+/// [`CACHE_VERSION`], and was written no earlier than `path`'s own mtime
+///
+/// Never errors: a missing, corrupt, mismatched-version or stale cache all
+/// just mean there's nothing usable here, same as a cold cache, so the
+/// caller falls back to re-parsing `path` itself.
+pub(super) fn load(path: &Arc<PathBuf>) -> Option<VecDeque<GCode>> {
+    let source_mtime = source_mtime_secs(path)?;
+    let file = File::open(cache_path(path)).ok()?;
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic).ok()?;
+    if &magic != CACHE_MAGIC {
+        return None;
+    }
+    let mut version = [0; 1];
+    reader.read_exact(&mut version).ok()?;
+    if version[0] != CACHE_VERSION {
+        return None;
+    }
+    let mut cached_mtime = [0; 8];
+    reader.read_exact(&mut cached_mtime).ok()?;
+    if u64::from_le_bytes(cached_mtime) < source_mtime {
+        debug!(target: target::INTERNAL, "gcode cache for {} is stale", path.display());
+        return None;
+    }
+
+    let cached: Vec<CachedCode> = ciborium::de::from_reader(reader).ok()?;
+    Some(
+        cached
+            .into_iter()
+            .filter_map(|code| GCode::from_cached(code, Arc::clone(path)))
+            .collect(),
+    )
+}
+
+/// Writes `codes` out as `path`'s cache sidecar, tagged with `path`'s
+/// current mtime so a later [`load`] can tell if `path` has changed since
+///
+/// Best-effort and silent: a print that's already finished parsing
+/// shouldn't fail just because its cache couldn't be written afterwards.
+pub(super) fn save(path: &Arc<PathBuf>, codes: &[GCode]) {
+    if let Err(e) = try_save(path, codes) {
+        warn!(target: target::INTERNAL, "failed to write gcode cache for {}: {e:#}", path.display());
+    }
+}
+
+fn try_save(path: &Path, codes: &[GCode]) -> Result<()> {
+    let source_mtime = source_mtime_secs(path).context("failed to stat source gcode file")?;
+    let tmp_path = cache_path(path).with_extension("cache.tmp");
+    let mut writer =
+        BufWriter::new(File::create(&tmp_path).context("failed to create temporary cache file")?);
+
+    writer
+        .write_all(CACHE_MAGIC)
+        .and_then(|_| writer.write_all(&[CACHE_VERSION]))
+        .and_then(|_| writer.write_all(&source_mtime.to_le_bytes()))
+        .context("failed to write cache header")?;
+
+    let cached: Vec<CachedCode> = codes.iter().map(GCode::to_cached).collect();
+    ciborium::ser::into_writer(&cached, &mut writer).context("failed to write cache body")?;
+    writer.flush().context("failed to flush cache file")?;
+    drop(writer);
+
+    fs::rename(&tmp_path, cache_path(path)).context("failed to atomically replace cache file")?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use super::{
+    error::GCodeError,
+    inner_decoder::Decoder as InnerDecoder,
+    parser::{GCode, IncrementalParser},
+    Action, DecoderError, State,
+};
+use crate::settings::Settings;
+use bytes::BytesMut;
+use gcode::Mnemonic;
+use std::{collections::VecDeque, path::PathBuf, sync::Arc};
+
+/// The same parse-then-decode pipeline [`super::StreamDecoder`] drives
+/// against a [`std::io::Read`], reshaped as a push-based
+/// `tokio_util::codec::Decoder`: [`Self::decode`] consumes complete gcode
+/// lines from `buf` until at least one `(Action, GCode)` pair is ready,
+/// returns one pair per call, and leaves the rest of `buf` (including any
+/// trailing partial line) for later calls
+///
+/// Wrap this directly in a `tokio_util::codec::Framed` to decode a print
+/// streamed in over a socket (a TCP upload, or an OctoPrint-style serial
+/// host link) without ever needing the whole job to exist as a local file;
+/// it shares the exact same [`InnerDecoder`] state machine `StreamDecoder`
+/// does, just fed lines from an external buffer instead of pulling its own.
+pub struct GCodeCodec {
+    incremental: IncrementalParser,
+    decoder: InnerDecoder,
+    ready: VecDeque<(Action, GCode)>,
+}
+
+impl GCodeCodec {
+    /// `path` only labels errors/spans, since the bytes this decodes don't
+    /// necessarily come from a local file at all
+    pub fn new(settings: Settings, state: State, path: PathBuf) -> Self {
+        Self {
+            incremental: IncrementalParser::new(Arc::new(path)),
+            decoder: InnerDecoder::with_state(settings, state),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Registers `handler` for `mnemonic`/`major_number`; see
+    /// [`InnerDecoder::register`]
+    pub fn register(
+        &mut self,
+        mnemonic: Mnemonic,
+        major_number: u32,
+        handler: impl FnMut(&mut State, &GCode) -> Result<Option<VecDeque<(Action, GCode)>>, GCodeError>
+            + Send
+            + 'static,
+    ) {
+        self.decoder.register(mnemonic, major_number, handler);
+    }
+
+    /// Returns the oldest pending action, consuming and decoding complete
+    /// gcode lines from `buf` one at a time (each into zero or more actions)
+    /// only until one is pending; returns `Ok(None)` once nothing is pending
+    /// and `buf` holds no complete line, same as a
+    /// `tokio_util::codec::Decoder::decode` awaiting more bytes
+    pub fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<(Action, GCode)>, DecoderError> {
+        loop {
+            if let Some(action) = self.ready.pop_front() {
+                return Ok(Some(action));
+            }
+            let Some(pos) = buf.iter().position(|&b| b == b'\n') else {
+                return Ok(None);
+            };
+            let line = buf.split_to(pos + 1);
+            self.incremental.feed(&line)?;
+            self.decode_ready_lines()?;
+        }
+    }
+
+    /// Parses whatever's left in `buf` as a final, newline-less line, once
+    /// the underlying socket/stream has reached EOF; any actions it decodes
+    /// to are picked up by the next call to [`Self::decode`]
+    pub fn finish(&mut self, buf: &mut BytesMut) -> Result<(), DecoderError> {
+        if !buf.is_empty() {
+            let line = buf.split_to(buf.len());
+            self.incremental.feed(&line)?;
+        }
+        self.incremental.finish()?;
+        self.decode_ready_lines()
+    }
+
+    fn decode_ready_lines(&mut self) -> Result<(), DecoderError> {
+        while let Some(code) = self.incremental.pop_ready() {
+            if let Some(actions) = self.decoder.decode(code)? {
+                self.ready.extend(actions);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn state(self) -> State {
+        self.decoder.state()
+    }
+}
@@ -1,8 +1,24 @@
-use super::super::state::StateError;
+use crate::api::values::ErrorCode;
 use gcode::{GCode, Word};
-use std::io::Error as IoError;
+use serde_json::{json, Value};
 use thiserror::Error;
 
+/// Coarse classification of a [`GCodeError`], letting a host decide whether
+/// it's safe to skip the offending line and keep printing or whether the
+/// whole job has to be aborted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GCodeErrorKind {
+    /// a code is missing, duplicates or misuses an argument, or otherwise
+    /// can't be made sense of; skipping the line and continuing is usually
+    /// safe
+    ArgumentError,
+    /// the requested motion or target would violate a configured kinematic
+    /// or thermal limit; continuing risks damaging the printer
+    LimitViolation,
+    /// the mnemonic isn't implemented by this decoder
+    UnsupportedCode,
+}
+
 #[derive(Debug, Error)]
 pub enum GCodeError {
     #[error("at least one argument is missing from this code: {}", .0)]
@@ -17,14 +33,141 @@ pub enum GCodeError {
     PosOutOfBounds(GCode),
     #[error("code {} isn't inside the allowed temperature range, must be inside [{};{}]", .0, .1, .2)]
     TempOutOfBounds(GCode, u16, u16),
+    #[error("arc code {} is malformed: either both R and I/J were given, neither was, R can't reach the endpoint, or I/J doesn't put the endpoint on the circle", .0)]
+    InvalidArc(GCode),
+    #[error("code {} has an invalid workplace: P must be between 1 and {}, and L must be 2 or 20", .0, .1)]
+    InvalidWorkplace(GCode, usize),
+    #[error("code {} references restore point slot S{}, which must be between 0 and {} and must have been previously saved with G60", .0, .1, .2)]
+    InvalidRestorePoint(GCode, usize, usize),
+    #[error("code {} selects tool {}, which must be between 0 and {}", .0, .1, .2)]
+    InvalidTool(GCode, u32, usize),
+    #[error("code {} targets {} = {}, outside the configured build volume limit of {}", .0, .1, .2, .3)]
+    OutOfBounds(GCode, char, f64, f64),
+    #[error("code {} requests unpark, but the decoder was never parked", .0)]
+    NotParked(GCode),
 }
 
-#[derive(Debug, Error)]
-pub enum DecoderError {
-    #[error(transparent)]
-    StateError(#[from] StateError),
-    #[error(transparent)]
-    GCodeError(#[from] GCodeError),
-    #[error(transparent)]
-    IoError(#[from] IoError),
+impl GCodeError {
+    // constructors mirroring the variants above, so call sites don't have to
+    // spell out tuple-variant construction directly; prefer these over
+    // `GCodeError::Variant(...)` in new code
+
+    pub fn missing_arguments(code: GCode) -> Self {
+        Self::MissingArguments(code)
+    }
+
+    pub fn unknown_code(code: GCode) -> Self {
+        Self::UnknownCode(code)
+    }
+
+    pub fn unknown_argument(arg: Word, code: GCode) -> Self {
+        Self::UnknownArgument(arg, code)
+    }
+
+    pub fn duplicate_argument(arg: Word, code: GCode) -> Self {
+        Self::DuplicateArgument(arg, code)
+    }
+
+    pub fn pos_out_of_bounds(code: GCode) -> Self {
+        Self::PosOutOfBounds(code)
+    }
+
+    pub fn temp_out_of_bounds(code: GCode, lower: u16, upper: u16) -> Self {
+        Self::TempOutOfBounds(code, lower, upper)
+    }
+
+    pub fn invalid_arc(code: GCode) -> Self {
+        Self::InvalidArc(code)
+    }
+
+    pub fn invalid_workplace(code: GCode, max: usize) -> Self {
+        Self::InvalidWorkplace(code, max)
+    }
+
+    pub fn invalid_restore_point(code: GCode, slot: usize, max: usize) -> Self {
+        Self::InvalidRestorePoint(code, slot, max)
+    }
+
+    pub fn invalid_tool(code: GCode, tool: u32, max: usize) -> Self {
+        Self::InvalidTool(code, tool, max)
+    }
+
+    pub fn limit(code: GCode, axis: char, value: f64, bound: f64) -> Self {
+        Self::OutOfBounds(code, axis, value, bound)
+    }
+
+    pub fn not_parked(code: GCode) -> Self {
+        Self::NotParked(code)
+    }
+
+    /// The gcode that caused this error, e.g. to look up its source line via
+    /// [`GCode::span`]
+    pub fn gcode(&self) -> &GCode {
+        match self {
+            Self::MissingArguments(code)
+            | Self::UnknownCode(code)
+            | Self::UnknownArgument(_, code)
+            | Self::DuplicateArgument(_, code)
+            | Self::PosOutOfBounds(code)
+            | Self::TempOutOfBounds(code, _, _)
+            | Self::InvalidArc(code)
+            | Self::InvalidWorkplace(code, _)
+            | Self::InvalidRestorePoint(code, _, _)
+            | Self::InvalidTool(code, _, _)
+            | Self::OutOfBounds(code, _, _, _)
+            | Self::NotParked(code) => code,
+        }
+    }
+
+    /// Whether a host can skip this code and keep printing, or has to abort
+    /// the job; see [`GCodeErrorKind`]
+    pub fn kind(&self) -> GCodeErrorKind {
+        match self {
+            Self::MissingArguments(_)
+            | Self::UnknownArgument(_, _)
+            | Self::DuplicateArgument(_, _)
+            | Self::InvalidArc(_)
+            | Self::InvalidWorkplace(_, _)
+            | Self::InvalidRestorePoint(_, _, _)
+            | Self::InvalidTool(_, _, _)
+            | Self::NotParked(_) => GCodeErrorKind::ArgumentError,
+            Self::PosOutOfBounds(_)
+            | Self::TempOutOfBounds(_, _, _)
+            | Self::OutOfBounds(_, _, _, _) => GCodeErrorKind::LimitViolation,
+            Self::UnknownCode(_) => GCodeErrorKind::UnsupportedCode,
+        }
+    }
+
+    /// A stable, machine-readable code for this error, so API clients can
+    /// branch on the kind of failure instead of matching on `text`
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::MissingArguments(_) => ErrorCode::GCodeMissingArgs,
+            Self::UnknownCode(_) => ErrorCode::GCodeUnknown,
+            Self::UnknownArgument(_, _) => ErrorCode::GCodeUnknown,
+            Self::DuplicateArgument(_, _) => ErrorCode::GCodeUnknown,
+            Self::PosOutOfBounds(_) => ErrorCode::PosOutOfBounds,
+            Self::TempOutOfBounds(_, _, _) => ErrorCode::TempOutOfBounds,
+            Self::InvalidArc(_) => ErrorCode::GCodeUnknown,
+            Self::InvalidWorkplace(_, _) => ErrorCode::GCodeUnknown,
+            Self::InvalidRestorePoint(_, _, _) => ErrorCode::GCodeUnknown,
+            Self::InvalidTool(_, _, _) => ErrorCode::GCodeUnknown,
+            Self::OutOfBounds(_, _, _, _) => ErrorCode::PosOutOfBounds,
+            Self::NotParked(_) => ErrorCode::GCodeUnknown,
+        }
+    }
+
+    /// Structured, code-specific details, e.g. the violated bound
+    pub fn details(&self) -> Option<Value> {
+        match self {
+            Self::TempOutOfBounds(_, min, max) => Some(json!({ "min": min, "max": max })),
+            Self::InvalidWorkplace(_, max) => Some(json!({ "max": max })),
+            Self::InvalidRestorePoint(_, slot, max) => Some(json!({ "slot": slot, "max": max })),
+            Self::InvalidTool(_, tool, max) => Some(json!({ "tool": tool, "max": max })),
+            Self::OutOfBounds(_, axis, value, limit) => {
+                Some(json!({ "axis": axis.to_string(), "value": value, "limit": limit }))
+            }
+            _ => None,
+        }
+    }
 }
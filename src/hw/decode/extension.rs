@@ -0,0 +1,57 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+};
+
+/// Per-run, per-type scratch space for code handlers registered via
+/// [`Decoder::register`][super::Decoder::register], letting a plugin keep
+/// its own state (e.g. an M3/M5 spindle's current RPM) across commands
+/// without the core decoder knowing anything about it
+///
+/// Lives inside [`State`][super::State] alongside the rest of the run's
+/// data, so it gets cleared and handed back to the caller the same way
+/// `gcode` does, see [`State::reset`][super::State::reset].
+#[derive(Default)]
+pub struct ExtensionDataStore {
+    data: HashMap<TypeId, Box<dyn Any + Send>>,
+}
+
+impl ExtensionDataStore {
+    /// The stored `T`, if some handler has ever inserted one
+    pub fn get<T: Any + Send>(&self) -> Option<&T> {
+        self.data
+            .get(&TypeId::of::<T>())
+            .map(|v| v.downcast_ref::<T>().unwrap())
+    }
+
+    /// The stored `T`, if some handler has ever inserted one
+    pub fn get_mut<T: Any + Send>(&mut self) -> Option<&mut T> {
+        self.data
+            .get_mut(&TypeId::of::<T>())
+            .map(|v| v.downcast_mut::<T>().unwrap())
+    }
+
+    /// The stored `T`, inserting the result of `default` first if nothing's
+    /// been stored yet
+    pub fn get_or_insert_with<T: Any + Send>(&mut self, default: impl FnOnce() -> T) -> &mut T {
+        self.data
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(default()) as Box<dyn Any + Send>)
+            .downcast_mut::<T>()
+            .unwrap()
+    }
+
+    /// Drops every plugin's stored state
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+}
+
+impl fmt::Debug for ExtensionDataStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtensionDataStore")
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
@@ -0,0 +1,64 @@
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+// fractional bits kept below the decimal point; 32 gives ~2.3e-10mm of
+// resolution, far tighter than any printer's mechanical precision, while
+// leaving the upper 32 bits for whole millimeters well past any sane axis
+// length
+const FRAC_BITS: u32 = 32;
+const FRAC_SCALE: f64 = (1u64 << FRAC_BITS) as f64;
+
+/// A deterministic, bit-for-bit reproducible millimeter(-ish) quantity
+///
+/// Wraps a Q32.32 fixed-point integer instead of an `f64`, so accumulating
+/// thousands of relative moves onto the decoder's persistent position state
+/// can't drift the way repeated floating-point addition can, and the same
+/// gcode always reduces to the exact same step counts on any platform.
+/// Arithmetic that isn't part of that persistent tracking (arc trig,
+/// step-rate math, ...) is free to drop back to `f64` via
+/// [`to_f64`][Self::to_f64].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fixed(i64);
+
+impl Fixed {
+    pub const ZERO: Self = Self(0);
+
+    /// Converts a floating-point millimeter value to the nearest
+    /// representable fixed-point quantity
+    pub fn from_f64(val: f64) -> Self {
+        Self((val * FRAC_SCALE).round() as i64)
+    }
+
+    /// Converts back to a floating-point value, e.g. for trig or step-rate
+    /// math that doesn't need to stay fixed-point
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / FRAC_SCALE
+    }
+}
+
+impl Add for Fixed {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, rhs: Self) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.0 -= rhs.0;
+    }
+}
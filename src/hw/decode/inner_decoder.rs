@@ -1,6 +1,11 @@
-use super::{error::GCodeError, parser::GCode, Action, AxisMovement, ExtruderMovement, Movement};
+use super::{
+    error::GCodeError, extension::ExtensionDataStore, fixed::Fixed, mesh, parser::GCode,
+    planner::Planner, profiler::Profiler, Accumulator, Action, AxisMovement, ExtruderMovement,
+    Movement,
+};
 use crate::{
     comms::{Axis, ReferenceRunOptParameters},
+    config::{BedMesh, Motors},
     log::target,
     settings::Settings,
     util::{bail_own, ensure_own},
@@ -8,94 +13,309 @@ use crate::{
 use anyhow::Result;
 use gcode::Mnemonic;
 use nanotec_stepper_driver::StepMode;
-use std::{collections::VecDeque, time::Duration};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    time::{Duration, Instant},
+};
 use tracing::trace;
 
 type GCodeResult<T> = Result<T, GCodeError>;
 
-#[derive(Debug, PartialEq, Eq)]
+/// A handler for a gcode not built into the decoder, registered via
+/// [`Decoder::register`]
+///
+/// Takes the same `&mut State`/`&GCode` a built-in handler would work with;
+/// a plugin that wants to keep state of its own across commands stores it in
+/// the passed-in `State`'s [`ExtensionDataStore`][State::extensions], which
+/// is reset and handed back to the caller exactly like the rest of `State`.
+/// Returns `Ok(None)` for a code that doesn't produce any actions, same as a
+/// built-in handler would.
+pub type GCodeHandler =
+    Box<dyn FnMut(&mut State, &GCode) -> GCodeResult<Option<VecDeque<(Action, GCode)>>> + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum CoordMode {
     Absolute,
     Relative,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Unit {
     Millimeters,
     Inches,
 }
 
+/// The plane G2/G3 arcs are interpolated in, selected by G17 (the default,
+/// XY)/G18 (XZ)/G19 (YZ)
+///
+/// The axis not part of the plane moves linearly across the arc's segments,
+/// same as E, making a combined XY arc with a Z change a helix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArcPlane {
+    Xy,
+    Xz,
+    Yz,
+}
+
 impl Unit {
-    pub fn in_mm(&self, val: f64) -> f64 {
-        match self {
+    /// Converts a raw gcode argument value into mm, the unit every internal
+    /// `prog_*`/feedrate/offset field is kept in regardless of `unit`
+    ///
+    /// Every linear argument decoded anywhere in this file (move X/Y/Z/E,
+    /// F feedrate, arc I/J/R, G10/G92 offsets, ...) is meant to be funneled
+    /// through this single conversion point as it's parsed, so a file
+    /// toggling G20/G21 mid-stream can never leave stale inch-scaled values
+    /// baked into `State`.
+    pub fn in_mm(&self, val: f64) -> Fixed {
+        Fixed::from_f64(match self {
             Self::Millimeters => val,
             Self::Inches => val * 25.4,
-        }
+        })
     }
 }
 
 #[derive(Debug)]
 struct GCodeState {
-    feedrate: Option<f64>,
-    x: f64,
-    y: f64,
-    z: f64,
-    e: f64,
+    feedrate: Option<Fixed>,
+    x: Fixed,
+    y: Fixed,
+    z: Fixed,
+    e: Fixed,
     xyz_coord_mode: CoordMode,
     e_coord_mode: CoordMode,
     unit: Unit,
+    // plane G2/G3 arcs are interpolated in, selected by G17/G18/G19
+    arc_plane: ArcPlane,
     hotend_target_temp: Option<u16>,
     bed_target_temp: Option<u16>,
+    // whether a G10 is currently in effect; makes a second G10 a no-op and
+    // lets G11 know there's anything to undo
+    retracted: bool,
+    // z-hop applied by the current retraction, in mm, so G11 can drop
+    // exactly what G10 raised even if the config changes in between
+    z_hop: Fixed,
+    // whether bed-mesh compensation (see `mesh`) is applied in `move_by`;
+    // toggled by M420, turned on by a successful G29
+    mesh_enabled: bool,
+    // restore points saved by G60, restored by G61; cleared on `reset`
+    // along with the rest of this run's state, unlike the workplace offsets
+    saved_states: [Option<SavedState>; RESTORE_POINT_COUNT],
+    // machine position saved by `park`, restored and cleared by `unpark`;
+    // see `ParkedState`
+    parked: Option<ParkedState>,
+    // tool selected by the last Tn; reset to 0 (T0) along with the rest of
+    // this run's state, unlike the per-tool offset table in `config::Tooling`
+    active_tool: u8,
+    // per-tool accumulated E position, so switching tools doesn't bleed one
+    // tool's extrusion into another's; `e` above always mirrors
+    // `tool_e[active_tool]`
+    tool_e: [Fixed; TOOL_COUNT],
+    // part-cooling fan PWM set by M106/M107, 0-255
+    fan_speed: u8,
 }
 
 impl Default for GCodeState {
     fn default() -> Self {
         Self {
             feedrate: None,
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
-            e: 0.0,
+            x: Fixed::ZERO,
+            y: Fixed::ZERO,
+            z: Fixed::ZERO,
+            e: Fixed::ZERO,
             xyz_coord_mode: CoordMode::Absolute,
             e_coord_mode: CoordMode::Relative,
             unit: Unit::Millimeters,
+            arc_plane: ArcPlane::Xy,
             hotend_target_temp: None,
             bed_target_temp: None,
+            retracted: false,
+            z_hop: Fixed::ZERO,
+            mesh_enabled: false,
+            saved_states: [None; RESTORE_POINT_COUNT],
+            parked: None,
+            active_tool: 0,
+            tool_e: [Fixed::ZERO; TOOL_COUNT],
+            fan_speed: 0,
         }
     }
 }
 
+/// The machine-absolute position saved by [`park`][Decoder::park], restored
+/// by [`unpark`][Decoder::unpark]
+///
+/// Unlike [`SavedState`], doesn't carry feedrate/coordinate-mode/unit, since
+/// `park`/`unpark` never touch those.
+#[derive(Debug, Clone, Copy)]
+struct ParkedState {
+    x: Fixed,
+    y: Fixed,
+    z: Fixed,
+}
+
+// number of G60/G61 restore-point slots kept around
+const RESTORE_POINT_COUNT: usize = 6;
+
+/// A full decoder-state snapshot taken by `G60`, restored by `G61`
+///
+/// `x`/`y`/`z` are stored in machine-absolute mm, independent of whatever
+/// workplace offset was active at save time, so restoring under a different
+/// offset (or after a unit change) still returns the head to the exact same
+/// physical spot instead of the save-time offset's idea of it.
+#[derive(Debug, Clone, Copy)]
+struct SavedState {
+    x: Fixed,
+    y: Fixed,
+    z: Fixed,
+    e: Fixed,
+    feedrate: Option<Fixed>,
+    xyz_coord_mode: CoordMode,
+    e_coord_mode: CoordMode,
+    unit: Unit,
+    arc_plane: ArcPlane,
+}
+
 #[derive(Debug)]
 struct ActualState {
-    x: f64,
-    y: f64,
-    z: f64,
+    x: Fixed,
+    y: Fixed,
+    z: Fixed,
     steps_x: u32,
     steps_y: u32,
     // not u32, because z position operates in the negative since the
     // endstop is at the positive end of the z-axis
     steps_z: i32,
-    z_hotend_location: f64,
+    // each axis' carried-over fractional step from the last move, fed back
+    // into the next one's rounding via `mm_to_steps_carried` so the summed
+    // rounding error between the ideal mm position and the issued integer
+    // steps can't drift past half a step no matter how many moves accumulate
+    err_x: f64,
+    err_y: f64,
+    err_z: f64,
+    err_e: f64,
+    z_hotend_location: Fixed,
+    // the active workplace's offset as of the last move, so `move_by` can
+    // tell how much it changed since; see `Workplaces`
+    workplace_offset: WorkplaceOffset,
+    // the active tool's offset as of the last move, so `move_by` can tell
+    // how much it changed since; see `tool_offset`
+    tool_offset: ToolOffset,
 }
 
 impl ActualState {
     fn new(z_hotend_location: f64) -> Self {
         Self {
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
+            x: Fixed::ZERO,
+            y: Fixed::ZERO,
+            z: Fixed::ZERO,
             steps_x: 0,
             steps_y: 0,
             steps_z: 0,
-            z_hotend_location,
+            err_x: 0.0,
+            err_y: 0.0,
+            err_z: 0.0,
+            err_e: 0.0,
+            z_hotend_location: Fixed::from_f64(z_hotend_location),
+            workplace_offset: WorkplaceOffset::default(),
+            tool_offset: ToolOffset::default(),
         }
     }
 }
 
+// number of workplace coordinate systems supported, i.e. G54-G59; the full
+// nine-system range from the spec (G59.1-G59.3) would need minor-numbered
+// gcodes, which `decode` doesn't support yet
+const WORKPLACE_COUNT: usize = 6;
+
+/// A workplace coordinate system's origin offset from the machine origin, in
+/// mm, set by [`g10_workplace_offset`][Decoder::g10_workplace_offset]
+#[derive(Debug, Default, Clone, Copy)]
+struct WorkplaceOffset {
+    x: Fixed,
+    y: Fixed,
+    z: Fixed,
+}
+
+/// The `G54`-`G59` workplace coordinate systems, each an offset from the
+/// machine origin that the active one's is added to every programmed
+/// position
+///
+/// Persists across [`State::reset`], unlike the rest of [`State`]'s gcode
+/// state, since these offsets are meant to survive between runs the same way
+/// they would on a real machine's controller.
+#[derive(Debug)]
+struct Workplaces {
+    offsets: [WorkplaceOffset; WORKPLACE_COUNT],
+    // None selects the machine coordinate system directly, i.e. no offset
+    active: Option<usize>,
+}
+
+impl Default for Workplaces {
+    fn default() -> Self {
+        Self {
+            offsets: [WorkplaceOffset::default(); WORKPLACE_COUNT],
+            active: None,
+        }
+    }
+}
+
+impl Workplaces {
+    /// The offset of whichever workplace is currently active, or a zero
+    /// offset while the machine coordinate system is selected
+    fn active_offset(&self) -> WorkplaceOffset {
+        self.active
+            .map_or(WorkplaceOffset::default(), |i| self.offsets[i])
+    }
+}
+
+// number of tools (extruders) modeled by the decoder; the physical machine
+// only ever drives a single extruder motor, so this only affects how
+// multi-material/wipe-tower gcode decodes (offsets, per-tool E tracking),
+// not how many motors actually get commanded
+const TOOL_COUNT: usize = 8;
+
+/// A tool's nozzle offset from tool 0's, in mm
+///
+/// Read straight out of [`config::Tooling`][crate::config::Tooling] on every
+/// use via [`tool_offset`], the same way retraction/bed-mesh settings are,
+/// rather than copied into [`State`]; since it's config it already persists
+/// across [`State::reset`] without any extra bookkeeping.
+#[derive(Debug, Default, Clone, Copy)]
+struct ToolOffset {
+    x: Fixed,
+    y: Fixed,
+    z: Fixed,
+}
+
+/// Resolves tool `tool`'s configured offset, falling back to zero for any
+/// tool beyond what [`config::Tooling::offsets`][crate::config::Tooling]
+/// configures
+fn tool_offset(tooling: &crate::config::Tooling, tool: u8) -> ToolOffset {
+    tooling
+        .offsets
+        .get(tool as usize)
+        .map(|o| ToolOffset {
+            x: Fixed::from_f64(o.x),
+            y: Fixed::from_f64(o.y),
+            z: Fixed::from_f64(o.z),
+        })
+        .unwrap_or_default()
+}
+
 #[derive(Debug)]
 pub struct State {
     gcode: GCodeState,
     actual: ActualState,
+    workplaces: Workplaces,
+    // the live bed-mesh grid `move_by` compensates against; seeded from
+    // `config::BedMesh` and replaceable wholesale via `State::set_bed_mesh`,
+    // so a new mesh doesn't need to match the shape of whatever it's replacing
+    bed_mesh: BedMesh,
+    // scratch space for handlers registered via `Decoder::register`; reset
+    // alongside `gcode` since it's run-scoped, not machine-scoped like
+    // `workplaces`/`bed_mesh`
+    extensions: ExtensionDataStore,
 }
 
 impl State {
@@ -103,21 +323,41 @@ impl State {
     ///
     /// `z_hotend_location` is the location of the hotend relative to the z-axis
     /// endstop. This means that it *must* be negative
-    pub fn new(z_hotend_location: f64) -> Self {
+    pub fn new(z_hotend_location: f64, bed_mesh: BedMesh) -> Self {
         Self {
             gcode: GCodeState::default(),
             actual: ActualState::new(z_hotend_location),
+            workplaces: Workplaces::default(),
+            bed_mesh,
+            extensions: ExtensionDataStore::default(),
         }
     }
 
     /// Will reset values like the feedrate which should only persist in one
-    /// run
+    /// run, as well as every registered handler's [`ExtensionDataStore`]
+    ///
+    /// Doesn't touch the workplace offsets set up via `G10 L2`/`G10 L20`;
+    /// those persist across runs like on a real machine's controller.
     pub fn reset(&mut self) {
         self.gcode = GCodeState::default();
+        self.extensions.clear();
     }
 
     pub fn set_z_hotend_location(&mut self, z_hotend_location: f64) {
-        self.actual.z_hotend_location = z_hotend_location
+        self.actual.z_hotend_location = Fixed::from_f64(z_hotend_location)
+    }
+
+    /// Replaces the live bed-mesh grid `move_by` compensates against, e.g.
+    /// with a freshly-measured one from a (currently unimplemented, see
+    /// `g29`) probing routine
+    pub fn set_bed_mesh(&mut self, bed_mesh: BedMesh) {
+        self.bed_mesh = bed_mesh;
+    }
+
+    /// Scratch space for handlers registered via `Decoder::register`, so a
+    /// plugin can keep its own typed state across commands in a run
+    pub fn extensions(&mut self) -> &mut ExtensionDataStore {
+        &mut self.extensions
     }
 }
 
@@ -136,16 +376,16 @@ fn extract_temp_from_code(
 ) -> GCodeResult<(Option<u16>, GCode)> {
     ensure_own!(
         !code.arguments().is_empty(),
-        GCodeError::MissingArguments(code)
+        GCodeError::missing_arguments(code)
     );
     let mut temp = None;
     for arg in code.arguments() {
         match arg.letter {
             'S' => {
-                ensure_own!(temp.is_none(), GCodeError::DuplicateArgument(*arg, code));
+                ensure_own!(temp.is_none(), GCodeError::duplicate_argument(*arg, code));
                 temp = Some(arg.value as u16)
             }
-            _ => bail_own!(GCodeError::UnknownArgument(*arg, code)),
+            _ => bail_own!(GCodeError::unknown_argument(*arg, code)),
         };
     }
     let temp = temp.unwrap();
@@ -154,7 +394,7 @@ fn extract_temp_from_code(
     } else {
         ensure_own!(
             lower_limit <= temp && temp <= upper_limit,
-            GCodeError::TempOutOfBounds(code.clone(), lower_limit, upper_limit)
+            GCodeError::temp_out_of_bounds(code.clone(), lower_limit, upper_limit)
         );
         Ok((Some(temp), code))
     }
@@ -163,33 +403,316 @@ fn extract_temp_from_code(
 // (distance_in_mm / translation) * (360/1.8) * microsteps_per_step
 // conversion from StepMode to f64 can't happen directly so we have to
 // do it this way
-fn mm_to_steps(mm: f64, translation: &f64, step_size: &StepMode) -> f64 {
-    ((mm / translation) * (360.0 / 1.8) * (*step_size as u8) as f64).round()
+fn mm_to_steps_exact(mm: Fixed, translation: &f64, step_size: &StepMode) -> f64 {
+    (mm.to_f64() / translation) * (360.0 / 1.8) * (*step_size as u8) as f64
+}
+
+fn mm_to_steps(mm: Fixed, translation: &f64, step_size: &StepMode) -> f64 {
+    mm_to_steps_exact(mm, translation, step_size).round()
+}
+
+/// Same conversion as [`mm_to_steps`], but carries the previous call's
+/// rounding remainder in through `err` and back out again, so a long run of
+/// moves rounds to the true commanded mm position instead of drifting by the
+/// sum of every move's own independent rounding
+fn mm_to_steps_carried(mm: Fixed, translation: &f64, step_size: &StepMode, err: &mut f64) -> f64 {
+    let ideal = mm_to_steps_exact(mm, translation, step_size) + *err;
+    let issued = ideal.round();
+    *err = ideal - issued;
+    issued
+}
+
+/// Clamps an optional `M204`/`M205` override, given in mm/s^2 (or mm/s^3 for
+/// jerk), to the axis' configured hardware maximum and converts it to the
+/// matching steps/s^2 (or steps/s^3) unit, falling back to the config
+/// default entirely when there's no override active
+fn effective_limit(
+    mm_override: Option<f64>,
+    cfg_limit: u32,
+    translation: &f64,
+    step_size: &StepMode,
+) -> u32 {
+    match mm_override {
+        Some(v) => {
+            let steps_per_mm = mm_to_steps(Fixed::from_f64(1.0), translation, step_size);
+            ((v * steps_per_mm).round() as u32).min(cfg_limit)
+        }
+        None => cfg_limit,
+    }
+}
+
+// how far the endpoint of a G2/G3 arc may deviate from the circle described
+// by its I/J center before the code is considered malformed, in mm
+const ARC_RADIUS_EPSILON: f64 = 0.01;
+
+/// The signed angle swept going from `start` to `end` around `center` in the
+/// given direction (`clockwise` == `true` for G2, `false` for G3)
+///
+/// Normalized into `[-2*PI, 0)` for clockwise and `(0, 2*PI]` for
+/// counter-clockwise, so a `start` equal to `end` sweeps a full circle
+/// instead of not moving at all, matching the usual gcode convention for
+/// arcs that return to their own start point.
+fn arc_sweep(
+    start_x: f64,
+    start_y: f64,
+    end_x: f64,
+    end_y: f64,
+    center_x: f64,
+    center_y: f64,
+    clockwise: bool,
+) -> f64 {
+    let theta_start = (start_y - center_y).atan2(start_x - center_x);
+    let theta_end = (end_y - center_y).atan2(end_x - center_x);
+    let two_pi = std::f64::consts::PI * 2.0;
+    let mut sweep = theta_end - theta_start;
+    if clockwise {
+        while sweep >= 0.0 {
+            sweep -= two_pi;
+        }
+    } else {
+        while sweep <= 0.0 {
+            sweep += two_pi;
+        }
+    }
+    sweep
+}
+
+// runtime M204/M205 overrides, all None until the corresponding code is
+// seen; reset to that on every new job, same as `GCodeState`
+#[derive(Debug, Default)]
+struct AccelOverrides {
+    // M204 P/T/R, in mm/s^2; whichever applies to a move overrides both its
+    // acceleration and deceleration limit
+    print: Option<f64>,
+    travel: Option<f64>,
+    retract: Option<f64>,
+    // M205 X/Y/Z/E, in mm/s^3
+    jerk_x: Option<f64>,
+    jerk_y: Option<f64>,
+    jerk_z: Option<f64>,
+    jerk_e: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AxisLimits {
+    accel_limit: u32,
+    decel_limit: u32,
+    accel_jerk_limit: u32,
+    decel_jerk_limit: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct EffectiveLimits {
+    x: AxisLimits,
+    y: AxisLimits,
+    z: AxisLimits,
+    e: AxisLimits,
+}
+
+impl AccelOverrides {
+    /// Resolves the per-axis accel/decel/jerk limits that currently apply to
+    /// a move, given whichever of `M204`'s `P`/`T`/`R` applies to it
+    ///
+    /// Active overrides are clamped to the axis' configured hardware
+    /// maximum rather than erroring, so an over-ambitious `M204`/`M205`
+    /// can't push the machine past what it's rated for.
+    fn resolve(&self, motors: &Motors, accel_override: Option<f64>) -> EffectiveLimits {
+        // cfg_limits is (accel_limit, decel_limit, accel_jerk_limit,
+        // decel_jerk_limit); scale is (translation, step_size)
+        fn axis(
+            cfg_limits: (u32, u32, u32, u32),
+            accel_override: Option<f64>,
+            jerk_override: Option<f64>,
+            scale: (&f64, &StepMode),
+        ) -> AxisLimits {
+            let (accel_limit, decel_limit, accel_jerk_limit, decel_jerk_limit) = cfg_limits;
+            let (translation, step_size) = scale;
+            AxisLimits {
+                accel_limit: effective_limit(accel_override, accel_limit, translation, step_size),
+                decel_limit: effective_limit(accel_override, decel_limit, translation, step_size),
+                accel_jerk_limit: effective_limit(
+                    jerk_override,
+                    accel_jerk_limit,
+                    translation,
+                    step_size,
+                ),
+                decel_jerk_limit: effective_limit(
+                    jerk_override,
+                    decel_jerk_limit,
+                    translation,
+                    step_size,
+                ),
+            }
+        }
+        EffectiveLimits {
+            x: axis(
+                (
+                    motors.x.accel_limit,
+                    motors.x.decel_limit,
+                    motors.x.accel_jerk_limit,
+                    motors.x.decel_jerk_limit,
+                ),
+                accel_override,
+                self.jerk_x,
+                (&motors.x.translation, &motors.x.step_size),
+            ),
+            y: axis(
+                (
+                    motors.y.accel_limit,
+                    motors.y.decel_limit,
+                    motors.y.accel_jerk_limit,
+                    motors.y.decel_jerk_limit,
+                ),
+                accel_override,
+                self.jerk_y,
+                (&motors.y.translation, &motors.y.step_size),
+            ),
+            z: axis(
+                (
+                    motors.z.accel_limit,
+                    motors.z.decel_limit,
+                    motors.z.accel_jerk_limit,
+                    motors.z.decel_jerk_limit,
+                ),
+                accel_override,
+                self.jerk_z,
+                (&motors.z.translation, &motors.z.step_size),
+            ),
+            e: axis(
+                (
+                    motors.e.accel_limit,
+                    motors.e.decel_limit,
+                    motors.e.accel_jerk_limit,
+                    motors.e.decel_jerk_limit,
+                ),
+                accel_override,
+                self.jerk_e,
+                (&motors.e.translation, &motors.e.step_size),
+            ),
+        }
+    }
 }
 
 // FIXME maybe change to fixed point?
-#[derive(Debug)]
 pub struct Decoder {
     settings: Settings,
     state: State,
+    planner: Planner,
+    accel_overrides: AccelOverrides,
+    // handlers for codes the decoder doesn't implement itself, see `register`
+    registry: HashMap<(Mnemonic, u32), GCodeHandler>,
+    profiler: Profiler,
+}
+
+impl fmt::Debug for Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Decoder")
+            .field("settings", &self.settings)
+            .field("state", &self.state)
+            .field("planner", &self.planner)
+            .field("accel_overrides", &self.accel_overrides)
+            .field("registry", &self.registry.keys().collect::<Vec<_>>())
+            .field("profiler", &self.profiler)
+            .finish()
+    }
 }
 
 impl Decoder {
     pub fn new(settings: Settings, z_hotend_location: f64) -> Self {
+        let planner = Planner::new(settings.config().decode.junction_deviation);
+        let bed_mesh = settings.config().bed_mesh.clone();
         Self {
             settings,
-            state: State::new(z_hotend_location),
+            state: State::new(z_hotend_location, bed_mesh),
+            planner,
+            accel_overrides: AccelOverrides::default(),
+            registry: HashMap::new(),
+            profiler: Profiler::default(),
         }
     }
 
     pub fn with_state(settings: Settings, state: State) -> Self {
-        Self { settings, state }
+        let planner = Planner::new(settings.config().decode.junction_deviation);
+        Self {
+            settings,
+            state,
+            planner,
+            accel_overrides: AccelOverrides::default(),
+            registry: HashMap::new(),
+            profiler: Profiler::default(),
+        }
+    }
+
+    /// Registers `handler` for `mnemonic`/`major_number`, so a caller can
+    /// support a code the decoder doesn't implement itself (e.g. M3/M5
+    /// spindle control, a firmware-specific M-code) without forking the
+    /// crate
+    ///
+    /// Only takes effect for codes [`decode`][Self::decode] would otherwise
+    /// reject with [`GCodeError::unknown_code`]; it can't override a code
+    /// the decoder already implements. Registering again for the same key
+    /// replaces whatever handler was registered before.
+    pub fn register(
+        &mut self,
+        mnemonic: Mnemonic,
+        major_number: u32,
+        handler: impl FnMut(&mut State, &GCode) -> GCodeResult<Option<VecDeque<(Action, GCode)>>>
+            + Send
+            + 'static,
+    ) {
+        self.registry
+            .insert((mnemonic, major_number), Box::new(handler));
     }
 
-    fn g0_1(&mut self, code: GCode) -> GCodeResult<(Action, GCode)> {
+    /// Falls back to a registered handler (see [`Self::register`]) for a
+    /// code the built-in match in [`Self::decode`] doesn't recognize,
+    /// bailing with [`GCodeError::unknown_code`] if none was registered
+    fn dispatch_extension(
+        &mut self,
+        code: GCode,
+    ) -> GCodeResult<Option<VecDeque<(Action, GCode)>>> {
+        match self
+            .registry
+            .get_mut(&(code.mnemonic(), code.major_number()))
+        {
+            Some(handler) => handler(&mut self.state, &code),
+            None => bail_own!(GCodeError::unknown_code(code)),
+        }
+    }
+
+    /// Turns on the self-profiler (see [`Self::profile_report`]); normal
+    /// decoding pays no extra cost until this is called
+    pub fn enable_profiling(&mut self) {
+        self.profiler.enable();
+    }
+
+    /// Turns off the self-profiler; stats already collected are kept, see
+    /// [`Self::profile_report`]
+    pub fn disable_profiling(&mut self) {
+        self.profiler.disable();
+    }
+
+    /// Per-code call count/total/max timing collected since profiling was
+    /// last enabled, sorted by total time descending
+    pub fn profile_report(&self) -> Vec<(Mnemonic, u32, Accumulator)> {
+        self.profiler.report()
+    }
+
+    /// Same as [`Self::profile_report`], serialized as JSON
+    pub fn profile_report_json(&self) -> Value {
+        self.profiler.report_json()
+    }
+
+    /// Resolves and hands back any moves still buffered in the look-ahead
+    /// planner, e.g. once the gcode source has run dry
+    pub fn flush_planner(&mut self) -> VecDeque<(Action, GCode)> {
+        self.planner.flush()
+    }
+
+    fn g0_1(&mut self, code: GCode) -> GCodeResult<VecDeque<(Action, GCode)>> {
         ensure_own!(
             !code.arguments().is_empty(),
-            GCodeError::MissingArguments(code)
+            GCodeError::missing_arguments(code)
         );
         let state = &mut self.state;
         let mut x = None;
@@ -204,9 +727,9 @@ impl Decoder {
                 'Z' => &mut z,
                 'E' => &mut e,
                 'F' => &mut f,
-                _ => bail_own!(GCodeError::UnknownArgument(*arg, code)),
+                _ => bail_own!(GCodeError::unknown_argument(*arg, code)),
             };
-            ensure_own!(letter.is_none(), GCodeError::DuplicateArgument(*arg, code));
+            ensure_own!(letter.is_none(), GCodeError::duplicate_argument(*arg, code));
             *letter = Some(state.gcode.unit.in_mm(arg.value as f64));
         }
         let mut x = x.unwrap_or_default();
@@ -214,7 +737,7 @@ impl Decoder {
         let mut z = z.unwrap_or_default();
         let mut e = e.unwrap_or_default();
 
-        fn calc_rel(new_coord: &mut f64, prog_coord: &mut f64) {
+        fn calc_rel(new_coord: &mut Fixed, prog_coord: &mut Fixed) {
             let rel_coord = *new_coord - *prog_coord;
             *prog_coord = *new_coord;
             *new_coord = rel_coord;
@@ -227,8 +750,8 @@ impl Decoder {
             calc_rel(&mut z, &mut state.gcode.z);
         } else {
             state.gcode.x += x;
-            state.gcode.x += y;
-            state.gcode.x += z;
+            state.gcode.y += y;
+            state.gcode.z += z;
         }
         // make e relative so we can calculate with it
         if state.gcode.e_coord_mode == CoordMode::Absolute {
@@ -237,53 +760,260 @@ impl Decoder {
             state.gcode.e += e;
         }
 
+        // save the feedrate for the next instructions
+        // unfortunately this seems to be widely used in gcode
+        if let Some(f) = f {
+            state.gcode.feedrate = Some(f);
+        }
+        let f = state
+            .gcode
+            .feedrate
+            .ok_or(GCodeError::missing_arguments(code.clone()))?;
+
+        self.move_by(x, y, z, e, f, code)
+    }
+
+    /// Computes step/speed movement for an mm delta from the current actual
+    /// position, already resolved relative to the actual/gcode coordinate
+    /// systems, and feeds it through the look-ahead [`Planner`]
+    ///
+    /// Shared by [`g0_1`][Self::g0_1] and the per-waypoint expansion arcs get
+    /// split into by [`g2_3`][Self::g2_3]. `x`, `y`, `z` and `e` are deltas
+    /// in mm, not absolute positions, and `f` is the feedrate in mm/min.
+    ///
+    /// Since the planner buffers moves for look-ahead, the returned queue
+    /// may be empty (nothing resolved yet) or contain moves other than this
+    /// one (resolved ones that were buffered before it).
+    fn move_by(
+        &mut self,
+        mut x: Fixed,
+        mut y: Fixed,
+        mut z: Fixed,
+        e: Fixed,
+        f: Fixed,
+        code: GCode,
+    ) -> GCodeResult<VecDeque<(Action, GCode)>> {
+        let state = &mut self.state;
         let cfg = self.settings.config();
 
+        // workplace-offset catch-up: fold in however much the active
+        // workplace's offset changed since the last move, the same way
+        // bed-mesh compensation folds in its own offset delta below, rather
+        // than re-adding the full offset every time and double-counting
+        // what's already baked into `state.actual`
+        let workplace_offset = state.workplaces.active_offset();
+        x += workplace_offset.x - state.actual.workplace_offset.x;
+        y += workplace_offset.y - state.actual.workplace_offset.y;
+        z += workplace_offset.z - state.actual.workplace_offset.z;
+        state.actual.workplace_offset = workplace_offset;
+
+        // tool-offset catch-up: same idea, for however much the active
+        // tool's offset changed since the last move (either the active
+        // tool itself changed, or its configured offset was reloaded)
+        let tool_offset = tool_offset(&cfg.tooling, state.gcode.active_tool);
+        x += tool_offset.x - state.actual.tool_offset.x;
+        y += tool_offset.y - state.actual.tool_offset.y;
+        z += tool_offset.z - state.actual.tool_offset.z;
+        state.actual.tool_offset = tool_offset;
+
         let actual_x_new = state.actual.x + x;
         let actual_y_new = state.actual.y + y;
+
+        // captured before `state.actual.x`/`y` are overwritten below, so the
+        // mesh-crossing split afterwards can still interpolate along the
+        // move's original start->end line
+        let (start_x, start_y) = (state.actual.x.to_f64(), state.actual.y.to_f64());
+        let (end_x, end_y) = (actual_x_new.to_f64(), actual_y_new.to_f64());
+        let mesh_enabled = state.gcode.mesh_enabled;
+
+        // bed-mesh compensation: adjust the z delta by however much the
+        // mesh's interpolated offset changed between the move's start and
+        // end xy, rather than by the offset at the end xy outright, since
+        // `state.actual.z` already has every earlier move's correction
+        // baked in and re-adding the full offset every time would double
+        // count it. The raw (pre-mesh) delta is kept around too, so the
+        // piecewise split below can give each sub-segment its own share of
+        // it rather than just the combined total.
+        let raw_z = z;
+        let mesh_splits = if mesh_enabled {
+            let offset_new = mesh::z_offset(&state.bed_mesh, end_x, end_y);
+            let offset_old = mesh::z_offset(&state.bed_mesh, start_x, start_y);
+            z += Fixed::from_f64(offset_new - offset_old);
+            // a single move can span several mesh cells, so split it at
+            // every boundary it crosses; otherwise the Z correction would
+            // jump straight from the start height to the end one instead of
+            // following the surface piecewise-linearly in between
+            mesh::split_fractions(&state.bed_mesh, start_x, start_y, end_x, end_y)
+        } else {
+            Vec::new()
+        };
+
         let actual_z_new = state.actual.z + z;
         // check lower limit
-        ensure_own!(actual_x_new >= 0.0, GCodeError::PosOutOfBounds(code));
-        ensure_own!(actual_y_new >= 0.0, GCodeError::PosOutOfBounds(code));
+        ensure_own!(
+            actual_x_new >= Fixed::ZERO,
+            GCodeError::pos_out_of_bounds(code.clone())
+        );
+        ensure_own!(
+            actual_y_new >= Fixed::ZERO,
+            GCodeError::pos_out_of_bounds(code.clone())
+        );
         ensure_own!(
             actual_z_new >= state.actual.z_hotend_location,
-            GCodeError::PosOutOfBounds(code)
+            GCodeError::pos_out_of_bounds(code.clone())
         );
         // check upper limits
         ensure_own!(
-            actual_x_new <= cfg.motors.x.limit as f64,
-            GCodeError::PosOutOfBounds(code)
+            actual_x_new <= Fixed::from_f64(cfg.motors.x.limit as f64),
+            GCodeError::pos_out_of_bounds(code.clone())
+        );
+        ensure_own!(
+            actual_y_new <= Fixed::from_f64(cfg.motors.y.limit as f64),
+            GCodeError::pos_out_of_bounds(code.clone())
+        );
+        ensure_own!(
+            actual_z_new <= Fixed::ZERO,
+            GCodeError::pos_out_of_bounds(code.clone())
+        );
+        // configured build volume: a tighter, operator-set bound layered on
+        // top of the machine's own physical travel limits above, checked
+        // against the true machine target (after workplace/tool offsets and
+        // bed-mesh compensation are already folded in). an E-only move
+        // passes this trivially: its x/y/z delta is zero, so `actual_*_new`
+        // is whatever the last move already passed the check with; a
+        // relative-mode move is checked on its resolved target like any other.
+        let build_volume = &cfg.build_volume;
+        let max_x = build_volume.min_x + build_volume.width;
+        let max_y = build_volume.min_y + build_volume.depth;
+        ensure_own!(
+            actual_x_new.to_f64() >= build_volume.min_x,
+            GCodeError::limit(code.clone(), 'X', actual_x_new.to_f64(), build_volume.min_x)
+        );
+        ensure_own!(
+            actual_x_new.to_f64() <= max_x,
+            GCodeError::limit(code.clone(), 'X', actual_x_new.to_f64(), max_x)
+        );
+        ensure_own!(
+            actual_y_new.to_f64() >= build_volume.min_y,
+            GCodeError::limit(code.clone(), 'Y', actual_y_new.to_f64(), build_volume.min_y)
         );
         ensure_own!(
-            actual_y_new <= cfg.motors.y.limit as f64,
-            GCodeError::PosOutOfBounds(code)
+            actual_y_new.to_f64() <= max_y,
+            GCodeError::limit(code.clone(), 'Y', actual_y_new.to_f64(), max_y)
         );
-        ensure_own!(actual_z_new <= 0.0, GCodeError::PosOutOfBounds(code));
+        // z grows negative with print height (see `ActualState::steps_z`), so
+        // a positive max build height caps how negative `actual_z_new` may go
+        if let Some(max_z) = build_volume.max_z {
+            ensure_own!(
+                actual_z_new.to_f64() >= -max_z,
+                GCodeError::limit(code.clone(), 'Z', -actual_z_new.to_f64(), max_z)
+            );
+        }
         state.actual.x = actual_x_new;
         state.actual.y = actual_y_new;
         state.actual.z = actual_z_new;
 
-        // save the feedrate for the next instructions
-        // unfortunately this seems to be widely used in gcode
-        if let Some(f) = f {
-            state.gcode.feedrate = Some(f as f64);
+        // split the move at every mesh-cell boundary found above (empty
+        // when compensation is off or the move stays within a single cell),
+        // feeding each sub-segment through `move_segment` on its own so the
+        // emitted steps track the mesh surface between them instead of just
+        // its two endpoints
+        let mut actions = VecDeque::new();
+        let mut t0 = 0.0;
+        for t1 in mesh_splits.into_iter().chain(std::iter::once(1.0)) {
+            let frac = t1 - t0;
+            let seg_x = Fixed::from_f64(x.to_f64() * frac);
+            let seg_y = Fixed::from_f64(y.to_f64() * frac);
+            let seg_e = Fixed::from_f64(e.to_f64() * frac);
+            let seg_mesh_z = if mesh_enabled {
+                let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+                let z0 = mesh::z_offset(
+                    &self.state.bed_mesh,
+                    lerp(start_x, end_x, t0),
+                    lerp(start_y, end_y, t0),
+                );
+                let z1 = mesh::z_offset(
+                    &self.state.bed_mesh,
+                    lerp(start_x, end_x, t1),
+                    lerp(start_y, end_y, t1),
+                );
+                Fixed::from_f64(z1 - z0)
+            } else {
+                Fixed::ZERO
+            };
+            let seg_z = Fixed::from_f64(raw_z.to_f64() * frac) + seg_mesh_z;
+            actions.extend(self.move_segment(seg_x, seg_y, seg_z, seg_e, f.to_f64(), code.clone()));
+            t0 = t1;
         }
-        let f = state
-            .gcode
-            .feedrate
-            .ok_or(GCodeError::MissingArguments(code.clone()))?;
+        Ok(actions)
+    }
+
+    /// Computes step/speed movement for a single straight-line sub-segment
+    /// of a [`move_by`][Self::move_by] move, already split at any mesh-cell
+    /// boundary it crosses, and feeds it through the look-ahead [`Planner`]
+    ///
+    /// `x`, `y`, `z` and `e` are this segment's own share of the overall
+    /// move's mm delta, and `f` is the overall move's feedrate in mm/min
+    /// (shared by every sub-segment, since a mesh-crossing split doesn't
+    /// change how fast the move travels, just how its Z is distributed).
+    fn move_segment(
+        &mut self,
+        x: Fixed,
+        y: Fixed,
+        z: Fixed,
+        e: Fixed,
+        f: f64,
+        code: GCode,
+    ) -> VecDeque<(Action, GCode)> {
+        let state = &mut self.state;
+        let cfg = self.settings.config();
+
+        // direction/distance in mm, captured before x/y/z get turned into
+        // step counts below, for the planner's junction-deviation angle
+        let direction = [x.to_f64(), y.to_f64(), z.to_f64()];
 
-        // CALCULATION
+        // M204 P/T: whichever applies to this move's accel/decel limit,
+        // depending on whether it's extruding
+        let accel_override = if e != Fixed::ZERO {
+            self.accel_overrides.print
+        } else {
+            self.accel_overrides.travel
+        };
+        let limits = self.accel_overrides.resolve(&cfg.motors, accel_override);
 
         // distance in mm
-        let s = (x * x + y * y + z * z).sqrt();
+        let s = (direction[0] * direction[0]
+            + direction[1] * direction[1]
+            + direction[2] * direction[2])
+            .sqrt();
         // time in s
         let t = s / (f / 60.0);
-        // distance in steps
-        let x = mm_to_steps(x, &cfg.motors.x.translation, &cfg.motors.x.step_size);
-        let y = mm_to_steps(y, &cfg.motors.y.translation, &cfg.motors.y.step_size);
-        let z = mm_to_steps(z, &cfg.motors.z.translation, &cfg.motors.z.step_size);
-        let e = mm_to_steps(e, &cfg.motors.e.translation, &cfg.motors.e.step_size);
+        // distance in steps, each carrying the previous move's rounding
+        // remainder so cumulative position/extrusion can't drift
+        let x = mm_to_steps_carried(
+            x,
+            &cfg.motors.x.translation,
+            &cfg.motors.x.step_size,
+            &mut state.actual.err_x,
+        );
+        let y = mm_to_steps_carried(
+            y,
+            &cfg.motors.y.translation,
+            &cfg.motors.y.step_size,
+            &mut state.actual.err_y,
+        );
+        let z = mm_to_steps_carried(
+            z,
+            &cfg.motors.z.translation,
+            &cfg.motors.z.step_size,
+            &mut state.actual.err_z,
+        );
+        let e = mm_to_steps_carried(
+            e,
+            &cfg.motors.e.translation,
+            &cfg.motors.e.step_size,
+            &mut state.actual.err_e,
+        );
 
         // speed in steps/second
         // distance_in_steps / time
@@ -293,9 +1023,9 @@ impl Decoder {
         let mut v_e = (e / t).round();
 
         macro_rules! limit {
-            ($axis:ident.$limit_name:ident, $limit_axis:ident, $limit_1:ident, $limit_2:ident, $limit_3:ident) => {{
-                if $limit_axis > cfg.motors.$axis.$limit_name as f64 {
-                    let limit_new = cfg.motors.$axis.$limit_name as f64;
+            ($source:expr, $axis:ident.$limit_name:ident, $limit_axis:ident, $limit_1:ident, $limit_2:ident, $limit_3:ident) => {{
+                if $limit_axis > $source.$axis.$limit_name as f64 {
+                    let limit_new = $source.$axis.$limit_name as f64;
                     $limit_1 = (($limit_1 / $limit_axis) * limit_new).round();
                     $limit_2 = (($limit_2 / $limit_axis) * limit_new).round();
                     $limit_3 = (($limit_3 / $limit_axis) * limit_new).round();
@@ -306,34 +1036,38 @@ impl Decoder {
 
         // fix speed if it hits any of the limits
         // afterwards it shouldn't be hitting any limit
-        limit!(x.speed_limit, v_x, v_y, v_z, v_e);
-        limit!(y.speed_limit, v_y, v_x, v_z, v_e);
-        limit!(z.speed_limit, v_z, v_x, v_y, v_e);
-        limit!(e.speed_limit, v_e, v_x, v_y, v_z);
+        // speed isn't overridden by M204/M205, so this always reads the
+        // configured limits directly
+        limit!(cfg.motors, x.speed_limit, v_x, v_y, v_z, v_e);
+        limit!(cfg.motors, y.speed_limit, v_y, v_x, v_z, v_e);
+        limit!(cfg.motors, z.speed_limit, v_z, v_x, v_y, v_e);
+        limit!(cfg.motors, e.speed_limit, v_e, v_x, v_y, v_z);
 
         macro_rules! calc_by_choosing {
-            ($limit_name:ident, $last_x:ident, $last_y:ident, $last_z:ident, $last_e:ident) => {{
-                let mut x = cfg.motors.x.$limit_name as f64;
+            ($source:expr, $limit_name:ident, $last_x:ident, $last_y:ident, $last_z:ident, $last_e:ident) => {{
+                let mut x = $source.x.$limit_name as f64;
                 let t = $last_x / x;
                 let mut y = ($last_y / t).round();
                 let mut z = ($last_z / t).round();
                 let mut e = ($last_e / t).round();
 
-                limit!(y.$limit_name, y, x, z, e);
-                limit!(z.$limit_name, z, x, y, e);
-                limit!(e.$limit_name, e, x, y, z);
+                limit!($source, y.$limit_name, y, x, z, e);
+                limit!($source, z.$limit_name, z, x, y, e);
+                limit!($source, e.$limit_name, e, x, y, z);
                 (x, y, z, e)
             }};
         }
 
-        // accel in steps/s^2
-        let (a0_x, a0_y, a0_z, a0_e) = calc_by_choosing!(accel_limit, v_x, v_y, v_z, v_e);
-        // accel jerk in steps/s^3
-        let (j0_x, j0_y, j0_z, j0_e) = calc_by_choosing!(accel_jerk_limit, a0_x, a0_y, a0_z, a0_e);
-        // decel in steps/s^2
-        let (a1_x, a1_y, a1_z, a1_e) = calc_by_choosing!(decel_limit, v_x, v_y, v_z, v_e);
-        // decel jerk in steps/s^3
-        let (j1_x, j1_y, j1_z, j1_e) = calc_by_choosing!(decel_jerk_limit, a1_x, a1_y, a1_z, a1_e);
+        // accel in steps/s^2, M204 P/T-aware
+        let (a0_x, a0_y, a0_z, a0_e) = calc_by_choosing!(limits, accel_limit, v_x, v_y, v_z, v_e);
+        // accel jerk in steps/s^3, M205 X/Y/Z/E-aware
+        let (j0_x, j0_y, j0_z, j0_e) =
+            calc_by_choosing!(limits, accel_jerk_limit, a0_x, a0_y, a0_z, a0_e);
+        // decel in steps/s^2, M204 P/T-aware
+        let (a1_x, a1_y, a1_z, a1_e) = calc_by_choosing!(limits, decel_limit, v_x, v_y, v_z, v_e);
+        // decel jerk in steps/s^3, M205 X/Y/Z/E-aware
+        let (j1_x, j1_y, j1_z, j1_e) =
+            calc_by_choosing!(limits, decel_jerk_limit, a1_x, a1_y, a1_z, a1_e);
 
         state.actual.steps_x += x as u32;
         state.actual.steps_y += y as u32;
@@ -386,142 +1120,776 @@ impl Decoder {
 
         // TODO check code output of macros
 
-        Ok((Action::MoveAll(movement), code))
-    }
+        // steps-per-mm of each axis, used to bring the per-axis accel this
+        // move ended up with back into mm/s^2 for the planner, since it
+        // doesn't deal in any single axis' steps
+        let steps_per_mm_x = mm_to_steps(
+            Fixed::from_f64(1.0),
+            &cfg.motors.x.translation,
+            &cfg.motors.x.step_size,
+        );
+        let steps_per_mm_y = mm_to_steps(
+            Fixed::from_f64(1.0),
+            &cfg.motors.y.translation,
+            &cfg.motors.y.step_size,
+        );
+        let steps_per_mm_z = mm_to_steps(
+            Fixed::from_f64(1.0),
+            &cfg.motors.z.translation,
+            &cfg.motors.z.step_size,
+        );
+        let acceleration = [
+            a0_x / steps_per_mm_x,
+            a0_y / steps_per_mm_y,
+            a0_z / steps_per_mm_z,
+        ];
 
-    /// Executes G0 command (does the same as [`g1`][Self::g1])
-    ///
-    /// Supported arguments: `X`, `Y`, `Z`, `E` and `F`
-    ///
-    /// # Errors
-    /// At least one argument must be present, otherwise [`GCodeError::MissingArguments`]
-    /// will be returned. Same if `F` is not present and has not been present
-    /// before.
-    fn g0(&mut self, code: GCode) -> GCodeResult<(Action, GCode)> {
-        assert_code!(code, General, 0, 0);
-        self.g0_1(code)
+        self.planner
+            .push(movement, code, direction, s, f / 60.0, acceleration)
     }
 
-    /// Executes G1 command (does the same as [`g0`][Self::g0])
+    /// Executes G2/G3 arc moves by expanding them into a sequence of linear
+    /// waypoints reusing [`move_by`][Self::move_by], since the motors can
+    /// only execute straight segments
     ///
-    /// Supported arguments: `X`, `Y`, `Z`, `E` and `F`
-    ///
-    /// # Errors
-    /// At least one argument must be present, otherwise [`GCodeError::MissingArguments`]
-    /// will be returned. Same if `F` is not present and has not been present
-    /// before.
-    fn g1(&mut self, code: GCode) -> GCodeResult<(Action, GCode)> {
-        assert_code!(code, General, 1, 0);
-        self.g0_1(code)
-    }
-
-    /// Executes G4 command
+    /// Supports the `I`/`J`/`K` center-offset form as well as the `R` radius
+    /// form; which two of `I`/`J`/`K` apply depends on the plane selected by
+    /// the last G17/G18/G19 (`I`/`J` for the default XY plane, `I`/`K` for
+    /// XZ, `J`/`K` for YZ). `clockwise` selects G2 (`true`) or G3 (`false`).
+    /// The axis outside the selected plane moves linearly across the arc's
+    /// segments, same as `E`, so a combined in-plane arc with an out-of-plane
+    /// change is a helix.
     ///
-    /// Supported arguments: `P` and `S`
+    /// Supported arguments: `X`, `Y`, `Z`, `E`, `F`, `I`, `J`, `K` and `R`
     ///
     /// # Errors
     /// At least one argument must be present, otherwise [`GCodeError::MissingArguments`]
-    /// will be returned.
-    fn g4(&mut self, code: GCode) -> GCodeResult<(Action, GCode)> {
-        assert_code!(code, General, 4, 0);
+    /// will be returned. Same if `F` is not present and has not been present
+    /// before. [`GCodeError::InvalidArc`] is returned if both `R` and one of
+    /// the plane's center-offset arguments are given, if neither is given,
+    /// if `R` can't reach the endpoint, or if the endpoint isn't actually on
+    /// the circle described by the center-offset arguments.
+    fn g2_3(&mut self, code: GCode, clockwise: bool) -> GCodeResult<VecDeque<(Action, GCode)>> {
+        assert_eq!(code.mnemonic(), Mnemonic::General);
+        assert_eq!(code.major_number(), if clockwise { 2 } else { 3 });
+        assert_eq!(code.minor_number(), 0);
         ensure_own!(
             !code.arguments().is_empty(),
-            GCodeError::MissingArguments(code)
+            GCodeError::missing_arguments(code)
         );
-        let mut millis = None;
-        let mut secs = None;
+        let state = &mut self.state;
+        let mut x = None;
+        let mut y = None;
+        let mut z = None;
+        let mut e = None;
+        let mut f = None;
+        let mut i = None;
+        let mut j = None;
+        let mut k = None;
+        let mut r = None;
         for arg in code.arguments().iter() {
-            match arg.letter {
-                'P' => {
-                    ensure_own!(millis.is_none(), GCodeError::DuplicateArgument(*arg, code));
-                    millis = Some(Duration::from_millis(arg.value as u64));
-                }
-                'S' => {
-                    ensure_own!(secs.is_none(), GCodeError::DuplicateArgument(*arg, code));
-                    secs = Some(Duration::from_secs(arg.value as u64));
-                }
-                _ => bail_own!(GCodeError::UnknownArgument(*arg, code)),
-            }
+            let letter = match arg.letter {
+                'X' => &mut x,
+                'Y' => &mut y,
+                'Z' => &mut z,
+                'E' => &mut e,
+                'F' => &mut f,
+                'I' => &mut i,
+                'J' => &mut j,
+                'K' => &mut k,
+                'R' => &mut r,
+                _ => bail_own!(GCodeError::unknown_argument(*arg, code)),
+            };
+            ensure_own!(letter.is_none(), GCodeError::duplicate_argument(*arg, code));
+            *letter = Some(state.gcode.unit.in_mm(arg.value as f64));
         }
-        let combined = millis.unwrap_or_default() + secs.unwrap_or_default();
-        Ok((Action::Wait(combined), code))
-    }
-
-    /// Executes G20 command
-    ///
-    /// Supported arguments: None
-    ///
-    /// Warning: Since this software is sane, it uses mm internally, so it would
-    /// be wise to just use mm in general
-    fn g20(&mut self, code: GCode) -> GCodeResult<()> {
-        assert_code!(code, General, 20, 0);
+        let plane = state.gcode.arc_plane;
+        // the plane's two center-offset arguments, in the same order as the
+        // plane's two in-plane axes below
+        let (oa, ob) = match plane {
+            ArcPlane::Xy => (i, j),
+            ArcPlane::Xz => (i, k),
+            ArcPlane::Yz => (j, k),
+        };
         ensure_own!(
-            code.arguments().is_empty(),
-            GCodeError::UnknownArgument(*code.arguments().first().unwrap(), code)
+            !(r.is_some() && (oa.is_some() || ob.is_some())),
+            GCodeError::invalid_arc(code)
         );
-        self.state.gcode.unit = Unit::Inches;
-        Ok(())
-    }
-
-    /// Executes G21 command
-    ///
-    /// Supported arguments: None
-    fn g21(&mut self, code: GCode) -> GCodeResult<()> {
-        assert_code!(code, General, 21, 0);
         ensure_own!(
-            code.arguments().is_empty(),
-            GCodeError::UnknownArgument(*code.arguments().first().unwrap(), code)
+            r.is_some() || oa.is_some() || ob.is_some(),
+            GCodeError::invalid_arc(code)
         );
-        self.state.gcode.unit = Unit::Millimeters;
-        Ok(())
-    }
+        let x = x.unwrap_or_default();
+        let y = y.unwrap_or_default();
+        let z = z.unwrap_or_default();
+        let e = e.unwrap_or_default();
 
-    /// Executes G28 command
-    ///
-    /// Supported arguments: `X`, `Y` and `Z`
-    ///
-    /// No arguments will assume all arguments present.
-    ///
-    /// Won't actually home Z axis, only X and Y, since the Z axis endstop is at
-    /// the bottom and homing it might destroy the manual homing measurement.
-    // FIXME maybe we could home the z axis by setting the power down to where
-    //       it wouldn't hurt the print head and then slowly move the bed
-    //       into the printhead and then zeroeing?
-    // FIXME drive given axis to origin
-    fn g28(&mut self, code: GCode) -> GCodeResult<VecDeque<(Action, GCode)>> {
-        assert_code!(code, General, 28, 0);
-        let mut x = false;
-        let mut y = false;
-        if code.arguments().is_empty() {
-            x = true;
-            y = true;
-        } else {
-            for arg in code.arguments().iter() {
-                let letter = match arg.letter {
-                    'X' => &mut x,
-                    'Y' => &mut y,
-                    _ => bail_own!(GCodeError::UnknownArgument(*arg, code)),
-                };
-                *letter = true;
-            }
+        fn calc_rel(new_coord: &mut Fixed, prog_coord: &mut Fixed) {
+            let rel_coord = *new_coord - *prog_coord;
+            *prog_coord = *new_coord;
+            *new_coord = rel_coord;
         }
-        let mut actions = VecDeque::with_capacity(2);
-        // Can't use ReferenceAll because that would home Z axis as well.
-        if x {
-            actions.push_back((
-                Action::ReferenceAxis(Axis::X, ReferenceRunOptParameters::default()),
-                code.clone(),
-            ));
+
+        // make x, y and z relative so we can calculate with them, same as g0_1
+        let (mut x, mut y, mut z) = (x, y, z);
+        if state.gcode.xyz_coord_mode == CoordMode::Absolute {
+            calc_rel(&mut x, &mut state.gcode.x);
+            calc_rel(&mut y, &mut state.gcode.y);
+            calc_rel(&mut z, &mut state.gcode.z);
+        } else {
+            state.gcode.x += x;
+            state.gcode.y += y;
+            state.gcode.z += z;
+        }
+        let mut e = e;
+        if state.gcode.e_coord_mode == CoordMode::Absolute {
+            calc_rel(&mut e, &mut state.gcode.e);
+        } else {
+            state.gcode.e += e;
+        }
+
+        if let Some(f) = f {
+            state.gcode.feedrate = Some(f);
+        }
+        let f = state
+            .gcode
+            .feedrate
+            .ok_or(GCodeError::missing_arguments(code.clone()))?;
+
+        // arc geometry needs floating-point trig, so drop to f64 here; only
+        // the persistent gcode/actual position state above stays fixed-point
+        let x = x.to_f64();
+        let y = y.to_f64();
+        let z = z.to_f64();
+        let e = e.to_f64();
+        let oa = oa.map(Fixed::to_f64);
+        let ob = ob.map(Fixed::to_f64);
+        let r = r.map(Fixed::to_f64);
+
+        let actual_x = state.actual.x.to_f64();
+        let actual_y = state.actual.y.to_f64();
+        let actual_z = state.actual.z.to_f64();
+        // the plane's two in-plane position deltas/machine positions, and
+        // the remaining axis' delta, which moves linearly across the arc
+        // (same as `E`) instead of being part of the circle
+        let (delta_a, delta_b, delta_l, actual_a, actual_b) = match plane {
+            ArcPlane::Xy => (x, y, z, actual_x, actual_y),
+            ArcPlane::Xz => (x, z, y, actual_x, actual_z),
+            ArcPlane::Yz => (y, z, x, actual_y, actual_z),
+        };
+
+        // the center-offset arguments are always relative to the start of
+        // the arc, regardless of xyz_coord_mode
+        let start_a = actual_a;
+        let start_b = actual_b;
+        let end_a = start_a + delta_a;
+        let end_b = start_b + delta_b;
+
+        let chord = (end_a - start_a).hypot(end_b - start_b);
+        let (center_a, center_b, radius) = if let Some(r) = r {
+            ensure_own!(2.0 * r.abs() >= chord, GCodeError::invalid_arc(code));
+            let mid_a = (start_a + end_a) / 2.0;
+            let mid_b = (start_b + end_b) / 2.0;
+            // distance from the midpoint of the chord to the center
+            let h = (r * r - (chord / 2.0) * (chord / 2.0)).max(0.0).sqrt();
+            // unit vector perpendicular to the chord
+            let (perp_a, perp_b) = if chord > f64::EPSILON {
+                (-(end_b - start_b) / chord, (end_a - start_a) / chord)
+            } else {
+                (1.0, 0.0)
+            };
+            let candidates = [
+                (mid_a + h * perp_a, mid_b + h * perp_b),
+                (mid_a - h * perp_a, mid_b - h * perp_b),
+            ];
+            // a positive R takes the arc <= 180 degrees, a negative R takes
+            // the arc > 180 degrees, regardless of direction
+            let short_way = r >= 0.0;
+            candidates
+                .into_iter()
+                .find(|&(ca, cb)| {
+                    let sweep = arc_sweep(start_a, start_b, end_a, end_b, ca, cb, clockwise);
+                    (sweep.abs() <= std::f64::consts::PI) == short_way
+                })
+                .map(|(ca, cb)| (ca, cb, r.abs()))
+                .ok_or_else(|| GCodeError::invalid_arc(code.clone()))?
+        } else {
+            let center_a = start_a + oa.unwrap_or_default();
+            let center_b = start_b + ob.unwrap_or_default();
+            let radius = (start_a - center_a).hypot(start_b - center_b);
+            ensure_own!(radius > f64::EPSILON, GCodeError::invalid_arc(code));
+            let end_radius = (end_a - center_a).hypot(end_b - center_b);
+            ensure_own!(
+                (radius - end_radius).abs() <= ARC_RADIUS_EPSILON,
+                GCodeError::invalid_arc(code)
+            );
+            (center_a, center_b, radius)
+        };
+
+        let sweep = arc_sweep(
+            start_a, start_b, end_a, end_b, center_a, center_b, clockwise,
+        );
+        let theta_start = (start_b - center_b).atan2(start_a - center_a);
+
+        let cfg = self.settings.config();
+        let tol = cfg.decode.arc_chord_tolerance.max(f64::EPSILON);
+        // max angular step a chord can sweep while staying within `tol` of
+        // the true arc is theta_max = 2*acos(1 - tol/radius); segment_count
+        // is the sweep divided into steps no larger than that, rounded up
+        let segment_count = (sweep.abs() / (2.0 * (1.0 - tol / radius.max(tol)).acos()))
+            .ceil()
+            .max(1.0) as u32;
+
+        let mut actions = VecDeque::with_capacity(segment_count as usize);
+        let mut prev_a = start_a;
+        let mut prev_b = start_b;
+        let mut prev_l = 0.0;
+        let mut prev_e = 0.0;
+        for n in 1..=segment_count {
+            let t = n as f64 / segment_count as f64;
+            let (waypoint_a, waypoint_b) = if n == segment_count {
+                (end_a, end_b)
+            } else {
+                let theta = theta_start + sweep * t;
+                (
+                    center_a + radius * theta.cos(),
+                    center_b + radius * theta.sin(),
+                )
+            };
+            let waypoint_l = delta_l * t;
+            let waypoint_e = e * t;
+            let (da, db, dl) = (
+                waypoint_a - prev_a,
+                waypoint_b - prev_b,
+                waypoint_l - prev_l,
+            );
+            let (dx, dy, dz) = match plane {
+                ArcPlane::Xy => (da, db, dl),
+                ArcPlane::Xz => (da, dl, db),
+                ArcPlane::Yz => (dl, da, db),
+            };
+            actions.extend(self.move_by(
+                Fixed::from_f64(dx),
+                Fixed::from_f64(dy),
+                Fixed::from_f64(dz),
+                Fixed::from_f64(waypoint_e - prev_e),
+                f,
+                code.clone(),
+            )?);
+            prev_a = waypoint_a;
+            prev_b = waypoint_b;
+            prev_l = waypoint_l;
+            prev_e = waypoint_e;
+        }
+        Ok(actions)
+    }
+
+    /// Moves only the z and e axes by the given mm deltas at the given
+    /// mm/min feedrate, bypassing the look-ahead planner
+    ///
+    /// Shared by [`g10`][Self::g10]/[`g11`][Self::g11]. Unlike
+    /// [`move_by`][Self::move_by] this never touches the programmed gcode
+    /// position, since the slicer doesn't expect firmware retraction to
+    /// shift it the way a regular G1 E move would.
+    fn retract_move(
+        &mut self,
+        dz: f64,
+        de: f64,
+        f: f64,
+        code: GCode,
+    ) -> GCodeResult<(Action, GCode)> {
+        let dz = Fixed::from_f64(dz);
+        let state = &mut self.state;
+        let cfg = self.settings.config();
+
+        let actual_z_new = state.actual.z + dz;
+        ensure_own!(
+            actual_z_new >= state.actual.z_hotend_location,
+            GCodeError::pos_out_of_bounds(code.clone())
+        );
+        ensure_own!(
+            actual_z_new <= Fixed::ZERO,
+            GCodeError::pos_out_of_bounds(code.clone())
+        );
+        state.actual.z = actual_z_new;
+
+        let s = (dz.to_f64() * dz.to_f64() + de * de).sqrt();
+        let t = s / (f / 60.0);
+        let z = mm_to_steps_carried(
+            dz,
+            &cfg.motors.z.translation,
+            &cfg.motors.z.step_size,
+            &mut state.actual.err_z,
+        );
+        let e = mm_to_steps_carried(
+            Fixed::from_f64(de),
+            &cfg.motors.e.translation,
+            &cfg.motors.e.step_size,
+            &mut state.actual.err_e,
+        );
+
+        let v_z = (z / t).round();
+        let v_e = (e / t).round();
+
+        state.actual.steps_z += z as i32;
+
+        let mut e_direction = cfg.motors.e.positive_direction;
+        if e < 0.0 {
+            e_direction = e_direction.reverse();
+        }
+
+        let movement = Movement {
+            x: AxisMovement {
+                distance: state.actual.steps_x as i32,
+                min_frequency: 1,
+                max_frequency: 1,
+                acceleration: cfg.motors.x.accel_limit,
+                deceleration: cfg.motors.x.decel_limit,
+                acceleration_jerk: cfg.motors.x.accel_jerk_limit,
+                deceleration_jerk: cfg.motors.x.decel_jerk_limit,
+            },
+            y: AxisMovement {
+                distance: state.actual.steps_y as i32,
+                min_frequency: 1,
+                max_frequency: 1,
+                acceleration: cfg.motors.y.accel_limit,
+                deceleration: cfg.motors.y.decel_limit,
+                acceleration_jerk: cfg.motors.y.accel_jerk_limit,
+                deceleration_jerk: cfg.motors.y.decel_jerk_limit,
+            },
+            z: AxisMovement {
+                distance: state.actual.steps_z,
+                min_frequency: 1,
+                max_frequency: v_z as u32,
+                acceleration: effective_limit(
+                    self.accel_overrides.retract,
+                    cfg.motors.z.accel_limit,
+                    &cfg.motors.z.translation,
+                    &cfg.motors.z.step_size,
+                ),
+                deceleration: effective_limit(
+                    self.accel_overrides.retract,
+                    cfg.motors.z.decel_limit,
+                    &cfg.motors.z.translation,
+                    &cfg.motors.z.step_size,
+                ),
+                acceleration_jerk: cfg.motors.z.accel_jerk_limit,
+                deceleration_jerk: cfg.motors.z.decel_jerk_limit,
+            },
+            e: ExtruderMovement {
+                direction: e_direction,
+                distance: e as u32,
+                min_frequency: 1,
+                max_frequency: v_e as u32,
+                acceleration: effective_limit(
+                    self.accel_overrides.retract,
+                    cfg.motors.e.accel_limit,
+                    &cfg.motors.e.translation,
+                    &cfg.motors.e.step_size,
+                ),
+                deceleration: effective_limit(
+                    self.accel_overrides.retract,
+                    cfg.motors.e.decel_limit,
+                    &cfg.motors.e.translation,
+                    &cfg.motors.e.step_size,
+                ),
+                acceleration_jerk: cfg.motors.e.accel_jerk_limit,
+                deceleration_jerk: cfg.motors.e.decel_jerk_limit,
+            },
+        };
+
+        Ok((Action::MoveAll(movement), code))
+    }
+
+    /// Executes the workplace-offset form of G10 (`L2`/`L20`), setting up
+    /// one of the [`Workplaces`] G54-G59 select
+    ///
+    /// Supported arguments: `L` (`2` or `20`), `P` (workplace number,
+    /// `1`-`6`), and any of `X`, `Y`, `Z`. `L2` sets the given axes' offsets
+    /// directly, in machine coordinates. `L20` instead sets them so that the
+    /// printer's current machine position reads as the given coordinate once
+    /// that workplace is selected. An axis not given is left unchanged.
+    ///
+    /// # Errors
+    /// [`GCodeError::MissingArguments`] if `L` or `P` is missing.
+    /// [`GCodeError::InvalidWorkplace`] if `P` is outside `1`-`6`, or `L`
+    /// isn't `2` or `20`. [`GCodeError::UnknownArgument`] for any argument
+    /// other than `L`, `P`, `X`, `Y` or `Z`.
+    fn g10_workplace_offset(&mut self, code: GCode) -> GCodeResult<()> {
+        let mut l = None;
+        let mut p = None;
+        let mut x = None;
+        let mut y = None;
+        let mut z = None;
+        for arg in code.arguments().iter() {
+            let letter = match arg.letter {
+                'L' => &mut l,
+                'P' => &mut p,
+                'X' => &mut x,
+                'Y' => &mut y,
+                'Z' => &mut z,
+                _ => bail_own!(GCodeError::unknown_argument(*arg, code)),
+            };
+            ensure_own!(letter.is_none(), GCodeError::duplicate_argument(*arg, code));
+            *letter = Some(*arg);
+        }
+        let l = l.ok_or_else(|| GCodeError::missing_arguments(code.clone()))?;
+        let p = p.ok_or_else(|| GCodeError::missing_arguments(code.clone()))?;
+        ensure_own!(
+            l.value == 2.0 || l.value == 20.0,
+            GCodeError::invalid_workplace(code.clone(), WORKPLACE_COUNT)
+        );
+        let index = p.value as i64 - 1;
+        ensure_own!(
+            (0..WORKPLACE_COUNT as i64).contains(&index),
+            GCodeError::invalid_workplace(code.clone(), WORKPLACE_COUNT)
+        );
+        let index = index as usize;
+
+        let state = &mut self.state;
+        let x = x.map(|a| state.gcode.unit.in_mm(a.value as f64));
+        let y = y.map(|a| state.gcode.unit.in_mm(a.value as f64));
+        let z = z.map(|a| state.gcode.unit.in_mm(a.value as f64));
+        let (actual_x, actual_y, actual_z) = (state.actual.x, state.actual.y, state.actual.z);
+
+        let offset = &mut state.workplaces.offsets[index];
+        if l.value == 2.0 {
+            // L2: the given value is the offset itself, in machine coordinates
+            if let Some(x) = x {
+                offset.x = x;
+            }
+            if let Some(y) = y {
+                offset.y = y;
+            }
+            if let Some(z) = z {
+                offset.z = z;
+            }
+        } else {
+            // L20: the offset is whatever makes the current machine
+            // position read as the given coordinate once this workplace is
+            // selected
+            if let Some(x) = x {
+                offset.x = actual_x - x;
+            }
+            if let Some(y) = y {
+                offset.y = actual_y - y;
+            }
+            if let Some(z) = z {
+                offset.z = actual_z - z;
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes G10 command: either firmware retraction, or (with an `L`
+    /// argument) setting up a workplace offset, see
+    /// [`g10_workplace_offset`][Self::g10_workplace_offset]
+    ///
+    /// Pulls the filament back by the configured retraction length at the
+    /// configured retraction feedrate, optionally raising the z axis by the
+    /// configured z-hop while retracted. Doesn't touch the programmed xyz/e
+    /// position, only the physical one, since the slicer isn't aware the
+    /// retraction happened. A second G10 while already retracted is a
+    /// no-op.
+    ///
+    /// Supported arguments: `S` and `Z`. `Z` overrides the configured z-hop
+    /// for this retract; `S` is accepted for slicers that always emit a
+    /// tool index, but is unused since there's only a single extruder.
+    ///
+    /// # Errors
+    /// Returns [`GCodeError::UnknownArgument`] for any argument other than
+    /// `S` or `Z`.
+    fn g10(&mut self, code: GCode) -> GCodeResult<VecDeque<(Action, GCode)>> {
+        assert_code!(code, General, 10, 0);
+        if code.arguments().iter().any(|arg| arg.letter == 'L') {
+            self.g10_workplace_offset(code)?;
+            return Ok(VecDeque::new());
+        }
+
+        let mut s_seen = false;
+        let mut z_hop = None;
+        for arg in code.arguments().iter() {
+            match arg.letter {
+                'S' => {
+                    ensure_own!(!s_seen, GCodeError::duplicate_argument(*arg, code));
+                    s_seen = true;
+                }
+                'Z' => {
+                    ensure_own!(z_hop.is_none(), GCodeError::duplicate_argument(*arg, code));
+                    z_hop = Some(self.state.gcode.unit.in_mm(arg.value as f64));
+                }
+                _ => bail_own!(GCodeError::unknown_argument(*arg, code)),
+            }
+        }
+
+        if self.state.gcode.retracted {
+            return Ok(VecDeque::new());
+        }
+
+        let cfg = self.settings.config();
+        let z_hop = z_hop
+            .map(Fixed::to_f64)
+            .or(cfg.retraction.z_hop)
+            .unwrap_or(0.0);
+        let length = cfg.retraction.length;
+        let feedrate = cfg.retraction.feedrate;
+
+        self.state.gcode.retracted = true;
+        self.state.gcode.z_hop = Fixed::from_f64(z_hop);
+
+        let mut dq = VecDeque::with_capacity(1);
+        dq.push_back(self.retract_move(z_hop, -length, feedrate, code)?);
+        Ok(dq)
+    }
+
+    /// Executes G11 command: reverses a firmware retraction
+    ///
+    /// Un-retracts the filament by the configured retraction length plus
+    /// the configured extra recover length, at the configured recover
+    /// feedrate, and drops whatever z-hop [`g10`][Self::g10] applied. A
+    /// G11 while not retracted is a no-op.
+    ///
+    /// Supported arguments: `S`, accepted for slicers that always emit a
+    /// tool index but unused since there's only a single extruder.
+    ///
+    /// # Errors
+    /// Returns [`GCodeError::UnknownArgument`] for any argument other than
+    /// `S`.
+    fn g11(&mut self, code: GCode) -> GCodeResult<VecDeque<(Action, GCode)>> {
+        assert_code!(code, General, 11, 0);
+        for arg in code.arguments().iter() {
+            ensure_own!(arg.letter == 'S', GCodeError::unknown_argument(*arg, code));
+        }
+
+        if !self.state.gcode.retracted {
+            return Ok(VecDeque::new());
+        }
+
+        let cfg = self.settings.config();
+        let length = cfg.retraction.length + cfg.retraction.extra_recover_length;
+        let feedrate = cfg.retraction.recover_feedrate;
+        let z_hop = self.state.gcode.z_hop.to_f64();
+
+        self.state.gcode.retracted = false;
+        self.state.gcode.z_hop = Fixed::ZERO;
+
+        let mut dq = VecDeque::with_capacity(1);
+        dq.push_back(self.retract_move(-z_hop, length, feedrate, code)?);
+        Ok(dq)
+    }
+
+    /// Executes G0 command (does the same as [`g1`][Self::g1])
+    ///
+    /// Supported arguments: `X`, `Y`, `Z`, `E` and `F`
+    ///
+    /// # Errors
+    /// At least one argument must be present, otherwise [`GCodeError::MissingArguments`]
+    /// will be returned. Same if `F` is not present and has not been present
+    /// before.
+    fn g0(&mut self, code: GCode) -> GCodeResult<VecDeque<(Action, GCode)>> {
+        assert_code!(code, General, 0, 0);
+        self.g0_1(code)
+    }
+
+    /// Executes G1 command (does the same as [`g0`][Self::g0])
+    ///
+    /// Supported arguments: `X`, `Y`, `Z`, `E` and `F`
+    ///
+    /// # Errors
+    /// At least one argument must be present, otherwise [`GCodeError::MissingArguments`]
+    /// will be returned. Same if `F` is not present and has not been present
+    /// before.
+    fn g1(&mut self, code: GCode) -> GCodeResult<VecDeque<(Action, GCode)>> {
+        assert_code!(code, General, 1, 0);
+        self.g0_1(code)
+    }
+
+    /// Executes G4 command
+    ///
+    /// Supported arguments: `P` and `S`
+    ///
+    /// # Errors
+    /// At least one argument must be present, otherwise [`GCodeError::MissingArguments`]
+    /// will be returned.
+    fn g4(&mut self, code: GCode) -> GCodeResult<(Action, GCode)> {
+        assert_code!(code, General, 4, 0);
+        ensure_own!(
+            !code.arguments().is_empty(),
+            GCodeError::missing_arguments(code)
+        );
+        let mut millis = None;
+        let mut secs = None;
+        for arg in code.arguments().iter() {
+            match arg.letter {
+                'P' => {
+                    ensure_own!(millis.is_none(), GCodeError::duplicate_argument(*arg, code));
+                    millis = Some(Duration::from_millis(arg.value as u64));
+                }
+                'S' => {
+                    ensure_own!(secs.is_none(), GCodeError::duplicate_argument(*arg, code));
+                    secs = Some(Duration::from_secs(arg.value as u64));
+                }
+                _ => bail_own!(GCodeError::unknown_argument(*arg, code)),
+            }
+        }
+        let combined = millis.unwrap_or_default() + secs.unwrap_or_default();
+        Ok((Action::Wait(combined), code))
+    }
+
+    /// Executes G20 command: switches to inch mode
+    ///
+    /// Every linear argument parsed while this is active is converted to mm
+    /// via [`Unit::in_mm`] as it's decoded; internal state always stays in
+    /// mm regardless.
+    ///
+    /// Supported arguments: None
+    fn g20(&mut self, code: GCode) -> GCodeResult<()> {
+        assert_code!(code, General, 20, 0);
+        ensure_own!(
+            code.arguments().is_empty(),
+            GCodeError::unknown_argument(*code.arguments().first().unwrap(), code)
+        );
+        self.state.gcode.unit = Unit::Inches;
+        Ok(())
+    }
+
+    /// Executes G21 command: switches back to millimeters, the default
+    ///
+    /// Supported arguments: None
+    fn g21(&mut self, code: GCode) -> GCodeResult<()> {
+        assert_code!(code, General, 21, 0);
+        ensure_own!(
+            code.arguments().is_empty(),
+            GCodeError::unknown_argument(*code.arguments().first().unwrap(), code)
+        );
+        self.state.gcode.unit = Unit::Millimeters;
+        Ok(())
+    }
+
+    /// Executes G28 command
+    ///
+    /// Supported arguments: `X`, `Y` and `Z`
+    ///
+    /// No arguments will assume all arguments present.
+    ///
+    /// `Z` first references into the endstop direction like `X`/`Y` do (safe,
+    /// since that's away from the bed), then emits `Action::ProbeZHotend` to
+    /// slowly lower it until it stalls against the print head, so a fresh
+    /// boot no longer needs a pre-measured manual hotend reference before a
+    /// print can use the full `G28`.
+    // FIXME `Action::ProbeZHotend`'s measured contact position only reaches
+    //       `shared_pos`/the existing manual "reference z via hotend"
+    //       endpoint, not synchronously back into this thread's own
+    //       `self.state.actual.z_hotend_location`/`steps_z`, since actions
+    //       are fire-and-forget onto the executor thread and there's no
+    //       result channel back for this one value yet; same kind of gap as
+    //       `actual_pos` not being resynced after referencing X/Y above.
+    fn g28(&mut self, code: GCode) -> GCodeResult<VecDeque<(Action, GCode)>> {
+        assert_code!(code, General, 28, 0);
+        let mut x = false;
+        let mut y = false;
+        let mut z = false;
+        if code.arguments().is_empty() {
+            x = true;
+            y = true;
+            z = true;
+        } else {
+            for arg in code.arguments().iter() {
+                let letter = match arg.letter {
+                    'X' => &mut x,
+                    'Y' => &mut y,
+                    'Z' => &mut z,
+                    _ => bail_own!(GCodeError::unknown_argument(*arg, code)),
+                };
+                *letter = true;
+            }
+        }
+        let mut actions = VecDeque::with_capacity(4);
+        // Can't use ReferenceAll because that would home Z into the endstop
+        // direction, not probe it down into the print head.
+        if x {
+            actions.push_back((
+                Action::ReferenceAxis(Axis::X, ReferenceRunOptParameters::default()),
+                code.clone(),
+            ));
         }
         if y {
             actions.push_back((
                 Action::ReferenceAxis(Axis::Y, ReferenceRunOptParameters::default()),
+                code.clone(),
+            ));
+        }
+        if z {
+            actions.push_back((
+                Action::ReferenceAxis(Axis::Z, ReferenceRunOptParameters::default()),
+                code.clone(),
+            ));
+            actions.push_back((
+                Action::ProbeZHotend(ReferenceRunOptParameters::default()),
                 code,
             ));
         }
         Ok(actions)
     }
 
+    /// Executes G29 command: enables mesh compensation and emits
+    /// [`Action::ProbeMesh`]
+    ///
+    /// Supported arguments: None
+    // FIXME there's no probe sensor abstraction in this codebase yet, so
+    // this doesn't actually probe anything; the mesh stays whatever was
+    // loaded from config (or set via `State::set_bed_mesh`), and the Action::ProbeMesh
+    // it emits is a no-op on the executor side too, until a probe exists to
+    // feed measured heights back in
+    fn g29(&mut self, code: GCode) -> GCodeResult<(Action, GCode)> {
+        assert_code!(code, General, 29, 0);
+        ensure_own!(
+            code.arguments().is_empty(),
+            GCodeError::unknown_argument(*code.arguments().first().unwrap(), code)
+        );
+        self.state.gcode.mesh_enabled = true;
+        Ok((Action::ProbeMesh, code))
+    }
+
+    /// Executes G17 command: selects the XY plane for subsequent G2/G3 arcs
+    ///
+    /// Supported arguments: None
+    fn g17(&mut self, code: GCode) -> GCodeResult<()> {
+        assert_code!(code, General, 17, 0);
+        ensure_own!(
+            code.arguments().is_empty(),
+            GCodeError::unknown_argument(*code.arguments().first().unwrap(), code)
+        );
+        self.state.gcode.arc_plane = ArcPlane::Xy;
+        Ok(())
+    }
+
+    /// Executes G18 command: selects the XZ plane for subsequent G2/G3 arcs
+    ///
+    /// Supported arguments: None
+    fn g18(&mut self, code: GCode) -> GCodeResult<()> {
+        assert_code!(code, General, 18, 0);
+        ensure_own!(
+            code.arguments().is_empty(),
+            GCodeError::unknown_argument(*code.arguments().first().unwrap(), code)
+        );
+        self.state.gcode.arc_plane = ArcPlane::Xz;
+        Ok(())
+    }
+
+    /// Executes G19 command: selects the YZ plane for subsequent G2/G3 arcs
+    ///
+    /// Supported arguments: None
+    fn g19(&mut self, code: GCode) -> GCodeResult<()> {
+        assert_code!(code, General, 19, 0);
+        ensure_own!(
+            code.arguments().is_empty(),
+            GCodeError::unknown_argument(*code.arguments().first().unwrap(), code)
+        );
+        self.state.gcode.arc_plane = ArcPlane::Yz;
+        Ok(())
+    }
+
     /// Executes G90 command
     ///
     /// Supported arguments: None
@@ -529,7 +1897,7 @@ impl Decoder {
         assert_code!(code, General, 90, 0);
         ensure_own!(
             code.arguments().is_empty(),
-            GCodeError::UnknownArgument(*code.arguments().first().unwrap(), code)
+            GCodeError::unknown_argument(*code.arguments().first().unwrap(), code)
         );
         self.state.gcode.xyz_coord_mode = CoordMode::Absolute;
         Ok(())
@@ -542,7 +1910,7 @@ impl Decoder {
         assert_code!(code, General, 91, 0);
         ensure_own!(
             code.arguments().is_empty(),
-            GCodeError::UnknownArgument(*code.arguments().first().unwrap(), code)
+            GCodeError::unknown_argument(*code.arguments().first().unwrap(), code)
         );
         self.state.gcode.xyz_coord_mode = CoordMode::Relative;
         Ok(())
@@ -559,7 +1927,7 @@ impl Decoder {
         assert_code!(code, General, 92, 0);
         ensure_own!(
             !code.arguments().is_empty(),
-            GCodeError::MissingArguments(code)
+            GCodeError::missing_arguments(code)
         );
         let mut x = None;
         let mut y = None;
@@ -572,9 +1940,9 @@ impl Decoder {
                 'Y' => &mut y,
                 'Z' => &mut z,
                 'E' => &mut e,
-                _ => bail_own!(GCodeError::UnknownArgument(*arg, code)),
+                _ => bail_own!(GCodeError::unknown_argument(*arg, code)),
             };
-            ensure_own!(letter.is_none(), GCodeError::DuplicateArgument(*arg, code));
+            ensure_own!(letter.is_none(), GCodeError::duplicate_argument(*arg, code));
             *letter = Some(state.gcode.unit.in_mm(arg.value as f64));
         }
         state.gcode.x = x.unwrap_or(state.gcode.x);
@@ -584,6 +1952,293 @@ impl Decoder {
         Ok(())
     }
 
+    /// Executes G60 command: snapshots the current position, feedrate,
+    /// units and coordinate modes into restore-point slot `S`
+    ///
+    /// The position is stored in machine-absolute terms (see
+    /// [`SavedState`]), so a workplace-offset or unit change between the
+    /// G60 and the matching [`g61`][Self::g61] doesn't shift the head.
+    ///
+    /// Supported arguments: `S` (slot number, `0`-`5`)
+    ///
+    /// # Errors
+    /// [`GCodeError::MissingArguments`] if `S` is missing.
+    /// [`GCodeError::InvalidRestorePoint`] if `S` is out of range.
+    /// [`GCodeError::UnknownArgument`] for any argument other than `S`.
+    fn g60(&mut self, code: GCode) -> GCodeResult<()> {
+        assert_code!(code, General, 60, 0);
+        let mut slot = None;
+        for arg in code.arguments().iter() {
+            match arg.letter {
+                'S' => {
+                    ensure_own!(slot.is_none(), GCodeError::duplicate_argument(*arg, code));
+                    slot = Some(arg.value as usize);
+                }
+                _ => bail_own!(GCodeError::unknown_argument(*arg, code)),
+            }
+        }
+        let slot = slot.ok_or_else(|| GCodeError::missing_arguments(code.clone()))?;
+        ensure_own!(
+            slot < RESTORE_POINT_COUNT,
+            GCodeError::invalid_restore_point(code.clone(), slot, RESTORE_POINT_COUNT - 1)
+        );
+        let state = &mut self.state;
+        state.gcode.saved_states[slot] = Some(SavedState {
+            x: state.actual.x,
+            y: state.actual.y,
+            z: state.actual.z,
+            e: state.gcode.e,
+            feedrate: state.gcode.feedrate,
+            xyz_coord_mode: state.gcode.xyz_coord_mode,
+            e_coord_mode: state.gcode.e_coord_mode,
+            unit: state.gcode.unit,
+            arc_plane: state.gcode.arc_plane,
+        });
+        Ok(())
+    }
+
+    /// Executes G61 command: restores a snapshot saved by
+    /// [`g60`][Self::g60], moving the head back to the saved machine
+    /// position and re-applying the saved feedrate, units and coordinate
+    /// modes
+    ///
+    /// The programmed xyz position is reconstructed under whichever
+    /// workplace offset is active right now, so the restored machine
+    /// position stays independent of any offset change since the G60.
+    /// A no-op if the saved position already matches the current one.
+    ///
+    /// Supported arguments: `S` (slot number, `0`-`5`)
+    ///
+    /// # Errors
+    /// [`GCodeError::MissingArguments`] if `S` is missing, or if a move is
+    /// needed and no feedrate is known. [`GCodeError::InvalidRestorePoint`]
+    /// if `S` is out of range or that slot hasn't been saved yet.
+    /// [`GCodeError::UnknownArgument`] for any argument other than `S`.
+    fn g61(&mut self, code: GCode) -> GCodeResult<VecDeque<(Action, GCode)>> {
+        assert_code!(code, General, 61, 0);
+        let mut slot = None;
+        for arg in code.arguments().iter() {
+            match arg.letter {
+                'S' => {
+                    ensure_own!(slot.is_none(), GCodeError::duplicate_argument(*arg, code));
+                    slot = Some(arg.value as usize);
+                }
+                _ => bail_own!(GCodeError::unknown_argument(*arg, code)),
+            }
+        }
+        let slot = slot.ok_or_else(|| GCodeError::missing_arguments(code.clone()))?;
+        ensure_own!(
+            slot < RESTORE_POINT_COUNT,
+            GCodeError::invalid_restore_point(code.clone(), slot, RESTORE_POINT_COUNT - 1)
+        );
+        let saved = self.state.gcode.saved_states[slot].ok_or_else(|| {
+            GCodeError::invalid_restore_point(code.clone(), slot, RESTORE_POINT_COUNT - 1)
+        })?;
+
+        self.state.gcode.feedrate = saved.feedrate;
+        self.state.gcode.xyz_coord_mode = saved.xyz_coord_mode;
+        self.state.gcode.e_coord_mode = saved.e_coord_mode;
+        self.state.gcode.unit = saved.unit;
+        self.state.gcode.arc_plane = saved.arc_plane;
+        self.state.gcode.e = saved.e;
+        let offset = self.state.workplaces.active_offset();
+        self.state.gcode.x = saved.x - offset.x;
+        self.state.gcode.y = saved.y - offset.y;
+        self.state.gcode.z = saved.z - offset.z;
+
+        let dx = saved.x - self.state.actual.x;
+        let dy = saved.y - self.state.actual.y;
+        let dz = saved.z - self.state.actual.z;
+        if dx == Fixed::ZERO && dy == Fixed::ZERO && dz == Fixed::ZERO {
+            return Ok(VecDeque::new());
+        }
+        let f = self
+            .state
+            .gcode
+            .feedrate
+            .ok_or_else(|| GCodeError::missing_arguments(code.clone()))?;
+        self.move_by(dx, dy, dz, Fixed::ZERO, f, code)
+    }
+
+    /// Retracts, lifts Z and travels to the configured park position, for
+    /// safely pausing a print
+    ///
+    /// Saves the current machine position so [`unpark`][Self::unpark] can
+    /// return to it, then retracts filament by
+    /// [`config::Park::retract_length`][crate::config::Park], lifts Z by
+    /// [`config::Park::lift`][crate::config::Park] (clamped via
+    /// `lift.min(distance to home)` so it never drives the head past its
+    /// home position), and finally travels X/Y to the configured park
+    /// coordinates. The planner is flushed both before and after, so the
+    /// sequence always executes immediately instead of waiting behind
+    /// whatever moves were still buffered, or being reordered against
+    /// whatever comes next.
+    ///
+    /// `code` is only used for error/line attribution; it isn't actually
+    /// decoded.
+    ///
+    /// # Errors
+    /// Same as [`move_by`][Self::move_by]/[`retract_move`][Self::retract_move]:
+    /// [`GCodeError::PosOutOfBounds`] or [`GCodeError::OutOfBounds`] if the
+    /// lift or travel would leave the machine's or build volume's limits.
+    pub fn park(&mut self, code: GCode) -> GCodeResult<VecDeque<(Action, GCode)>> {
+        let park = self.settings.config().park.clone();
+        self.state.gcode.parked = Some(ParkedState {
+            x: self.state.actual.x,
+            y: self.state.actual.y,
+            z: self.state.actual.z,
+        });
+
+        let mut actions = self.flush_planner();
+
+        if park.retract_length > 0.0 {
+            actions.push_back(self.retract_move(
+                0.0,
+                -park.retract_length,
+                park.retract_feedrate,
+                code.clone(),
+            )?);
+        }
+
+        // z grows negative with print height (see `ActualState::steps_z`),
+        // so the distance left to the home position is `0 - actual.z`
+        let lift = park.lift.min(-self.state.actual.z.to_f64()).max(0.0);
+        if lift > 0.0 {
+            actions.extend(self.move_by(
+                Fixed::ZERO,
+                Fixed::ZERO,
+                Fixed::from_f64(lift),
+                Fixed::ZERO,
+                Fixed::from_f64(park.lift_feedrate),
+                code.clone(),
+            )?);
+        }
+
+        let dx = Fixed::from_f64(park.x) - self.state.actual.x;
+        let dy = Fixed::from_f64(park.y) - self.state.actual.y;
+        if dx != Fixed::ZERO || dy != Fixed::ZERO {
+            actions.extend(self.move_by(
+                dx,
+                dy,
+                Fixed::ZERO,
+                Fixed::ZERO,
+                Fixed::from_f64(park.travel_feedrate),
+                code.clone(),
+            )?);
+        }
+
+        actions.extend(self.flush_planner());
+        Ok(actions)
+    }
+
+    /// Reverses [`park`][Self::park]: travels back to the saved position and
+    /// re-primes the retracted filament
+    ///
+    /// Un-retracts by `retract_length` plus
+    /// [`config::Park::extra_recover_length`][crate::config::Park], the same
+    /// idea as [`g11`][Self::g11], and clears the saved position so a
+    /// following `unpark` without an intervening `park` errors out instead
+    /// of silently repeating the move.
+    ///
+    /// `code` is only used for error/line attribution; it isn't actually
+    /// decoded.
+    ///
+    /// # Errors
+    /// [`GCodeError::NotParked`] if there's no saved position, i.e.
+    /// [`park`][Self::park] wasn't called since the last `unpark` (or since
+    /// the last [`reset`][Self::reset]). Otherwise the same as
+    /// [`move_by`][Self::move_by]/[`retract_move`][Self::retract_move].
+    pub fn unpark(&mut self, code: GCode) -> GCodeResult<VecDeque<(Action, GCode)>> {
+        let saved = self
+            .state
+            .gcode
+            .parked
+            .take()
+            .ok_or_else(|| GCodeError::not_parked(code.clone()))?;
+        let park = self.settings.config().park.clone();
+
+        let mut actions = self.flush_planner();
+
+        let dx = saved.x - self.state.actual.x;
+        let dy = saved.y - self.state.actual.y;
+        let dz = saved.z - self.state.actual.z;
+        if dx != Fixed::ZERO || dy != Fixed::ZERO || dz != Fixed::ZERO {
+            actions.extend(self.move_by(
+                dx,
+                dy,
+                dz,
+                Fixed::ZERO,
+                Fixed::from_f64(park.travel_feedrate),
+                code.clone(),
+            )?);
+        }
+
+        let recover_length = park.retract_length + park.extra_recover_length;
+        if recover_length > 0.0 {
+            actions.push_back(self.retract_move(
+                0.0,
+                recover_length,
+                park.recover_feedrate,
+                code.clone(),
+            )?);
+        }
+
+        actions.extend(self.flush_planner());
+        Ok(actions)
+    }
+
+    /// Executes G54-G59: selects one of the [`Workplaces`] set up by
+    /// [`g10_workplace_offset`][Self::g10_workplace_offset] as the active
+    /// one
+    ///
+    /// Doesn't move anything by itself; the offset change is folded into
+    /// the next move's delta by [`move_by`][Self::move_by], the same way it
+    /// folds in bed-mesh compensation changes.
+    ///
+    /// Supported arguments: None
+    // FIXME only G54-G59 are implemented, not the full G54.1/G59.1-G59.3
+    // range the gcode spec allows; that needs minor-numbered gcodes, which
+    // `decode` doesn't support yet
+    fn select_workplace(&mut self, code: GCode) -> GCodeResult<()> {
+        assert_eq!(code.mnemonic(), Mnemonic::General);
+        assert_eq!(code.minor_number(), 0);
+        ensure_own!(
+            code.arguments().is_empty(),
+            GCodeError::unknown_argument(*code.arguments().first().unwrap(), code)
+        );
+        let index = (code.major_number() - 54) as usize;
+        self.state.workplaces.active = Some(index);
+        Ok(())
+    }
+
+    /// Executes Tn: selects tool `n` as the active tool, so subsequent moves
+    /// apply its configured [`ToolOffset`] and its own accumulated E position
+    /// rather than the previous tool's
+    ///
+    /// Supported arguments: None
+    // FIXME gcode's T-1 ("no tool selected", used by tool-changer configs to
+    // zero all offsets) can't be represented here: `GCode::major_number`
+    // returns a `u32`, so a negative tool number never reaches this handler
+    // in the first place.
+    fn tool_change(&mut self, code: GCode) -> GCodeResult<()> {
+        assert_eq!(code.mnemonic(), Mnemonic::ToolChange);
+        ensure_own!(
+            code.arguments().is_empty(),
+            GCodeError::unknown_argument(*code.arguments().first().unwrap(), code)
+        );
+        let tool = code.major_number();
+        ensure_own!(
+            (tool as usize) < TOOL_COUNT,
+            GCodeError::invalid_tool(code, tool, TOOL_COUNT - 1)
+        );
+        let tool = tool as u8;
+        let state = &mut self.state.gcode;
+        state.tool_e[state.active_tool as usize] = state.e;
+        state.active_tool = tool;
+        state.e = state.tool_e[tool as usize];
+        Ok(())
+    }
+
     /// Executes M82 command
     ///
     /// Supported arguments: None
@@ -591,7 +2246,7 @@ impl Decoder {
         assert_code!(code, Miscellaneous, 82, 0);
         ensure_own!(
             code.arguments().is_empty(),
-            GCodeError::UnknownArgument(*code.arguments().first().unwrap(), code)
+            GCodeError::unknown_argument(*code.arguments().first().unwrap(), code)
         );
         self.state.gcode.e_coord_mode = CoordMode::Absolute;
         Ok(())
@@ -604,12 +2259,127 @@ impl Decoder {
         assert_code!(code, Miscellaneous, 83, 0);
         ensure_own!(
             code.arguments().is_empty(),
-            GCodeError::UnknownArgument(*code.arguments().first().unwrap(), code)
+            GCodeError::unknown_argument(*code.arguments().first().unwrap(), code)
         );
         self.state.gcode.e_coord_mode = CoordMode::Relative;
         Ok(())
     }
 
+    /// Executes M204 command: overrides the acceleration limits [`move_by`][Self::move_by]
+    /// and [`retract_move`][Self::retract_move] use in place of the
+    /// configured per-axis `accel_limit`/`decel_limit`
+    ///
+    /// Supported arguments: `P` (print-move acceleration), `T` (travel-move
+    /// acceleration) and `R` (retraction acceleration), all in mm/s^2.
+    /// Whichever applies to a given move overrides both its acceleration
+    /// and deceleration limit. An override above the axis' configured
+    /// hardware maximum is silently clamped down to it rather than
+    /// rejected, so the machine stays within what it's rated for. Resets to
+    /// the config values on the next [`reset`][Self::reset].
+    ///
+    /// # Errors
+    /// At least one argument must be present, otherwise [`GCodeError::MissingArguments`]
+    /// will be returned. [`GCodeError::UnknownArgument`] is returned for any
+    /// argument other than `P`, `T` or `R`.
+    fn m204(&mut self, code: GCode) -> GCodeResult<()> {
+        assert_code!(code, Miscellaneous, 204, 0);
+        ensure_own!(
+            !code.arguments().is_empty(),
+            GCodeError::missing_arguments(code)
+        );
+        for arg in code.arguments().iter() {
+            let over = match arg.letter {
+                'P' => &mut self.accel_overrides.print,
+                'T' => &mut self.accel_overrides.travel,
+                'R' => &mut self.accel_overrides.retract,
+                _ => bail_own!(GCodeError::unknown_argument(*arg, code)),
+            };
+            ensure_own!(over.is_none(), GCodeError::duplicate_argument(*arg, code));
+            *over = Some(arg.value as f64);
+        }
+        Ok(())
+    }
+
+    /// Executes M205 command: overrides the jerk limits [`move_by`][Self::move_by]
+    /// uses, and the look-ahead planner's junction-deviation
+    ///
+    /// Supported arguments: `X`, `Y`, `Z`, `E` (per-axis jerk, in mm/s^3)
+    /// and `S` (junction-deviation, in mm). An axis jerk override above the
+    /// axis' configured hardware maximum is silently clamped down to it
+    /// rather than rejected, so the machine stays within what it's rated
+    /// for. Resets to the config values on the next [`reset`][Self::reset].
+    ///
+    /// # Errors
+    /// At least one argument must be present, otherwise [`GCodeError::MissingArguments`]
+    /// will be returned. [`GCodeError::UnknownArgument`] is returned for any
+    /// argument other than `S`, `X`, `Y`, `Z` or `E`.
+    fn m205(&mut self, code: GCode) -> GCodeResult<()> {
+        assert_code!(code, Miscellaneous, 205, 0);
+        ensure_own!(
+            !code.arguments().is_empty(),
+            GCodeError::missing_arguments(code)
+        );
+        let mut junction_deviation = None;
+        for arg in code.arguments().iter() {
+            let over = match arg.letter {
+                'X' => &mut self.accel_overrides.jerk_x,
+                'Y' => &mut self.accel_overrides.jerk_y,
+                'Z' => &mut self.accel_overrides.jerk_z,
+                'E' => &mut self.accel_overrides.jerk_e,
+                'S' => {
+                    ensure_own!(
+                        junction_deviation.is_none(),
+                        GCodeError::duplicate_argument(*arg, code)
+                    );
+                    junction_deviation = Some(arg.value as f64);
+                    continue;
+                }
+                _ => bail_own!(GCodeError::unknown_argument(*arg, code)),
+            };
+            ensure_own!(over.is_none(), GCodeError::duplicate_argument(*arg, code));
+            *over = Some(arg.value as f64);
+        }
+        if let Some(junction_deviation) = junction_deviation {
+            self.planner.set_junction_deviation(junction_deviation);
+        }
+        Ok(())
+    }
+
+    /// Executes M420 command: toggles bed-mesh compensation on/off
+    ///
+    /// Supported arguments: `S` (`0` to disable, `1` to enable)
+    ///
+    /// # Errors
+    /// At least one argument must be present, otherwise [`GCodeError::MissingArguments`]
+    /// will be returned. [`GCodeError::UnknownArgument`] is returned for any
+    /// argument other than `S`, and for an `S` value other than `0`/`1`.
+    fn m420(&mut self, code: GCode) -> GCodeResult<()> {
+        assert_code!(code, Miscellaneous, 420, 0);
+        ensure_own!(
+            !code.arguments().is_empty(),
+            GCodeError::missing_arguments(code)
+        );
+        let mut enabled = None;
+        for arg in code.arguments().iter() {
+            match arg.letter {
+                'S' => {
+                    ensure_own!(
+                        enabled.is_none(),
+                        GCodeError::duplicate_argument(*arg, code)
+                    );
+                    ensure_own!(
+                        arg.value == 0.0 || arg.value == 1.0,
+                        GCodeError::unknown_argument(*arg, code)
+                    );
+                    enabled = Some(arg.value != 0.0);
+                }
+                _ => bail_own!(GCodeError::unknown_argument(*arg, code)),
+            }
+        }
+        self.state.gcode.mesh_enabled = enabled.unwrap();
+        Ok(())
+    }
+
     /// Executes M104 command
     ///
     /// Supported arguments: `S`
@@ -632,12 +2402,13 @@ impl Decoder {
         let cfg = &self.settings.config().hotend;
         let (target, code) = extract_temp_from_code(code, cfg.lower_limit, cfg.upper_limit)?;
         self.state.gcode.hotend_target_temp = target;
+        let timeout = cfg.wait_timeout.map(Duration::from_secs);
         let mut dq = VecDeque::with_capacity(2);
         dq.push_back((
             Action::HotendTarget(self.state.gcode.hotend_target_temp),
             code.clone(),
         ));
-        dq.push_back((Action::WaitHotendTarget, code));
+        dq.push_back((Action::WaitHotendTarget(timeout), code));
         Ok(dq)
     }
 
@@ -659,20 +2430,44 @@ impl Decoder {
         assert_code!(code, Miscellaneous, 190, 0);
         let cfg = &self.settings.config().bed;
         let (temp, code) = extract_temp_from_code(code, cfg.lower_limit, cfg.upper_limit)?;
-        Ok((Action::WaitBedMinTemp(temp), code))
+        let timeout = cfg.wait_timeout.map(Duration::from_secs);
+        Ok((Action::WaitBedMinTemp(temp, timeout), code))
+    }
+
+    /// Executes M106 command
+    ///
+    /// Supported arguments: `S` (PWM, 0-255; missing `S` means full speed, as
+    /// per the usual gcode convention)
+    fn m106(&mut self, code: GCode) -> GCodeResult<(Action, GCode)> {
+        assert_code!(code, Miscellaneous, 106, 0);
+        let mut speed = None;
+        for arg in code.arguments().iter() {
+            match arg.letter {
+                'S' => {
+                    ensure_own!(speed.is_none(), GCodeError::duplicate_argument(*arg, code));
+                    speed = Some(arg.value.clamp(0.0, 255.0) as u8);
+                }
+                _ => bail_own!(GCodeError::unknown_argument(*arg, code)),
+            }
+        }
+        self.state.gcode.fan_speed = speed.unwrap_or(255);
+        Ok((Action::FanSpeed(self.state.gcode.fan_speed), code))
+    }
+
+    /// Executes M107 command
+    fn m107(&mut self, code: GCode) -> GCodeResult<(Action, GCode)> {
+        assert_code!(code, Miscellaneous, 107, 0);
+        ensure_own!(
+            code.arguments().is_empty(),
+            GCodeError::unknown_argument(code.arguments()[0], code)
+        );
+        self.state.gcode.fan_speed = 0;
+        Ok((Action::FanSpeed(0), code))
     }
 
     // Necessary GCode TODO:
     // G28
     //
-    // Optional GCode TODO:
-    // G10
-    // G11
-    // G2
-    // G3
-    // G10, for offsets
-    // G29?
-    //
     // Not-possible GCodes:
     // G30
     // G32
@@ -695,7 +2490,7 @@ impl Decoder {
     pub fn decode(&mut self, code: GCode) -> GCodeResult<Option<VecDeque<(Action, GCode)>>> {
         trace!(
             target: target::INTERNAL,
-            feedrate = self.state.gcode.feedrate,
+            feedrate = ?self.state.gcode.feedrate,
             "Decoding {}",
             code,
         );
@@ -707,19 +2502,42 @@ impl Decoder {
             }};
         }
         // since we don't implement any minor numbers:
-        ensure_own!(code.minor_number() == 0, GCodeError::UnknownCode(code));
-        match code.mnemonic() {
+        ensure_own!(code.minor_number() == 0, GCodeError::unknown_code(code));
+        // every code other than a G0/G1/G2/G3 move acts as a flush boundary
+        // for the look-ahead planner, so any moves still buffered in it
+        // always execute before whatever this code produces
+        let is_move = matches!(code.mnemonic(), Mnemonic::General)
+            && matches!(code.major_number(), 0 | 1 | 2 | 3);
+        let mut flushed = if is_move {
+            VecDeque::new()
+        } else {
+            self.planner.flush()
+        };
+        let profile_key = (code.mnemonic(), code.major_number());
+        let profile_start = self.profiler.is_enabled().then(Instant::now);
+        let dispatch_result = match code.mnemonic() {
             Mnemonic::General => match code.major_number() {
-                0 => self.g0(code).map(|a| Some(vecdq![a])),
-                1 => self.g1(code).map(|a| Some(vecdq![a])),
+                0 => self.g0(code).map(Some),
+                1 => self.g1(code).map(Some),
+                2 => self.g2_3(code, true).map(Some),
+                3 => self.g2_3(code, false).map(Some),
                 4 => self.g4(code).map(|a| Some(vecdq![a])),
+                10 => self.g10(code).map(Some),
+                11 => self.g11(code).map(Some),
+                17 => self.g17(code).map(|_| None),
+                18 => self.g18(code).map(|_| None),
+                19 => self.g19(code).map(|_| None),
                 20 => self.g20(code).map(|_| None),
                 21 => self.g21(code).map(|_| None),
                 28 => self.g28(code).map(|dq| Some(dq)),
+                29 => self.g29(code).map(|a| Some(vecdq![a])),
+                60 => self.g60(code).map(|_| None),
+                61 => self.g61(code).map(Some),
+                54..=59 => self.select_workplace(code).map(|_| None),
                 90 => self.g90(code).map(|_| None),
                 91 => self.g91(code).map(|_| None),
                 92 => self.g92(code).map(|_| None),
-                _ => bail_own!(GCodeError::UnknownCode(code)),
+                _ => self.dispatch_extension(code),
             },
             Mnemonic::Miscellaneous => match code.major_number() {
                 82 => self.m82(code).map(|_| None),
@@ -728,40 +2546,193 @@ impl Decoder {
                 // do that afaik
                 84 => Ok(None),
                 104 => self.m104(code).map(|a| Some(vecdq![a])),
-                // M106 and M107 don't need to do anything because control of
-                // the fan happens automatically because why wouldn't it?
-                // (safer for the machine and all...)
-                106 => Ok(None),
-                // see M106
-                107 => Ok(None),
+                106 => self.m106(code).map(|a| Some(vecdq![a])),
+                107 => self.m107(code).map(|a| Some(vecdq![a])),
                 109 => self.m109(code).map(|dq| Some(dq)),
                 140 => self.m140(code).map(|a| Some(vecdq![a])),
                 190 => self.m190(code).map(|a| Some(vecdq![a])),
-                _ => bail_own!(GCodeError::UnknownCode(code)),
+                204 => self.m204(code).map(|_| None),
+                205 => self.m205(code).map(|_| None),
+                420 => self.m420(code).map(|_| None),
+                _ => self.dispatch_extension(code),
             },
-            Mnemonic::ToolChange => match code.major_number() {
-                // T0 doesn't need to do anything, we can't change tools anyways
-                0 => Ok(None),
-                _ => bail_own!(GCodeError::UnknownCode(code)),
-            },
-            _ => bail_own!(GCodeError::UnknownCode(code)),
+            Mnemonic::ToolChange => self.tool_change(code).map(|_| None),
+            _ => self.dispatch_extension(code),
+        };
+        if let Some(start) = profile_start {
+            self.profiler.record(profile_key, start.elapsed());
         }
+        let result = dispatch_result?;
+        if let Some(actions) = result {
+            flushed.extend(actions);
+        }
+        let result = (!flushed.is_empty()).then_some(flushed);
         // FIXME https://github.com/rust-lang/rust/issues/91345
-        .map(|ok| {
+        Ok(result).map(|ok| {
             trace!(target: target::INTERNAL, "Decoded to {:?}", ok);
             ok
         })
     }
 
     /// Will reset values like the feedrate which should only persist in one
-    /// run
+    /// run, as well as any `M204`/`M205` overrides, since those shouldn't
+    /// carry over into the next job either
     // FIXME actual_pos might not match the actual real position of the printer,
     // which might then cause it to error out once the next gcode is started
     pub fn reset(&mut self) {
-        self.state.reset()
+        self.state.reset();
+        self.accel_overrides = AccelOverrides::default();
+        self.planner
+            .set_junction_deviation(self.settings.config().decode.junction_deviation);
     }
 
     pub fn state(self) -> State {
         self.state
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{
+            AxisMotor, Bed, BedMesh, BuildVolume, Config, Decode, ExtruderMotor, Hotend, Motors,
+            Park, Retraction, Tooling,
+        },
+        settings,
+    };
+    use nanotec_stepper_driver::{RotationDirection, StepMode};
+    use std::{io::Cursor, path::PathBuf};
+
+    fn test_axis_motor(limit: u32) -> AxisMotor {
+        AxisMotor {
+            address: 1,
+            translation: 8.0,
+            step_size: StepMode::default(),
+            quickstop_ramp: 1,
+            limit,
+            speed_limit: 1_000_000,
+            accel_limit: 3_000_000,
+            decel_limit: 3_000_000,
+            accel_jerk_limit: 100_000_000,
+            decel_jerk_limit: 100_000_000,
+            endstop_direction: RotationDirection::Left,
+            default_reference_speed: 1_000,
+            default_reference_accel: 50_000,
+            default_reference_jerk: 100_000,
+            reference_speed_slow: 200,
+            home_backoff: 2.0,
+            home_probes: 0,
+            default_probe_speed: 200,
+            default_probe_accel: 5_000,
+            default_probe_jerk: 10_000,
+            default_probe_step: 0.1,
+        }
+    }
+
+    fn test_settings() -> Settings {
+        let config = Config {
+            general: Default::default(),
+            log: Default::default(),
+            api: Default::default(),
+            socket: Default::default(),
+            errors: Default::default(),
+            motors: Motors {
+                driver: Default::default(),
+                backend: Default::default(),
+                position_error_rate: 0.0,
+                driver_error_rate: 0.0,
+                port: String::from("/dev/null"),
+                baud_rate: 115_200,
+                timeout: 1,
+                x: test_axis_motor(1_000),
+                y: test_axis_motor(1_000),
+                z: test_axis_motor(1_000),
+                e: ExtruderMotor {
+                    address: 4,
+                    positive_direction: RotationDirection::Left,
+                    translation: 4.0,
+                    step_size: StepMode::default(),
+                    quickstop_ramp: 1,
+                    speed_limit: 1_000_000,
+                    accel_limit: 3_000_000,
+                    decel_limit: 3_000_000,
+                    accel_jerk_limit: 100_000_000,
+                    decel_jerk_limit: 100_000_000,
+                },
+            },
+            pi: Default::default(),
+            gpio: Default::default(),
+            execute: Default::default(),
+            checkpoint: Default::default(),
+            gcode: Default::default(),
+            decode: Decode::default(),
+            retraction: Retraction::default(),
+            bed_mesh: BedMesh::default(),
+            tooling: Tooling::default(),
+            build_volume: BuildVolume::default(),
+            park: Park::default(),
+            macros: Default::default(),
+            telemetry: Default::default(),
+            mqtt: None,
+            hotend: Hotend {
+                upper_limit: 250,
+                lower_limit: 40,
+                wait_timeout: None,
+                pid: Default::default(),
+                filter: Default::default(),
+            },
+            bed: Bed {
+                upper_limit: 100,
+                lower_limit: 40,
+                wait_timeout: None,
+                pid: Default::default(),
+                filter: Default::default(),
+            },
+        };
+        settings::settings(config).expect("test config should build valid settings")
+    }
+
+    // decodes every code in `gcode`, driving the decoder's state the same way
+    // a real gcode source would
+    fn decode_all(decoder: &mut Decoder, gcode: &str) {
+        let mut parser = super::super::parser::Parser::new(
+            Cursor::new(gcode.as_bytes().to_vec()),
+            PathBuf::from("test"),
+        );
+        loop {
+            let codes = parser.try_n(16).expect("test gcode should parse");
+            if codes.is_empty() {
+                break;
+            }
+            for code in codes {
+                decoder.decode(code).expect("test gcode should decode");
+            }
+        }
+    }
+
+    #[test]
+    fn relative_moves_reach_the_same_steps_as_the_equivalent_absolute_move() {
+        let settings = test_settings();
+
+        let mut relative = Decoder::new(settings.clone(), -5.0);
+        decode_all(
+            &mut relative,
+            "G91\n\
+             G1 X1 Y2 Z0 F600\n\
+             G1 X1 Y2 Z0 F600\n\
+             G1 X1 Y2 Z0 F600\n\
+             G1 X1 Y2 Z0 F600\n\
+             G1 X1 Y2 Z0 F600\n",
+        );
+
+        let mut absolute = Decoder::new(settings, -5.0);
+        decode_all(&mut absolute, "G90\nG1 X5 Y10 Z0 F600\n");
+
+        let relative_state = relative.state();
+        let absolute_state = absolute.state();
+        assert_eq!(relative_state.actual.steps_x, absolute_state.actual.steps_x);
+        assert_eq!(relative_state.actual.steps_y, absolute_state.actual.steps_y);
+        assert_eq!(relative_state.actual.steps_z, absolute_state.actual.steps_z);
+    }
+}
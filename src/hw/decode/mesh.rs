@@ -0,0 +1,169 @@
+use crate::config::BedMesh;
+
+/// Bilinearly-interpolated Z offset for `(x, y)` from `mesh`
+///
+/// Points outside the mesh's bounds are clamped to the nearest edge cell
+/// instead of extrapolated. Returns `0.0` for a mesh with fewer than two
+/// rows/columns, or with zero-size bounds, since there's nothing sensible to
+/// interpolate between.
+pub fn z_offset(mesh: &BedMesh, x: f64, y: f64) -> f64 {
+    let rows = mesh.heights.len();
+    let cols = mesh.heights.first().map_or(0, Vec::len);
+    if rows < 2 || cols < 2 || mesh.max_x <= mesh.min_x || mesh.max_y <= mesh.min_y {
+        return 0.0;
+    }
+
+    let col_f = ((x - mesh.min_x) / (mesh.max_x - mesh.min_x) * (cols - 1) as f64)
+        .clamp(0.0, (cols - 1) as f64);
+    let row_f = ((y - mesh.min_y) / (mesh.max_y - mesh.min_y) * (rows - 1) as f64)
+        .clamp(0.0, (rows - 1) as f64);
+    let col0 = (col_f.floor() as usize).min(cols - 2);
+    let row0 = (row_f.floor() as usize).min(rows - 2);
+    let tx = col_f - col0 as f64;
+    let ty = row_f - row0 as f64;
+
+    let z00 = mesh.heights[row0][col0];
+    let z10 = mesh.heights[row0][col0 + 1];
+    let z01 = mesh.heights[row0 + 1][col0];
+    let z11 = mesh.heights[row0 + 1][col0 + 1];
+
+    (1.0 - tx) * (1.0 - ty) * z00 + tx * (1.0 - ty) * z10 + (1.0 - tx) * ty * z01 + tx * ty * z11
+}
+
+/// Fractions (strictly between `0.0` and `1.0`, ascending) along the straight
+/// XY segment from `(x0, y0)` to `(x1, y1)` where it crosses a mesh row or
+/// column boundary
+///
+/// A single [`z_offset`] lookup at each endpoint only captures the plane of
+/// the cell(s) the endpoints happen to fall in; splitting the segment at
+/// every boundary it crosses lets a caller interpolate through the
+/// intermediate cells too, instead of jumping straight from the start
+/// height to the end one.
+///
+/// Empty for a degenerate mesh (see [`z_offset`]) or a segment that doesn't
+/// cross any boundary; boundaries outside the mesh's bounds are ignored,
+/// matching [`z_offset`]'s clamp-to-edge-cell behaviour there.
+pub fn split_fractions(mesh: &BedMesh, x0: f64, y0: f64, x1: f64, y1: f64) -> Vec<f64> {
+    let rows = mesh.heights.len();
+    let cols = mesh.heights.first().map_or(0, Vec::len);
+    if rows < 2 || cols < 2 || mesh.max_x <= mesh.min_x || mesh.max_y <= mesh.min_y {
+        return Vec::new();
+    }
+
+    // fractions along (v0, v1) where it crosses one of the `count` grid
+    // lines 1..count-1 (the mesh's own edges at 0/count-1 are never crossed
+    // from inside its bounds, so they're excluded)
+    fn crossings(v0: f64, v1: f64, count: usize) -> Vec<f64> {
+        if (v1 - v0).abs() < f64::EPSILON {
+            return Vec::new();
+        }
+        let (lo, hi) = (v0.min(v1), v0.max(v1));
+        let first = lo.floor() as i64 + 1;
+        let last = hi.ceil() as i64 - 1;
+        (first..=last)
+            .filter(|line| *line > 0 && (*line as usize) + 1 < count)
+            .map(|line| (line as f64 - v0) / (v1 - v0))
+            .collect()
+    }
+
+    let col0 = (x0 - mesh.min_x) / (mesh.max_x - mesh.min_x) * (cols - 1) as f64;
+    let col1 = (x1 - mesh.min_x) / (mesh.max_x - mesh.min_x) * (cols - 1) as f64;
+    let row0 = (y0 - mesh.min_y) / (mesh.max_y - mesh.min_y) * (rows - 1) as f64;
+    let row1 = (y1 - mesh.min_y) / (mesh.max_y - mesh.min_y) * (rows - 1) as f64;
+
+    let mut fractions: Vec<f64> = crossings(col0, col1, cols)
+        .into_iter()
+        .chain(crossings(row0, row1, rows))
+        .filter(|f| *f > 0.0 && *f < 1.0)
+        .collect();
+    fractions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    fractions.dedup_by(|a, b| (*a - *b).abs() < f64::EPSILON);
+    fractions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_mesh() -> BedMesh {
+        BedMesh {
+            min_x: 0.0,
+            min_y: 0.0,
+            max_x: 200.0,
+            max_y: 200.0,
+            heights: vec![
+                vec![0.0, 0.1, 0.2],
+                vec![0.1, 0.2, 0.3],
+                vec![0.2, 0.3, 0.4],
+            ],
+        }
+    }
+
+    #[test]
+    fn on_node_returns_exact_height() {
+        let mesh = test_mesh();
+        assert_eq!(z_offset(&mesh, 0.0, 0.0), 0.0);
+        assert_eq!(z_offset(&mesh, 100.0, 100.0), 0.2);
+        assert_eq!(z_offset(&mesh, 200.0, 200.0), 0.4);
+    }
+
+    #[test]
+    fn on_edge_interpolates_along_one_axis() {
+        let mesh = test_mesh();
+        // halfway along the bottom row, between z00 (0.0) and z10 (0.1)
+        assert!((z_offset(&mesh, 50.0, 0.0) - 0.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn outside_mesh_clamps_to_nearest_cell() {
+        let mesh = test_mesh();
+        assert_eq!(z_offset(&mesh, -50.0, -50.0), z_offset(&mesh, 0.0, 0.0));
+        assert_eq!(z_offset(&mesh, 500.0, 500.0), z_offset(&mesh, 200.0, 200.0));
+    }
+
+    #[test]
+    fn degenerate_mesh_returns_zero() {
+        let mesh = BedMesh {
+            min_x: 0.0,
+            min_y: 0.0,
+            max_x: 0.0,
+            max_y: 0.0,
+            heights: vec![],
+        };
+        assert_eq!(z_offset(&mesh, 10.0, 10.0), 0.0);
+    }
+
+    #[test]
+    fn split_fractions_finds_every_column_and_row_crossing() {
+        let mesh = test_mesh();
+        // crosses the column-1 line (x=100) and the row-1 line (y=100)
+        let fractions = split_fractions(&mesh, 0.0, 0.0, 200.0, 200.0);
+        assert_eq!(fractions.len(), 1);
+        assert!((fractions[0] - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn split_fractions_within_one_cell_is_empty() {
+        let mesh = test_mesh();
+        assert!(split_fractions(&mesh, 10.0, 10.0, 90.0, 90.0).is_empty());
+    }
+
+    #[test]
+    fn split_fractions_outside_mesh_ignores_crossing() {
+        let mesh = test_mesh();
+        // entirely beyond max_x/max_y, clamped to the same edge cell throughout
+        assert!(split_fractions(&mesh, 250.0, 250.0, 300.0, 300.0).is_empty());
+    }
+
+    #[test]
+    fn split_fractions_degenerate_mesh_is_empty() {
+        let mesh = BedMesh {
+            min_x: 0.0,
+            min_y: 0.0,
+            max_x: 0.0,
+            max_y: 0.0,
+            heights: vec![],
+        };
+        assert!(split_fractions(&mesh, 0.0, 0.0, 10.0, 10.0).is_empty());
+    }
+}
@@ -1,25 +1,54 @@
+mod cache;
+mod codec;
 pub mod error;
-mod file_decoder;
+mod extension;
+mod fixed;
 mod inner_decoder;
+mod mesh;
 mod parser;
+mod planner;
+mod profiler;
+mod stream_decoder;
 mod threaded_decoder;
+mod uring_reader;
 
 pub use self::{
-    file_decoder::FileDecoder,
+    codec::GCodeCodec,
+    error::GCodeErrorKind,
+    extension::ExtensionDataStore,
     inner_decoder::State,
     parser::{GCode, GCodeSpan, ParserError, ParsingError},
+    profiler::{Accumulator, Profiler},
+    stream_decoder::{FileDecoder, StreamDecoder},
     threaded_decoder::ThreadedDecoder,
 };
 use super::GCodeError;
-use crate::comms::{Axis, ReferenceRunOptParameters};
+use crate::{
+    api::values::ErrorCode,
+    comms::{Axis, ReferenceRunOptParameters},
+};
 use anyhow::Result;
 use nanotec_stepper_driver::RotationDirection;
+use serde_json::Value;
 use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Debug, Default)]
 pub struct AxisMovement {
     pub distance: i32,
+    // step frequency (hz) to enter/exit this move at; the look-ahead
+    // planner resolves both from a standstill (decode::planner) down to
+    // whatever junction speed it shares with its neighbours
+    //
+    // the Nanotec backend (motors::mod) does send this to the motor before
+    // every move, but since it also blocks for that move's own completion
+    // status before returning, the motor has already decelerated to a full
+    // stop by the time the next move's commands go out -- so on real
+    // hardware this currently only ever takes effect from a standstill, same
+    // as the old `min_frequency: 1` placeholder it replaced. Carrying actual
+    // velocity across a move boundary needs the driver's continuation-record
+    // chaining (see `set_continuation_record` in motors::mod), which isn't
+    // wired up yet.
     pub min_frequency: u32,
     pub max_frequency: u32,
     // accel and decel are in hz/s
@@ -33,6 +62,7 @@ pub struct AxisMovement {
 pub struct ExtruderMovement {
     pub direction: RotationDirection,
     pub distance: u32,
+    // see AxisMovement::min_frequency/max_frequency
     pub min_frequency: u32,
     pub max_frequency: u32,
     // accel and decel are in hz/s
@@ -59,18 +89,54 @@ pub enum Action {
     // only allows referencing the z axis into the endstop direction
     // referencing it into the hotend direction can only be done manually
     ReferenceAxis(Axis, ReferenceRunOptParameters),
+    // slowly lowers z until it stalls against the print head, see
+    // `g28`/`Motors::probe_z_hotend`
+    ProbeZHotend(ReferenceRunOptParameters),
     HotendTarget(Option<u16>),
     BedTarget(Option<u16>),
-    WaitHotendTarget,
-    WaitBedTarget,
-    WaitBedMinTemp(Option<u16>),
+    // timeout is how long to wait before giving up, aborting the print and
+    // turning the heaters off; None waits forever
+    WaitHotendTarget(Option<Duration>),
+    WaitBedTarget(Option<Duration>),
+    WaitBedMinTemp(Option<u16>, Option<Duration>),
     Wait(Duration),
+    // probes the bed-mesh grid configured in `bed_mesh`
+    ProbeMesh,
+    // part-cooling fan PWM, 0-255; see `ThermalBackend::set_fan_speed`
+    FanSpeed(u8),
 }
 
 pub trait Decoder: Iterator<Item = Result<(Action, GCode), DecoderError>> {
     fn state(self) -> State;
 }
 
+/// Coarse classification of a [`DecoderError`], letting a host decide
+/// whether it's safe to skip the offending line and keep printing or
+/// whether the job has to be aborted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecoderErrorKind {
+    /// the line itself couldn't be parsed; not even a gcode to skip
+    ParseError,
+    /// a code is missing, duplicates or misuses an argument; skipping the
+    /// line and continuing is usually safe
+    ArgumentError,
+    /// the requested motion or target would violate a configured kinematic
+    /// or thermal limit; continuing risks damaging the printer
+    LimitViolation,
+    /// the mnemonic isn't implemented by this decoder
+    UnsupportedCode,
+}
+
+impl From<GCodeErrorKind> for DecoderErrorKind {
+    fn from(kind: GCodeErrorKind) -> Self {
+        match kind {
+            GCodeErrorKind::ArgumentError => Self::ArgumentError,
+            GCodeErrorKind::LimitViolation => Self::LimitViolation,
+            GCodeErrorKind::UnsupportedCode => Self::UnsupportedCode,
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum DecoderError {
     #[error("Error while parsing: {}", .0)]
@@ -78,3 +144,31 @@ pub enum DecoderError {
     #[error("Error while decoding: {}", .0)]
     GCodeError(#[from] GCodeError),
 }
+
+impl DecoderError {
+    /// Whether a host can skip the offending line and keep printing, or has
+    /// to abort the job; see [`DecoderErrorKind`]
+    pub fn kind(&self) -> DecoderErrorKind {
+        match self {
+            Self::ParserError(_) => DecoderErrorKind::ParseError,
+            Self::GCodeError(e) => e.kind().into(),
+        }
+    }
+
+    /// A stable, machine-readable code for this error, so API clients can
+    /// branch on the kind of failure instead of matching on `text`
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::ParserError(_) => ErrorCode::Other,
+            Self::GCodeError(e) => e.code(),
+        }
+    }
+
+    /// Structured, code-specific details, e.g. the violated bound
+    pub fn details(&self) -> Option<Value> {
+        match self {
+            Self::ParserError(_) => None,
+            Self::GCodeError(e) => e.details(),
+        }
+    }
+}
@@ -1,10 +1,13 @@
+use super::cache;
 use crate::log::target;
 use anyhow::Result;
 use gcode::{full_parse_with_callbacks, Callbacks, GCode as InnerGCode, Mnemonic, Span, Word};
+use serde::{Deserialize, Serialize};
 use std::{
     collections::VecDeque,
     fmt::{self, Display},
-    io::{BufRead, BufReader, Error as IoError, Lines, Read},
+    io::{Error as IoError, ErrorKind as IoErrorKind, Read},
+    mem,
     path::PathBuf,
     sync::Arc,
 };
@@ -37,53 +40,166 @@ impl GCodeSpan {
     }
 }
 
+// holds either the code as the `gcode` crate parsed it, or one rebuilt from
+// a cache sidecar (see `super::cache`); kept as an enum rather than always
+// re-wrapping a fresh `InnerGCode` since that type only comes from actually
+// parsing a line, not from deserializing one
+#[derive(Debug, Clone)]
+enum GCodeRepr {
+    Parsed(InnerGCode),
+    Cached {
+        mnemonic: Mnemonic,
+        major_number: u32,
+        minor_number: u32,
+        arguments: Vec<Word>,
+    },
+}
+
 #[derive(Debug, Clone)]
 pub struct GCode {
-    code: InnerGCode,
-    line_offset: usize,
+    repr: GCodeRepr,
+    line: usize,
     origin: Arc<PathBuf>,
 }
 
+fn mnemonic_letter(mnemonic: Mnemonic) -> char {
+    match mnemonic {
+        Mnemonic::General => 'G',
+        Mnemonic::Miscellaneous => 'M',
+        Mnemonic::ToolChange => 'T',
+    }
+}
+
+// round-trips a `Mnemonic` through `CachedCode` without pulling in a
+// `FromStr`/`Display` impl this crate doesn't own
+fn mnemonic_tag(mnemonic: Mnemonic) -> u8 {
+    match mnemonic {
+        Mnemonic::General => 0,
+        Mnemonic::Miscellaneous => 1,
+        Mnemonic::ToolChange => 2,
+    }
+}
+
+fn mnemonic_from_tag(tag: u8) -> Option<Mnemonic> {
+    match tag {
+        0 => Some(Mnemonic::General),
+        1 => Some(Mnemonic::Miscellaneous),
+        2 => Some(Mnemonic::ToolChange),
+        _ => None,
+    }
+}
+
+/// The plain-data shape [`GCode`] serializes to/from for the gcode cache
+/// sidecar; see [`super::cache`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct CachedCode {
+    mnemonic: u8,
+    major_number: u32,
+    minor_number: u32,
+    arguments: Vec<(char, f32)>,
+    line: usize,
+}
+
 impl Display for GCode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{} from {}:{}",
-            self.code,
-            self.origin.display(),
-            self.code.span().line + self.line_offset + 1
-        )
+        match &self.repr {
+            GCodeRepr::Parsed(code) => {
+                write!(f, "{code} from {}:{}", self.origin.display(), self.line)
+            }
+            GCodeRepr::Cached {
+                mnemonic,
+                major_number,
+                minor_number,
+                arguments,
+            } => {
+                write!(f, "{}{major_number}", mnemonic_letter(*mnemonic))?;
+                if *minor_number != 0 {
+                    write!(f, ".{minor_number}")?;
+                }
+                for arg in arguments {
+                    write!(f, " {}{}", arg.letter, arg.value)?;
+                }
+                write!(f, " from {}:{}", self.origin.display(), self.line)
+            }
+        }
     }
 }
 
 impl GCode {
     fn new(code: InnerGCode, line_offset: usize, origin: Arc<PathBuf>) -> Self {
+        let line = code.span().line + line_offset + 1;
         Self {
-            code,
-            line_offset,
+            repr: GCodeRepr::Parsed(code),
+            line,
+            origin,
+        }
+    }
+
+    /// Rebuilds a [`GCode`] from its cached representation, for a
+    /// [`super::cache::load`] hit
+    pub(super) fn from_cached(cached: CachedCode, origin: Arc<PathBuf>) -> Option<Self> {
+        Some(Self {
+            repr: GCodeRepr::Cached {
+                mnemonic: mnemonic_from_tag(cached.mnemonic)?,
+                major_number: cached.major_number,
+                minor_number: cached.minor_number,
+                arguments: cached
+                    .arguments
+                    .into_iter()
+                    .map(|(letter, value)| Word { letter, value })
+                    .collect(),
+            },
+            line: cached.line,
             origin,
+        })
+    }
+
+    /// The cached representation of this [`GCode`], for [`super::cache::save`]
+    pub(super) fn to_cached(&self) -> CachedCode {
+        CachedCode {
+            mnemonic: mnemonic_tag(self.mnemonic()),
+            major_number: self.major_number(),
+            minor_number: self.minor_number(),
+            arguments: self
+                .arguments()
+                .iter()
+                .map(|w| (w.letter, w.value))
+                .collect(),
+            line: self.line,
         }
     }
 
     pub fn mnemonic(&self) -> Mnemonic {
-        self.code.mnemonic()
+        match &self.repr {
+            GCodeRepr::Parsed(code) => code.mnemonic(),
+            GCodeRepr::Cached { mnemonic, .. } => *mnemonic,
+        }
     }
 
     pub fn major_number(&self) -> u32 {
-        self.code.major_number()
+        match &self.repr {
+            GCodeRepr::Parsed(code) => code.major_number(),
+            GCodeRepr::Cached { major_number, .. } => *major_number,
+        }
     }
 
     pub fn minor_number(&self) -> u32 {
-        self.code.minor_number()
+        match &self.repr {
+            GCodeRepr::Parsed(code) => code.minor_number(),
+            GCodeRepr::Cached { minor_number, .. } => *minor_number,
+        }
     }
 
     pub fn arguments(&self) -> &[Word] {
-        self.code.arguments()
+        match &self.repr {
+            GCodeRepr::Parsed(code) => code.arguments(),
+            GCodeRepr::Cached { arguments, .. } => arguments,
+        }
     }
 
     pub fn span(&self) -> GCodeSpan {
         GCodeSpan {
-            line: self.code.span().line + self.line_offset + 1,
+            line: self.line,
             path: Arc::clone(&self.origin),
         }
     }
@@ -185,6 +301,83 @@ impl Callbacks for &mut UnforgivingCallbacks {
     }
 }
 
+/// Like [`UnforgivingCallbacks`], but collects every [`ParsingError`]
+/// instead of bailing at the first one, for [`Parser::validate`]
+///
+/// Unlike `UnforgivingCallbacks`, `path` doesn't need the take-once trick:
+/// since an error here doesn't stop parsing, more than one may need a
+/// `GCodeSpan` built from it, so it just stays clonable throughout.
+#[derive(Debug)]
+struct ForgivingCallbacks {
+    path: Arc<PathBuf>,
+    errors: Vec<ParsingError>,
+}
+
+impl ForgivingCallbacks {
+    pub fn new(path: Arc<PathBuf>) -> Self {
+        Self {
+            path,
+            errors: Vec::new(),
+        }
+    }
+}
+
+macro_rules! push_err {
+    ($self:ident, $err:ident, $span:ident) => {{
+        $self.errors.push(ParsingError::$err(GCodeSpan::new(
+            Arc::clone(&$self.path),
+            $span.line,
+        )))
+    }};
+}
+
+impl Callbacks for &mut ForgivingCallbacks {
+    fn unknown_content(&mut self, _text: &str, span: Span) {
+        push_err!(self, UnknownContent, span)
+    }
+
+    fn gcode_buffer_overflowed(
+        &mut self,
+        _mnemonic: gcode::Mnemonic,
+        _major_number: u32,
+        _minor_number: u32,
+        _arguments: &[gcode::Word],
+        _span: Span,
+    ) {
+        panic!("gcode buffer overflowed, even though it is a Vec")
+    }
+
+    fn gcode_argument_buffer_overflowed(
+        &mut self,
+        _mnemonic: gcode::Mnemonic,
+        _major_number: u32,
+        _minor_number: u32,
+        _argument: gcode::Word,
+    ) {
+        panic!("gcode argument buffer overflowed, even though it is a Vec")
+    }
+
+    fn comment_buffer_overflow(&mut self, _comment: gcode::Comment<'_>) {
+        panic!("comment buffer overflowed, even though it is a Vec")
+    }
+
+    fn unexpected_line_number(&mut self, _line_number: f32, span: Span) {
+        push_err!(self, UnexpectedLineNumber, span)
+    }
+
+    fn argument_without_a_command(&mut self, _letter: char, _value: f32, span: Span) {
+        push_err!(self, ArgumentWithoutCommand, span)
+    }
+
+    fn number_without_a_letter(&mut self, _value: &str, span: Span) {
+        push_err!(self, NumberWithoutLetter, span)
+    }
+
+    fn letter_without_a_number(&mut self, _value: &str, span: Span) {
+        push_err!(self, LetterWithoutNumebr, span)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum ParserError {
     #[error(transparent)]
@@ -193,20 +386,32 @@ pub enum ParserError {
     ParsingError(#[from] ParsingError),
 }
 
+/// The push-based core of [`Parser`]: owns nothing but a byte buffer and a
+/// queue of decoded [`GCode`]s, with no opinion on where its bytes come
+/// from
+///
+/// [`Self::feed`] is the only way in; it appends to the buffer, splits off
+/// and parses every complete line it now contains, and leaves whatever
+/// trailing partial line is left for the next `feed` to complete. This is
+/// what lets [`Parser`] sit on a blocking [`Read`] (pulling its own bytes
+/// in `try_n`/`validate`) while also being usable directly against
+/// something that only hands out bytes as they arrive, like a streamed
+/// upload.
 #[derive(Debug)]
-pub struct Parser<R: Read> {
-    reader: Lines<BufReader<R>>,
+pub(super) struct IncrementalParser {
+    buf: Vec<u8>,
+    ready: VecDeque<GCode>,
     next_line: usize,
     callbacks: UnforgivingCallbacks,
     path: Arc<PathBuf>,
     prev_err: bool,
 }
 
-impl<R: Read> Parser<R> {
-    pub fn new(reader: R, path: PathBuf) -> Self {
-        let path = Arc::new(path);
+impl IncrementalParser {
+    pub(super) fn new(path: Arc<PathBuf>) -> Self {
         Self {
-            reader: BufReader::new(reader).lines(),
+            buf: Vec::new(),
+            ready: VecDeque::new(),
             next_line: 1,
             callbacks: UnforgivingCallbacks::new(Arc::clone(&path)),
             path,
@@ -214,49 +419,261 @@ impl<R: Read> Parser<R> {
         }
     }
 
-    /// Tries to parse the next n lines from the gcode file
+    /// Pops the oldest line parsed so far off [`Self::ready`], for a caller
+    /// driving this incrementally (e.g. [`super::codec::GCodeCodec`])
+    /// instead of all at once via [`Parser::try_n`]
+    pub(super) fn pop_ready(&mut self) -> Option<GCode> {
+        self.ready.pop_front()
+    }
+
+    /// Appends `bytes`, parsing and enqueuing every complete line they
+    /// complete in [`Self::ready`]
     ///
     /// # Panics
     /// Will panic if it gets called after an error was previously thrown
-    pub fn try_n(&mut self, n: usize) -> Result<VecDeque<GCode>, ParserError> {
+    pub(super) fn feed(&mut self, bytes: &[u8]) -> Result<(), ParserError> {
         assert!(!self.prev_err, "error previously occured in this parser");
-        // could in theory be more than n but it's bound to be ~n
-        let mut codes = VecDeque::with_capacity(n);
-        let line_n_start = self.next_line;
-        let line_n_end = line_n_start + n;
-        self.next_line = line_n_end;
+        self.buf.extend_from_slice(bytes);
+        while let Some(pos) = self.buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=pos).collect();
+            self.parse_line(&line[..line.len() - 1])?;
+        }
+        Ok(())
+    }
+
+    /// Parses whatever's left in the buffer as a final, newline-less line,
+    /// once the underlying source has reached EOF
+    pub(super) fn finish(&mut self) -> Result<(), ParserError> {
+        if !self.buf.is_empty() {
+            let line = mem::take(&mut self.buf);
+            self.parse_line(&line)?;
+        }
+        Ok(())
+    }
+
+    fn parse_line(&mut self, line: &[u8]) -> Result<(), ParserError> {
+        let line = match std::str::from_utf8(line) {
+            Ok(line) => line,
+            Err(_) => {
+                self.prev_err = true;
+                return Err(
+                    IoError::new(IoErrorKind::InvalidData, "invalid utf-8 in gcode line").into(),
+                );
+            }
+        };
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let i = self.next_line;
+        self.next_line += 1;
+        self.ready.extend(
+            full_parse_with_callbacks(line, &mut self.callbacks)
+                .next()
+                .unwrap()
+                .gcodes()
+                .into_iter()
+                .map(|code| GCode::new(code.clone(), i, Arc::clone(&self.path))),
+        );
+        if let Some(e) = self.callbacks.check_err() {
+            self.prev_err = true;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+// a configurable-size chunk pulled from `R` at a time large enough that a
+// typical gcode file doesn't need many reads, small enough that it doesn't
+// matter if most of it goes unused by a partial last chunk
+const DEFAULT_CAPACITY: usize = 64 * 1024;
+
+// either bytes still need to be pulled from the file/stream and fed in, or
+// the already decoded stream loaded from a cache sidecar (see
+// `super::cache`)
+#[derive(Debug)]
+enum ParserSource<R: Read> {
+    Live(R),
+    Cached(VecDeque<GCode>),
+}
+
+#[derive(Debug)]
+pub struct Parser<R: Read> {
+    source: ParserSource<R>,
+    incremental: IncrementalParser,
+    // reused every call instead of allocated fresh, since it's sized for
+    // exactly this purpose
+    scratch: Vec<u8>,
+    eof: bool,
+    path: Arc<PathBuf>,
+    // every `GCode` parsed so far this session, so it can be written out to
+    // the cache sidecar once the file's fully read; `None` if this parser
+    // isn't tracking a cache at all (plain `new`, or a cache hit that
+    // doesn't need rewriting)
+    pending_cache: Option<Vec<GCode>>,
+}
+
+impl<R: Read> Parser<R> {
+    pub fn new(reader: R, path: PathBuf) -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY, reader, path)
+    }
+
+    /// Like [`Self::new`], but reads `reader` in `capacity`-sized chunks
+    /// instead of [`DEFAULT_CAPACITY`], to cut down on syscalls/allocations
+    /// against a particularly large file
+    pub fn with_capacity(capacity: usize, reader: R, path: PathBuf) -> Self {
+        Self::from_parts(capacity, ParserSource::Live(reader), Arc::new(path), None)
+    }
+
+    /// Like [`Self::new`], but first checks for a gcode cache sidecar next
+    /// to `path` (see [`super::cache`]) and, if it's fresh, yields its
+    /// already-decoded [`GCode`]s directly instead of re-parsing `reader`
+    /// line by line
+    ///
+    /// Falls back to a normal parse of `reader` - and rewrites the cache
+    /// once it's fully read - on any miss.
+    pub fn from_cache_or_new(reader: R, path: PathBuf) -> Self {
+        let path = Arc::new(path);
+        if let Some(codes) = cache::load(&path) {
+            debug!(target: target::INTERNAL, "using gcode cache for {}", path.display());
+            return Self::from_parts(DEFAULT_CAPACITY, ParserSource::Cached(codes), path, None);
+        }
+        Self::from_parts(
+            DEFAULT_CAPACITY,
+            ParserSource::Live(reader),
+            path,
+            Some(Vec::new()),
+        )
+    }
+
+    fn from_parts(
+        capacity: usize,
+        source: ParserSource<R>,
+        path: Arc<PathBuf>,
+        pending_cache: Option<Vec<GCode>>,
+    ) -> Self {
+        Self {
+            source,
+            incremental: IncrementalParser::new(Arc::clone(&path)),
+            scratch: vec![0; capacity],
+            eof: false,
+            path,
+            pending_cache,
+        }
+    }
+
+    /// Tries to parse the next n lines from the gcode file, reading and
+    /// [`IncrementalParser::feed`]-ing further chunks of `reader` as
+    /// needed
+    ///
+    /// # Panics
+    /// Will panic if it gets called after an error was previously thrown
+    pub fn try_n(&mut self, n: usize) -> Result<VecDeque<GCode>, ParserError> {
+        let reader = match &mut self.source {
+            ParserSource::Cached(codes) => {
+                let n = n.min(codes.len());
+                return Ok(codes.drain(..n).collect());
+            }
+            ParserSource::Live(reader) => reader,
+        };
+        while self.incremental.ready.len() < n && !self.eof {
+            let read = reader.read(&mut self.scratch)?;
+            if read == 0 {
+                self.eof = true;
+                self.incremental.finish()?;
+            } else {
+                self.incremental.feed(&self.scratch[..read])?;
+            }
+        }
+        let take = n.min(self.incremental.ready.len());
         debug!(
             target: target::INTERNAL,
-            "Parsing lines {} to {} of {}",
-            line_n_start,
-            line_n_end,
+            "Parsed {} gcodes of {}",
+            take,
             self.path.display()
         );
-        for i in line_n_start..self.next_line {
-            if let Some(line) = self.reader.next() {
-                let line = match line {
-                    Ok(line) => line,
-                    Err(e) => {
-                        self.prev_err = true;
-                        return Err(e.into());
-                    }
-                };
-                codes.extend(
-                    full_parse_with_callbacks(&line, &mut self.callbacks)
-                        .next()
-                        .unwrap()
-                        .gcodes()
-                        .into_iter()
-                        .map(|code| GCode::new(code.clone(), i, Arc::clone(&self.path))),
-                );
-                if let Some(e) = self.callbacks.check_err() {
-                    self.prev_err = true;
-                    return Err(e.into());
-                }
-            } else {
-                return Ok(codes);
+        let codes: VecDeque<GCode> = self.incremental.ready.drain(..take).collect();
+        if let Some(pending) = &mut self.pending_cache {
+            pending.extend(codes.iter().cloned());
+        }
+        if self.eof && self.incremental.ready.is_empty() {
+            if let Some(pending) = self.pending_cache.take() {
+                cache::save(&self.path, &pending);
             }
         }
         Ok(codes)
     }
+
+    /// Parses every remaining line up to EOF in one pass, collecting every
+    /// [`ParsingError`] along the way instead of bailing at the first one,
+    /// like an assembler emitting a fault list rather than stopping at the
+    /// first bad instruction
+    ///
+    /// Meant for validating an uploaded job before it's actually printed:
+    /// unlike `try_n`, this never sets `prev_err`, so it's only sound to
+    /// call on a `Parser` that isn't also being driven through `try_n`. A
+    /// line that fails to even read (e.g. invalid UTF-8) ends the pass
+    /// early, since there's no `GCodeSpan` to blame for an IO error.
+    pub fn validate(&mut self) -> (VecDeque<GCode>, Vec<ParsingError>) {
+        let reader = match &mut self.source {
+            // already validated when it was originally parsed and cached
+            ParserSource::Cached(codes) => return (mem::take(codes), Vec::new()),
+            ParserSource::Live(reader) => reader,
+        };
+        let mut callbacks = ForgivingCallbacks::new(Arc::clone(&self.path));
+        let mut codes = VecDeque::new();
+        let mut buf = Vec::new();
+        let mut next_line = self.incremental.next_line;
+        let mut scratch = vec![0; self.scratch.len()];
+        loop {
+            let read = match reader.read(&mut scratch) {
+                Ok(read) => read,
+                Err(_) => break,
+            };
+            if read == 0 {
+                if !buf.is_empty() {
+                    validate_line(&buf, &mut next_line, &mut callbacks, &self.path, &mut codes);
+                }
+                break;
+            }
+            buf.extend_from_slice(&scratch[..read]);
+            while let Some(pos) = buf.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buf.drain(..=pos).collect();
+                if !validate_line(
+                    &line[..line.len() - 1],
+                    &mut next_line,
+                    &mut callbacks,
+                    &self.path,
+                    &mut codes,
+                ) {
+                    return (codes, callbacks.errors);
+                }
+            }
+        }
+        (codes, callbacks.errors)
+    }
+}
+
+// parses one already-delimited line for `Parser::validate`, returning
+// `false` if it isn't valid UTF-8 (ending the pass early, same as an
+// unreadable line)
+fn validate_line(
+    line: &[u8],
+    next_line: &mut usize,
+    callbacks: &mut ForgivingCallbacks,
+    path: &Arc<PathBuf>,
+    codes: &mut VecDeque<GCode>,
+) -> bool {
+    let Ok(line) = std::str::from_utf8(line) else {
+        return false;
+    };
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let i = *next_line;
+    *next_line += 1;
+    codes.extend(
+        full_parse_with_callbacks(line, callbacks)
+            .next()
+            .unwrap()
+            .gcodes()
+            .into_iter()
+            .map(|code| GCode::new(code.clone(), i, Arc::clone(path))),
+    );
+    true
 }
@@ -0,0 +1,248 @@
+use super::{parser::GCode, Action, Movement};
+use std::collections::VecDeque;
+
+// how many consecutive moves to buffer for look-ahead before they're forced
+// through the junction-velocity passes, even if more keep arriving right
+// behind them
+const WINDOW: usize = 32;
+
+// dot product of two consecutive moves' unit direction vectors above
+// which the moves are considered collinear enough to not limit the corner
+// speed at all, sidestepping the near-zero denominator the formula below
+// would otherwise hit
+const COLLINEAR_DOT: f64 = 0.9999;
+
+#[derive(Debug)]
+struct PendingMove {
+    movement: Movement,
+    code: GCode,
+    // unit direction of travel in XYZ mm-space; None for a zero-length move
+    // (e.g. a pure E retraction), which can't be meaningfully angled against
+    // its neighbours
+    direction: Option<[f64; 3]>,
+    // move length in mm
+    distance: f64,
+    // cruise speed implied by the gcode's feedrate, in mm/s
+    nominal_speed: f64,
+    // per-axis (x, y, z) acceleration limit this move ended up with, in mm/s^2
+    acceleration: [f64; 3],
+}
+
+impl PendingMove {
+    /// The move's acceleration limit along its own direction of travel: the
+    /// most restrictive of `acceleration[a] / |direction[a]|` over every axis
+    /// the move actually displaces along, since that axis reaches its own
+    /// accel limit first otherwise
+    ///
+    /// Falls back to the most restrictive axis outright for a zero-length
+    /// move, which has no direction to project onto.
+    fn path_acceleration(&self) -> f64 {
+        let axis_limit = match self.direction {
+            Some(direction) => (0..3)
+                .filter(|&a| direction[a].abs() > f64::EPSILON)
+                .map(|a| self.acceleration[a] / direction[a].abs())
+                .fold(f64::INFINITY, f64::min),
+            None => f64::INFINITY,
+        };
+        if axis_limit.is_finite() {
+            axis_limit
+        } else {
+            self.acceleration.into_iter().fold(f64::INFINITY, f64::min)
+        }
+    }
+}
+
+/// Buffers consecutive move [`Action`]s and assigns them non-zero junction
+/// entry/exit speeds via a junction-deviation look-ahead, so consecutive
+/// moves don't decelerate all the way to a standstill at every corner
+///
+/// Moves are [`push`][Self::push]ed in as they're decoded and held back
+/// until [`flush`][Self::flush] runs (buffer full, a non-move action got
+/// decoded, or the gcode source ran dry), at which point their entry/exit
+/// step-rates are finalized and handed back in order.
+#[derive(Debug)]
+pub struct Planner {
+    junction_deviation: f64,
+    window: VecDeque<PendingMove>,
+}
+
+impl Planner {
+    pub fn new(junction_deviation: f64) -> Self {
+        Self {
+            junction_deviation,
+            window: VecDeque::with_capacity(WINDOW),
+        }
+    }
+
+    /// Overrides the junction-deviation used for future junction-speed
+    /// calculations, e.g. from a runtime `M205 S`
+    ///
+    /// Doesn't touch moves already buffered in the window; they were pushed
+    /// with whatever acceleration they were decoded with regardless, so
+    /// re-resolving their junction speed here would be inconsistent anyway.
+    pub fn set_junction_deviation(&mut self, junction_deviation: f64) {
+        self.junction_deviation = junction_deviation;
+    }
+
+    /// Buffers a freshly decoded move, flushing the window first if it was
+    /// already full
+    ///
+    /// `direction` is the move's XYZ delta in mm (not normalized), `distance`
+    /// its length in mm, `nominal_speed` the cruise speed implied by the
+    /// gcode's feedrate in mm/s, and `acceleration` its per-axis (x, y, z)
+    /// acceleration limit in mm/s^2.
+    pub fn push(
+        &mut self,
+        movement: Movement,
+        code: GCode,
+        direction: [f64; 3],
+        distance: f64,
+        nominal_speed: f64,
+        acceleration: [f64; 3],
+    ) -> VecDeque<(Action, GCode)> {
+        let ready = if self.window.len() >= WINDOW {
+            self.flush()
+        } else {
+            VecDeque::new()
+        };
+        let direction = (distance > f64::EPSILON).then(|| {
+            [
+                direction[0] / distance,
+                direction[1] / distance,
+                direction[2] / distance,
+            ]
+        });
+        self.window.push_back(PendingMove {
+            movement,
+            code,
+            direction,
+            distance,
+            nominal_speed,
+            acceleration,
+        });
+        ready
+    }
+
+    /// Resolves junction entry/exit speeds for every move currently in the
+    /// window and hands them all back in order, emptying the window
+    ///
+    /// The very first move's entry and the very last move's exit are both
+    /// assumed to be a standstill, since nothing is known about whatever
+    /// comes before/after the window.
+    pub fn flush(&mut self) -> VecDeque<(Action, GCode)> {
+        let len = self.window.len();
+        if len == 0 {
+            return VecDeque::new();
+        }
+
+        let mut entry: Vec<f64> = self.window.iter().map(|m| m.nominal_speed).collect();
+        let mut exit = entry.clone();
+        // each move's accel limit along its own direction of travel,
+        // resolved once up front since both the junction clamp below and the
+        // backward/forward passes need it
+        let path_accel: Vec<f64> = self
+            .window
+            .iter()
+            .map(PendingMove::path_acceleration)
+            .collect();
+        // clamp every junction to the corner speed the junction-deviation
+        // model allows, as well as to both sides' own cruise speed
+        for i in 0..len - 1 {
+            let a = &self.window[i];
+            let b = &self.window[i + 1];
+            let v_j = match (a.direction, b.direction) {
+                (Some(d1), Some(d2)) => junction_speed(
+                    d1,
+                    d2,
+                    path_accel[i].min(path_accel[i + 1]),
+                    self.junction_deviation,
+                ),
+                _ => f64::INFINITY,
+            };
+            let junction = v_j.min(a.nominal_speed).min(b.nominal_speed);
+            exit[i] = exit[i].min(junction);
+            entry[i + 1] = entry[i + 1].min(junction);
+        }
+        // window boundaries: we don't know what's beyond them, so come to a
+        // full stop at both ends
+        entry[0] = 0.0;
+        exit[len - 1] = 0.0;
+
+        // reverse pass: can't enter a move faster than decelerating at its
+        // own accel limit over its own distance would allow, given its exit
+        // speed
+        for i in (0..len).rev() {
+            let m = &self.window[i];
+            entry[i] = entry[i].min((exit[i].powi(2) + 2.0 * path_accel[i] * m.distance).sqrt());
+            if i > 0 {
+                exit[i - 1] = exit[i - 1].min(entry[i]);
+            }
+        }
+        // forward pass: can't exit a move faster than accelerating at its
+        // own accel limit over its own distance would allow, given its entry
+        // speed
+        for i in 0..len {
+            let m = &self.window[i];
+            exit[i] = exit[i].min((entry[i].powi(2) + 2.0 * path_accel[i] * m.distance).sqrt());
+            if i + 1 < len {
+                entry[i + 1] = entry[i + 1].min(exit[i]);
+            }
+        }
+
+        self.window
+            .drain(..)
+            .zip(entry)
+            .zip(exit)
+            .map(|((m, entry), exit)| finalize(m, entry, exit))
+            .collect()
+    }
+}
+
+/// The max speed (in mm/s) the junction between two moves with unit
+/// direction vectors `d1`/`d2` can be taken at, via the junction-deviation
+/// model: `v_j = sqrt(a * δ * sin(θ/2) / (1 − sin(θ/2)))`, where `θ` is the
+/// corner's interior angle between the reversed incoming direction and the
+/// outgoing one (π for two moves continuing in a straight line, 0 for a full
+/// reversal) and `δ` is `junction_deviation`.
+fn junction_speed(d1: [f64; 3], d2: [f64; 3], acceleration: f64, junction_deviation: f64) -> f64 {
+    let dot = (d1[0] * d2[0] + d1[1] * d2[1] + d1[2] * d2[2]).clamp(-1.0, 1.0);
+    if dot >= COLLINEAR_DOT {
+        return f64::INFINITY;
+    }
+    // sin(θ/2) via the half-angle identity sin(x/2) = sqrt((1 - cos(x)) / 2),
+    // using cos(θ) = −dot since θ is the corner's interior angle, the
+    // supplement of the angle directly between d1 and d2
+    let sin_half = ((1.0 + dot) / 2.0).sqrt();
+    (acceleration * junction_deviation * sin_half / (1.0 - sin_half)).sqrt()
+}
+
+/// Scales a move's per-axis cruise frequencies down to its resolved
+/// entry/exit speed and turns it back into the `(Action, GCode)` the rest of
+/// the decoder deals in
+fn finalize(m: PendingMove, entry: f64, exit: f64) -> (Action, GCode) {
+    let PendingMove {
+        mut movement,
+        code,
+        nominal_speed,
+        ..
+    } = m;
+    let (entry_frac, exit_frac) = if nominal_speed > f64::EPSILON {
+        (entry / nominal_speed, exit / nominal_speed)
+    } else {
+        (0.0, 0.0)
+    };
+
+    macro_rules! scale {
+        ($axis:expr) => {{
+            let cruise = $axis.max_frequency as f64;
+            $axis.min_frequency = ((cruise * entry_frac).round() as u32).max(1);
+            $axis.max_frequency = ((cruise * exit_frac).round() as u32).max(1);
+        }};
+    }
+    scale!(movement.x);
+    scale!(movement.y);
+    scale!(movement.z);
+    scale!(movement.e);
+
+    (Action::MoveAll(movement), code)
+}
@@ -0,0 +1,81 @@
+use gcode::Mnemonic;
+use serde_json::{json, Value};
+use std::{collections::HashMap, time::Duration};
+
+/// Per-code timing totals collected by [`Profiler`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Accumulator {
+    pub calls: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl Accumulator {
+    fn record(&mut self, elapsed: Duration) {
+        self.calls += 1;
+        self.total += elapsed;
+        if elapsed > self.max {
+            self.max = elapsed;
+        }
+    }
+}
+
+/// Optional, low-overhead self-profiler for
+/// [`Decoder::decode`][super::inner_decoder::Decoder::decode], recording a
+/// timed interval for every command it dispatches, keyed by
+/// mnemonic/major number
+///
+/// Disabled by default so normal operation pays no `Instant::now` cost;
+/// enable it with [`Self::enable`] to find which codes dominate processing
+/// time on constrained hardware.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    enabled: bool,
+    stats: HashMap<(Mnemonic, u32), Accumulator>,
+}
+
+impl Profiler {
+    pub fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    pub fn disable(&mut self) {
+        self.enabled = false;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(super) fn record(&mut self, key: (Mnemonic, u32), elapsed: Duration) {
+        self.stats.entry(key).or_default().record(elapsed);
+    }
+
+    /// Per-code stats collected so far, sorted by total time descending so
+    /// the hottest codes come first
+    pub fn report(&self) -> Vec<(Mnemonic, u32, Accumulator)> {
+        let mut report: Vec<_> = self
+            .stats
+            .iter()
+            .map(|(&(mnemonic, major), &acc)| (mnemonic, major, acc))
+            .collect();
+        report.sort_by(|a, b| b.2.total.cmp(&a.2.total));
+        report
+    }
+
+    /// Same as [`Self::report`], serialized as a JSON array so it can be
+    /// shipped over the API without a bespoke response type
+    pub fn report_json(&self) -> Value {
+        json!(self
+            .report()
+            .into_iter()
+            .map(|(mnemonic, major, acc)| json!({
+                "mnemonic": format!("{mnemonic:?}"),
+                "major_number": major,
+                "calls": acc.calls,
+                "total_nanos": acc.total.as_nanos() as u64,
+                "max_nanos": acc.max.as_nanos() as u64,
+            }))
+            .collect::<Vec<_>>())
+    }
+}
@@ -0,0 +1,205 @@
+use super::{
+    error::GCodeError,
+    inner_decoder::Decoder as InnerDecoder,
+    parser::{GCode, Parser, ParserError},
+    uring_reader::UringFileReader,
+    Accumulator, Action, Decoder, DecoderError, State,
+};
+use crate::settings::Settings;
+use anyhow::Result;
+use gcode::Mnemonic;
+use serde_json::Value;
+use std::{collections::VecDeque, fs::File, io::Read, path::PathBuf};
+
+const BUFSIZE: usize = 512;
+
+/// A [`Decoder`] generic over where its gcode bytes come from, instead of
+/// requiring a local file: anything implementing [`Read`] works, so a print
+/// can be decoded straight off a TCP socket or a host serial link
+/// (OctoPrint-style) just as easily as off disk
+///
+/// [`FileDecoder`] is a thin alias of this over [`UringFileReader`], keeping
+/// the `io_uring` read-ahead and gcode cache sidecar (see [`super::cache`])
+/// that only make sense for an actual local file; a plain stream has no path
+/// to cache against and no reason to read ahead of the sender, so it's built
+/// through [`Self::new`] instead of [`FileDecoder::with_state`].
+pub struct StreamDecoder<R: Read> {
+    parser: Parser<R>,
+    buf: VecDeque<(Action, GCode)>,
+    decoder: InnerDecoder,
+}
+
+impl<R: Read> StreamDecoder<R> {
+    /// Decodes `reader`'s gcode directly, without checking for or writing a
+    /// cache sidecar; `path` is only used to label errors/spans, since `R`
+    /// isn't necessarily seekable or local
+    pub fn new(settings: Settings, state: State, reader: R, path: PathBuf) -> Self {
+        Self {
+            parser: Parser::new(reader, path),
+            buf: VecDeque::with_capacity(BUFSIZE),
+            decoder: InnerDecoder::with_state(settings, state),
+        }
+    }
+
+    /// Discards every already-decoded action up to (but not including)
+    /// `line`, replaying each one through the decoder without executing it
+    /// so things like position and feedrate stay correct
+    ///
+    /// Used to resume a checkpointed print partway through its file: the
+    /// checkpoint only remembers the line it got to, so the decoder has to
+    /// be fast-forwarded back to that point before real actions are handed
+    /// out again.
+    ///
+    /// # Errors
+    /// On failure, returns the decoder's [`State`] as of the last
+    /// successfully applied line alongside the error, so a caller can fall
+    /// back to that instead of losing track of where the machine actually is.
+    pub fn fast_forward_to(mut self, line: usize) -> Result<Self, (DecoderError, State)> {
+        loop {
+            if self.buf.front().is_none() {
+                if let Err(e) = self.check_buffer() {
+                    return Err((e, self.decoder.state()));
+                }
+            }
+            match self.buf.front() {
+                Some((_, code)) if code.span().line() < line => {
+                    self.buf.pop_front();
+                }
+                // either we've reached `line`, or the file ran out before we
+                // did; either way there's nothing more to skip
+                _ => break,
+            }
+        }
+        Ok(self)
+    }
+
+    /// Registers `handler` for `mnemonic`/`major_number`, so this decoder
+    /// can support a code it doesn't implement itself; see
+    /// [`InnerDecoder::register`]
+    ///
+    /// Has to happen before this [`StreamDecoder`] is handed off to a
+    /// [`ThreadedDecoder`][super::ThreadedDecoder], since that moves it onto
+    /// its own thread and there's no channel to register a handler through
+    /// afterwards.
+    pub fn register(
+        &mut self,
+        mnemonic: Mnemonic,
+        major_number: u32,
+        handler: impl FnMut(&mut State, &GCode) -> Result<Option<VecDeque<(Action, GCode)>>, GCodeError>
+            + Send
+            + 'static,
+    ) {
+        self.decoder.register(mnemonic, major_number, handler);
+    }
+
+    /// Turns on the self-profiler; see [`InnerDecoder::enable_profiling`]
+    pub fn enable_profiling(&mut self) {
+        self.decoder.enable_profiling();
+    }
+
+    /// Turns off the self-profiler; see [`InnerDecoder::disable_profiling`]
+    pub fn disable_profiling(&mut self) {
+        self.decoder.disable_profiling();
+    }
+
+    /// Per-code timing stats collected so far; see
+    /// [`InnerDecoder::profile_report`]
+    pub fn profile_report(&self) -> Vec<(Mnemonic, u32, Accumulator)> {
+        self.decoder.profile_report()
+    }
+
+    /// Same as [`Self::profile_report`], serialized as JSON
+    pub fn profile_report_json(&self) -> Value {
+        self.decoder.profile_report_json()
+    }
+
+    fn check_buffer(&mut self) -> Result<(), DecoderError> {
+        // a batch of nothing but moves can be held back by the planner in
+        // its entirety, so keep reading until something is ready or the
+        // source runs dry
+        while self.buf.is_empty() {
+            // TODO opitmise
+            let Ok(codes) = self.parser.try_n(BUFSIZE) else {
+                break;
+            };
+            if codes.is_empty() {
+                // no more lines left to read: the planner will never see
+                // another move to flush on, so drain whatever it's still
+                // holding onto now
+                self.buf.extend(self.decoder.flush_planner());
+                break;
+            }
+            for code in codes.into_iter() {
+                if let Some(actions) = self.decoder.decode(code)? {
+                    self.buf.extend(actions);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StreamDecoder<UringFileReader> {
+    /// # Errors
+    /// Returns a [`DecoderError`] if opening the given path fails or its
+    /// `io_uring` read-ahead can't be set up, alongside back the `state`
+    /// passed in, untouched, so a caller that can't proceed doesn't also
+    /// lose track of where the machine actually is
+    pub fn with_state(
+        settings: Settings,
+        state: State,
+        path: PathBuf,
+    ) -> Result<Self, (DecoderError, State)> {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) => return Err((DecoderError::from(ParserError::from(e)), state)),
+        };
+        Self::with_state_and_file(settings, state, file, path)
+    }
+
+    /// # Errors
+    /// Returns a [`DecoderError`] if `file`'s `io_uring` read-ahead can't be
+    /// set up, alongside the `state` passed in, untouched, so a caller that
+    /// can't proceed doesn't also lose track of where the machine actually is
+    pub fn with_state_and_file(
+        settings: Settings,
+        state: State,
+        file: File,
+        path: PathBuf,
+    ) -> Result<Self, (DecoderError, State)> {
+        let reader = match UringFileReader::new(file) {
+            Ok(reader) => reader,
+            Err(e) => return Err((DecoderError::from(ParserError::from(e)), state)),
+        };
+        Ok(Self {
+            parser: Parser::from_cache_or_new(reader, path),
+            buf: VecDeque::with_capacity(BUFSIZE),
+            decoder: InnerDecoder::with_state(settings, state),
+        })
+    }
+}
+
+impl<R: Read> Decoder for StreamDecoder<R> {
+    fn state(self) -> State {
+        self.decoder.state()
+    }
+}
+
+impl<R: Read> Iterator for StreamDecoder<R> {
+    type Item = Result<(Action, GCode), DecoderError>;
+
+    /// Tries to get the next (Action, GCode) tuple and if necessary reads it from
+    /// the file/stream and decodes it
+    ///
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.check_buffer() {
+            return Some(Err(e));
+        }
+        self.buf.pop_front().map(|a| Ok(a))
+    }
+}
+
+/// A [`StreamDecoder`] over a local file, with `io_uring` read-ahead and a
+/// gcode cache sidecar; see [`StreamDecoder`]'s docs for why those aren't
+/// available to a generic `R`
+pub type FileDecoder = StreamDecoder<UringFileReader>;
@@ -7,6 +7,13 @@ use std::{
     thread::{self, JoinHandle},
 };
 
+// the bounded channel below is exactly the backpressure boundary this is
+// here for: the decoder thread keeps `decoder.next()` (parsing + decoding,
+// including any blocking file read) running ahead on its own thread, filling
+// this many actions into `gcode_send` before it blocks on a full channel;
+// `Executor`'s consumer side (`executor_loop`) only ever does a plain
+// `recv`/`try_recv` off `action_recv()`, so a slow/large file stalls the
+// decoder thread, not the one issuing steps
 const BUFSIZE: usize = 32;
 
 enum DecoderExitComms {
@@ -43,6 +50,11 @@ fn decoder_loop<D: Decoder>(
     }
 }
 
+/// Drives a [`Decoder`] (e.g. [`super::FileDecoder`]) to completion on a
+/// dedicated background thread, so its own blocking I/O and parsing never
+/// runs on the caller's thread; actions flow out through a bounded
+/// `crossbeam` channel (see [`BUFSIZE`]) that the caller just `recv`s off of,
+/// acting as a double-buffered prefetch queue between the two
 pub struct ThreadedDecoder<D: Decoder + Send + 'static> {
     // needs to be in an Option in order to implement drop and Decoder::state
     // If Decoder::state gets called we take the handle out of the option and call
@@ -88,6 +100,15 @@ impl<D: Decoder + Send + 'static> Decoder for ThreadedDecoder<D> {
     }
 }
 
+impl<D: Decoder + Send + 'static> ThreadedDecoder<D> {
+    /// The raw channel actions are delivered on, for callers that need to
+    /// `select!` on it alongside other channels instead of just pulling the
+    /// next action via [`Iterator::next`]
+    pub fn action_recv(&self) -> &Receiver<Result<(Action, GCode), DecoderError>> {
+        &self.gcode_recv
+    }
+}
+
 impl<D: Decoder + Send + 'static> Iterator for ThreadedDecoder<D> {
     type Item = Result<(Action, GCode), DecoderError>;
 
@@ -0,0 +1,181 @@
+use crate::log::target;
+use io_uring::{opcode, types, IoUring};
+use std::{
+    fs::File,
+    io::{self, Read},
+    mem,
+    os::unix::io::AsRawFd,
+};
+use tracing::warn;
+
+// tags the cancellation op's own completion so it's distinguishable from the
+// read completion it's cancelling; `submit_read` only ever tags with 0/1
+// (the buffer index), so this can't collide with one of those
+const CANCEL_USER_DATA: u64 = u64::MAX;
+
+// large enough that a typical gcode line never straddles more than two
+// reads, small enough that two of them in flight at once isn't a problem
+const BUF_SIZE: usize = 64 * 1024;
+
+/// A [`Read`] over a [`File`] that keeps the next chunk's `io_uring` read
+/// already submitted while whatever was read before it is being parsed
+///
+/// The decoder thread still blocks when it actually runs out of buffered
+/// bytes, but by then the next read has usually already completed in the
+/// background, so disk latency overlaps with parsing instead of stalling it;
+/// see [`super::FileDecoder`].
+pub struct UringFileReader {
+    file: File,
+    ring: IoUring,
+    buffers: [Box<[u8]>; 2],
+    // which of `buffers` is currently being drained by `read`
+    active: usize,
+    active_len: usize,
+    active_pos: usize,
+    // absolute file offset the next submitted read should start at
+    next_offset: u64,
+    eof: bool,
+}
+
+impl UringFileReader {
+    pub fn new(file: File) -> io::Result<Self> {
+        let mut this = Self {
+            file,
+            ring: IoUring::new(2)?,
+            buffers: [
+                vec![0u8; BUF_SIZE].into_boxed_slice(),
+                vec![0u8; BUF_SIZE].into_boxed_slice(),
+            ],
+            active: 0,
+            active_len: 0,
+            active_pos: 0,
+            next_offset: 0,
+            eof: false,
+        };
+        this.submit_read(0)?;
+        this.complete_read()?;
+        if !this.eof {
+            this.submit_read(1)?;
+        }
+        Ok(this)
+    }
+
+    fn submit_read(&mut self, buf_idx: usize) -> io::Result<()> {
+        let fd = types::Fd(self.file.as_raw_fd());
+        let buf = &mut self.buffers[buf_idx];
+        let entry = opcode::Read::new(fd, buf.as_mut_ptr(), buf.len() as u32)
+            .offset(self.next_offset)
+            .build()
+            .user_data(buf_idx as u64);
+        // safety: `buf` stays alive and isn't touched again until the
+        // matching completion is reaped in `complete_read`
+        unsafe {
+            self.ring.submission().push(&entry).map_err(|_| {
+                io::Error::new(io::ErrorKind::Other, "io_uring submission queue is full")
+            })?;
+        }
+        self.ring.submit()?;
+        Ok(())
+    }
+
+    /// Blocks until the oldest still-outstanding read completes, making its
+    /// buffer the new active one
+    fn complete_read(&mut self) -> io::Result<()> {
+        self.ring.submitter().submit_and_wait(1)?;
+        let cqe = self
+            .ring
+            .completion()
+            .next()
+            .expect("a submitted read should have completed");
+        let n = cqe.result();
+        if n < 0 {
+            return Err(io::Error::from_raw_os_error(-n));
+        }
+        let n = n as usize;
+        self.active = cqe.user_data() as usize;
+        self.active_len = n;
+        self.active_pos = 0;
+        self.next_offset += n as u64;
+        self.eof = n == 0;
+        Ok(())
+    }
+
+    /// Leaks `buffers` instead of letting `Drop` free them as usual
+    ///
+    /// Only called when a cancellation couldn't be submitted or reaped, so
+    /// the kernel may still be writing into `buffers[1 - self.active]`;
+    /// losing that memory for the life of the process is the price of not
+    /// freeing it out from under an in-flight read.
+    fn leak_buffers(&mut self) {
+        let buffers = mem::replace(&mut self.buffers, [Box::default(), Box::default()]);
+        mem::forget(buffers);
+    }
+}
+
+impl Read for UringFileReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.active_pos == self.active_len {
+            if self.eof {
+                return Ok(0);
+            }
+            // the other buffer's read was submitted ahead of time in the
+            // previous call, so this usually just reaps an already-finished
+            // completion instead of waiting on a fresh one
+            self.complete_read()?;
+            if !self.eof {
+                self.submit_read(1 - self.active)?;
+            }
+        }
+        let available = &self.buffers[self.active][self.active_pos..self.active_len];
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.active_pos += n;
+        Ok(n)
+    }
+}
+
+impl Drop for UringFileReader {
+    /// The prefetch design always keeps one read submitted ahead of `read`
+    /// actually needing it (see `submit_read`'s call sites), so unless `eof`
+    /// was already reached there's still a read in flight against
+    /// `buffers[1 - self.active]` when this is dropped. It has to be
+    /// cancelled and reaped here before `buffers` is freed below, since the
+    /// kernel may otherwise still be writing into it after that memory is
+    /// gone.
+    fn drop(&mut self) {
+        if self.eof {
+            return;
+        }
+        let target_user_data = (1 - self.active) as u64;
+        let cancel = opcode::AsyncCancel::new(target_user_data)
+            .build()
+            .user_data(CANCEL_USER_DATA);
+        // safety: `AsyncCancel` doesn't reference any buffer, so there's
+        // nothing that needs to stay alive past this submission
+        let submitted = unsafe { self.ring.submission().push(&cancel) }.is_ok();
+        if !submitted || self.ring.submit().is_err() {
+            warn!(
+                target: target::INTERNAL,
+                "failed to submit cancellation for an in-flight io_uring read, leaking its buffers"
+            );
+            self.leak_buffers();
+            return;
+        }
+        // one completion for the cancelled read itself, one for the cancel
+        // op; both have to be reaped before `buffers` goes away
+        let mut reaped = 0;
+        while reaped < 2 {
+            match self.ring.submitter().submit_and_wait(1) {
+                Ok(_) => reaped += self.ring.completion().count(),
+                Err(e) => {
+                    warn!(
+                        target: target::INTERNAL,
+                        "failed to reap a cancelled io_uring read, leaking its buffers: {}", e
+                    );
+                    self.leak_buffers();
+                    break;
+                }
+            }
+        }
+    }
+}
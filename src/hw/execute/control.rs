@@ -1,23 +1,71 @@
-use super::{super::callbacks::StopCallback, ExecutorCtrlComms, ExecutorManualComms, SharedRawPos};
+use super::{
+    super::{callbacks::StopCallback, decode::GCodeSpan},
+    Breakpoint, Debugger, ExecutorCtrlComms, ExecutorManualComms, SharedRawPos,
+};
 use crate::{
     comms::{Axis, ControlComms, ReferenceRunOptParameters},
+    hw::{
+        callbacks::{PrintOutcome, StopReason},
+        comms::CancelReason,
+    },
+    log::target,
     settings::Settings,
     util::ensure_own,
 };
 use anyhow::{Context, Result};
 use atomic_float::AtomicF64;
 use crossbeam::channel::Sender;
+use futures::channel::oneshot;
 use std::{
     fs::File,
     mem::ManuallyDrop,
     path::PathBuf,
     sync::{
         atomic::{AtomicUsize, Ordering},
-        Arc,
+        Arc, Mutex,
     },
     thread::JoinHandle,
 };
 use thiserror::Error;
+use tracing::warn;
+
+/// A [`StopCallback`] that reports a print's outcome through a oneshot
+/// channel instead of running arbitrary callback logic itself; backs
+/// [`ExecutorCtrl::print_with_handle`]
+///
+/// Only the first of `stop`/`fail` to run actually sends anything, since a
+/// print only ends once; the executor thread always calls `fail` (if it's
+/// going to call it at all) before the matching `stop`, so this only
+/// matters in case that ordering ever changes.
+pub struct OutcomeCallback(Mutex<Option<oneshot::Sender<PrintOutcome>>>);
+
+impl OutcomeCallback {
+    fn new(sender: oneshot::Sender<PrintOutcome>) -> Self {
+        Self(Mutex::new(Some(sender)))
+    }
+
+    fn send(&self, outcome: PrintOutcome) {
+        if let Some(sender) = self.0.lock().unwrap().take() {
+            // the receiving end not caring anymore isn't an error here
+            let _ = sender.send(outcome);
+        }
+    }
+}
+
+impl StopCallback for OutcomeCallback {
+    fn stop(&self, reason: StopReason) {
+        self.send(match reason {
+            StopReason::Finished => PrintOutcome::Completed,
+            StopReason::Cancelled => PrintOutcome::Stopped,
+        });
+    }
+
+    fn fail(&self, err: &anyhow::Error) {
+        // anyhow::Error isn't Clone, so the chain is re-rendered into a
+        // fresh one rather than passed through as-is
+        self.send(PrintOutcome::Failed(anyhow::anyhow!("{:#}", err)));
+    }
+}
 
 #[derive(Debug, Error)]
 #[error("{} was out of bounds, was {}, must be <= {}", .0, .1, .2)]
@@ -37,6 +85,14 @@ pub struct ExecutorCtrl {
     // location of the hotend on the z axis, assuming zero point is at endstop
     // shared with the executor thread, only to calculate the z position properly
     shared_z_hotend_location: Arc<AtomicF64>,
+    // interrupts a blocking Action::Wait (dwell or temperature hold) the
+    // executor thread is currently parked in; sent alongside Stop/Pause,
+    // since the executor thread can't poll executor_ctrl_send for itself
+    // until the wait it's stuck in returns
+    cancel_send: Sender<ControlComms<CancelReason>>,
+    // shared with the executor thread for as long as it runs, not just one
+    // print, same as `line`/`shared_pos` are
+    debugger: Arc<Mutex<Debugger>>,
 }
 
 impl ExecutorCtrl {
@@ -47,6 +103,8 @@ impl ExecutorCtrl {
         executor_manual_send: Sender<ExecutorManualComms>,
         shared_pos: SharedRawPos,
         shared_z_hotend_location: Arc<AtomicF64>,
+        cancel_send: Sender<ControlComms<CancelReason>>,
+        debugger: Arc<Mutex<Debugger>>,
     ) -> Self {
         Self {
             settings,
@@ -56,6 +114,8 @@ impl ExecutorCtrl {
             line: Arc::new(AtomicUsize::new(0)),
             shared_pos,
             shared_z_hotend_location,
+            cancel_send,
+            debugger,
         }
     }
 
@@ -65,6 +125,17 @@ impl ExecutorCtrl {
             .unwrap();
     }
 
+    /// Interrupts whatever blocking wait the executor thread might currently
+    /// be parked in, so `Stop`/`Pause` take effect immediately instead of
+    /// only once the wait naturally resolves
+    ///
+    /// `reason` is threaded all the way through to the executor loop, so it
+    /// can tell a `Pause` apart from a `Stop` and react accordingly instead
+    /// of always aborting the print outright
+    fn send_cancel(&self, reason: CancelReason) {
+        self.cancel_send.send(ControlComms::Msg(reason)).unwrap();
+    }
+
     // end_callback could in theory also be a generic but that would be
     // 1. a pain in the ass to properly pass around that generic
     // 2. since the callback is a new one every time, we could also allow
@@ -80,16 +151,113 @@ impl ExecutorCtrl {
         Ok(())
     }
 
+    /// Like [`Self::print`], but instead of taking a callback, returns a
+    /// [`oneshot::Receiver`] that resolves with a [`PrintOutcome`] once the
+    /// print ends, however it ends
+    ///
+    /// Lets an async caller (e.g. a Rocket handler) simply `.await` the
+    /// print instead of having to implement [`StopCallback`] itself.
+    pub fn print_with_handle(&self, path: PathBuf) -> Result<oneshot::Receiver<PrintOutcome>> {
+        let (sender, receiver) = oneshot::channel();
+        self.print(path, Box::new(OutcomeCallback::new(sender)))?;
+        Ok(receiver)
+    }
+
+    /// Runs a startup/idle/cancel gcode macro rather than a real print
+    ///
+    /// Unlike `print`, this doesn't share `current_line`/the api's state
+    /// info with the macro's progress, since macros aren't something a
+    /// client is expected to track
+    pub fn run_macro(&self, path: PathBuf) -> Result<()> {
+        let file = File::open(&path).context("failed to open macro gcode file")?;
+        self.send_executor_ctrl(ExecutorCtrlComms::RunMacro(
+            file,
+            path,
+            Arc::new(AtomicUsize::new(0)),
+        ));
+        Ok(())
+    }
+
+    /// Resumes a checkpointed print, fast-forwarding the decoder to
+    /// `start_line` instead of starting from the top of the file
+    pub fn resume(&self, path: PathBuf, start_line: usize) -> Result<()> {
+        let file = File::open(&path).context("failed to open gcode file")?;
+        self.send_executor_ctrl(ExecutorCtrlComms::Resume(
+            file,
+            path,
+            start_line,
+            Arc::clone(&self.line),
+        ));
+        Ok(())
+    }
+
     pub fn stop(&self) {
-        self.send_executor_ctrl(ExecutorCtrlComms::Stop)
+        self.send_executor_ctrl(ExecutorCtrlComms::Stop);
+        self.send_cancel(CancelReason::Stop);
     }
 
     pub fn play(&self) {
         self.send_executor_ctrl(ExecutorCtrlComms::Play)
     }
 
+    // FIXME ideally this would have the decoder take an automatic G60-style
+    // snapshot on pause and a matching G61 on the next play(), so a pause
+    // can't leave the programmed position out of sync with the physical
+    // one. That needs the decoder thread to accept a synchronous
+    // save/restore request, which `ThreadedDecoder`/`decoder_exit_send`
+    // don't support yet (only `Exit` and a one-shot final `State` handoff).
     pub fn pause(&self) {
-        self.send_executor_ctrl(ExecutorCtrlComms::Pause)
+        self.send_executor_ctrl(ExecutorCtrlComms::Pause);
+        self.send_cancel(CancelReason::Pause);
+    }
+
+    /// Executes exactly one already-queued action, then stays paused
+    ///
+    /// Only has an effect while paused; while printing or stopped it's a
+    /// no-op.
+    pub fn step(&self) {
+        self.send_executor_ctrl(ExecutorCtrlComms::Step)
+    }
+
+    pub fn set_breakpoint(&self, breakpoint: Breakpoint) {
+        self.debugger.lock().unwrap().set_breakpoint(breakpoint);
+    }
+
+    pub fn clear_breakpoint(&self, breakpoint: Breakpoint) {
+        self.debugger.lock().unwrap().clear_breakpoint(breakpoint);
+    }
+
+    pub fn breakpoints(&self) -> Vec<Breakpoint> {
+        self.debugger.lock().unwrap().breakpoints()
+    }
+
+    pub fn set_trace_only(&self, trace_only: bool) {
+        self.debugger.lock().unwrap().set_trace_only(trace_only);
+    }
+
+    pub fn trace_only(&self) -> bool {
+        self.debugger.lock().unwrap().trace_only()
+    }
+
+    /// Ignores breakpoints for the next `count` codes once execution
+    /// resumes; doesn't itself resume a print paused at a breakpoint, see
+    /// [`super::super::HwCtrl::try_debug_step`]
+    pub fn set_step_budget(&self, count: u32) {
+        self.debugger.lock().unwrap().step(count);
+    }
+
+    /// The span of the code a breakpoint is currently holding the print in
+    /// front of, if any
+    pub fn debug_span(&self) -> Option<GCodeSpan> {
+        self.debugger.lock().unwrap().current_span()
+    }
+
+    /// Swaps in a freshly re-read [`Settings`], e.g. after a config reload
+    ///
+    /// Only affects prints started after this call; a print already running
+    /// keeps using the `Settings` it was started with.
+    pub fn reload_settings(&self, settings: Settings) {
+        self.send_executor_ctrl(ExecutorCtrlComms::Reload(settings))
     }
 
     pub fn current_line(&self) -> usize {
@@ -170,10 +338,23 @@ impl ExecutorCtrl {
 impl Drop for ExecutorCtrl {
     fn drop(&mut self) {
         self.executor_ctrl_send.send(ControlComms::Exit).unwrap();
+        // in case the executor thread is currently parked in a blocking
+        // wait, so join() below doesn't hang until the wait naturally
+        // resolves; reason doesn't matter since the thread is exiting
+        // either way
+        self.send_cancel(CancelReason::Stop);
         // safety:
         // since we are in drop, self.executor_handle will not be used again
-        unsafe { ManuallyDrop::take(&mut self.executor_handle) }
+        //
+        // the executor thread already reports a panic of its own as an error
+        // and triggers an estop before unwinding (see `execute::start`), so a
+        // join error here just means we're shutting down anyway; warn
+        // instead of panicking a second time on top of it
+        if unsafe { ManuallyDrop::take(&mut self.executor_handle) }
             .join()
-            .unwrap();
+            .is_err()
+        {
+            warn!(target: target::INTERNAL, "executor thread panicked while exiting");
+        }
     }
 }
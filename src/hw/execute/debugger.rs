@@ -0,0 +1,140 @@
+use super::super::decode::{Action, GCode, GCodeSpan};
+use crate::log::target;
+use gcode::Mnemonic;
+use std::str::FromStr;
+use tracing::info;
+
+/// A condition the executor should halt on instead of dispatching the
+/// matching [`GCode`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// halts right before the [`GCode`] originating from this source line
+    Line(usize),
+    /// halts right before the next [`GCode`] with this mnemonic/major
+    /// number, e.g. `Code(Mnemonic::Miscellaneous, 104)` for every `M104`
+    Code(Mnemonic, u32),
+}
+
+impl Breakpoint {
+    fn matches(&self, code: &GCode) -> bool {
+        match *self {
+            Breakpoint::Line(line) => code.span().line() == line,
+            Breakpoint::Code(mnemonic, major) => {
+                code.mnemonic() == mnemonic && code.major_number() == major
+            }
+        }
+    }
+}
+
+/// Unrecognized [`Mnemonic`] name, see [`Mnemonic::from_str`]
+#[derive(Debug)]
+pub struct UnknownMnemonicError;
+
+/// Parses the same `{mnemonic:?}` spelling the decoder's profiler report
+/// already serializes a [`Mnemonic`] as, so a breakpoint set over the api
+/// can name one back
+pub fn mnemonic_from_str(s: &str) -> Result<Mnemonic, UnknownMnemonicError> {
+    match s {
+        "General" => Ok(Mnemonic::General),
+        "Miscellaneous" => Ok(Mnemonic::Miscellaneous),
+        "ToolChange" => Ok(Mnemonic::ToolChange),
+        _ => Err(UnknownMnemonicError),
+    }
+}
+
+/// CPU-monitor-style debugger for the print loop: holds breakpoints keyed
+/// by source line or mnemonic/number, a trace-only mode, and a step budget
+///
+/// Owned behind an `Arc<Mutex<_>>` shared between [`super::ExecutorCtrl`]
+/// and the executor thread, same as `line`/`shared_pos` are; it outlives
+/// any individual print.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    trace_only: bool,
+    // codes left to dispatch while ignoring breakpoints, set by `step`;
+    // reaching 0 makes `intercept` stop bypassing normal evaluation
+    repeat: u32,
+    // the action/code a breakpoint most recently halted in front of,
+    // dequeued but not yet run; `Some` exactly while the executor is
+    // paused because of a breakpoint rather than an explicit Pause
+    held: Option<(Action, GCode)>,
+}
+
+impl Debugger {
+    pub fn set_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn clear_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.retain(|b| *b != breakpoint);
+    }
+
+    pub fn breakpoints(&self) -> Vec<Breakpoint> {
+        self.breakpoints.clone()
+    }
+
+    pub fn set_trace_only(&mut self, trace_only: bool) {
+        self.trace_only = trace_only;
+    }
+
+    pub fn trace_only(&self) -> bool {
+        self.trace_only
+    }
+
+    /// Ignores breakpoints for the next `count` codes once execution
+    /// resumes, instead of halting at the very next one that matches
+    pub fn step(&mut self, count: u32) {
+        self.repeat = count;
+    }
+
+    /// The span of the code a breakpoint is currently holding the print in
+    /// front of, if any
+    pub fn current_span(&self) -> Option<GCodeSpan> {
+        self.held.as_ref().map(|(_, code)| code.span())
+    }
+
+    /// Consulted right before a freshly dequeued action/code is dispatched;
+    /// returns it back if it should actually run now, or holds onto it and
+    /// returns `None` if the executor should halt in front of it instead
+    pub fn intercept(&mut self, action: Action, code: GCode) -> Option<(Action, GCode)> {
+        if self.trace_only {
+            info!(target: target::PUBLIC, "trace: {}", code);
+            return Some((action, code));
+        }
+        if self.repeat > 0 {
+            self.repeat -= 1;
+            return Some((action, code));
+        }
+        if self.breakpoints.iter().any(|bp| bp.matches(&code)) {
+            self.held = Some((action, code));
+            return None;
+        }
+        Some((action, code))
+    }
+
+    /// Hands back whatever `intercept` most recently held onto, so resuming
+    /// (continue or step) can dispatch it without it immediately
+    /// re-triggering the very breakpoint it just served
+    pub fn take_held(&mut self) -> Option<(Action, GCode)> {
+        self.held.take()
+    }
+}
+
+impl FromStr for Breakpoint {
+    type Err = UnknownMnemonicError;
+
+    /// Parses either a bare line number or `<mnemonic>:<major>` (e.g.
+    /// `Miscellaneous:104` for `M104`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(line) = s.parse::<usize>() {
+            return Ok(Breakpoint::Line(line));
+        }
+        let (mnemonic, major) = s.split_once(':').ok_or(UnknownMnemonicError)?;
+        let mnemonic = mnemonic_from_str(mnemonic)?;
+        let major = major.parse::<u32>().map_err(|_| UnknownMnemonicError)?;
+        Ok(Breakpoint::Code(mnemonic, major))
+    }
+}
@@ -1,89 +1,286 @@
-use super::{super::decode::Action, motors::Motors};
+mod thermal;
+
+pub use self::thermal::{PiThermalBackend, ThermalBackend};
+use super::{
+    super::decode::{Action, GCodeSpan},
+    motors::{error::MotorsError, Motors},
+    timer::TimerQueue,
+};
 use crate::{
-    comms::{Axis, ReferenceRunOptParameters},
-    hw::pi::PiCtrl,
+    comms::{Axis, ControlComms, ReferenceRunOptParameters},
+    hw::{
+        comms::CancelReason,
+        gpio::GpioEndstops,
+        pi::{Cancelled, PiCtrl},
+    },
     log::target,
     settings::Settings,
 };
 use anyhow::Result;
-use std::{thread, time::Duration};
-use tracing::debug;
+use crossbeam::channel::Receiver;
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// A wait was interrupted by a cancellation (the print being stopped or
+/// paused) before it would otherwise have resolved, carrying which of the
+/// two triggered it
+///
+/// Not classified into an [`crate::api::values::ErrorCode`] and not meant to
+/// reach an operator as a fault; the executor loop special-cases it, aborting
+/// the print cleanly and silently for [`CancelReason::Stop`] while leaving it
+/// alone for [`CancelReason::Pause`] (which is already handled by the
+/// matching `ExecutorCtrlComms::Pause` sent alongside the cancellation)
+/// instead of logging it like a real error.
+#[derive(Debug, Error)]
+#[error("execution was cancelled")]
+pub struct CancelledError(pub CancelReason);
+
+/// Everything [`Executor::exec`] can fail with, each variant (other than
+/// [`Self::Cancelled`]) carrying the [`GCodeSpan`] of the action that failed
+/// so a report can point at the line that actually faulted instead of just
+/// an opaque message
+#[derive(Debug, Error)]
+pub enum ExecutorError {
+    /// see [`CancelledError`]; not a real fault, and not tied to a
+    /// particular line since it's the currently-running action being
+    /// interrupted rather than one failing on its own
+    #[error(transparent)]
+    Cancelled(#[from] CancelledError),
+    /// an axis is unreferenced or faulted; see [`MotorsError`]
+    #[error("{source} at {span}")]
+    MotorIo { source: MotorsError, span: GCodeSpan },
+    /// anything else an `exec_*` helper failed with (a thermal wait, a
+    /// driver init, ...) that isn't worth its own variant yet
+    #[error("{source:#} at {span}")]
+    Other { source: anyhow::Error, span: GCodeSpan },
+}
+
+/// Classifies an `exec_*` helper's `anyhow::Error` into an [`ExecutorError`],
+/// recovering a [`CancelledError`]/[`MotorsError`] it was built from where
+/// possible instead of flattening everything into [`ExecutorError::Other`]
+fn classify_err(err: anyhow::Error, span: GCodeSpan) -> ExecutorError {
+    let err = match err.downcast::<CancelledError>() {
+        Ok(cancelled) => return ExecutorError::Cancelled(cancelled),
+        Err(err) => err,
+    };
+    match err.downcast::<MotorsError>() {
+        Ok(source) => ExecutorError::MotorIo { source, span },
+        Err(source) => ExecutorError::Other { source, span },
+    }
+}
 
 pub struct Executor {
     settings: Settings,
     motors: Motors,
-    pi_ctrl: PiCtrl,
+    thermal: Box<dyn ThermalBackend>,
+    timer_queue: TimerQueue,
+    cancel_recv: Receiver<ControlComms<CancelReason>>,
+    // latest debounced level of each externally-wired gpio endstop,
+    // maintained by the gpio monitor thread; only consulted after a
+    // reference run to cross-check it against `MotorStatus`, never acted on
+    // directly
+    gpio_endstops: Arc<RwLock<GpioEndstops>>,
 }
 
 impl Executor {
-    pub fn new(settings: Settings, motors: Motors, pi_ctrl: PiCtrl) -> Self {
+    pub fn new(
+        settings: Settings,
+        motors: Motors,
+        pi_ctrl: PiCtrl,
+        cancel_recv: Receiver<ControlComms<CancelReason>>,
+        gpio_endstops: Arc<RwLock<GpioEndstops>>,
+    ) -> Self {
+        Self::with_thermal_backend(
+            settings,
+            motors,
+            Box::new(PiThermalBackend::new(pi_ctrl)),
+            cancel_recv,
+            gpio_endstops,
+        )
+    }
+
+    /// Same as [`Self::new`], but with the hotend/bed/fan actuation plugged
+    /// in explicitly instead of defaulting to [`PiThermalBackend`] -- e.g. to
+    /// simulate a printer, drive different hardware or log every call
+    pub fn with_thermal_backend(
+        settings: Settings,
+        motors: Motors,
+        thermal: Box<dyn ThermalBackend>,
+        cancel_recv: Receiver<ControlComms<CancelReason>>,
+        gpio_endstops: Arc<RwLock<GpioEndstops>>,
+    ) -> Self {
         Self {
             settings,
             motors,
-            pi_ctrl,
+            thermal,
+            timer_queue: TimerQueue::new(),
+            cancel_recv,
+            gpio_endstops,
         }
     }
 
-    fn exec_wait(&self, time: Duration) {
-        thread::sleep(time);
+    fn exec_wait(&mut self, time: Duration) -> Result<(), CancelledError> {
+        self.timer_queue.schedule(time);
+        match self.timer_queue.park_until_next(&self.cancel_recv) {
+            None => Ok(()),
+            Some(reason) => Err(CancelledError(reason)),
+        }
     }
 
     fn exec_hotend_target(&self, target: Option<u16>) -> Result<()> {
         // shouldn't panic because decoder should check the target
-        self.pi_ctrl.try_set_hotend_target(target).unwrap();
+        self.thermal.set_hotend_target(target).unwrap();
         Ok(())
     }
 
     fn exec_bed_target(&self, target: Option<u16>) -> Result<()> {
         // shouldn't panic because decoder should check the target
-        self.pi_ctrl.try_set_bed_target(target).unwrap();
+        self.thermal.set_bed_target(target).unwrap();
         Ok(())
     }
 
-    fn exec_wait_hotend_target(&self) -> Result<()> {
-        // shouldn't panic because nothing else should change the target
-        self.pi_ctrl.try_wait_hotend_target().unwrap();
-        Ok(())
+    fn exec_wait_hotend_target(&self, timeout: Option<Duration>) -> Result<()> {
+        match self.thermal.wait_for_hotend(timeout, &self.cancel_recv) {
+            Ok(res) => Ok(res?),
+            Err(Cancelled(reason)) => Err(CancelledError(reason).into()),
+        }
     }
 
-    fn exec_wait_bed_target(&self) -> Result<()> {
-        // shouldn't panic because nothing else should change the target
-        self.pi_ctrl.try_wait_bed_target().unwrap();
-        Ok(())
+    fn exec_wait_bed_target(&self, timeout: Option<Duration>) -> Result<()> {
+        match self.thermal.wait_for_bed(timeout, &self.cancel_recv) {
+            Ok(res) => Ok(res?),
+            Err(Cancelled(reason)) => Err(CancelledError(reason).into()),
+        }
     }
 
-    fn exec_wait_bed_min_temp(&self, temp: Option<u16>) -> Result<()> {
+    fn exec_wait_bed_min_temp(&self, temp: Option<u16>, timeout: Option<Duration>) -> Result<()> {
         // shouldn't panic because decoder should check the temp
-        // shouldn't panic because nothing else should change the target
-        self.pi_ctrl.try_wait_min_bed_temp(temp).unwrap().unwrap();
+        match self
+            .thermal
+            .wait_for_bed_min_temp(temp, timeout, &self.cancel_recv)
+            .unwrap()
+        {
+            Ok(res) => Ok(res?),
+            Err(Cancelled(reason)) => Err(CancelledError(reason).into()),
+        }
+    }
+
+    fn exec_fan_speed(&self, speed: u8) -> Result<()> {
+        self.thermal.set_fan_speed(speed);
         Ok(())
     }
 
+    /// Turns both the hotend and bed heaters off
+    ///
+    /// Used whenever an executed action fails (e.g. a temperature wait timing
+    /// out) or a print is stopped mid-action, since at that point we can no
+    /// longer trust whatever is (or isn't) heating to ever reach the target.
+    pub fn heaters_off(&self) {
+        self.thermal.heaters_off();
+    }
+
     pub fn exec_reference_axis(
         &mut self,
         axis: Axis,
         parameters: ReferenceRunOptParameters,
     ) -> Result<()> {
-        match axis {
+        let result = match axis {
             Axis::X => self.motors.reference_x(&self.settings, parameters),
             Axis::Y => self.motors.reference_y(&self.settings, parameters),
             Axis::Z => self.motors.reference_z(&self.settings, parameters),
+        };
+        if result.is_ok() {
+            self.check_gpio_endstop_after_reference(axis);
+        }
+        result
+    }
+
+    /// Cross-checks a just-completed reference run against the externally
+    /// wired gpio endstop for `axis`, if one is configured, warning if they
+    /// disagree (e.g. miswired or a limit switch that doesn't actually reach
+    /// the contact point the motor driver stopped at)
+    ///
+    /// Purely informational -- `MotorStatus` is still what the reference run
+    /// itself succeeded or failed on.
+    fn check_gpio_endstop_after_reference(&self, axis: Axis) {
+        let endstops = *self.gpio_endstops.read().unwrap();
+        let triggered = match axis {
+            Axis::X => endstops.x,
+            Axis::Y => endstops.y,
+            Axis::Z => endstops.z,
+        };
+        match triggered {
+            Some(false) => warn!(
+                target: target::INTERNAL,
+                "{:?} axis reference completed but its gpio endstop doesn't read as triggered -- check the switch/wiring",
+                axis
+            ),
+            // either it agrees, or this axis has no gpio endstop configured
+            Some(true) | None => (),
+        }
+    }
+
+    // FIXME there's no probe sensor abstraction yet, so this can't actually
+    // drive the toolhead over the configured grid and measure anything; the
+    // bed-mesh stays whatever was loaded from config until one exists
+    fn exec_probe_mesh(&self) -> Result<()> {
+        Ok(())
+    }
+
+    // FIXME the contact position this finds only reaches `DecoderState`
+    // through `shared_pos`/the existing manual "reference z via hotend"
+    // mechanism, not synchronously into the decoder thread's own
+    // `z_hotend_location` the way a real G28 would; see the FIXME on `g28`
+    fn exec_probe_z_hotend(&mut self, params: ReferenceRunOptParameters) -> Result<()> {
+        self.motors
+            .probe_z_hotend(&self.settings, params)
+            .map(|_| ())
+    }
+
+    /// Whether a cancellation has already arrived on `cancel_recv` without
+    /// us currently being parked in one of the blocking waits that would
+    /// normally pick it up
+    ///
+    /// Checked at the top of [`Self::exec`] so a batch of several already-
+    /// decoded actions (see the tick-draining loop in `executor_loop`)
+    /// doesn't keep issuing new ones once a stop/pause has been requested,
+    /// instead of only reacting once a `Wait`/temperature hold happens to be
+    /// in flight. This can't interrupt an `Action::MoveAll` that's already
+    /// mid-transfer though -- `nanotec_stepper_driver`'s status wait is a
+    /// single opaque blocking call with no cancellation hook, so a move in
+    /// progress still runs to completion; an e-stop bypasses this
+    /// altogether by halting the motors directly from the dedicated estop
+    /// thread instead of going through the executor at all.
+    fn cancel_pending(&self) -> Option<CancelReason> {
+        match self.cancel_recv.try_recv() {
+            Ok(ControlComms::Msg(reason)) => Some(reason),
+            Ok(ControlComms::Exit) => Some(CancelReason::Stop),
+            Err(_) => None,
         }
     }
 
-    pub fn exec(&mut self, action: Action) -> Result<()> {
+    pub fn exec(&mut self, action: Action, span: GCodeSpan) -> Result<(), ExecutorError> {
         debug!(target: target::INTERNAL, "Executing {:?}", action);
-        match action {
+        if let Some(reason) = self.cancel_pending() {
+            return Err(CancelledError(reason).into());
+        }
+        let result: Result<()> = match action {
             Action::MoveAll(m) => self.motors.move_all(&m, self.settings.config()),
             Action::ReferenceAxis(a, params) => self.exec_reference_axis(a, params),
+            Action::ProbeZHotend(params) => self.exec_probe_z_hotend(params),
             Action::HotendTarget(t) => self.exec_hotend_target(t),
             Action::BedTarget(t) => self.exec_bed_target(t),
-            // FIXME add timeouts for temp waits, otherwise it might wait forever
-            //       or add error checking
-            Action::WaitHotendTarget => self.exec_wait_hotend_target(),
-            Action::WaitBedTarget => self.exec_wait_bed_target(),
-            Action::WaitBedMinTemp(t) => self.exec_wait_bed_min_temp(t),
-            Action::Wait(d) => Ok(self.exec_wait(d)),
-        }
+            Action::WaitHotendTarget(timeout) => self.exec_wait_hotend_target(timeout),
+            Action::WaitBedTarget(timeout) => self.exec_wait_bed_target(timeout),
+            Action::WaitBedMinTemp(t, timeout) => self.exec_wait_bed_min_temp(t, timeout),
+            Action::Wait(d) => Ok(self.exec_wait(d)?),
+            Action::ProbeMesh => self.exec_probe_mesh(),
+            Action::FanSpeed(speed) => self.exec_fan_speed(speed),
+        };
+        result.map_err(|e| classify_err(e, span))
     }
 }
@@ -0,0 +1,106 @@
+use crate::{
+    comms::ControlComms,
+    hw::{
+        comms::CancelReason,
+        pi::{Cancelled, PiCtrl, PiCtrlError, WaitTempError},
+    },
+};
+use anyhow::Result;
+use crossbeam::channel::Receiver;
+use std::time::Duration;
+
+/// Drives the hotend/bed heaters and part-cooling fan on behalf of the
+/// [`Executor`][super::Executor], decoupling the G-code-level semantics of
+/// `M104`/`M109`/`M140`/`M190`/`M106`/`M107` from a single hardware
+/// assumption
+///
+/// [`PiThermalBackend`] reproduces today's behavior by forwarding straight
+/// to [`PiCtrl`]; a caller that wants to simulate the printer, drive
+/// different hardware or just log every call can hand the [`Executor`] its
+/// own implementation instead, see [`Executor::with_thermal_backend`].
+pub trait ThermalBackend: Send {
+    fn set_hotend_target(&self, target: Option<u16>) -> Result<(), PiCtrlError>;
+
+    fn set_bed_target(&self, target: Option<u16>) -> Result<()>;
+
+    fn wait_for_hotend(
+        &self,
+        timeout: Option<Duration>,
+        cancel_recv: &Receiver<ControlComms<CancelReason>>,
+    ) -> Result<Result<(), WaitTempError>, Cancelled>;
+
+    fn wait_for_bed(
+        &self,
+        timeout: Option<Duration>,
+        cancel_recv: &Receiver<ControlComms<CancelReason>>,
+    ) -> Result<Result<(), WaitTempError>, Cancelled>;
+
+    fn wait_for_bed_min_temp(
+        &self,
+        min_temp: Option<u16>,
+        timeout: Option<Duration>,
+        cancel_recv: &Receiver<ControlComms<CancelReason>>,
+    ) -> Result<Result<Result<(), WaitTempError>, Cancelled>, PiCtrlError>;
+
+    /// Sets the part-cooling fan's PWM duty cycle, 0-255
+    fn set_fan_speed(&self, speed: u8);
+
+    /// Turns both heaters off; see [`Executor::heaters_off`][super::Executor::heaters_off]
+    fn heaters_off(&self);
+}
+
+/// The default [`ThermalBackend`], forwarding every call straight to a
+/// [`PiCtrl`]
+pub struct PiThermalBackend(PiCtrl);
+
+impl PiThermalBackend {
+    pub fn new(pi_ctrl: PiCtrl) -> Self {
+        Self(pi_ctrl)
+    }
+}
+
+impl ThermalBackend for PiThermalBackend {
+    fn set_hotend_target(&self, target: Option<u16>) -> Result<(), PiCtrlError> {
+        self.0.try_set_hotend_target(target)
+    }
+
+    fn set_bed_target(&self, target: Option<u16>) -> Result<()> {
+        self.0.try_set_bed_target(target)
+    }
+
+    fn wait_for_hotend(
+        &self,
+        timeout: Option<Duration>,
+        cancel_recv: &Receiver<ControlComms<CancelReason>>,
+    ) -> Result<Result<(), WaitTempError>, Cancelled> {
+        self.0.try_wait_hotend_target(timeout, cancel_recv)
+    }
+
+    fn wait_for_bed(
+        &self,
+        timeout: Option<Duration>,
+        cancel_recv: &Receiver<ControlComms<CancelReason>>,
+    ) -> Result<Result<(), WaitTempError>, Cancelled> {
+        self.0.try_wait_bed_target(timeout, cancel_recv)
+    }
+
+    fn wait_for_bed_min_temp(
+        &self,
+        min_temp: Option<u16>,
+        timeout: Option<Duration>,
+        cancel_recv: &Receiver<ControlComms<CancelReason>>,
+    ) -> Result<Result<Result<(), WaitTempError>, Cancelled>, PiCtrlError> {
+        self.0.try_wait_min_bed_temp(min_temp, timeout, cancel_recv)
+    }
+
+    // FIXME there's no fan PWM abstraction yet, so this is a no-op until one
+    // exists; see `Executor::exec_probe_mesh` for a similar stopgap
+    fn set_fan_speed(&self, _speed: u8) {}
+
+    fn heaters_off(&self) {
+        // shouldn't panic because decoder should check the target
+        self.0.try_set_hotend_target(None).unwrap();
+        // shouldn't panic because decoder should check the target
+        self.0.try_set_bed_target(None).unwrap();
+    }
+}
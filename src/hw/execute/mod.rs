@@ -1,25 +1,33 @@
 mod control;
+mod debugger;
 mod executor;
 mod motors;
+mod timer;
 
-pub use self::control::{ExecutorCtrl, OutOfBoundsError};
+pub use self::control::{ExecutorCtrl, OutOfBoundsError, OutcomeCallback};
+pub use self::debugger::{mnemonic_from_str, Breakpoint, Debugger, UnknownMnemonicError};
+pub use self::executor::{PiThermalBackend, ThermalBackend};
+pub use self::motors::error::{MotorError, MotorsError};
 use self::{
     super::{
-        comms::EStopComms,
+        callbacks::{StopCallback, StopReason},
+        comms::{CancelReason, EStopComms},
         decode::State as DecoderState,
-        decode::{Decoder, FileDecoder, ThreadedDecoder},
+        decode::{Decoder, DecoderError, FileDecoder, ThreadedDecoder},
+        gpio::GpioEndstops,
         pi::PiCtrl,
     },
-    executor::Executor,
+    executor::{CancelledError, Executor, ExecutorError},
     motors::Motors,
 };
 use crate::{
     comms::{Axis, ControlComms, ReferenceRunOptParameters},
+    config::BedMesh,
     log::target,
     settings::Settings,
     util::send_err,
 };
-use anyhow::{Context, Error, Result};
+use anyhow::{anyhow, Context, Error, Result};
 use crossbeam::{
     channel::{self, Receiver, Sender, TryRecvError},
     select,
@@ -27,23 +35,41 @@ use crossbeam::{
 use std::{
     fs::File,
     mem,
+    panic::{self, AssertUnwindSafe},
     path::PathBuf,
     sync::{
         atomic::{AtomicI32, AtomicUsize, Ordering},
-        Arc,
+        Arc, Mutex, RwLock,
     },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
-use tracing::{debug, info};
+use thiserror::Error;
+use tracing::{debug, info, warn};
 
 enum ExecutorCtrlComms {
-    /// sends the already open file, the path to that file (for error messages)
-    /// and an atomic that the currently executed line will be written into by
-    /// the executor
-    Print(File, PathBuf, Arc<AtomicUsize>),
+    /// sends the already open file, the path to that file (for error messages),
+    /// an atomic that the currently executed line will be written into by the
+    /// executor, and a callback to run once the print ends (however it ends)
+    Print(File, PathBuf, Arc<AtomicUsize>, Box<dyn StopCallback>),
+    /// like `Print`, but fast-forwards the decoder to the given line before
+    /// resuming execution from there, instead of starting from the top
+    Resume(File, PathBuf, usize, Arc<AtomicUsize>),
+    /// like `Print`, but for a startup/idle/cancel gcode macro rather than a
+    /// real print: the line counter isn't shared with `ExecutorCtrl::line`
+    /// since macros aren't reflected in `current_line`/the api's state info
+    RunMacro(File, PathBuf, Arc<AtomicUsize>),
     Stop,
     Play,
     Pause,
+    /// executes exactly one already-decoded action while paused, then stays
+    /// paused; a no-op while printing or stopped
+    Step,
+    /// swaps in a freshly re-read config for prints/macros started from this
+    /// point on; doesn't affect a print already in progress, and the
+    /// `Executor`'s own copy (used for axis reference runs) keeps the config
+    /// it was started with
+    Reload(Settings),
 }
 
 #[derive(Debug, Clone, Default)]
@@ -67,6 +93,28 @@ enum InnerState {
 pub(self) struct PrintingData {
     pub decoder: ThreadedDecoder<FileDecoder>,
     pub line: Arc<AtomicUsize>,
+    // run once this print stops, however it stops; `None` for a resumed
+    // print or a macro, neither of which currently have anyone to notify
+    pub end_callback: Option<Box<dyn StopCallback>>,
+}
+
+/// Why a [`State`] transition was refused, instead of the transition just
+/// panicking and taking the executor thread down with it
+///
+/// These are only expected to surface when a control message raced a
+/// transition already in flight (e.g. a print finished between `try_play`
+/// being checked at the `HwCtrl` level and the matching `Play` actually
+/// being processed here); see [`executor_loop`]'s handling of each.
+#[derive(Debug, Error)]
+enum StateError {
+    #[error("printer is already printing or paused")]
+    AlreadyPrinting,
+    #[error("printer is stopped")]
+    Stopped,
+    #[error("printer isn't stopped")]
+    NotStopped,
+    #[error(transparent)]
+    Decoder(#[from] DecoderError),
 }
 
 struct State {
@@ -75,55 +123,142 @@ struct State {
 }
 
 impl State {
-    pub fn new(z_hotend_location: f64) -> Self {
+    pub fn new(z_hotend_location: f64, bed_mesh: BedMesh) -> Self {
         Self {
-            inner: InnerState::Stopped(DecoderState::new(z_hotend_location)),
+            inner: InnerState::Stopped(DecoderState::new(z_hotend_location, bed_mesh)),
             data: None,
         }
     }
 
-    pub fn print(&mut self, settings: Settings, file: File, path: PathBuf, line: Arc<AtomicUsize>) {
-        match &self.inner {
-            InnerState::Stopped(_) => {
-                let decoder_state = match mem::replace(&mut self.inner, InnerState::Printing) {
-                    InnerState::Stopped(ds) => ds,
-                    _ => unreachable!(),
-                };
-                let decoder = ThreadedDecoder::new(FileDecoder::with_state_and_file(
-                    settings,
-                    decoder_state,
-                    file,
-                    path,
-                ))
-                .expect("starting the decoder thread failed");
-                self.data = Some(PrintingData { decoder, line })
+    /// If opening the print file (e.g. its io_uring read-ahead hitting the
+    /// process's instance limit) fails, the printer is left stopped with the
+    /// same decoder state it had before the attempt, same as a failed
+    /// [`State::resume`].
+    pub fn print(
+        &mut self,
+        settings: Settings,
+        file: File,
+        path: PathBuf,
+        line: Arc<AtomicUsize>,
+        end_callback: Option<Box<dyn StopCallback>>,
+    ) -> Result<(), StateError> {
+        let decoder_state = match &self.inner {
+            InnerState::Stopped(_) => match mem::replace(&mut self.inner, InnerState::Printing) {
+                InnerState::Stopped(ds) => ds,
+                _ => unreachable!(),
+            },
+            _ => return Err(StateError::AlreadyPrinting),
+        };
+        match FileDecoder::with_state_and_file(settings, decoder_state, file, path) {
+            Ok(file_decoder) => {
+                let decoder =
+                    ThreadedDecoder::new(file_decoder).expect("starting the decoder thread failed");
+                self.data = Some(PrintingData {
+                    decoder,
+                    line,
+                    end_callback,
+                });
+                Ok(())
+            }
+            Err((e, decoder_state)) => {
+                self.inner = InnerState::Stopped(decoder_state);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Like [`State::print`], but fast-forwards the decoder to `line`
+    /// before handing out actions, so a checkpointed print resumes from
+    /// where it left off instead of from the top of the file
+    ///
+    /// If fast-forwarding fails (e.g. the file was edited since the
+    /// checkpoint was written and a line before `line` no longer decodes;
+    /// a file with fewer than `line` lines just resumes at its end), the printer is
+    /// left stopped with the decoder state as of the last line it did
+    /// successfully replay, rather than resumed; a failed resume isn't
+    /// expected to be retried anyway.
+    pub fn resume(
+        &mut self,
+        settings: Settings,
+        file: File,
+        path: PathBuf,
+        line: usize,
+        line_counter: Arc<AtomicUsize>,
+    ) -> Result<(), StateError> {
+        let decoder_state = match &self.inner {
+            InnerState::Stopped(_) => match mem::replace(&mut self.inner, InnerState::Printing) {
+                InnerState::Stopped(ds) => ds,
+                _ => unreachable!(),
+            },
+            _ => return Err(StateError::AlreadyPrinting),
+        };
+        match FileDecoder::with_state_and_file(settings, decoder_state, file, path)
+            .and_then(|file_decoder| file_decoder.fast_forward_to(line))
+        {
+            Ok(file_decoder) => {
+                let decoder =
+                    ThreadedDecoder::new(file_decoder).expect("starting the decoder thread failed");
+                self.data = Some(PrintingData {
+                    decoder,
+                    line: line_counter,
+                    end_callback: None,
+                });
+                Ok(())
+            }
+            Err((e, decoder_state)) => {
+                self.inner = InnerState::Stopped(decoder_state);
+                Err(e.into())
             }
-            _ => panic!("printer is already printing/paused"),
         }
     }
 
-    pub fn stop(&mut self) {
+    /// Notifies the current print's end-callback (if any) that it's ending
+    /// because of a real error, so a callback that cares about the
+    /// distinction (e.g. [`OutcomeCallback`]) doesn't have to infer it from
+    /// `StopReason` alone
+    ///
+    /// Call this before `stop(StopReason::Cancelled)`, which still has to
+    /// run afterwards to actually reset the state and fire `stop` itself.
+    pub fn fail(&self, err: &Error) {
+        if let Some(data) = &self.data {
+            if let Some(end_callback) = &data.end_callback {
+                end_callback.fail(err);
+            }
+        }
+    }
+
+    pub fn stop(&mut self, reason: StopReason) {
         match self.inner {
             InnerState::Stopped(_) => (),
             _ => {
-                let mut decoder_state = self.data.take().unwrap().decoder.state();
+                let data = self.data.take().unwrap();
+                let mut decoder_state = data.decoder.state();
                 decoder_state.reset();
                 self.inner = InnerState::Stopped(decoder_state);
+                if let Some(end_callback) = data.end_callback {
+                    end_callback.stop(reason);
+                }
             }
         }
     }
 
-    pub fn play(&mut self) {
+    pub fn play(&mut self) -> Result<(), StateError> {
         match self.inner {
-            InnerState::Stopped(_) => panic!("can't continue, printer is stopped"),
-            _ => self.inner = InnerState::Printing,
+            InnerState::Stopped(_) => Err(StateError::Stopped),
+            _ => {
+                self.inner = InnerState::Printing;
+                Ok(())
+            }
         }
     }
 
-    pub fn pause(&mut self) {
+    pub fn pause(&mut self) -> Result<(), StateError> {
         match self.inner {
-            InnerState::Stopped(_) => panic!("can't continue, printer is stopped"),
-            _ => self.inner = InnerState::Paused,
+            InnerState::Stopped(_) => Err(StateError::Stopped),
+            _ => {
+                self.inner = InnerState::Paused;
+                Ok(())
+            }
         }
     }
 
@@ -131,43 +266,142 @@ impl State {
         self.data.as_mut()
     }
 
-    pub fn decoder_state_mut(&mut self) -> &mut DecoderState {
+    pub fn is_printing(&self) -> bool {
+        matches!(self.inner, InnerState::Printing)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        matches!(self.inner, InnerState::Paused)
+    }
+
+    pub fn decoder_state_mut(&mut self) -> Result<&mut DecoderState, StateError> {
         match &mut self.inner {
-            InnerState::Stopped(decoder_state) => decoder_state,
-            _ => panic!("can't read decoder state, printer isn't stopped"),
+            InnerState::Stopped(decoder_state) => Ok(decoder_state),
+            _ => Err(StateError::NotStopped),
         }
     }
 }
 
 fn executor_loop(
-    settings: Settings,
+    mut settings: Settings,
     mut exec: Executor,
     executor_ctrl_recv: Receiver<ControlComms<ExecutorCtrlComms>>,
     executor_manual_recv: Receiver<ExecutorManualComms>,
     shared_z_pos_raw: Arc<AtomicI32>,
     error_send: Sender<ControlComms<Error>>,
+    debugger: Arc<Mutex<Debugger>>,
 ) {
-    let mut state = State::new(-(settings.config().motors.z.limit as f64));
+    let mut state = State::new(
+        -(settings.config().motors.z.limit as f64),
+        settings.config().bed_mesh.clone(),
+    );
+    // shared between the printing and single-step paths: reports a real
+    // error (unless it's a cancellation, which isn't a fault), and aborts
+    // the print -- but only for a `Stop`-triggered cancellation. A `Pause`
+    // is already being handled by the matching `ExecutorCtrlComms::Pause`
+    // sent alongside it, so the print should actually pause instead of
+    // being aborted like any other error would.
+    macro_rules! handle_exec_err {
+        ($e:expr) => {{
+            let cancel_reason = match &$e {
+                ExecutorError::Cancelled(CancelledError(reason)) => Some(*reason),
+                _ => None,
+            };
+            if cancel_reason != Some(CancelReason::Pause) {
+                if cancel_reason.is_none() {
+                    let err = Error::from($e);
+                    state.fail(&err);
+                    // FIXME alert hwctrl of error
+                    error_send.send(ControlComms::Msg(err)).unwrap();
+                }
+                state.stop(StopReason::Cancelled);
+                exec.heaters_off();
+            }
+        }};
+    }
     // has to be macro so break will work
     macro_rules! handle_ctrl_msg {
         ($msg:expr) => {{
             match $msg {
                 ControlComms::Msg(c) => match c {
-                    ExecutorCtrlComms::Print(file, path, line) => {
+                    ExecutorCtrlComms::Print(file, path, line, end_callback) => {
                         debug!(target: target::INTERNAL, "executor thread starting print");
-                        state.print(settings.clone(), file, path, line);
+                        let result =
+                            state.print(settings.clone(), file, path, line, Some(end_callback));
+                        if let Err(e) = result {
+                            // FIXME alert hwctrl of error
+                            error_send.send(ControlComms::Msg(e.into())).unwrap();
+                        }
+                    }
+                    ExecutorCtrlComms::Resume(file, path, start_line, line) => {
+                        debug!(target: target::INTERNAL, "executor thread resuming print");
+                        if let Err(e) = state.resume(settings.clone(), file, path, start_line, line) {
+                            // FIXME alert hwctrl of error
+                            error_send.send(ControlComms::Msg(e.into())).unwrap();
+                        }
+                    }
+                    ExecutorCtrlComms::RunMacro(file, path, line) => {
+                        debug!(target: target::INTERNAL, "executor thread running gcode macro");
+                        if let Err(e) = state.print(settings.clone(), file, path, line, None) {
+                            // FIXME alert hwctrl of error
+                            error_send.send(ControlComms::Msg(e.into())).unwrap();
+                        }
                     }
                     ExecutorCtrlComms::Stop => {
                         debug!(target: target::INTERNAL, "executor thread stopping");
-                        state.stop();
+                        state.stop(StopReason::Cancelled);
                     }
                     ExecutorCtrlComms::Play => {
                         debug!(target: target::INTERNAL, "executor thread contiuing");
-                        state.play();
+                        if let Err(e) = state.play() {
+                            // FIXME alert hwctrl of error
+                            error_send.send(ControlComms::Msg(e.into())).unwrap();
+                        }
                     }
                     ExecutorCtrlComms::Pause => {
                         debug!(target: target::INTERNAL, "executor thread pausing");
-                        state.pause();
+                        if let Err(e) = state.pause() {
+                            // FIXME alert hwctrl of error
+                            error_send.send(ControlComms::Msg(e.into())).unwrap();
+                        }
+                    }
+                    ExecutorCtrlComms::Step => {
+                        debug!(target: target::INTERNAL, "executor thread single-stepping");
+                        if state.is_paused() {
+                            let printing_data =
+                                state.decoder_mut().expect("paused implies decoder data is present");
+                            // a breakpoint's held action takes priority over
+                            // pulling a fresh one, so stepping past a
+                            // breakpoint doesn't lose it
+                            let next = match debugger.lock().unwrap().take_held() {
+                                Some(held) => Ok(Ok(held)),
+                                None => printing_data.decoder.action_recv().recv(),
+                            };
+                            match next {
+                                Ok(Ok((action, code))) => {
+                                    printing_data
+                                        .line
+                                        .store(code.span().line(), Ordering::Release);
+                                    debug!(target: target::PUBLIC, "Executing {} (step)", code);
+                                    if let Err(e) = exec.exec(action, code.span()) {
+                                        handle_exec_err!(e);
+                                    }
+                                }
+                                Ok(Err(e)) => {
+                                    let e = Error::from(e);
+                                    state.fail(&e);
+                                    // FIXME alert hwctrl of error
+                                    error_send.send(ControlComms::Msg(e)).unwrap();
+                                    state.stop(StopReason::Cancelled);
+                                }
+                                // decoder thread exited, nothing left to print
+                                Err(_) => state.stop(StopReason::Finished),
+                            }
+                        }
+                    }
+                    ExecutorCtrlComms::Reload(new_settings) => {
+                        debug!(target: target::INTERNAL, "executor thread reloading settings");
+                        settings = new_settings;
                     }
                 },
                 ControlComms::Exit => {
@@ -193,27 +427,104 @@ fn executor_loop(
                 }
             },
         }
-        if let Some(printing_data) = state.decoder_mut() {
-            if let Some(res) = printing_data.decoder.next() {
-                let (action, code) = match res {
-                    Ok(t) => t,
-                    Err(e) => {
-                        // FIXME alert hwctrl of error
-                        error_send.send(ControlComms::Msg(e.into())).unwrap();
-                        state.stop();
-                        continue;
-                    }
-                };
-                // FIXME maybe use Ordering::Relaxed since it doesn't really matter?
+        if state.is_printing() {
+            // a breakpoint's held action is resumed before anything else
+            // this tick, so continuing/stepping past it doesn't lose it
+            // nor immediately re-trigger the same breakpoint
+            if let Some((action, code)) = debugger.lock().unwrap().take_held() {
+                let printing_data = state
+                    .decoder_mut()
+                    .expect("printing implies decoder data is present");
                 printing_data
                     .line
                     .store(code.span().line(), Ordering::Release);
-                debug!(target: target::PUBLIC, "Executing {}", code);
-                send_err!(exec.exec(action), error_send)
-            } else {
-                // FIXME alert hwctrl of finish
-                state.stop();
+                debug!(target: target::PUBLIC, "Executing {} (resumed)", code);
+                if let Err(e) = exec.exec(action, code.span()) {
+                    handle_exec_err!(e);
+                }
             }
+        }
+        if state.is_printing() {
+            // wake on a fixed tick and drain everything that's ready by then
+            // into a single batch, instead of firing off each action the
+            // instant it decodes; bounds how much we jitter the RS485 bus.
+            // actions that are still queued once the tick runs dry are left
+            // for the next tick rather than busy-waiting for more.
+            let tick = Duration::from_micros(settings.config().execute.tick_micros);
+            let tick_deadline = Instant::now() + tick;
+            let printing_data = state
+                .decoder_mut()
+                .expect("printing implies decoder data is present");
+            let action_recv = printing_data.decoder.action_recv();
+            macro_rules! run_action {
+                ($action:expr, $code:expr) => {{
+                    match debugger.lock().unwrap().intercept($action, $code) {
+                        Some((action, code)) => {
+                            // FIXME maybe use Ordering::Relaxed since it doesn't really matter?
+                            printing_data
+                                .line
+                                .store(code.span().line(), Ordering::Release);
+                            debug!(target: target::PUBLIC, "Executing {}", code);
+                            if let Err(e) = exec.exec(action, code.span()) {
+                                handle_exec_err!(e);
+                            }
+                        }
+                        // a breakpoint matched; hold here until resumed
+                        None => state
+                            .pause()
+                            .expect("is_printing() implies pausing is always legal"),
+                    }
+                }};
+            }
+            // select! instead of a plain blocking recv so a control message
+            // (e.g. Pause) is honored even while we're waiting on the first
+            // buffered action of the tick
+            select! {
+                recv(executor_ctrl_recv) -> msg => handle_ctrl_msg!(msg.unwrap()),
+                recv(action_recv) -> msg => match msg {
+                    Ok(Ok((action, code))) => run_action!(action, code),
+                    Ok(Err(e)) => {
+                        let e = Error::from(e);
+                        state.fail(&e);
+                        // FIXME alert hwctrl of error
+                        error_send.send(ControlComms::Msg(e)).unwrap();
+                        state.stop(StopReason::Cancelled);
+                    }
+                    // decoder thread exited, nothing left to print
+                    Err(_) => state.stop(StopReason::Finished),
+                },
+            }
+            // keep draining whatever's already buffered until the tick runs
+            // out or the decoder can't keep up, rather than stopping at one
+            while state.is_printing() && Instant::now() < tick_deadline {
+                let printing_data = state
+                    .decoder_mut()
+                    .expect("printing implies decoder data is present");
+                match printing_data.decoder.action_recv().try_recv() {
+                    Ok(Ok((action, code))) => run_action!(action, code),
+                    Ok(Err(e)) => {
+                        let e = Error::from(e);
+                        state.fail(&e);
+                        // FIXME alert hwctrl of error
+                        error_send.send(ControlComms::Msg(e)).unwrap();
+                        state.stop(StopReason::Cancelled);
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    // decoder thread exited, nothing left to print
+                    Err(TryRecvError::Disconnected) => state.stop(StopReason::Finished),
+                }
+            }
+            // the batch ran dry before the tick did: sleep out the rest of
+            // it instead of starting the next batch early
+            if state.is_printing() {
+                if let Some(rest) = tick_deadline.checked_duration_since(Instant::now()) {
+                    thread::sleep(rest);
+                }
+            }
+        } else if state.is_paused() {
+            // don't pull any further actions while paused, just wait for the
+            // next control message (e.g. Resume/Step/Cancel)
+            handle_ctrl_msg!(executor_ctrl_recv.recv().unwrap());
         } else {
             // TODO run manual movement commands through decoder somehow
             select! {
@@ -223,7 +534,10 @@ fn executor_loop(
                     ExecutorManualComms::ReferenceZAxisHotend => {
                         let pos_steps = shared_z_pos_raw.load(Ordering::Acquire);
                         let pos_mm = settings.config().motors.z.steps_to_mm(pos_steps);
-                        state.decoder_state_mut().set_z_hotend_location(pos_mm)
+                        state
+                            .decoder_state_mut()
+                            .expect("not printing/paused implies the printer is stopped")
+                            .set_z_hotend_location(pos_mm)
                     }
                 }
             }
@@ -231,18 +545,43 @@ fn executor_loop(
     }
 }
 
+/// Renders a panic payload caught by [`panic::catch_unwind`] as a message
+/// suitable for an error report, falling back to a generic description for
+/// payloads that aren't a `&str`/`String` (e.g. a custom panic payload type)
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        String::from("<no panic message>")
+    }
+}
+
 pub fn start(
     settings: Settings,
     pi_ctrl: PiCtrl,
     estop_recv: Receiver<ControlComms<EStopComms>>,
+    estop_send: Sender<ControlComms<EStopComms>>,
     error_send: Sender<ControlComms<Error>>,
+    gpio_endstops: Arc<RwLock<GpioEndstops>>,
 ) -> Result<(JoinHandle<()>, JoinHandle<()>, ExecutorCtrl)> {
     let (executor_ctrl_send, executor_ctrl_recv) = channel::unbounded();
     let (executor_manual_send, executor_manual_recv) = channel::unbounded();
+    // lets Stop/Pause interrupt a blocking Action::Wait (dwell or
+    // temperature hold) from outside the executor thread, since that thread
+    // is busy running the wait itself and can't also poll
+    // executor_ctrl_recv until it returns; see Executor::exec_wait and
+    // PiCtrl::try_wait_*.
+    let (cancel_send, cancel_recv) = channel::unbounded();
     let (setup_send, setup_recv) = channel::bounded(1);
     let settings_clone = settings.clone();
     let shared_pos = SharedRawPos::default();
     let shared_pos_clone = shared_pos.clone();
+    // shared with the executor thread for its entire lifetime, not just one
+    // print, same as shared_pos/line are
+    let debugger = Arc::new(Mutex::new(Debugger::default()));
+    let debugger_clone = Arc::clone(&debugger);
     // do it this way all in the executorhread because we can't send motors between
     // threads. We then send the result of the setup via the above channel.
     // the setup is all in a function so we can use the ? operator for convenience
@@ -253,22 +592,25 @@ pub fn start(
                 settings: &Settings,
                 estop_recv: Receiver<ControlComms<EStopComms>>,
                 shared_pos: SharedRawPos,
+                error_send: Sender<ControlComms<Error>>,
             ) -> Result<(Motors, JoinHandle<()>)> {
                 let (mut motors, mut estop) = Motors::new(settings.clone(), shared_pos)?;
                 let estop_handle = thread::Builder::new()
                     .name(String::from("estop"))
                     .spawn(move || {
-                        loop {
+                        let error_send_on_panic = error_send.clone();
+                        let result = panic::catch_unwind(AssertUnwindSafe(move || loop {
                             match estop_recv
                                 .recv()
                                 .expect("estop channel was unexpectedly closed")
                             {
-                                // if there's an IO error writing, it's probably a good plan to
-                                // panic
                                 ControlComms::Msg(m) => match m {
                                     EStopComms::EStop => {
                                         info!(target: target::PUBLIC, "executing estop");
-                                        estop.estop(2000).unwrap()
+                                        if let Err(e) = estop.estop(2000) {
+                                            // FIXME alert hwctrl of error
+                                            error_send.send(ControlComms::Msg(e)).unwrap();
+                                        }
                                     }
                                 },
                                 ControlComms::Exit => {
@@ -276,6 +618,16 @@ pub fn start(
                                     break;
                                 }
                             }
+                        }));
+                        if let Err(panic) = result {
+                            let message = panic_message(&*panic);
+                            warn!(target: target::INTERNAL, "estop thread panicked: {}", message);
+                            error_send_on_panic
+                                .send(ControlComms::Msg(anyhow!(
+                                    "estop thread panicked: {}",
+                                    message
+                                )))
+                                .unwrap();
                         }
                     })
                     .context("Creating the estop thread failed")?;
@@ -283,18 +635,44 @@ pub fn start(
                 Ok((motors, estop_handle))
             }
             let shared_z_pos_raw = Arc::clone(&shared_pos_clone.z);
-            match setup(&settings, estop_recv, shared_pos_clone) {
+            match setup(&settings, estop_recv, shared_pos_clone, error_send.clone()) {
                 Ok((motors, estop_handle)) => {
-                    let executor = Executor::new(settings.clone(), motors, pi_ctrl);
-                    setup_send.send(Ok(estop_handle)).unwrap();
-                    executor_loop(
-                        settings,
-                        executor,
-                        executor_ctrl_recv,
-                        executor_manual_recv,
-                        shared_z_pos_raw,
-                        error_send,
+                    let executor = Executor::new(
+                        settings.clone(),
+                        motors,
+                        pi_ctrl,
+                        cancel_recv,
+                        gpio_endstops,
                     );
+                    setup_send.send(Ok(estop_handle)).unwrap();
+                    let error_send_on_panic = error_send.clone();
+                    let result = panic::catch_unwind(AssertUnwindSafe(move || {
+                        executor_loop(
+                            settings,
+                            executor,
+                            executor_ctrl_recv,
+                            executor_manual_recv,
+                            shared_z_pos_raw,
+                            error_send,
+                            debugger_clone,
+                        );
+                    }));
+                    if let Err(panic) = result {
+                        let message = panic_message(&*panic);
+                        warn!(
+                            target: target::INTERNAL,
+                            "executor thread panicked, triggering an estop: {}", message
+                        );
+                        error_send_on_panic
+                            .send(ControlComms::Msg(anyhow!(
+                                "executor thread panicked: {}",
+                                message
+                            )))
+                            .unwrap();
+                        estop_send
+                            .send(ControlComms::Msg(EStopComms::EStop))
+                            .unwrap();
+                    }
                 }
                 Err(e) => {
                     setup_send.send(Err(e)).unwrap();
@@ -311,6 +689,8 @@ pub fn start(
             executor_ctrl_send,
             executor_manual_send,
             shared_pos,
+            cancel_send,
+            debugger,
         ),
     ))
 }
@@ -0,0 +1,74 @@
+use super::{
+    super::super::decode::{AxisMovement, Movement},
+    error::MotorError,
+};
+use crate::{comms::ReferenceRunOptParameters, config::Config, settings::Settings};
+use anyhow::Result;
+
+/// Whether a referenceable axis's absolute position can be trusted
+///
+/// Starts `Invalid` and is only ever set `Valid` by a successful
+/// `reference_*` call; any `MotorError`/`DriverError` reported for the axis
+/// (from `move_all` or from referencing itself) puts it back to `Invalid`.
+/// `move_all` refuses to move an `Invalid` axis instead of trusting a
+/// possibly-stale position, so a controller has to explicitly reference it
+/// back to `Valid` first rather than risk crashing it into an endstop.
+///
+/// The extruder isn't tracked here: it has no reference run and no endstop
+/// to crash into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotorState {
+    Valid,
+    Invalid,
+}
+
+/// Which x/y/z axes `m` would move (`distance != 0`) but are currently
+/// `Invalid`, in x/y/z order; shared by every [`MotorBackend`] so they all
+/// refuse to move an unreferenced axis the same way
+pub(super) fn unreferenced_axes(
+    m: &Movement,
+    x: MotorState,
+    y: MotorState,
+    z: MotorState,
+) -> Vec<&'static str> {
+    [
+        (m.x.distance != 0, "x", x),
+        (m.y.distance != 0, "y", y),
+        (m.z.distance != 0, "z", z),
+    ]
+    .into_iter()
+    .filter(|&(moves, _, state)| moves && state == MotorState::Invalid)
+    .map(|(_, axis, _)| axis)
+    .collect()
+}
+
+/// The motor control surface `Motors` drives, factored out so the rest of
+/// the daemon (the executor, `start()`) can run against either the real
+/// `nanotec_stepper_driver`-backed implementation or an in-memory
+/// [`super::sim::SimMotors`], chosen at runtime by `[motors] backend`
+pub trait MotorBackend: Send {
+    fn init(&mut self) -> Result<()>;
+    fn reference_x(&mut self, settings: &Settings, params: ReferenceRunOptParameters)
+        -> Result<()>;
+    fn reference_y(&mut self, settings: &Settings, params: ReferenceRunOptParameters)
+        -> Result<()>;
+    fn reference_z(&mut self, settings: &Settings, params: ReferenceRunOptParameters)
+        -> Result<()>;
+    fn probe_z_hotend(
+        &mut self,
+        settings: &Settings,
+        params: ReferenceRunOptParameters,
+    ) -> Result<i32>;
+    fn move_all(&mut self, m: &Movement, config: &Config) -> Result<()>;
+    fn move_x(&mut self, m: &AxisMovement) -> Result<(), MotorError>;
+    fn move_y(&mut self, m: &AxisMovement) -> Result<(), MotorError>;
+    fn move_z(&mut self, m: &AxisMovement) -> Result<(), MotorError>;
+}
+
+/// The estop handle `Motors::new` hands back alongside a `MotorBackend`,
+/// run from the dedicated estop thread (see `hw::execute::start`) rather
+/// than through `MotorBackend` itself, since it has to keep working even
+/// while something else holds the backend busy mid-move
+pub trait MotorEStop: Send {
+    fn estop(&mut self, millis: u64) -> Result<()>;
+}
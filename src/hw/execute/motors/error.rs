@@ -1,4 +1,6 @@
+use crate::api::values::ErrorCode;
 use nanotec_stepper_driver::DriverError;
+use serde_json::Value;
 use std::fmt::Display;
 use thiserror::Error;
 
@@ -8,35 +10,102 @@ pub enum MotorError {
     DriverError(#[from] DriverError),
     #[error("position error occured while driving the motor")]
     PositionError,
+    /// only ever produced by [`super::sim::SimMotors`]'s `driver_error_rate`
+    /// fault injection: the real backend would report an actual
+    /// `DriverError` here, but that type can only be constructed by the
+    /// `nanotec_stepper_driver` crate itself
+    #[error("simulated driver fault")]
+    SimulatedDriverFault,
 }
 
-/// Able to contain errors for all motors
-///
-/// Be aware of the invariant that at least one of the fields should contain an
-/// error.
+impl MotorError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::DriverError(_) => ErrorCode::Driver,
+            Self::PositionError => ErrorCode::Position,
+            Self::SimulatedDriverFault => ErrorCode::Driver,
+        }
+    }
+
+    pub fn details(&self) -> Option<Value> {
+        None
+    }
+}
+
+/// Able to contain errors for all motors, or report that a move was refused
+/// outright
 #[derive(Debug, Error)]
-pub struct MotorsError {
-    pub x: Option<MotorError>,
-    pub y: Option<MotorError>,
-    pub z: Option<MotorError>,
-    pub e: Option<MotorError>,
+pub enum MotorsError {
+    /// at least one motor reported an error while moving
+    ///
+    /// Be aware of the invariant that at least one of the fields should
+    /// contain an error.
+    Faults {
+        x: Option<MotorError>,
+        y: Option<MotorError>,
+        z: Option<MotorError>,
+        e: Option<MotorError>,
+    },
+    /// `move_all` was refused because one or more of the axes it would have
+    /// moved aren't currently referenced; see [`super::backend::MotorState`]
+    NotReferenced { axes: Vec<&'static str> },
 }
 
-impl Display for MotorsError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "at least one motor reported an error:\n")?;
-        if let Some(x) = &self.x {
-            write!(f, "    x: {}\n", x)?;
+impl MotorsError {
+    /// The code of the first axis that reported an error, in x/y/z/e order
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Faults { x, y, z, e } => x
+                .as_ref()
+                .or(y.as_ref())
+                .or(z.as_ref())
+                .or(e.as_ref())
+                .map(MotorError::code)
+                .unwrap_or(ErrorCode::Driver),
+            Self::NotReferenced { .. } => ErrorCode::State,
         }
-        if let Some(y) = &self.y {
-            write!(f, "    y: {}\n", y)?;
-        }
-        if let Some(z) = &self.z {
-            write!(f, "    z: {}\n", z)?;
+    }
+
+    /// Reports which axes were affected, so a client doesn't have to parse
+    /// the message text to find out
+    pub fn details(&self) -> Option<Value> {
+        match self {
+            Self::Faults { x, y, z, e } => {
+                let axes: Vec<&str> = [(x, "x"), (y, "y"), (z, "z"), (e, "e")]
+                    .into_iter()
+                    .filter_map(|(err, axis)| err.as_ref().map(|_| axis))
+                    .collect();
+                Some(serde_json::json!({ "axes": axes }))
+            }
+            Self::NotReferenced { axes } => Some(serde_json::json!({ "axes": axes })),
         }
-        if let Some(e) = &self.e {
-            write!(f, "    x: {}\n", e)?;
+    }
+}
+
+impl Display for MotorsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Faults { x, y, z, e } => {
+                write!(f, "at least one motor reported an error:\n")?;
+                if let Some(x) = x {
+                    write!(f, "    x: {}\n", x)?;
+                }
+                if let Some(y) = y {
+                    write!(f, "    y: {}\n", y)?;
+                }
+                if let Some(z) = z {
+                    write!(f, "    z: {}\n", z)?;
+                }
+                if let Some(e) = e {
+                    write!(f, "    e: {}\n", e)?;
+                }
+                Ok(())
+            }
+            Self::NotReferenced { axes } => write!(
+                f,
+                "refused to move: {} not referenced, reference it first",
+                axes.join(", ")
+            ),
         }
-        Ok(())
     }
 }
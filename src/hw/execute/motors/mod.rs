@@ -3,27 +3,31 @@
 // in development anyways
 #![cfg_attr(feature = "dev_no_motors", allow(unused_imports, unused_macros))]
 
+pub mod backend;
 pub mod error;
+#[cfg(not(feature = "dev_no_motors"))]
+mod sim;
 
+pub use self::backend::{MotorBackend, MotorEStop, MotorState};
 use self::error::{MotorError, MotorsError};
+#[cfg(not(feature = "dev_no_motors"))]
+use self::{backend::unreferenced_axes, sim::SimMotors};
 use super::{
     super::decode::{AxisMovement, ExtruderMovement, Movement},
     SharedRawPos,
 };
+#[cfg(not(feature = "dev_no_motors"))]
+use crate::config::MotorBackendKind;
 use crate::{
     comms::ReferenceRunOptParameters,
     config::{AxisMotor as AxisMotorConfig, Config, ExtruderMotor as ExtruderMotorConfig},
     settings::Settings,
 };
 use anyhow::{ensure, Context, Result};
-// we want to mask the EStop struct for the dev_no_motors build since otherwise
-// that would make the build fail
-#[cfg(not(feature = "dev_no_motors"))]
-use nanotec_stepper_driver::EStop;
 use nanotec_stepper_driver::{
     AllMotor, Driver, DriverError, ErrorCorrectionMode, Ignore, LimitSwitchBehavior,
     LimitSwitchBehaviorNormal, LimitSwitchBehaviorReference, Motor, MotorStatus, PositioningMode,
-    RampType, Repetitions, RespondMode, ResponseHandle, SendAutoStatus,
+    RampType, Repetitions, RespondMode, ResponseHandle, RotationDirection, SendAutoStatus,
 };
 use std::{
     sync::{
@@ -39,20 +43,36 @@ struct AxisMotorWrap {
     pos_steps: Arc<AtomicI32>,
 }
 
-// TODO maybe store state for all motors as in valid or invalid
-// -> set invalid after encountered error,
-// -> set invalid at the beginning
-//
-// => no move allowed when state is invalid, only reference can fix that. if that
-//    fails, keep state invalid
+/// The `nanotec_stepper_driver`-backed [`MotorBackend`], talking to the real
+/// motors over a serialport bus
 #[cfg(not(feature = "dev_no_motors"))]
-pub struct Motors {
+struct NanotecMotors {
     settings: Settings,
     all: AllMotor,
     x: AxisMotorWrap,
     y: AxisMotorWrap,
     z: AxisMotorWrap,
     e: Motor<SendAutoStatus>,
+    x_state: MotorState,
+    y_state: MotorState,
+    z_state: MotorState,
+}
+
+/// Dispatches to whichever [`MotorBackend`] `[motors] backend` selects;
+/// everything outside this module (the executor, `start()`) only ever sees
+/// this, so it can't tell whether it's driving real hardware or
+/// [`SimMotors`]
+#[cfg(not(feature = "dev_no_motors"))]
+pub struct Motors {
+    backend: Box<dyn MotorBackend>,
+}
+
+// same conversion `decode::inner_decoder` uses, duplicated here since probing
+// and the homing back-off are the only places this module needs to turn mm
+// into steps itself; every
+// other movement already arrives pre-converted in an `AxisMovement`
+fn mm_to_steps(mm: f64, cfg: &AxisMotorConfig) -> i32 {
+    ((mm / cfg.translation) * (360.0 / 1.8) * (cfg.step_size as u8) as f64) as i32
 }
 
 fn prepare_move_axis(
@@ -63,8 +83,10 @@ fn prepare_move_axis(
     // if distance is set to 0, ignore setting the other values, it means
     // the motor won't move anyways
     if am.distance != 0 {
-        // don't set min frequency, since that is alwyas the same and we already
-        // set it
+        // the planner resolves this move's entry/exit frequency from the
+        // junction it shares with its neighbours, so it has to be sent every
+        // move rather than left at init_axis's standstill default
+        motor.set_min_frequency(am.min_frequency)?.wait().unwrap();
         motor.set_max_frequency(am.max_frequency)?.wait().unwrap();
         motor
             .set_accel_ramp_no_conversion(am.acceleration)?
@@ -87,25 +109,30 @@ fn prepare_move_axis(
 }
 
 macro_rules! make_reference_motor {
-    ($name:ident, $axis:ident) => {
-        pub fn $name(
-            &mut self,
-            settings: &Settings,
-            params: ReferenceRunOptParameters,
-        ) -> Result<()> {
-            Motors::reference_motor(
+    ($name:ident, $axis:ident, $state:ident) => {
+        fn $name(&mut self, settings: &Settings, params: ReferenceRunOptParameters) -> Result<()> {
+            // mark invalid up front so a failed re-reference doesn't leave a
+            // previously-valid axis looking trustworthy
+            self.$state = MotorState::Invalid;
+            let cfg = &settings.config().motors.$axis;
+            NanotecMotors::reference_motor(
                 &mut self.$axis.motor,
                 params
                     .speed
                     .unwrap_or(settings.motors().$axis().get_reference_speed()),
+                cfg.reference_speed_slow,
                 params
                     .accel_decel
                     .unwrap_or(settings.motors().$axis().get_reference_accel_decel()),
                 params
                     .jerk
                     .unwrap_or(settings.motors().$axis().get_reference_jerk()),
+                mm_to_steps(cfg.home_backoff, cfg),
+                cfg.home_probes,
+                cfg.endstop_direction,
             )?;
             self.$axis.pos_steps.store(0, Ordering::Release);
+            self.$state = MotorState::Valid;
             Ok(())
         }
     };
@@ -113,7 +140,12 @@ macro_rules! make_reference_motor {
 
 macro_rules! make_move_motor {
     ($name:ident, $axis:ident) => {
-        pub fn $name(&mut self, m: &AxisMovement) -> Result<(), MotorError> {
+        // blocks until this move's own MotorStatus comes back before
+        // returning, so the motor has already come to a full stop by the
+        // time the caller can issue the next move; see
+        // AxisMovement::min_frequency's doc for what that means for the
+        // planner's resolved junction speed
+        fn $name(&mut self, m: &AxisMovement) -> Result<(), MotorError> {
             self.$axis
                 .motor
                 .set_respond_mode(RespondMode::Quiet)?
@@ -144,8 +176,11 @@ macro_rules! make_move_motor {
 }
 
 #[cfg(not(feature = "dev_no_motors"))]
-impl Motors {
-    pub(super) fn new(settings: Settings, shared_pos: SharedRawPos) -> Result<(Self, EStop)> {
+impl NanotecMotors {
+    fn new(
+        settings: Settings,
+        shared_pos: SharedRawPos,
+    ) -> Result<(Self, nanotec_stepper_driver::EStop)> {
         let cfg = settings.config();
         let iface = serialport::new(cfg.motors.port.as_str(), cfg.motors.baud_rate)
             .timeout(Duration::from_secs(cfg.motors.timeout))
@@ -186,11 +221,135 @@ impl Motors {
                 pos_steps: shared_pos.z,
             },
             e,
+            x_state: MotorState::Invalid,
+            y_state: MotorState::Invalid,
+            z_state: MotorState::Invalid,
         };
         Ok((motors, estop))
     }
 
-    pub fn init(&mut self) -> Result<()> {
+    /// Approaches the endstop in `endstop_direction` at `speed` to find it
+    /// roughly, then runs `probes` back-off/slow-re-approach passes (retreat
+    /// `backoff_steps` away from the switch, re-approach at `speed_slow`) for
+    /// a more repeatable trigger point than a single run gives; every pass,
+    /// fast or slow, has to actually report `MotorStatus::Ready` or this
+    /// returns an error instead of trusting a switch that may never have
+    /// triggered
+    fn reference_motor(
+        motor: &mut Motor<SendAutoStatus>,
+        speed: u32,
+        speed_slow: u32,
+        accel: u32,
+        jerk: u32,
+        backoff_steps: i32,
+        probes: u32,
+        endstop_direction: RotationDirection,
+    ) -> Result<()> {
+        fn approach(
+            motor: &mut Motor<SendAutoStatus>,
+            speed: u32,
+            accel: u32,
+            jerk: u32,
+        ) -> Result<()> {
+            motor
+                .set_positioning_mode(PositioningMode::ExternalReference)?
+                .wait()
+                .ignore()?;
+            // don't set min frequency, since that is alwyas the same and we already
+            // set it
+            motor.set_max_frequency(speed)?.wait().ignore()?;
+            motor.set_accel_ramp_no_conversion(accel)?.wait().ignore()?;
+            motor.set_brake_ramp_no_conversion(accel)?.wait().ignore()?;
+            motor.set_max_accel_jerk(jerk)?.wait().ignore()?;
+            motor.set_max_brake_jerk(jerk)?.wait().ignore()?;
+            let status = motor.start_motor()?.wait().ignore()?.wait().ignore()?;
+            ensure!(
+                status == MotorStatus::Ready,
+                "motor error while referencing, status was {}",
+                status
+            );
+            Ok(())
+        }
+
+        fn back_off(
+            motor: &mut Motor<SendAutoStatus>,
+            steps: i32,
+            away_from_endstop: RotationDirection,
+            accel: u32,
+            jerk: u32,
+        ) -> Result<()> {
+            motor
+                .set_rotation_direction(away_from_endstop)?
+                .wait()
+                .ignore()?;
+            motor
+                .set_positioning_mode(PositioningMode::Relative)?
+                .wait()
+                .ignore()?;
+            motor.set_travel_distance(steps)?.wait().ignore()?;
+            motor.set_accel_ramp_no_conversion(accel)?.wait().ignore()?;
+            motor.set_brake_ramp_no_conversion(accel)?.wait().ignore()?;
+            motor.set_max_accel_jerk(jerk)?.wait().ignore()?;
+            motor.set_max_brake_jerk(jerk)?.wait().ignore()?;
+            let status = motor.start_motor()?.wait().ignore()?.wait().ignore()?;
+            ensure!(
+                status == MotorStatus::Ready,
+                "motor error while backing off the endstop, status was {}",
+                status
+            );
+            Ok(())
+        }
+
+        let away_from_endstop = match endstop_direction {
+            RotationDirection::Left => RotationDirection::Right,
+            RotationDirection::Right => RotationDirection::Left,
+        };
+
+        approach(motor, speed, accel, jerk)?;
+        for _ in 0..probes {
+            back_off(motor, backoff_steps, away_from_endstop, accel, jerk)?;
+            // rotation_direction was flipped to back off; point it back at
+            // the endstop before re-approaching
+            motor
+                .set_rotation_direction(endstop_direction)?
+                .wait()
+                .ignore()?;
+            approach(motor, speed_slow, accel, jerk)?;
+        }
+
+        // reset values to what they were before, see also init_motor
+        motor
+            .set_positioning_mode(PositioningMode::Absolute)?
+            .wait()
+            .ignore()?;
+        Ok(())
+    }
+
+    fn update_xzy(&self, m: &Movement) {
+        macro_rules! update_axis {
+            ($axis:ident) => {{
+                self.$axis
+                    .pos_steps
+                    .store(m.$axis.distance, Ordering::Release)
+            }};
+        }
+        update_axis!(x);
+        update_axis!(y);
+        update_axis!(z);
+    }
+}
+
+#[cfg(not(feature = "dev_no_motors"))]
+impl MotorEStop for nanotec_stepper_driver::EStop {
+    fn estop(&mut self, millis: u64) -> Result<()> {
+        nanotec_stepper_driver::EStop::estop(self, millis)?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "dev_no_motors"))]
+impl MotorBackend for NanotecMotors {
+    fn init(&mut self) -> Result<()> {
         fn init_axis(motor: &mut Motor<SendAutoStatus>, config: &AxisMotorConfig) -> Result<()> {
             motor.set_step_mode(config.step_size)?.wait().ignore()?;
             // Maybe change normal limit switch behavior to all stop or all ignore?
@@ -225,8 +384,8 @@ impl Motors {
                 .set_positioning_mode(PositioningMode::Absolute)?
                 .wait()
                 .ignore()?;
-            // set min frequency here so we dont have to set it later, which
-            // saves commands to send to the motor
+            // standstill default; prepare_move_axis overrides this with the
+            // planner-resolved junction frequency before every real move
             motor.set_min_frequency(1)?.wait().ignore()?;
             motor
                 .set_rotation_direction(config.endstop_direction)?
@@ -294,56 +453,71 @@ impl Motors {
         Ok(())
     }
 
-    fn reference_motor(
-        motor: &mut Motor<SendAutoStatus>,
-        speed: u32,
-        accel: u32,
-        jerk: u32,
-    ) -> Result<()> {
-        motor
-            .set_positioning_mode(PositioningMode::ExternalReference)?
-            .wait()
-            .ignore()?;
-        // don't set min frequency, since that is alwyas the same and we already
-        // set it
-        motor.set_max_frequency(speed)?.wait().ignore()?;
-        motor.set_accel_ramp_no_conversion(accel)?.wait().ignore()?;
-        motor.set_brake_ramp_no_conversion(accel)?.wait().ignore()?;
-        motor.set_max_accel_jerk(jerk)?.wait().ignore()?;
-        motor.set_max_brake_jerk(jerk)?.wait().ignore()?;
-        let status = motor.start_motor()?.wait().ignore()?.wait().ignore()?;
-        // reset values to what they were before, see also init_motor
-        motor
-            .set_positioning_mode(PositioningMode::Absolute)?
-            .wait()
-            .ignore()?;
-        ensure!(
-            status == MotorStatus::Ready,
-            "motor error while referencing, status was {}",
-            status
-        );
-        Ok(())
-    }
-
-    make_reference_motor!(reference_x, x);
-    make_reference_motor!(reference_y, y);
-    make_reference_motor!(reference_z, z);
+    make_reference_motor!(reference_x, x, x_state);
+    make_reference_motor!(reference_y, y, y_state);
+    make_reference_motor!(reference_z, z, z_state);
 
-    fn update_xzy(&self, m: &Movement) {
-        macro_rules! update_axis {
-            ($axis:ident) => {{
-                self.$axis
-                    .pos_steps
-                    .store(m.$axis.distance, Ordering::Release)
-            }};
+    /// Slowly lowers z in small increments (`AxisMotorConfig::default_probe_step`
+    /// mm per step), checking for a [`MotorStatus::PosError`] after each one,
+    /// until either a step stalls (the nozzle made contact with the bed/print)
+    /// or the configured axis `limit` is exhausted without ever stalling
+    ///
+    /// Returns the step position contact was detected at. This is only an
+    /// approximation bounded by one probe step, since (unlike referencing
+    /// into a limit switch) there's no way to read back the exact position
+    /// the motor actually stopped at, only that it stopped; see the FIXME on
+    /// `g28`.
+    fn probe_z_hotend(
+        &mut self,
+        settings: &Settings,
+        params: ReferenceRunOptParameters,
+    ) -> Result<i32> {
+        let cfg = &settings.config().motors.z;
+        let max_frequency = params.speed.unwrap_or(cfg.default_probe_speed);
+        let acceleration = params.accel_decel.unwrap_or(cfg.default_probe_accel);
+        let acceleration_jerk = params.jerk.unwrap_or(cfg.default_probe_jerk);
+        let probe_step = mm_to_steps(cfg.default_probe_step, cfg);
+        let max_steps = mm_to_steps(cfg.limit as f64, cfg);
+        let mut target = 0;
+        loop {
+            target -= probe_step;
+            ensure!(
+                target.unsigned_abs() <= max_steps.unsigned_abs(),
+                "z probe travelled its full axis limit without detecting contact with the print head"
+            );
+            let movement = AxisMovement {
+                distance: target,
+                min_frequency: 0,
+                max_frequency,
+                acceleration,
+                deceleration: acceleration,
+                acceleration_jerk,
+                deceleration_jerk: acceleration_jerk,
+            };
+            match self.move_z(&movement) {
+                Ok(()) => continue,
+                Err(MotorError::PositionError) => {
+                    self.z.pos_steps.store(target, Ordering::Release);
+                    return Ok(target);
+                }
+                Err(e) => return Err(e.into()),
+            }
         }
-        update_axis!(x);
-        update_axis!(y);
-        update_axis!(z);
     }
 
     // will only return a DriverError or MotorsError
-    pub fn move_all(&mut self, m: &Movement, config: &Config) -> Result<()> {
+    //
+    // like make_move_motor!, blocks for every axis's own MotorStatus before
+    // returning, so each move still runs to a full stop before the next
+    // one's commands go out; see AxisMovement::min_frequency's doc
+    fn move_all(&mut self, m: &Movement, config: &Config) -> Result<()> {
+        // refuse to move a referenceable axis we don't trust the position of
+        // instead of silently trusting a stale position
+        let unreferenced = unreferenced_axes(m, self.x_state, self.y_state, self.z_state);
+        if !unreferenced.is_empty() {
+            return Err(MotorsError::NotReferenced { axes: unreferenced }.into());
+        }
+
         // set all quiet so setting of values goes faster
         // we can unwrap here and all following until we set respondmode to notquiet
         // again because there won't be any response anyways so there can't be
@@ -365,8 +539,9 @@ impl Motors {
             // the motor won't move anyways
             if em.distance != 0 {
                 motor.set_rotation_direction(em.direction)?.wait().unwrap();
-                // don't set min frequency, since that is alwyas the same and we already
-                // set it
+                // see prepare_move_axis: the planner-resolved value has to be
+                // sent every move, not just once at init
+                motor.set_min_frequency(em.min_frequency)?.wait().unwrap();
                 motor.set_max_frequency(em.max_frequency)?.wait().unwrap();
                 motor
                     .set_accel_ramp_no_conversion(em.acceleration)?
@@ -388,9 +563,17 @@ impl Motors {
             Ok(())
         }
 
-        prepare_move_axis(&mut self.x.motor, &m.x)?;
-        prepare_move_axis(&mut self.y.motor, &m.y)?;
-        prepare_move_axis(&mut self.z.motor, &m.z)?;
+        macro_rules! prepare_axis {
+            ($axis:ident, $state:ident) => {
+                if let Err(e) = prepare_move_axis(&mut self.$axis.motor, &m.$axis) {
+                    self.$state = MotorState::Invalid;
+                    return Err(e.into());
+                }
+            };
+        }
+        prepare_axis!(x, x_state);
+        prepare_axis!(y, y_state);
+        prepare_axis!(z, z_state);
         prepare_move_extruder(&mut self.e, &m.e)?;
 
         // set respondmode to notquiet so we will receive the status once
@@ -423,27 +606,37 @@ impl Motors {
             self.update_xzy(m);
             Ok(())
         } else {
-            // invariant of MotorsError will be fulfilled because errs isn't empty
-            let mut me = MotorsError {
-                x: None,
-                y: None,
-                z: None,
-                e: None,
-            };
+            let (mut ex, mut ey, mut ez, mut ee) = (None, None, None, None);
             // we can unwrap because we already know that these are the errors
             for (addr, err) in errs.into_iter().map(|t| (t.0, t.1.unwrap_err())) {
                 // since the returnvalue of all.start_motors is a map of
                 // address -> Result we need to map the address back to the actual
                 // motor again
                 match addr {
-                    x if x == self.x.motor.address() => me.x = Some(err),
-                    y if y == self.y.motor.address() => me.y = Some(err),
-                    z if z == self.z.motor.address() => me.z = Some(err),
-                    e if e == self.e.address() => me.e = Some(err),
+                    x if x == self.x.motor.address() => {
+                        self.x_state = MotorState::Invalid;
+                        ex = Some(err);
+                    }
+                    y if y == self.y.motor.address() => {
+                        self.y_state = MotorState::Invalid;
+                        ey = Some(err);
+                    }
+                    z if z == self.z.motor.address() => {
+                        self.z_state = MotorState::Invalid;
+                        ez = Some(err);
+                    }
+                    e if e == self.e.address() => ee = Some(err),
                     _ => unreachable!("Received error from address that doesn't exist in the driver, it should have thrown an error already")
                 }
             }
-            Err(me.into())
+            // invariant of MotorsError::Faults is fulfilled because errs wasn't empty
+            Err(MotorsError::Faults {
+                x: ex,
+                y: ey,
+                z: ez,
+                e: ee,
+            }
+            .into())
         }
     }
 
@@ -452,6 +645,88 @@ impl Motors {
     make_move_motor!(move_z, z);
 }
 
+#[cfg(not(feature = "dev_no_motors"))]
+impl Motors {
+    pub(super) fn new(
+        settings: Settings,
+        shared_pos: SharedRawPos,
+    ) -> Result<(Self, Box<dyn MotorEStop>)> {
+        Ok(match settings.config().motors.backend {
+            MotorBackendKind::Nanotec => {
+                let (motors, estop) = NanotecMotors::new(settings, shared_pos)?;
+                (
+                    Self {
+                        backend: Box::new(motors),
+                    },
+                    Box::new(estop),
+                )
+            }
+            MotorBackendKind::Sim => (
+                Self {
+                    backend: Box::new(SimMotors::new(
+                        shared_pos,
+                        settings.config().motors.position_error_rate,
+                        settings.config().motors.driver_error_rate,
+                    )),
+                },
+                Box::new(sim::SimEStop),
+            ),
+        })
+    }
+
+    pub fn init(&mut self) -> Result<()> {
+        self.backend.init()
+    }
+
+    pub fn reference_x(
+        &mut self,
+        settings: &Settings,
+        params: ReferenceRunOptParameters,
+    ) -> Result<()> {
+        self.backend.reference_x(settings, params)
+    }
+
+    pub fn reference_y(
+        &mut self,
+        settings: &Settings,
+        params: ReferenceRunOptParameters,
+    ) -> Result<()> {
+        self.backend.reference_y(settings, params)
+    }
+
+    pub fn reference_z(
+        &mut self,
+        settings: &Settings,
+        params: ReferenceRunOptParameters,
+    ) -> Result<()> {
+        self.backend.reference_z(settings, params)
+    }
+
+    pub fn probe_z_hotend(
+        &mut self,
+        settings: &Settings,
+        params: ReferenceRunOptParameters,
+    ) -> Result<i32> {
+        self.backend.probe_z_hotend(settings, params)
+    }
+
+    pub fn move_all(&mut self, m: &Movement, config: &Config) -> Result<()> {
+        self.backend.move_all(m, config)
+    }
+
+    pub fn move_x(&mut self, m: &AxisMovement) -> Result<(), MotorError> {
+        self.backend.move_x(m)
+    }
+
+    pub fn move_y(&mut self, m: &AxisMovement) -> Result<(), MotorError> {
+        self.backend.move_y(m)
+    }
+
+    pub fn move_z(&mut self, m: &AxisMovement) -> Result<(), MotorError> {
+        self.backend.move_z(m)
+    }
+}
+
 #[cfg(feature = "dev_no_motors")]
 pub struct EStop {}
 
@@ -506,6 +781,14 @@ impl Motors {
         Ok(())
     }
 
+    pub fn probe_z_hotend(
+        &mut self,
+        _settings: &Settings,
+        _params: ReferenceRunOptParameters,
+    ) -> Result<i32> {
+        Ok(0)
+    }
+
     pub fn move_all(&mut self, _m: &Movement, _config: &Config) -> Result<()> {
         Ok(())
     }
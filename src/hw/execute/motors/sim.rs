@@ -0,0 +1,239 @@
+use super::{
+    super::super::decode::{AxisMovement, Movement},
+    backend::{unreferenced_axes, MotorBackend, MotorEStop, MotorState},
+    error::{MotorError, MotorsError},
+    SharedRawPos,
+};
+use crate::{comms::ReferenceRunOptParameters, config::Config, log::target, settings::Settings};
+use anyhow::Result;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::sync::atomic::Ordering;
+use tracing::debug;
+
+/// An in-memory stand-in for [`super::NanotecMotors`], used when `[motors]
+/// backend = "sim"`; lets the rest of the daemon (API, executor, estop
+/// thread) run end-to-end with no serial port attached
+///
+/// Axis positions live in the same [`SharedRawPos`] atomics the real
+/// backend writes to, so anything downstream that reads back a position
+/// (e.g. the z-hotend reference) can't tell which backend is driving. There's
+/// no real motion to simulate a duration for: a move "completes" the instant
+/// it's requested, and referencing "finds" its simulated endstop the same
+/// way.
+///
+/// `position_error_rate`/`driver_error_rate` (`[motors] position_error_rate`/
+/// `driver_error_rate`) let a test configure this backend to fail on
+/// purpose: each simulated axis move draws once from `rng`, failing with a
+/// [`MotorError::PositionError`] or [`MotorError::SimulatedDriverFault`] at
+/// those probabilities, so `move_all`'s `MotorsError::Faults` mapping can be
+/// exercised without real hardware ever misbehaving.
+#[derive(Debug)]
+pub struct SimMotors {
+    shared_pos: SharedRawPos,
+    x_state: MotorState,
+    y_state: MotorState,
+    z_state: MotorState,
+    e_pos_steps: i32,
+    position_error_rate: f64,
+    driver_error_rate: f64,
+    rng: StdRng,
+}
+
+impl SimMotors {
+    pub(super) fn new(
+        shared_pos: SharedRawPos,
+        position_error_rate: f64,
+        driver_error_rate: f64,
+    ) -> Self {
+        Self {
+            shared_pos,
+            x_state: MotorState::Invalid,
+            y_state: MotorState::Invalid,
+            z_state: MotorState::Invalid,
+            e_pos_steps: 0,
+            position_error_rate,
+            driver_error_rate,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// One simulated `start_motor` draw: `driver_error_rate` and
+    /// `position_error_rate` are each a slice of `[0.0, 1.0)`, checked in
+    /// that order so the two never overlap
+    fn draw_fault(&mut self) -> Option<MotorError> {
+        let draw: f64 = self.rng.gen();
+        if draw < self.driver_error_rate {
+            Some(MotorError::SimulatedDriverFault)
+        } else if draw < self.driver_error_rate + self.position_error_rate {
+            Some(MotorError::PositionError)
+        } else {
+            None
+        }
+    }
+}
+
+impl MotorBackend for SimMotors {
+    fn init(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn reference_x(
+        &mut self,
+        _settings: &Settings,
+        _params: ReferenceRunOptParameters,
+    ) -> Result<()> {
+        debug!(target: target::INTERNAL, "sim: referenced x");
+        self.shared_pos.x.store(0, Ordering::Release);
+        self.x_state = MotorState::Valid;
+        Ok(())
+    }
+
+    fn reference_y(
+        &mut self,
+        _settings: &Settings,
+        _params: ReferenceRunOptParameters,
+    ) -> Result<()> {
+        debug!(target: target::INTERNAL, "sim: referenced y");
+        self.shared_pos.y.store(0, Ordering::Release);
+        self.y_state = MotorState::Valid;
+        Ok(())
+    }
+
+    fn reference_z(
+        &mut self,
+        _settings: &Settings,
+        _params: ReferenceRunOptParameters,
+    ) -> Result<()> {
+        debug!(target: target::INTERNAL, "sim: referenced z");
+        self.shared_pos.z.store(0, Ordering::Release);
+        self.z_state = MotorState::Valid;
+        Ok(())
+    }
+
+    fn probe_z_hotend(
+        &mut self,
+        settings: &Settings,
+        _params: ReferenceRunOptParameters,
+    ) -> Result<i32> {
+        // simulate contact halfway down the configured travel limit, since
+        // there's no real bed/nozzle to actually stall against
+        let cfg = &settings.config().motors.z;
+        let contact = -super::mm_to_steps(cfg.limit as f64 / 2.0, cfg);
+        self.shared_pos.z.store(contact, Ordering::Release);
+        Ok(contact)
+    }
+
+    fn move_all(&mut self, m: &Movement, _config: &Config) -> Result<()> {
+        let unreferenced = unreferenced_axes(m, self.x_state, self.y_state, self.z_state);
+        if !unreferenced.is_empty() {
+            return Err(MotorsError::NotReferenced { axes: unreferenced }.into());
+        }
+        // one draw per axis, same as the real backend getting one status
+        // back per motor from a single start_motor() broadcast
+        let (ex, ey, ez, ee) = (
+            self.draw_fault(),
+            self.draw_fault(),
+            self.draw_fault(),
+            self.draw_fault(),
+        );
+        if ex.is_some() || ey.is_some() || ez.is_some() || ee.is_some() {
+            if ex.is_some() {
+                self.x_state = MotorState::Invalid;
+            }
+            if ey.is_some() {
+                self.y_state = MotorState::Invalid;
+            }
+            if ez.is_some() {
+                self.z_state = MotorState::Invalid;
+            }
+            return Err(MotorsError::Faults {
+                x: ex,
+                y: ey,
+                z: ez,
+                e: ee,
+            }
+            .into());
+        }
+        self.shared_pos.x.store(m.x.distance, Ordering::Release);
+        self.shared_pos.y.store(m.y.distance, Ordering::Release);
+        self.shared_pos.z.store(m.z.distance, Ordering::Release);
+        self.e_pos_steps = m.e.distance as i32;
+        Ok(())
+    }
+
+    fn move_x(&mut self, m: &AxisMovement) -> Result<(), MotorError> {
+        if let Some(e) = self.draw_fault() {
+            return Err(e);
+        }
+        self.shared_pos.x.store(m.distance, Ordering::Release);
+        Ok(())
+    }
+
+    fn move_y(&mut self, m: &AxisMovement) -> Result<(), MotorError> {
+        if let Some(e) = self.draw_fault() {
+            return Err(e);
+        }
+        self.shared_pos.y.store(m.distance, Ordering::Release);
+        Ok(())
+    }
+
+    fn move_z(&mut self, m: &AxisMovement) -> Result<(), MotorError> {
+        if let Some(e) = self.draw_fault() {
+            return Err(e);
+        }
+        self.shared_pos.z.store(m.distance, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// A no-op estop for [`SimMotors`], consistent with the `dev_no_motors`
+/// build's stub `EStop`
+#[derive(Debug, Default)]
+pub struct SimEStop;
+
+impl MotorEStop for SimEStop {
+    fn estop(&mut self, _millis: u64) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn motors(position_error_rate: f64, driver_error_rate: f64) -> SimMotors {
+        SimMotors::new(SharedRawPos::default(), position_error_rate, driver_error_rate)
+    }
+
+    #[test]
+    fn draw_fault_never_fires_at_zero_rates() {
+        let mut motors = motors(0.0, 0.0);
+        for _ in 0..100 {
+            assert!(motors.draw_fault().is_none());
+        }
+    }
+
+    #[test]
+    fn draw_fault_always_reports_driver_fault_at_rate_one() {
+        let mut motors = motors(0.0, 1.0);
+        assert!(matches!(
+            motors.draw_fault(),
+            Some(MotorError::SimulatedDriverFault)
+        ));
+    }
+
+    #[test]
+    fn draw_fault_always_reports_position_error_at_rate_one() {
+        let mut motors = motors(1.0, 0.0);
+        assert!(matches!(motors.draw_fault(), Some(MotorError::PositionError)));
+    }
+
+    #[test]
+    fn move_x_reports_the_drawn_fault_instead_of_moving() {
+        let mut motors = motors(1.0, 0.0);
+        assert!(matches!(
+            motors.move_x(&AxisMovement::default()),
+            Err(MotorError::PositionError)
+        ));
+    }
+}
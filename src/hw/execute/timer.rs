@@ -0,0 +1,121 @@
+use crate::{comms::ControlComms, hw::comms::CancelReason};
+use crossbeam::{channel, channel::Receiver, select};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    time::{Duration, Instant},
+};
+
+// ticks the queue counts deadlines in; chosen independently of the
+// executor's batching tick (`config::Execute::tick_micros`) since this one
+// only needs to be fine enough that rounding a scheduled wait to the
+// nearest tick is imperceptible
+const TICK_HZ: u64 = 1000;
+
+/// A monotonic, tick-counted min-heap of scheduled wakeups
+///
+/// Durations are converted to ticks once, at schedule time, so every
+/// queued deadline is compared against the same `now_tick()` instead of
+/// each carrying its own `Instant`. Backs [`Action::Wait`][wait] so waits
+/// go through one small, testable scheduler instead of an ad hoc
+/// `thread::sleep`.
+///
+/// [wait]: crate::hw::decode::Action::Wait
+#[derive(Debug)]
+pub struct TimerQueue {
+    origin: Instant,
+    // min-heap via `Reverse`, so the earliest deadline is always on top
+    deadlines: BinaryHeap<Reverse<u64>>,
+}
+
+impl TimerQueue {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            deadlines: BinaryHeap::new(),
+        }
+    }
+
+    fn now_tick(&self) -> u64 {
+        duration_to_ticks(self.origin.elapsed())
+    }
+
+    /// Schedules a wakeup `duration` from now
+    pub fn schedule(&mut self, duration: Duration) {
+        // saturate rather than overflow on an absurdly long wait
+        let deadline = self.now_tick().saturating_add(duration_to_ticks(duration));
+        self.deadlines.push(Reverse(deadline));
+    }
+
+    /// How long until the next scheduled deadline; `None` means the queue
+    /// is empty, i.e. there's nothing left to wait for
+    pub fn time_until_next(&self) -> Option<Duration> {
+        self.deadlines
+            .peek()
+            .map(|Reverse(deadline)| ticks_to_duration(deadline.saturating_sub(self.now_tick())))
+    }
+
+    /// Pops every deadline that has already passed, coalescing any that
+    /// landed on the same tick into a single call; returns how many fired
+    pub fn pop_expired(&mut self) -> usize {
+        let now = self.now_tick();
+        let mut fired = 0;
+        while matches!(self.deadlines.peek(), Some(Reverse(deadline)) if *deadline <= now) {
+            self.deadlines.pop();
+            fired += 1;
+        }
+        fired
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deadlines.is_empty()
+    }
+
+    /// Blocks until the next scheduled deadline has passed or `cancel_recv`
+    /// fires first, popping the deadline in the former case
+    ///
+    /// A no-op if the queue is empty. Returns `Some(reason)` if cancelled
+    /// before the deadline passed; the deadline is popped in that case too,
+    /// so an abandoned wait can't hold up the next one.
+    ///
+    /// Selects between [`channel::after`] and `cancel_recv` instead of a
+    /// plain `thread::sleep`, so a cancellation (e.g. the print being
+    /// stopped or paused) interrupts the wait instead of hanging until the
+    /// deadline.
+    pub fn park_until_next(
+        &mut self,
+        cancel_recv: &Receiver<ControlComms<CancelReason>>,
+    ) -> Option<CancelReason> {
+        if let Some(remaining) = self.time_until_next() {
+            select! {
+                recv(channel::after(remaining)) -> _ => {}
+                recv(cancel_recv) -> msg => {
+                    self.deadlines.pop();
+                    return Some(match msg.expect("cancel channel was unexpectedly closed") {
+                        ControlComms::Msg(reason) => reason,
+                        ControlComms::Exit => CancelReason::Stop,
+                    });
+                }
+            }
+        }
+        self.pop_expired();
+        None
+    }
+}
+
+impl Default for TimerQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn duration_to_ticks(duration: Duration) -> u64 {
+    duration
+        .as_secs()
+        .saturating_mul(TICK_HZ)
+        .saturating_add((duration.subsec_nanos() as u64).saturating_mul(TICK_HZ) / 1_000_000_000)
+}
+
+fn ticks_to_duration(ticks: u64) -> Duration {
+    Duration::from_secs_f64(ticks as f64 / TICK_HZ as f64)
+}
@@ -0,0 +1,139 @@
+use super::{start as hw_start, HwCtrl, PositionInfo, ResumeError, StateError, StateInfo};
+use crate::{comms::ControlComms, settings::Settings};
+use anyhow::{Error, Result};
+use crossbeam::channel::Sender;
+use std::{collections::HashMap, fmt, path::PathBuf, thread::JoinHandle};
+use thiserror::Error;
+
+/// Identifies one printer in a [`Fleet`]; typically the key it's configured
+/// under (e.g. `"printer1"`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PrinterId(String);
+
+impl PrinterId {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+}
+
+impl fmt::Display for PrinterId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FleetError {
+    #[error("no printer configured with id {0}")]
+    UnknownPrinter(PrinterId),
+    #[error(transparent)]
+    State(#[from] StateError),
+    #[error(transparent)]
+    Resume(#[from] ResumeError),
+    #[error(transparent)]
+    Other(#[from] Error),
+}
+
+struct FleetPrinter {
+    ctrl: HwCtrl,
+    // the estop thread handle HwCtrl::init hands back alongside itself; see
+    // Fleet::exit
+    estop_handle: JoinHandle<()>,
+}
+
+/// One `HwCtrl` (and the executor/estop thread pair [`super::start`] spawns
+/// for it) per configured printer, routed to by [`PrinterId`] instead of this
+/// process only ever driving a single machine
+///
+/// Each printer is otherwise fully independent of the others -- a fault on
+/// one doesn't touch any other's state -- except for [`Self::broadcast_estop`],
+/// which deliberately reaches across all of them at once.
+pub struct Fleet {
+    printers: HashMap<PrinterId, FleetPrinter>,
+}
+
+impl Fleet {
+    /// Starts one printer per `(id, settings)` pair
+    ///
+    /// # Errors
+    /// Fails the whole fleet if any single printer fails to start, same as a
+    /// single [`super::start`] would
+    pub fn start(
+        printers: impl IntoIterator<Item = (PrinterId, Settings)>,
+        error_send: Sender<ControlComms<Error>>,
+    ) -> Result<Self> {
+        let printers = printers
+            .into_iter()
+            .map(|(id, settings)| {
+                let (estop_handle, ctrl) = hw_start(settings, error_send.clone())?;
+                Ok((id, FleetPrinter { ctrl, estop_handle }))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+        Ok(Self { printers })
+    }
+
+    /// Every configured printer's id
+    pub fn ids(&self) -> impl Iterator<Item = &PrinterId> {
+        self.printers.keys()
+    }
+
+    /// The underlying [`HwCtrl`] for `id`, for anything not already routed
+    /// through one of `Fleet`'s own methods
+    pub fn printer(&self, id: &PrinterId) -> Result<&HwCtrl, FleetError> {
+        self.printers
+            .get(id)
+            .map(|p| &p.ctrl)
+            .ok_or_else(|| FleetError::UnknownPrinter(id.clone()))
+    }
+
+    pub fn state_info(&self, id: &PrinterId) -> Result<StateInfo, FleetError> {
+        Ok(self.printer(id)?.state_info())
+    }
+
+    pub fn pos_info(&self, id: &PrinterId) -> Result<PositionInfo, FleetError> {
+        Ok(self.printer(id)?.pos_info())
+    }
+
+    pub fn try_print(&self, id: &PrinterId, path: PathBuf) -> Result<(), FleetError> {
+        Ok(self.printer(id)?.try_print(path)?)
+    }
+
+    pub fn try_resume(&self, id: &PrinterId) -> Result<(), FleetError> {
+        Ok(self.printer(id)?.try_resume()?)
+    }
+
+    pub fn try_play(&self, id: &PrinterId) -> Result<(), FleetError> {
+        Ok(self.printer(id)?.try_play()?)
+    }
+
+    pub fn try_pause(&self, id: &PrinterId) -> Result<(), FleetError> {
+        Ok(self.printer(id)?.try_pause()?)
+    }
+
+    pub fn stop(&self, id: &PrinterId) -> Result<(), FleetError> {
+        self.printer(id)?.stop();
+        Ok(())
+    }
+
+    pub fn estop(&self, id: &PrinterId) -> Result<(), FleetError> {
+        self.printer(id)?.estop();
+        Ok(())
+    }
+
+    /// E-stops every configured printer at once, instead of requiring a
+    /// separate call per id
+    pub fn broadcast_estop(&self) {
+        for printer in self.printers.values() {
+            printer.ctrl.estop();
+        }
+    }
+
+    /// Shuts down every printer and waits for their estop threads to exit,
+    /// the same sequence `main` runs for a single [`HwCtrl`]
+    pub fn exit(self) {
+        for printer in self.printers.into_values() {
+            printer.ctrl.exit();
+            printer.estop_handle.join().unwrap();
+        }
+    }
+}
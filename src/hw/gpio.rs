@@ -0,0 +1,347 @@
+//! A safety interlock for machines without a dedicated I/O driver (like
+//! RevPi's `/dev/piControl0`, see [`super::pi::RevPi`]) or motor-integrated
+//! limit switches: polls a handful of digital inputs opened directly through
+//! the Linux sysfs GPIO interface (`/sys/class/gpio`), debounces them, and
+//! reacts to a physical e-stop button and a filament-runout switch exactly
+//! like the existing [`InputEvent`] pipeline does; external per-axis
+//! endstops are only recorded for [`HwCtrl::gpio_endstops`][gpio_endstops] to
+//! cross-check against the motor driver's own status after a reference run.
+//!
+//! Every pin is opt-in via [`crate::config::Gpio`]; a machine with nothing
+//! configured just runs an idle thread.
+//!
+//! [gpio_endstops]: super::HwCtrl::gpio_endstops
+
+use super::pi::InputEvent;
+use crate::{
+    api::values::ErrorCode, comms::ControlComms, config::GpioPin, log::target, settings::Settings,
+};
+use anyhow::{Context, Result};
+use crossbeam::channel::{self, Receiver, RecvTimeoutError, Sender};
+use serde_json::Value;
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    mem::ManuallyDrop,
+    sync::{Arc, RwLock},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tracing::debug;
+
+const GPIO_SYSFS_ROOT: &str = "/sys/class/gpio";
+
+#[derive(Debug, Error)]
+pub enum SysfsGpioError {
+    #[error("failed to export gpio {0} via {}/export", GPIO_SYSFS_ROOT)]
+    Export(u32, #[source] std::io::Error),
+    #[error("failed to set gpio {0} to input direction")]
+    Direction(u32, #[source] std::io::Error),
+    #[error("failed to open the value file for gpio {0}")]
+    OpenValue(u32, #[source] std::io::Error),
+    #[error("failed to read the value file for gpio {0}")]
+    ReadValue(u32, #[source] std::io::Error),
+    #[error("gpio {0}'s value file contained {1:?}, expected \"0\" or \"1\"")]
+    InvalidValue(u32, String),
+}
+
+/// A physical e-stop button or filament-runout switch tripped through the
+/// gpio subsystem, surfaced as an [`crate::api::values::ApiError`] in
+/// addition to the [`InputEvent`] it triggers, since unlike RevPi's polling
+/// this is new, easy-to-miswire hardware worth calling out distinctly
+#[derive(Debug, Error)]
+pub enum GpioTripError {
+    #[error("the gpio e-stop input (pin {0}) was triggered")]
+    EStop(u32),
+    #[error("the gpio filament-runout input (pin {0}) was triggered")]
+    FilamentRunout(u32),
+}
+
+impl GpioTripError {
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode::GpioInput
+    }
+
+    pub fn details(&self) -> Option<Value> {
+        None
+    }
+}
+
+/// One digital input opened via the Linux sysfs GPIO interface
+#[derive(Debug)]
+struct SysfsGpio {
+    pin: u32,
+    active_low: bool,
+    value_file: File,
+}
+
+impl SysfsGpio {
+    fn open(cfg: &GpioPin) -> Result<Self, SysfsGpioError> {
+        let gpio_dir = format!("{}/gpio{}", GPIO_SYSFS_ROOT, cfg.pin);
+        if !std::path::Path::new(&gpio_dir).exists() {
+            std::fs::write(format!("{}/export", GPIO_SYSFS_ROOT), cfg.pin.to_string())
+                .map_err(|e| SysfsGpioError::Export(cfg.pin, e))?;
+        }
+        std::fs::write(format!("{}/direction", gpio_dir), "in")
+            .map_err(|e| SysfsGpioError::Direction(cfg.pin, e))?;
+        let value_file = File::open(format!("{}/value", gpio_dir))
+            .map_err(|e| SysfsGpioError::OpenValue(cfg.pin, e))?;
+        Ok(Self {
+            pin: cfg.pin,
+            active_low: cfg.active_low,
+            value_file,
+        })
+    }
+
+    /// Whether the pin currently reads as active, already accounting for
+    /// `active_low`
+    fn read_active(&mut self) -> Result<bool, SysfsGpioError> {
+        self.value_file
+            .seek(SeekFrom::Start(0))
+            .map_err(|e| SysfsGpioError::ReadValue(self.pin, e))?;
+        let mut buf = String::new();
+        self.value_file
+            .read_to_string(&mut buf)
+            .map_err(|e| SysfsGpioError::ReadValue(self.pin, e))?;
+        let high = match buf.trim() {
+            "0" => false,
+            "1" => true,
+            other => return Err(SysfsGpioError::InvalidValue(self.pin, other.to_owned())),
+        };
+        Ok(high != self.active_low)
+    }
+}
+
+/// Filters raw, possibly bouncing gpio samples into a stable level, only
+/// reporting a change once the new level has held for the configured
+/// debounce duration
+#[derive(Debug)]
+struct Debounce {
+    stable: bool,
+    pending: Option<(bool, Instant)>,
+}
+
+impl Debounce {
+    fn new(initial: bool) -> Self {
+        Self {
+            stable: initial,
+            pending: None,
+        }
+    }
+
+    /// Feeds a fresh raw sample, returning `Some(level)` the instant a new
+    /// level has held stable for `debounce`; `None` while unchanged or still
+    /// bouncing
+    fn sample(&mut self, raw: bool, debounce: Duration) -> Option<bool> {
+        if raw == self.stable {
+            self.pending = None;
+            return None;
+        }
+        match self.pending {
+            Some((level, since)) if level == raw => {
+                if since.elapsed() >= debounce {
+                    self.stable = raw;
+                    self.pending = None;
+                    Some(raw)
+                } else {
+                    None
+                }
+            }
+            _ => {
+                self.pending = Some((raw, Instant::now()));
+                None
+            }
+        }
+    }
+}
+
+/// A configured gpio input, opened and ready to be polled
+struct MonitoredPin {
+    gpio: SysfsGpio,
+    debounce: Debounce,
+    debounce_duration: Duration,
+}
+
+impl MonitoredPin {
+    fn open(cfg: &GpioPin) -> Result<Self, SysfsGpioError> {
+        let mut gpio = SysfsGpio::open(cfg)?;
+        let initial = gpio.read_active()?;
+        Ok(Self {
+            gpio,
+            debounce: Debounce::new(initial),
+            debounce_duration: Duration::from_millis(cfg.debounce_millis),
+        })
+    }
+
+    /// Polls the pin once, returning a freshly debounced level if it just
+    /// changed
+    fn poll(&mut self) -> Result<Option<bool>, SysfsGpioError> {
+        let raw = self.gpio.read_active()?;
+        Ok(self.debounce.sample(raw, self.debounce_duration))
+    }
+}
+
+/// Latest debounced level of each externally-wired gpio endstop, `None` if
+/// that axis has no gpio endstop configured
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GpioEndstops {
+    pub x: Option<bool>,
+    pub y: Option<bool>,
+    pub z: Option<bool>,
+}
+
+struct MonitoredPins {
+    estop: Option<MonitoredPin>,
+    x_endstop: Option<MonitoredPin>,
+    y_endstop: Option<MonitoredPin>,
+    z_endstop: Option<MonitoredPin>,
+    filament_runout: Option<MonitoredPin>,
+}
+
+impl MonitoredPins {
+    fn open(settings: &Settings) -> Result<(Self, GpioEndstops)> {
+        let cfg = &settings.config().gpio;
+        let open = |pin: &Option<GpioPin>| -> Result<Option<MonitoredPin>> {
+            pin.as_ref()
+                .map(MonitoredPin::open)
+                .transpose()
+                .context("failed to open a configured gpio pin")
+        };
+        let x_endstop = open(&cfg.x_endstop)?;
+        let y_endstop = open(&cfg.y_endstop)?;
+        let z_endstop = open(&cfg.z_endstop)?;
+        let endstops = GpioEndstops {
+            x: x_endstop.as_ref().map(|p| p.debounce.stable),
+            y: y_endstop.as_ref().map(|p| p.debounce.stable),
+            z: z_endstop.as_ref().map(|p| p.debounce.stable),
+        };
+        Ok((
+            Self {
+                estop: open(&cfg.estop)?,
+                x_endstop,
+                y_endstop,
+                z_endstop,
+                filament_runout: open(&cfg.filament_runout)?,
+            },
+            endstops,
+        ))
+    }
+}
+
+fn gpio_monitor_loop(
+    settings: Settings,
+    gpio_monitor_recv: Receiver<ControlComms<()>>,
+    input_event_send: Sender<ControlComms<InputEvent>>,
+    error_send: Sender<ControlComms<anyhow::Error>>,
+    mut pins: MonitoredPins,
+    endstops: Arc<RwLock<GpioEndstops>>,
+) {
+    let poll_interval = Duration::from_millis(settings.config().gpio.poll_interval_millis);
+    loop {
+        match gpio_monitor_recv.recv_timeout(poll_interval) {
+            Ok(ControlComms::Exit) | Err(RecvTimeoutError::Disconnected) => break,
+            // nothing else is ever sent over this channel
+            Ok(ControlComms::Msg(())) => unreachable!(),
+            Err(RecvTimeoutError::Timeout) => (),
+        }
+        if let Some(pin) = pins.estop.as_mut() {
+            match pin.poll() {
+                Ok(Some(true)) => {
+                    debug!(target: target::INTERNAL, "gpio e-stop pin {} tripped", pin.gpio.pin);
+                    input_event_send
+                        .send(ControlComms::Msg(InputEvent::EStop))
+                        .unwrap();
+                    error_send
+                        .send(ControlComms::Msg(GpioTripError::EStop(pin.gpio.pin).into()))
+                        .unwrap();
+                }
+                Ok(_) => (),
+                Err(e) => error_send.send(ControlComms::Msg(e.into())).unwrap(),
+            }
+        }
+        if let Some(pin) = pins.filament_runout.as_mut() {
+            match pin.poll() {
+                Ok(Some(true)) => {
+                    debug!(target: target::INTERNAL, "gpio filament-runout pin {} tripped", pin.gpio.pin);
+                    input_event_send
+                        .send(ControlComms::Msg(InputEvent::FilamentRunout))
+                        .unwrap();
+                    error_send
+                        .send(ControlComms::Msg(
+                            GpioTripError::FilamentRunout(pin.gpio.pin).into(),
+                        ))
+                        .unwrap();
+                }
+                Ok(_) => (),
+                Err(e) => error_send.send(ControlComms::Msg(e.into())).unwrap(),
+            }
+        }
+        macro_rules! poll_endstop {
+            ($pin:expr, $field:ident) => {
+                if let Some(pin) = $pin.as_mut() {
+                    match pin.poll() {
+                        Ok(Some(level)) => endstops.write().unwrap().$field = Some(level),
+                        Ok(None) => (),
+                        Err(e) => error_send.send(ControlComms::Msg(e.into())).unwrap(),
+                    }
+                }
+            };
+        }
+        poll_endstop!(pins.x_endstop, x);
+        poll_endstop!(pins.y_endstop, y);
+        poll_endstop!(pins.z_endstop, z);
+    }
+}
+
+/// Owns the gpio monitor thread; mirrors the `*Ctrl`/background-thread
+/// pattern used by [`super::InputMonitorCtrl`]/[`super::CheckpointCtrl`]
+#[derive(Debug)]
+pub struct GpioMonitorCtrl {
+    gpio_monitor_handle: ManuallyDrop<JoinHandle<()>>,
+    gpio_monitor_send: Sender<ControlComms<()>>,
+}
+
+impl GpioMonitorCtrl {
+    pub fn start(
+        settings: Settings,
+        input_event_send: Sender<ControlComms<InputEvent>>,
+        error_send: Sender<ControlComms<anyhow::Error>>,
+    ) -> Result<(Self, Arc<RwLock<GpioEndstops>>)> {
+        let (pins, endstops) = MonitoredPins::open(&settings)?;
+        let endstops = Arc::new(RwLock::new(endstops));
+        let endstops_clone = Arc::clone(&endstops);
+        let (gpio_monitor_send, gpio_monitor_recv) = channel::unbounded();
+        let gpio_monitor_handle = thread::Builder::new()
+            .name(String::from("gpio_monitor"))
+            .spawn(move || {
+                gpio_monitor_loop(
+                    settings,
+                    gpio_monitor_recv,
+                    input_event_send,
+                    error_send,
+                    pins,
+                    endstops_clone,
+                )
+            })
+            .context("Creating the gpio monitor thread failed")?;
+        Ok((
+            Self {
+                gpio_monitor_handle: ManuallyDrop::new(gpio_monitor_handle),
+                gpio_monitor_send,
+            },
+            endstops,
+        ))
+    }
+}
+
+impl Drop for GpioMonitorCtrl {
+    fn drop(&mut self) {
+        self.gpio_monitor_send.send(ControlComms::Exit).unwrap();
+        // safety:
+        // since we are in drop, self.gpio_monitor_handle will not be used again
+        unsafe { ManuallyDrop::take(&mut self.gpio_monitor_handle) }
+            .join()
+            .unwrap();
+    }
+}
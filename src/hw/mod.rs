@@ -1,32 +1,106 @@
 mod callbacks;
+mod checkpoint;
 mod comms;
 mod decode;
 mod execute;
+mod fleet;
+mod gpio;
 mod pi;
 mod state;
 
 use self::{
-    callbacks::{EStopCallback, StopCallback},
+    callbacks::{PrintOutcome, StopCallback, StopReason},
     comms::EStopComms,
     execute::{ExecutorCtrl, ExecutorStopper, OutOfBoundsError},
-    pi::PiCtrl,
+    gpio::GpioMonitorCtrl,
+    pi::{InputEvent, PiCtrl},
     state::{State, StateInfo as InnerStateInfo},
 };
-pub use self::{decode::error::GCodeError, state::StateError};
+pub use self::{
+    decode::{
+        error::GCodeError, Accumulator, DecoderError, DecoderErrorKind, ExtensionDataStore,
+        GCodeErrorKind, GCodeSpan,
+    },
+    execute::{
+        mnemonic_from_str, Breakpoint, MotorError, MotorsError, PiThermalBackend, ThermalBackend,
+        UnknownMnemonicError,
+    },
+    fleet::{Fleet, FleetError, PrinterId},
+    gpio::{GpioEndstops, GpioTripError},
+    pi::{InputState, InputsSnapshot, OvertempError, RunawayError},
+    state::StateError,
+};
 use crate::{
     comms::{Axis, ControlComms, ReferenceRunOptParameters},
+    log::target,
     settings::Settings,
     util::ensure_own,
+    APP_NAME,
 };
-use anyhow::{ensure, Error, Result};
-use crossbeam::channel::{self, Sender};
+use anyhow::{ensure, Context, Error, Result};
+use checkpoint::Checkpoint;
+use crossbeam::channel::{self, Receiver, RecvTimeoutError, Select, Sender};
 use serde::Serialize;
 use std::{
+    fs,
+    mem::ManuallyDrop,
     path::PathBuf,
     sync::{Arc, RwLock},
-    thread::JoinHandle,
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 use thiserror::Error;
+use tracing::warn;
+
+/// Resolves a configured `macros.<name>` gcode source to an openable path:
+/// used directly if it names an existing file, otherwise treated as the
+/// literal gcode and written out to a scratch file, since the executor can
+/// currently only run a macro from a file/path like a regular print
+fn resolve_macro_gcode(name: &str, gcode: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(gcode);
+    if path.is_file() {
+        return Ok(path);
+    }
+    let path = std::env::temp_dir().join(format!("{}-{}-macro.gcode", APP_NAME, name));
+    fs::write(&path, gcode).context("failed to write out inline macro gcode")?;
+    Ok(path)
+}
+
+/// Resolves and runs the `macros.<name>` gcode, if any is configured; logs a
+/// warning instead of propagating an error, since a failing macro shouldn't
+/// be able to take down whatever triggered it (daemon startup, a stop, ...)
+fn run_configured_macro(executor_ctrl: &ExecutorCtrl, name: &str, gcode: &Option<String>) {
+    let Some(gcode) = gcode else {
+        return;
+    };
+    let result = resolve_macro_gcode(name, gcode).and_then(|path| executor_ctrl.run_macro(path));
+    if let Err(e) = result {
+        warn!(target: target::INTERNAL, "failed to run {} gcode macro: {:#}", name, e);
+    }
+}
+
+/// Like [`run_configured_macro`], but blocks the caller until the macro
+/// gcode actually finishes running, via the same completion channel
+/// [`ExecutorCtrl::print_with_handle`] hands a real print; only
+/// `macros.print_start` needs this, since a print must not start until its
+/// print_start macro has actually finished running
+fn run_configured_macro_blocking(executor_ctrl: &ExecutorCtrl, name: &str, gcode: &Option<String>) {
+    let Some(gcode) = gcode else {
+        return;
+    };
+    let result = resolve_macro_gcode(name, gcode).and_then(|path| {
+        let outcome_recv = executor_ctrl.print_with_handle(path)?;
+        match outcome_recv.recv() {
+            Ok(PrintOutcome::Failed(e)) => Err(e),
+            // `Stopped`/a disconnected channel both mean nothing is left to
+            // wait for; whoever called this doesn't need to distinguish why
+            _ => Ok(()),
+        }
+    });
+    if let Err(e) = result {
+        warn!(target: target::INTERNAL, "failed to run {} gcode macro: {:#}", name, e);
+    }
+}
 
 #[derive(Debug, Error)]
 pub enum TryReferenceError {
@@ -36,6 +110,16 @@ pub enum TryReferenceError {
     OutOfBoundsError(#[from] OutOfBoundsError),
 }
 
+#[derive(Debug, Error)]
+pub enum ResumeError {
+    #[error(transparent)]
+    StateError(#[from] StateError),
+    #[error("there is no checkpointed print to resume")]
+    NoCheckpoint,
+    #[error(transparent)]
+    Other(#[from] Error),
+}
+
 #[derive(Debug, Serialize)]
 pub struct PrintingStateInfo {
     path: PathBuf,
@@ -66,39 +150,275 @@ pub struct PositionInfo {
     pub z: f64,
 }
 
+pub struct TemperatureInfo {
+    pub hotend: f64,
+    pub hotend_target: Option<u16>,
+    pub bed: f64,
+    pub bed_target: Option<u16>,
+}
+
 struct ExecutorGCodeCallback {
     state: Arc<RwLock<State>>,
+    settings: Settings,
+    pi_ctrl: Arc<PiCtrl>,
+    executor_ctrl: Arc<ExecutorCtrl>,
 }
 
 impl ExecutorGCodeCallback {
-    fn new(state: Arc<RwLock<State>>) -> Self {
-        Self { state }
+    fn new(
+        state: Arc<RwLock<State>>,
+        settings: Settings,
+        pi_ctrl: Arc<PiCtrl>,
+        executor_ctrl: Arc<ExecutorCtrl>,
+    ) -> Self {
+        Self {
+            state,
+            settings,
+            pi_ctrl,
+            executor_ctrl,
+        }
     }
 }
 
 impl StopCallback for ExecutorGCodeCallback {
-    fn stop(&self) {
+    fn stop(&self, reason: StopReason) {
         let mut state = self.state.write().unwrap();
-        // TODO maybe ensure that heaters etc. are turned off?
+        // shouldn't panic because decoder should check the target
+        self.pi_ctrl.try_set_hotend_target(None).unwrap();
+        // shouldn't panic because decoder should check the target
+        self.pi_ctrl.try_set_bed_target(None).unwrap();
         state.stop();
+        let (name, gcode) = match reason {
+            StopReason::Cancelled => ("cancel", &self.settings.config().macros.cancel),
+            StopReason::Finished => ("idle", &self.settings.config().macros.idle),
+        };
+        run_configured_macro(&self.executor_ctrl, name, gcode);
     }
 }
 
-struct PiCtrlCallbacks {
-    // state: Arc<RwLock<State>>,
-    // executor_stopper: ExecutorStopper,
-    estop_send: Sender<ControlComms<EStopComms>>,
+/// Reacts to the safety-relevant input edges the pi thread forwards over
+/// `input_event_recv`, since the pi thread itself can't know about
+/// `executor_ctrl`/`state` (it's started before either exists, see the
+/// module doc on [`callbacks`])
+///
+/// Mirrors the `*Ctrl`/background-thread pattern used by [`CheckpointCtrl`].
+#[derive(Debug)]
+struct InputMonitorCtrl {
+    input_monitor_handle: ManuallyDrop<JoinHandle<()>>,
+    input_monitor_send: Sender<ControlComms<()>>,
+}
+
+impl InputMonitorCtrl {
+    fn start(
+        state: Arc<RwLock<State>>,
+        executor_ctrl: Arc<ExecutorCtrl>,
+        estop_send: Sender<ControlComms<EStopComms>>,
+        input_event_recv: Receiver<ControlComms<InputEvent>>,
+    ) -> Result<Self> {
+        let (input_monitor_send, input_monitor_recv) = channel::unbounded();
+        let input_monitor_handle = thread::Builder::new()
+            .name(String::from("input_monitor"))
+            .spawn(move || loop {
+                let mut sel = Select::new();
+                let exit_idx = sel.recv(&input_monitor_recv);
+                let event_idx = sel.recv(&input_event_recv);
+                let op = sel.select();
+                match op.index() {
+                    idx if idx == exit_idx => {
+                        match op.recv(&input_monitor_recv) {
+                            Ok(ControlComms::Exit) | Err(_) => break,
+                            // nothing else is ever sent over this channel
+                            Ok(ControlComms::Msg(())) => unreachable!(),
+                        }
+                    }
+                    idx if idx == event_idx => match op.recv(&input_event_recv) {
+                        Ok(ControlComms::Msg(InputEvent::EStop)) => {
+                            // the pi thread already cut the heaters on this
+                            // same edge; halt the motors too
+                            estop_send
+                                .send(ControlComms::Msg(EStopComms::EStop))
+                                .unwrap();
+                            state.write().unwrap().stop();
+                        }
+                        Ok(ControlComms::Msg(InputEvent::FilamentRunout)) => {
+                            let mut state = state.write().unwrap();
+                            if !state.is_stopped() {
+                                executor_ctrl.pause();
+                                state.pause().expect("already checked !state.is_stopped() above");
+                            }
+                        }
+                        Ok(ControlComms::Exit) | Err(_) => break,
+                    },
+                    _ => unreachable!("selected an index that wasn't registered"),
+                }
+            })
+            .context("Creating the input monitor thread failed")?;
+        Ok(Self {
+            input_monitor_handle: ManuallyDrop::new(input_monitor_handle),
+            input_monitor_send,
+        })
+    }
+}
+
+impl Drop for InputMonitorCtrl {
+    fn drop(&mut self) {
+        self.input_monitor_send.send(ControlComms::Exit).unwrap();
+        // safety:
+        // since we are in drop, self.input_monitor_handle will not be used again
+        unsafe { ManuallyDrop::take(&mut self.input_monitor_handle) }
+            .join()
+            .unwrap();
+    }
+}
+
+/// Periodically persists the active print's progress to disk, so it can be
+/// resumed if the daemon dies mid-print
+///
+/// Mirrors the `*Ctrl`/background-thread pattern used by [`PiCtrl`] and
+/// [`ExecutorCtrl`]: owns the thread handle and tears it down on drop.
+#[derive(Debug)]
+struct CheckpointCtrl {
+    checkpoint_handle: ManuallyDrop<JoinHandle<()>>,
+    checkpoint_send: Sender<ControlComms<()>>,
+}
+
+impl CheckpointCtrl {
+    fn start(
+        settings: Settings,
+        state: Arc<RwLock<State>>,
+        executor_ctrl: Arc<ExecutorCtrl>,
+        pi_ctrl: Arc<PiCtrl>,
+    ) -> Result<Self> {
+        let (checkpoint_send, checkpoint_recv) = channel::unbounded();
+        let checkpoint_handle = thread::Builder::new()
+            .name(String::from("checkpoint"))
+            .spawn(move || loop {
+                let interval = Duration::from_secs(settings.config().checkpoint.interval_secs);
+                match checkpoint_recv.recv_timeout(interval) {
+                    Ok(ControlComms::Exit) | Err(RecvTimeoutError::Disconnected) => break,
+                    Err(RecvTimeoutError::Timeout) => {
+                        let checkpoint_path = &settings.config().checkpoint.path;
+                        let result = match state.read().unwrap().info() {
+                            InnerStateInfo::Printing(path) | InnerStateInfo::Paused(path) => {
+                                Checkpoint::new(
+                                    path,
+                                    executor_ctrl.current_line(),
+                                    pi_ctrl.hotend_target(),
+                                    pi_ctrl.bed_target(),
+                                )
+                                .save(checkpoint_path)
+                            }
+                            // nothing to resume, so don't let a stale checkpoint linger
+                            InnerStateInfo::Stopped => Checkpoint::clear(checkpoint_path),
+                        };
+                        if let Err(e) = result {
+                            warn!(target: target::INTERNAL, "failed to update checkpoint: {:#}", e);
+                        }
+                    }
+                }
+            })
+            .context("Creating the checkpoint thread failed")?;
+        Ok(Self {
+            checkpoint_handle: ManuallyDrop::new(checkpoint_handle),
+            checkpoint_send,
+        })
+    }
+}
+
+impl Drop for CheckpointCtrl {
+    fn drop(&mut self) {
+        self.checkpoint_send.send(ControlComms::Exit).unwrap();
+        // safety:
+        // since we are in drop, self.checkpoint_handle will not be used again
+        unsafe { ManuallyDrop::take(&mut self.checkpoint_handle) }
+            .join()
+            .unwrap();
+    }
+}
+
+/// Runs the configured `macros.idle` gcode again once the printer has been
+/// sitting stopped for `macros.idle_timeout` seconds with nothing happening,
+/// on top of the `idle`/`cancel` macro that already runs the instant a print
+/// stops/finishes
+///
+/// Mirrors the `*Ctrl`/background-thread pattern used by [`CheckpointCtrl`].
+#[derive(Debug)]
+struct IdleCtrl {
+    idle_handle: ManuallyDrop<JoinHandle<()>>,
+    idle_send: Sender<ControlComms<()>>,
 }
 
-// TODO uncomment once estop on the pi thread is actually implemented
-// impl EStopCallback for PiCtrlCallbacks {
-//     fn estop(&self) {
-//         self.estop_send
-//             .send(ControlComms::Msg(EStopComms::EStop))
-//             .unwrap()
-//     }
-// }
+impl IdleCtrl {
+    fn start(
+        settings: Settings,
+        state: Arc<RwLock<State>>,
+        executor_ctrl: Arc<ExecutorCtrl>,
+    ) -> Result<Self> {
+        let (idle_send, idle_recv) = channel::unbounded();
+        let idle_handle = thread::Builder::new()
+            .name(String::from("idle_timeout"))
+            .spawn(move || loop {
+                // poll at a fixed, short cadence rather than sleeping for
+                // the whole configured `idle_timeout`, so a shorter timeout
+                // picked up via a config reload takes effect promptly
+                // instead of only after the old, longer one elapses
+                let poll_interval = Duration::from_secs(1);
+                match idle_recv.recv_timeout(poll_interval) {
+                    Ok(ControlComms::Exit) | Err(RecvTimeoutError::Disconnected) => break,
+                    Err(RecvTimeoutError::Timeout) => {
+                        if let Some(timeout) = settings.config().macros.idle_timeout {
+                            let fired = state
+                                .write()
+                                .unwrap()
+                                .idle_timeout_elapsed(Duration::from_secs(timeout));
+                            if fired {
+                                run_configured_macro(
+                                    &executor_ctrl,
+                                    "idle",
+                                    &settings.config().macros.idle,
+                                );
+                            }
+                        }
+                    }
+                }
+            })
+            .context("Creating the idle-timeout thread failed")?;
+        Ok(Self {
+            idle_handle: ManuallyDrop::new(idle_handle),
+            idle_send,
+        })
+    }
+}
 
+impl Drop for IdleCtrl {
+    fn drop(&mut self) {
+        self.idle_send.send(ControlComms::Exit).unwrap();
+        // safety:
+        // since we are in drop, self.idle_handle will not be used again
+        unsafe { ManuallyDrop::take(&mut self.idle_handle) }
+            .join()
+            .unwrap();
+    }
+}
+
+/// Owns everything needed to run exactly one printer: one `executor`/estop
+/// thread pair (via `executor_ctrl`/`estop_send`), one `PiCtrl`, one
+/// checkpoint file. There's deliberately no id here distinguishing it from
+/// some other printer, because nothing in this process currently runs more
+/// than one `HwCtrl` at a time.
+///
+/// Turning this into a fleet (one daemon managing several machines) isn't a
+/// leaf change on `HwCtrl` itself -- `state`/`pi_ctrl`/`checkpoint_ctrl`/
+/// `gpio_endstops` etc. would all need to become per-printer, `settings`
+/// would need a `[[printer]]`-style table instead of one global config, and
+/// every api endpoint that reaches `HwCtrl` through `AppState` (see
+/// `api::mod`) would need a printer id added to its route and request types.
+/// `start()`/`ExecutorCtrl` already take everything they need as plain
+/// arguments (settings, a `PiCtrl`, channels), so spawning several pairs of
+/// them keyed by an id is the easy part; the actual work is threading that id
+/// through the api and config layers above this module, which is too broad a
+/// change to land as one incremental step without breaking every other
+/// request in this backlog that assumes a single printer.
 #[derive(Debug, Clone)]
 pub struct HwCtrl {
     state: Arc<RwLock<State>>,
@@ -106,6 +426,15 @@ pub struct HwCtrl {
     executor_ctrl: Arc<ExecutorCtrl>,
     pi_ctrl: Arc<PiCtrl>,
     estop_send: Sender<ControlComms<EStopComms>>,
+    checkpoint_ctrl: Arc<CheckpointCtrl>,
+    input_monitor_ctrl: Arc<InputMonitorCtrl>,
+    idle_ctrl: Arc<IdleCtrl>,
+    gpio_monitor_ctrl: Arc<GpioMonitorCtrl>,
+    gpio_endstops: Arc<RwLock<GpioEndstops>>,
+    // the checkpoint found on startup, if any, waiting to be resumed via
+    // `try_resume`; taken once resumed (or once something else starts a
+    // fresh print, making it stale)
+    pending_resume: Arc<RwLock<Option<Checkpoint>>>,
 }
 
 macro_rules! pos_info_axis {
@@ -125,27 +454,83 @@ impl HwCtrl {
         // lock state so we have sole control over the state and noone else
         // can for example report an error until all parts are fully initialised
         let _lock = state.write().unwrap();
-        let pi_ctrl = pi::start(settings.clone(), error_send.clone())?;
+        let (input_event_send, input_event_recv) = channel::unbounded();
+        let pi_ctrl = pi::start(
+            settings.clone(),
+            error_send.clone(),
+            input_event_send.clone(),
+        )?;
         let pi_ctrl = Arc::new(pi_ctrl);
+        let (gpio_monitor_ctrl, gpio_endstops) =
+            GpioMonitorCtrl::start(settings.clone(), input_event_send, error_send.clone())?;
         let (estop_send, estop_recv) = channel::unbounded();
         let (exec_stopper, exec_start) = execute::init();
-        let (estop_handle, executor_ctrl) =
-            exec_start(settings.clone(), pi_ctrl.clone(), estop_recv, error_send)?;
+        let (estop_handle, executor_ctrl) = exec_start(
+            settings.clone(),
+            pi_ctrl.clone(),
+            estop_recv,
+            estop_send.clone(),
+            error_send,
+            Arc::clone(&gpio_endstops),
+        )?;
+        let executor_ctrl = Arc::new(executor_ctrl);
+        let pending_resume = Checkpoint::load(&settings.config().checkpoint.path)
+            .context("failed to load checkpoint file")?;
+        let checkpoint_ctrl = CheckpointCtrl::start(
+            settings.clone(),
+            Arc::clone(&state),
+            Arc::clone(&executor_ctrl),
+            Arc::clone(&pi_ctrl),
+        )?;
+        let input_monitor_ctrl = InputMonitorCtrl::start(
+            Arc::clone(&state),
+            Arc::clone(&executor_ctrl),
+            estop_send.clone(),
+            input_event_recv,
+        )?;
+        let idle_ctrl =
+            IdleCtrl::start(settings.clone(), Arc::clone(&state), Arc::clone(&executor_ctrl))?;
         // since we're done with the setup we can unlock state to be able to move
         // it
         drop(_lock);
+        run_configured_macro(&executor_ctrl, "startup", &settings.config().macros.startup);
         Ok((
             estop_handle,
             Self {
                 state,
                 settings,
-                executor_ctrl: Arc::new(executor_ctrl),
+                executor_ctrl,
                 pi_ctrl,
                 estop_send,
+                checkpoint_ctrl: Arc::new(checkpoint_ctrl),
+                input_monitor_ctrl: Arc::new(input_monitor_ctrl),
+                idle_ctrl: Arc::new(idle_ctrl),
+                gpio_monitor_ctrl: Arc::new(gpio_monitor_ctrl),
+                gpio_endstops,
+                pending_resume: Arc::new(RwLock::new(pending_resume)),
             },
         ))
     }
 
+    /// Current level and rising-edge count of every digital input the pi
+    /// thread polls (endstops, e-stop, filament runout)
+    pub fn inputs(&self) -> InputsSnapshot {
+        self.pi_ctrl.inputs()
+    }
+
+    /// Latest debounced level of each externally-wired gpio endstop
+    /// (`None` for an axis without one configured), to cross-check against
+    /// the motor driver's own status
+    pub fn gpio_endstops(&self) -> GpioEndstops {
+        *self.gpio_endstops.read().unwrap()
+    }
+
+    /// Whether a checkpoint from a previous, interrupted print is available
+    /// to be resumed via [`HwCtrl::try_resume`]
+    pub fn has_pending_resume(&self) -> bool {
+        self.pending_resume.read().unwrap().is_some()
+    }
+
     pub fn state_info(&self) -> StateInfo {
         let state = self.state.read().unwrap();
         StateInfo::new(state.info(), self.executor_ctrl.current_line())
@@ -163,6 +548,17 @@ impl HwCtrl {
         }
     }
 
+    /// Last measured hotend/bed temperatures, regardless of whether a target
+    /// is currently set for either
+    pub fn temperature_info(&self) -> TemperatureInfo {
+        TemperatureInfo {
+            hotend: self.pi_ctrl.hotend_measured(),
+            hotend_target: self.pi_ctrl.hotend_target(),
+            bed: self.pi_ctrl.bed_measured(),
+            bed_target: self.pi_ctrl.bed_target(),
+        }
+    }
+
     pub fn try_reference_axis(
         &self,
         axis: Axis,
@@ -188,11 +584,73 @@ impl HwCtrl {
     pub fn try_print(&self, path: PathBuf) -> Result<()> {
         let mut state = self.state.write().unwrap();
         ensure!(state.is_stopped(), StateError::NotStopped);
+        run_configured_macro_blocking(
+            &self.executor_ctrl,
+            "print_start",
+            &self.settings.config().macros.print_start,
+        );
         self.executor_ctrl.print(
             path.clone(),
-            Box::new(ExecutorGCodeCallback::new(Arc::clone(&self.state))),
+            Box::new(ExecutorGCodeCallback::new(
+                Arc::clone(&self.state),
+                self.settings.clone(),
+                Arc::clone(&self.pi_ctrl),
+                Arc::clone(&self.executor_ctrl),
+            )),
         )?;
-        state.print(path);
+        state
+            .print(path)
+            .expect("already checked state.is_stopped() above");
+        // a fresh print makes any leftover checkpoint from an earlier,
+        // interrupted print stale
+        *self.pending_resume.write().unwrap() = None;
+        Ok(())
+    }
+
+    /// Tries to resume the print recorded in the checkpoint found on
+    /// startup, fast-forwarding the decoder to the checkpointed line and
+    /// re-establishing the checkpointed heater targets
+    ///
+    /// Positions and feedrate don't need to be persisted in the checkpoint
+    /// itself: `StreamDecoder::fast_forward_to` rebuilds them by replaying
+    /// every action up to the checkpointed line back through the decoder
+    /// (without executing any of them), which is equivalent to restoring a
+    /// snapshotted `State` but doesn't need the planner/mesh state to be
+    /// (de)serializable.
+    ///
+    /// FIXME this doesn't move the toolhead back to the checkpointed axis
+    /// position first; there's no "move to absolute position" manual
+    /// command yet for it to reuse, so resuming currently assumes the
+    /// toolhead is still wherever it was when the daemon died. Re-running
+    /// referencing here isn't a substitute, since that homes to the endstop
+    /// rather than the in-progress position.
+    ///
+    /// Should only be used by the API thread, not the decoder thread
+    pub fn try_resume(&self) -> Result<(), ResumeError> {
+        let mut state = self.state.write().unwrap();
+        ensure_own!(state.is_stopped(), StateError::NotStopped);
+        let checkpoint = self
+            .pending_resume
+            .write()
+            .unwrap()
+            .take()
+            .ok_or(ResumeError::NoCheckpoint)?;
+        // shouldn't panic because the checkpointed target was valid when saved
+        self.pi_ctrl
+            .try_set_hotend_target(checkpoint.hotend_target)
+            .unwrap();
+        // shouldn't panic because the checkpointed target was valid when saved
+        self.pi_ctrl
+            .try_set_bed_target(checkpoint.bed_target)
+            .unwrap();
+        self.executor_ctrl
+            .resume(checkpoint.path.clone(), checkpoint.line)?;
+        state
+            .print(checkpoint.path)
+            .expect("already checked state.is_stopped() above");
+        if let Err(e) = Checkpoint::clear(&self.settings.config().checkpoint.path) {
+            warn!(target: target::INTERNAL, "failed to clear checkpoint: {:#}", e);
+        }
         Ok(())
     }
 
@@ -200,13 +658,16 @@ impl HwCtrl {
         let mut state = self.state.write().unwrap();
         self.executor_ctrl.stop();
         state.stop();
+        if let Err(e) = Checkpoint::clear(&self.settings.config().checkpoint.path) {
+            warn!(target: target::INTERNAL, "failed to clear checkpoint: {:#}", e);
+        }
     }
 
     pub fn try_play(&self) -> Result<(), StateError> {
         let mut state = self.state.write().unwrap();
         ensure_own!(!state.is_stopped(), StateError::Stopped);
         self.executor_ctrl.play();
-        state.play();
+        state.play().expect("already checked !state.is_stopped() above");
         Ok(())
     }
 
@@ -217,10 +678,55 @@ impl HwCtrl {
         let mut state = self.state.write().unwrap();
         ensure_own!(!state.is_stopped(), StateError::Stopped);
         self.executor_ctrl.pause();
-        state.pause();
+        state.pause().expect("already checked !state.is_stopped() above");
+        Ok(())
+    }
+
+    pub fn set_breakpoint(&self, breakpoint: Breakpoint) {
+        self.executor_ctrl.set_breakpoint(breakpoint);
+    }
+
+    pub fn clear_breakpoint(&self, breakpoint: Breakpoint) {
+        self.executor_ctrl.clear_breakpoint(breakpoint);
+    }
+
+    pub fn breakpoints(&self) -> Vec<Breakpoint> {
+        self.executor_ctrl.breakpoints()
+    }
+
+    pub fn set_trace_only(&self, trace_only: bool) {
+        self.executor_ctrl.set_trace_only(trace_only);
+    }
+
+    pub fn trace_only(&self) -> bool {
+        self.executor_ctrl.trace_only()
+    }
+
+    /// Ignores breakpoints for the next `count` codes, resuming a print
+    /// currently paused at one the same way [`Self::try_play`] would
+    pub fn try_debug_step(&self, count: u32) -> Result<(), StateError> {
+        let mut state = self.state.write().unwrap();
+        ensure_own!(!state.is_stopped(), StateError::Stopped);
+        self.executor_ctrl.set_step_budget(count);
+        self.executor_ctrl.play();
+        state.play().expect("already checked !state.is_stopped() above");
         Ok(())
     }
 
+    /// The span of the code a breakpoint is currently holding the print in
+    /// front of, if any
+    pub fn debug_span(&self) -> Option<GCodeSpan> {
+        self.executor_ctrl.debug_span()
+    }
+
+    /// Pushes a freshly re-read [`Settings`] into the executor thread
+    ///
+    /// Only affects prints started after this call; see
+    /// [`ExecutorCtrl::reload_settings`].
+    pub fn reload_settings(&self, settings: Settings) {
+        self.executor_ctrl.reload_settings(settings)
+    }
+
     pub fn estop(&self) {
         self.estop_send
             .send(ControlComms::Msg(EStopComms::EStop))
@@ -228,6 +734,10 @@ impl HwCtrl {
     }
 
     pub fn exit(self) {
+        drop(self.checkpoint_ctrl);
+        drop(self.input_monitor_ctrl);
+        drop(self.idle_ctrl);
+        drop(self.gpio_monitor_ctrl);
         drop(self.executor_ctrl);
         drop(self.pi_ctrl);
         self.estop_send.send(ControlComms::Exit).unwrap();
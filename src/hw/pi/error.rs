@@ -1,4 +1,6 @@
+use crate::api::values::ErrorCode;
 use anyhow::Error;
+use serde_json::Value;
 use std::fmt::Display;
 use thiserror::Error;
 
@@ -6,6 +8,10 @@ use thiserror::Error;
 pub enum WaitTempError {
     #[error("The target temperature changed to an incompatible temperature")]
     TargetChanged,
+    #[error("timed out waiting for the target temperature to be reached")]
+    Timeout,
+    #[error("a heater fault tripped the printer's thermal protection")]
+    HeaterFault,
 }
 
 /// Thrown if an error occurs while trying to exit the pi thread
@@ -30,3 +36,47 @@ pub enum PiCtrlError {
     #[error("target temperature is out of bounds, was {}, must be in range [{};{}]", .0, .1, .2)]
     TargetOutOfBounds(u16, u16, u16),
 }
+
+/// A heater was commanded near full power for longer than its configured
+/// timeout without the measured temperature rising enough
+///
+/// Raised by [`PiThreadData::update_hotend_heat`][update_hotend]/
+/// [`update_bed_heat`][update_bed]; by the time this is raised every heater
+/// is already switched off and latched there, so there's nothing left for a
+/// caller to do but surface it to the operator, who has to explicitly
+/// re-arm via `PiCtrl::clear_fault` once the problem's been dealt with.
+///
+/// [update_hotend]: super::PiThreadData::update_hotend_heat
+/// [update_bed]: super::PiThreadData::update_bed_heat
+#[derive(Debug, Error)]
+#[error("thermal runaway: heater was commanded near full power for over {0:?} without the temperature rising by at least {1}°C")]
+pub struct RunawayError(pub std::time::Duration, pub f64);
+
+impl RunawayError {
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode::ThermalRunaway
+    }
+
+    pub fn details(&self) -> Option<Value> {
+        None
+    }
+}
+
+/// The measured temperature exceeded its configured upper limit by more than
+/// `overtemp_margin`, regardless of commanded duty
+///
+/// Raised the same way as [`RunawayError`]; see its docs for what's already
+/// done by the time a caller sees this.
+#[derive(Debug, Error)]
+#[error("overtemp: measured {0}°C exceeds the upper limit of {1}°C by more than the {2}°C margin")]
+pub struct OvertempError(pub f64, pub u16, pub f64);
+
+impl OvertempError {
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode::ThermalRunaway
+    }
+
+    pub fn details(&self) -> Option<Value> {
+        None
+    }
+}
@@ -0,0 +1,75 @@
+use crate::config::TempFilter;
+use std::{collections::VecDeque, f64::consts::PI, time::Duration};
+
+/// The running state backing one `[hotend.filter]`/`[bed.filter]`, smoothing
+/// out noisy thermistor/ADC readings before they reach the overtemp check
+/// and the PID loop
+///
+/// The first sample seeds the filter's output directly instead of starting
+/// from zero, so there's no startup ramp while it catches up to the actual
+/// temperature.
+#[derive(Debug)]
+pub struct TempFilterState {
+    mode: Mode,
+    seeded: bool,
+}
+
+#[derive(Debug)]
+enum Mode {
+    Ewma { alpha: f64, y: f64 },
+    MovingAverage { taps: usize, window: VecDeque<f64> },
+    None,
+}
+
+impl TempFilterState {
+    pub fn new(cfg: &TempFilter, check_interval: Duration) -> Self {
+        let mode = match *cfg {
+            TempFilter::Ewma { cutoff_hz } => {
+                let dt = check_interval.as_secs_f64();
+                let rc = 1.0 / (2.0 * PI * cutoff_hz);
+                Mode::Ewma {
+                    alpha: dt / (dt + rc),
+                    y: 0.0,
+                }
+            }
+            TempFilter::MovingAverage { taps } => Mode::MovingAverage {
+                taps: taps.max(1),
+                window: VecDeque::with_capacity(taps.max(1)),
+            },
+            TempFilter::None => Mode::None,
+        };
+        Self {
+            mode,
+            seeded: false,
+        }
+    }
+
+    /// Feeds in the next raw reading and returns the filtered value
+    pub fn sample(&mut self, raw: f64) -> f64 {
+        if !self.seeded {
+            self.seeded = true;
+            match &mut self.mode {
+                Mode::Ewma { y, .. } => *y = raw,
+                Mode::MovingAverage { taps, window } => {
+                    window.extend(std::iter::repeat(raw).take(*taps))
+                }
+                Mode::None => {}
+            }
+            return raw;
+        }
+        match &mut self.mode {
+            Mode::Ewma { alpha, y } => {
+                *y += *alpha * (raw - *y);
+                *y
+            }
+            Mode::MovingAverage { taps, window } => {
+                window.push_back(raw);
+                if window.len() > *taps {
+                    window.pop_front();
+                }
+                window.iter().sum::<f64>() / window.len() as f64
+            }
+            Mode::None => raw,
+        }
+    }
+}
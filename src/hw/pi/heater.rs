@@ -0,0 +1,209 @@
+use super::error::RunawayError;
+use crate::config::PidControl;
+use std::time::{Duration, Instant};
+
+/// A standard PID controller whose continuous output is clamped to
+/// `0.0..=1.0`, suited for driving a boolean heater as a duty cycle
+///
+/// `output_max` isn't a separate tunable here the way it is on some PID
+/// implementations, since the output already has a fixed, meaningful
+/// ceiling: a duty cycle of `1.0`, i.e. the heater on for the whole
+/// [`PwmWindow`]. Anti-windup is done by clamping the accumulated
+/// `integral` term directly (rather than conditionally pausing
+/// integration while saturated) - simpler, and equivalent in practice
+/// since both just bound how much `Ki*integral` can contribute once the
+/// output unsaturates.
+#[derive(Debug)]
+struct Pid {
+    kp: f64,
+    ki: f64,
+    kd: f64,
+    // anti-windup band the accumulated integral is clamped to, so it can't
+    // keep growing while the output is already saturated and cause a big
+    // overshoot once it isn't anymore
+    integral_limit: f64,
+    integral: f64,
+    last_error: f64,
+}
+
+impl Pid {
+    fn new(kp: f64, ki: f64, kd: f64, integral_limit: f64) -> Self {
+        Self {
+            kp,
+            ki,
+            kd,
+            integral_limit: integral_limit.abs(),
+            integral: 0.0,
+            last_error: 0.0,
+        }
+    }
+
+    /// Advances the loop by `dt` seconds and returns the new duty cycle
+    fn update(&mut self, setpoint: f64, measured: f64, dt: f64) -> f64 {
+        let error = setpoint - measured;
+        self.integral =
+            (self.integral + error * dt).clamp(-self.integral_limit, self.integral_limit);
+        let derivative = if dt > 0.0 {
+            (error - self.last_error) / dt
+        } else {
+            0.0
+        };
+        self.last_error = error;
+        (self.kp * error + self.ki * self.integral + self.kd * derivative).clamp(0.0, 1.0)
+    }
+
+    /// Drops the accumulated integral and derivative history, so a stale
+    /// value from the previous setpoint doesn't cause a big initial
+    /// overshoot/undershoot the next time this loop is given a target
+    fn reset(&mut self) {
+        self.integral = 0.0;
+        self.last_error = 0.0;
+    }
+}
+
+/// Turns a `0.0..=1.0` duty cycle into on/off decisions over a fixed window
+#[derive(Debug)]
+struct PwmWindow {
+    len: Duration,
+    start: Instant,
+}
+
+impl PwmWindow {
+    fn new(len: Duration) -> Self {
+        Self {
+            len,
+            start: Instant::now(),
+        }
+    }
+
+    /// Whether the heater should be on right now for the given duty cycle,
+    /// rolling over into a fresh window once the current one elapses
+    fn sample(&mut self, duty: f64) -> bool {
+        let mut elapsed = self.start.elapsed();
+        if elapsed >= self.len {
+            self.start = Instant::now();
+            elapsed = Duration::ZERO;
+        }
+        elapsed < self.len.mul_f64(duty.clamp(0.0, 1.0))
+    }
+}
+
+/// Watches for a heater being commanded near full power without the
+/// temperature actually rising, which usually means the heater cartridge or
+/// thermistor has come loose
+#[derive(Debug)]
+struct RunawayGuard {
+    threshold: f64,
+    timeout: Duration,
+    min_rise: f64,
+    // when the heater first crossed `threshold`, and the temperature at
+    // that point; reset as soon as the duty drops back below it
+    since: Option<(Instant, f64)>,
+}
+
+impl RunawayGuard {
+    fn new(threshold: f64, timeout: Duration, min_rise: f64) -> Self {
+        Self {
+            threshold,
+            timeout,
+            min_rise,
+            since: None,
+        }
+    }
+
+    /// Feeds in the current duty cycle and measured temperature; returns an
+    /// error once the heater has been commanded near full power for
+    /// `timeout` without the temperature rising by `min_rise`
+    fn check(&mut self, duty: f64, measured: f64) -> Result<(), RunawayError> {
+        if duty < self.threshold {
+            self.since = None;
+            return Ok(());
+        }
+        let &mut (started_at, started_temp) = self.since.get_or_insert((Instant::now(), measured));
+        if started_at.elapsed() < self.timeout {
+            return Ok(());
+        }
+        if measured - started_temp >= self.min_rise {
+            // still rising fast enough; start a fresh timeout window from here
+            self.since = Some((Instant::now(), measured));
+            return Ok(());
+        }
+        Err(RunawayError(self.timeout, self.min_rise))
+    }
+
+    fn reset(&mut self) {
+        self.since = None;
+    }
+}
+
+/// Runs one heater's closed-loop temperature control: a PID loop whose
+/// continuous output is converted into a boolean duty cycle over a fixed
+/// PWM window, with thermal-runaway protection layered on top
+#[derive(Debug)]
+pub struct HeaterLoop {
+    pid: Pid,
+    window: PwmWindow,
+    runaway: RunawayGuard,
+    last_sample: Option<Instant>,
+    hysteresis: f64,
+    hysteresis_samples: u32,
+    at_target_streak: u32,
+}
+
+impl HeaterLoop {
+    pub fn new(cfg: &PidControl) -> Self {
+        Self {
+            pid: Pid::new(cfg.kp, cfg.ki, cfg.kd, cfg.integral_limit),
+            window: PwmWindow::new(Duration::from_millis(cfg.pwm_window_millis)),
+            runaway: RunawayGuard::new(
+                cfg.runaway_duty_threshold,
+                Duration::from_secs(cfg.runaway_timeout_secs),
+                cfg.runaway_min_rise,
+            ),
+            last_sample: None,
+            hysteresis: cfg.hysteresis,
+            hysteresis_samples: cfg.hysteresis_samples,
+            at_target_streak: 0,
+        }
+    }
+
+    /// Advances the loop by one sample, returning whether the heater output
+    /// should currently be on
+    ///
+    /// If a thermal runaway is detected, the loop is reset and the heater
+    /// should be considered off and its target cleared; it's up to the
+    /// caller to actually do that and to disarm the PID loop.
+    pub fn sample(&mut self, target: Option<f64>, measured: f64) -> Result<bool, RunawayError> {
+        let Some(target) = target else {
+            self.pid.reset();
+            self.runaway.reset();
+            self.at_target_streak = 0;
+            self.last_sample = None;
+            return Ok(false);
+        };
+        let now = Instant::now();
+        let dt = self
+            .last_sample
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f64());
+        self.last_sample = Some(now);
+        let duty = self.pid.update(target, measured, dt);
+        if let Err(e) = self.runaway.check(duty, measured) {
+            self.pid.reset();
+            self.runaway.reset();
+            self.at_target_streak = 0;
+            return Err(e);
+        }
+        if (measured - target).abs() <= self.hysteresis {
+            self.at_target_streak += 1;
+        } else {
+            self.at_target_streak = 0;
+        }
+        Ok(self.window.sample(duty))
+    }
+
+    /// Whether the measured temperature has stayed within hysteresis of the
+    /// target for enough consecutive samples to count as "reached"
+    pub fn at_target(&self) -> bool {
+        self.at_target_streak >= self.hysteresis_samples
+    }
+}
@@ -1,31 +1,153 @@
 mod error;
+mod filter;
+mod heater;
 mod pi;
 
-pub use self::error::{ExitError, PiCtrlError, WaitTempError};
-use self::pi::RevPi;
+pub use self::error::{ExitError, OvertempError, PiCtrlError, RunawayError, WaitTempError};
+use self::{filter::TempFilterState, heater::HeaterLoop, pi::RevPi};
 use crate::{
     comms::ControlComms,
+    config::{Bed, Hotend},
+    hw::comms::CancelReason,
     log::target,
+    ring_buffer::RingBuffer,
     settings::Settings,
     util::{ensure_own, send_err},
 };
 use anyhow::{Context, Error, Result};
-use crossbeam::channel::{self, Receiver, Sender, TryRecvError};
+use atomic_float::AtomicF64;
+use crossbeam::{
+    channel::{self, Receiver, Select, Sender, TryRecvError},
+    select,
+};
 use once_cell::sync::OnceCell;
+use serde::Serialize;
 use std::{
     collections::BTreeMap,
     mem::{self, ManuallyDrop},
     sync::{
-        atomic::{AtomicU16, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU16, Ordering},
+        Arc, RwLock,
     },
     thread::{self, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tracing::debug;
 
+/// One pi-thread tick's hotend/bed actual and target readings, buffered in
+/// [`PiCtrl`]'s lock-free telemetry ring
+///
+/// `uptime_millis` is measured from when the pi thread started rather than
+/// being a wall-clock timestamp, same reasoning as
+/// [`crate::api::telemetry::Sample`].
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct TempSample {
+    pub uptime_millis: u64,
+    pub hotend_actual: f64,
+    pub hotend_target: Option<u16>,
+    pub bed_actual: f64,
+    pub bed_target: Option<u16>,
+}
+
+/// A safety-relevant input edge detected by the pi thread, forwarded to
+/// `HwCtrl`-level code so it can react without the pi thread needing to know
+/// about the executor or print state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    /// the physical e-stop input was just triggered
+    EStop,
+    /// the filament-runout input was just triggered; sent on every rising
+    /// edge, whether or not a print is running to be affected by it
+    FilamentRunout,
+}
+
+/// The current level and rising-edge count of one digital input, as exposed
+/// over the `/inputs` endpoint
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct InputState {
+    pub active: bool,
+    pub edge_count: u64,
+}
+
+impl InputState {
+    /// Updates the stored level, bumping `edge_count` if this is a rising
+    /// edge, and returns whether it was one
+    fn update(&mut self, active: bool) -> bool {
+        let rising_edge = active && !self.active;
+        self.active = active;
+        if rising_edge {
+            self.edge_count += 1;
+        }
+        rising_edge
+    }
+}
+
+/// A snapshot of every digital input the pi thread polls, shared out to
+/// [`PiCtrl::inputs`]
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct InputsSnapshot {
+    pub x_endstop: InputState,
+    pub y_endstop: InputState,
+    pub z_endstop: InputState,
+    pub estop: InputState,
+    pub filament_runout: InputState,
+}
+
 type WaitTempComms = Result<(), WaitTempError>;
 
+/// Returned by the cancellable `try_wait_*` methods when `cancel_recv` fires
+/// before the wait would otherwise have resolved, carrying which control
+/// request (stop or pause) triggered it
+#[derive(Debug)]
+pub struct Cancelled(pub CancelReason);
+
+/// Blocks on a wait-temp notification, optionally bounded by `timeout`,
+/// bailing out early with [`Cancelled`] if `cancel_recv` fires first
+///
+/// Selected over with [`Select`] rather than `notify_recv.recv_timeout`
+/// directly, since the number of live branches depends on whether `timeout`
+/// is set; lets a print being stopped/paused interrupt a temperature wait
+/// instead of it blocking until the target is reached or the timeout lapses.
+///
+/// On a timeout or cancellation the `Sender` half may still be sitting in
+/// one of `PiThreadData`'s waiting pools; see the FIXME on
+/// [`notify_waiting_target_changed`][PiThreadData::notify_waiting_target_changed]
+/// for why that's tolerated rather than cleaned up here.
+fn recv_wait_temp(
+    notify_recv: Receiver<WaitTempComms>,
+    timeout: Option<Duration>,
+    cancel_recv: &Receiver<ControlComms<CancelReason>>,
+) -> Result<WaitTempComms, Cancelled> {
+    let after = timeout.map(channel::after);
+    let mut sel = Select::new();
+    let notify_idx = sel.recv(&notify_recv);
+    let cancel_idx = sel.recv(cancel_recv);
+    let after_idx = after.as_ref().map(|after| sel.recv(after));
+    loop {
+        let op = sel.select();
+        let idx = op.index();
+        if idx == notify_idx {
+            return Ok(op
+                .recv(&notify_recv)
+                .expect("pi thread dropped a wait-temp notify channel without sending"));
+        } else if idx == cancel_idx {
+            let reason = match op
+                .recv(cancel_recv)
+                .expect("cancel channel was unexpectedly closed")
+            {
+                ControlComms::Msg(reason) => reason,
+                ControlComms::Exit => CancelReason::Stop,
+            };
+            return Err(Cancelled(reason));
+        } else if Some(idx) == after_idx {
+            let _ = op.recv(after.as_ref().unwrap());
+            return Ok(Err(WaitTempError::Timeout));
+        } else {
+            unreachable!("selected an index that wasn't registered")
+        }
+    }
+}
+
 #[derive(Debug)]
 enum InnerPiComms {
     SetHotendTarget(Option<u16>),
@@ -35,6 +157,7 @@ enum InnerPiComms {
     WaitMinBedTemp(Option<u16>, Sender<WaitTempComms>),
     Stop,
     EStop,
+    ClearFault,
 }
 
 type PiComms = ControlComms<InnerPiComms>;
@@ -91,6 +214,11 @@ pub struct PiCtrl {
     pi_send: Sender<PiComms>,
     hotend_target: AtomicTargetTemp,
     bed_target: AtomicTargetTemp,
+    hotend_measured: Arc<AtomicF64>,
+    bed_measured: Arc<AtomicF64>,
+    inputs: Arc<RwLock<InputsSnapshot>>,
+    telemetry: Arc<RingBuffer<TempSample>>,
+    faulted: Arc<AtomicBool>,
 }
 
 impl PiCtrl {
@@ -100,6 +228,11 @@ impl PiCtrl {
         pi_send: Sender<PiComms>,
         hotend_target: AtomicTargetTemp,
         bed_target: AtomicTargetTemp,
+        hotend_measured: Arc<AtomicF64>,
+        bed_measured: Arc<AtomicF64>,
+        inputs: Arc<RwLock<InputsSnapshot>>,
+        telemetry: Arc<RingBuffer<TempSample>>,
+        faulted: Arc<AtomicBool>,
     ) -> Self {
         Self {
             settings,
@@ -107,9 +240,20 @@ impl PiCtrl {
             pi_send,
             hotend_target,
             bed_target,
+            hotend_measured,
+            bed_measured,
+            inputs,
+            telemetry,
+            faulted,
         }
     }
 
+    /// Current level and rising-edge count of every digital input the pi
+    /// thread polls
+    pub fn inputs(&self) -> InputsSnapshot {
+        *self.inputs.read().unwrap()
+    }
+
     pub fn hotend_target(&self) -> Option<u16> {
         self.hotend_target.load()
     }
@@ -118,6 +262,40 @@ impl PiCtrl {
         self.bed_target.load()
     }
 
+    /// Last temperature read from the hotend thermistor, regardless of
+    /// whether a target is currently set
+    pub fn hotend_measured(&self) -> f64 {
+        self.hotend_measured.load(Ordering::Acquire)
+    }
+
+    /// Last temperature read from the bed thermistor, regardless of whether
+    /// a target is currently set
+    pub fn bed_measured(&self) -> f64 {
+        self.bed_measured.load(Ordering::Acquire)
+    }
+
+    /// The buffered window of the most recent [`TempSample`]s, oldest first;
+    /// see [`RingBuffer::snapshot`]
+    pub fn telemetry_snapshot(&self) -> Vec<TempSample> {
+        self.telemetry.snapshot()
+    }
+
+    /// Whether a heater fault has latched `write_*_heat` off; see
+    /// [`Self::clear_fault`] to re-arm
+    pub fn faulted(&self) -> bool {
+        self.faulted.load(Ordering::Acquire)
+    }
+
+    /// Re-arms the heaters after an operator has investigated a heater
+    /// fault, letting [`PiThreadData::update_hotend_heat`]/
+    /// [`update_bed_heat`][PiThreadData::update_bed_heat] resume driving
+    /// them
+    pub fn clear_fault(&self) {
+        self.pi_send
+            .send(ControlComms::Msg(InnerPiComms::ClearFault))
+            .unwrap()
+    }
+
     pub fn try_set_hotend_target(&self, target: Option<u16>) -> Result<(), PiCtrlError> {
         if let Some(temp) = target.as_ref() {
             let cfg = &self.settings.config().hotend;
@@ -151,28 +329,42 @@ impl PiCtrl {
         Ok(())
     }
 
-    pub fn try_wait_hotend_target(&self) -> Result<(), WaitTempError> {
+    /// Blocks until the hotend reaches its target, `timeout` elapses (giving
+    /// `Ok(Err(WaitTempError::Timeout))`, so a stuck heater can't hang a
+    /// print forever), or `cancel_recv` fires first; see [`recv_wait_temp`]
+    pub fn try_wait_hotend_target(
+        &self,
+        timeout: Option<Duration>,
+        cancel_recv: &Receiver<ControlComms<CancelReason>>,
+    ) -> Result<WaitTempComms, Cancelled> {
         let (notify_send, notify_recv) = channel::bounded(1);
         self.pi_send
             .send(ControlComms::Msg(InnerPiComms::WaitHotendTarget(
                 notify_send,
             )))
             .unwrap();
-        notify_recv.recv().unwrap()
+        recv_wait_temp(notify_recv, timeout, cancel_recv)
     }
 
-    pub fn try_wait_bed_target(&self) -> Result<(), WaitTempError> {
+    /// Same as [`Self::try_wait_hotend_target`], but for the bed
+    pub fn try_wait_bed_target(
+        &self,
+        timeout: Option<Duration>,
+        cancel_recv: &Receiver<ControlComms<CancelReason>>,
+    ) -> Result<WaitTempComms, Cancelled> {
         let (notify_send, notify_recv) = channel::bounded(1);
         self.pi_send
             .send(ControlComms::Msg(InnerPiComms::WaitBedTarget(notify_send)))
             .unwrap();
-        notify_recv.recv().unwrap()
+        recv_wait_temp(notify_recv, timeout, cancel_recv)
     }
 
     pub fn try_wait_min_bed_temp(
         &self,
         min_temp: Option<u16>,
-    ) -> Result<Result<(), WaitTempError>, PiCtrlError> {
+        timeout: Option<Duration>,
+        cancel_recv: &Receiver<ControlComms<CancelReason>>,
+    ) -> Result<Result<WaitTempComms, Cancelled>, PiCtrlError> {
         self.ensure_bed_target_in_range(&min_temp)?;
         let (notify_send, notify_recv) = channel::bounded(1);
         self.pi_send
@@ -181,7 +373,7 @@ impl PiCtrl {
                 notify_send,
             )))
             .unwrap();
-        Ok(notify_recv.recv().unwrap())
+        Ok(recv_wait_temp(notify_recv, timeout, cancel_recv))
     }
 
     pub fn stop(&self) {
@@ -214,20 +406,43 @@ struct PiThreadData {
     pi: RevPi,
     hotend_target: AtomicTargetTemp,
     hotend_waiting: Vec<Sender<WaitTempComms>>,
+    hotend_loop: HeaterLoop,
+    hotend_filter: TempFilterState,
     bed_target: AtomicTargetTemp,
     bed_waiting: Vec<Sender<WaitTempComms>>,
     bed_min_waiting: BTreeMap<Option<u16>, Sender<WaitTempComms>>,
+    bed_loop: HeaterLoop,
+    bed_filter: TempFilterState,
+    hotend_measured: Arc<AtomicF64>,
+    bed_measured: Arc<AtomicF64>,
+    inputs: Arc<RwLock<InputsSnapshot>>,
+    // sole writer; PiCtrl::telemetry_snapshot reads through its own Arc
+    telemetry: Arc<RingBuffer<TempSample>>,
+    telemetry_start: Instant,
+    // sole writer; PiCtrl::faulted reads through its own Arc
+    faulted: Arc<AtomicBool>,
 }
 
 impl PiThreadData {
-    pub fn new() -> Result<Self> {
+    pub fn new(settings: &Settings) -> Result<Self> {
+        let check_interval = Duration::from_millis(settings.config().pi.check_interval);
         Ok(Self {
             pi: RevPi::new()?,
             hotend_target: AtomicTargetTemp::new(None),
             hotend_waiting: Vec::new(),
+            hotend_loop: HeaterLoop::new(&settings.config().hotend.pid),
+            hotend_filter: TempFilterState::new(&settings.config().hotend.filter, check_interval),
             bed_target: AtomicTargetTemp::new(None),
             bed_waiting: Vec::new(),
             bed_min_waiting: BTreeMap::new(),
+            bed_loop: HeaterLoop::new(&settings.config().bed.pid),
+            bed_filter: TempFilterState::new(&settings.config().bed.filter, check_interval),
+            hotend_measured: Arc::new(AtomicF64::new(0.)),
+            bed_measured: Arc::new(AtomicF64::new(0.)),
+            inputs: Arc::new(RwLock::new(InputsSnapshot::default())),
+            telemetry: Arc::new(RingBuffer::new(settings.config().pi.telemetry_samples)),
+            telemetry_start: Instant::now(),
+            faulted: Arc::new(AtomicBool::new(false)),
         })
     }
 
@@ -235,47 +450,225 @@ impl PiThreadData {
         (self.hotend_target.clone(), self.bed_target.clone())
     }
 
+    pub fn get_measured(&self) -> (Arc<AtomicF64>, Arc<AtomicF64>) {
+        (
+            Arc::clone(&self.hotend_measured),
+            Arc::clone(&self.bed_measured),
+        )
+    }
+
+    pub fn get_inputs(&self) -> Arc<RwLock<InputsSnapshot>> {
+        Arc::clone(&self.inputs)
+    }
+
+    pub fn get_telemetry(&self) -> Arc<RingBuffer<TempSample>> {
+        Arc::clone(&self.telemetry)
+    }
+
+    pub fn get_faulted(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.faulted)
+    }
+
+    /// Records this tick's hotend/bed actual and target readings into the
+    /// telemetry ring; called once per `pi_loop` tick after both
+    /// heaters have been updated so `measured` is fresh for each.
+    fn record_telemetry(&self) {
+        self.telemetry.push(TempSample {
+            uptime_millis: self.telemetry_start.elapsed().as_millis() as u64,
+            hotend_actual: self.hotend_measured.load(Ordering::Acquire),
+            hotend_target: self.hotend_target.load(),
+            bed_actual: self.bed_measured.load(Ordering::Acquire),
+            bed_target: self.bed_target.load(),
+        });
+    }
+
+    /// Polls every digital input and updates the shared snapshot and edge
+    /// counters, returning the safety-relevant events (if any) that should be
+    /// forwarded to `HwCtrl`-level code so it can react (halt motors, pause
+    /// a print, ...)
+    pub fn update_inputs(&mut self, active_high_filament_runout: bool) -> Result<Vec<InputEvent>> {
+        let x_endstop = self.pi.read_x_endstop()?;
+        let y_endstop = self.pi.read_y_endstop()?;
+        let z_endstop = self.pi.read_z_endstop()?;
+        let estop = self.pi.read_estop()?;
+        let filament_runout = self.pi.read_filament_runout()? != active_high_filament_runout;
+        let mut events = Vec::new();
+        let mut inputs = self.inputs.write().unwrap();
+        inputs.x_endstop.update(x_endstop);
+        inputs.y_endstop.update(y_endstop);
+        inputs.z_endstop.update(z_endstop);
+        if inputs.estop.update(estop) {
+            events.push(InputEvent::EStop);
+        }
+        if inputs.filament_runout.update(filament_runout) {
+            events.push(InputEvent::FilamentRunout);
+        }
+        Ok(events)
+    }
+
+    /// Reads the hotend thermistor, runs it through `cfg.filter` to smooth
+    /// out sensor jitter, advances its [`HeaterLoop`] by one tick and writes
+    /// the resulting duty cycle out, notifying any waiters once the loop
+    /// says the target has been reached
+    ///
+    /// Does nothing beyond the temperature read while [`Self::fault`]ed.
+    /// The filtered temperature exceeding `cfg.upper_limit + cfg.pid.
+    /// overtemp_margin`, or a thermal runaway detected by the
+    /// [`HeaterLoop`], latches a fault instead of just clearing this one
+    /// heater's target; see [`Self::fault`].
     #[cfg(not(feature = "dev_no_pi"))]
-    pub fn update_hotend_heat(&mut self) -> Result<()> {
-        // FIXME TODO
-        Ok(())
+    pub fn update_hotend_heat(
+        &mut self,
+        cfg: &Hotend,
+        error_send: &Sender<ControlComms<Error>>,
+    ) -> Result<()> {
+        let measured = self.hotend_filter.sample(self.pi.read_hotend_temp());
+        self.hotend_measured.store(measured, Ordering::Release);
+        if self.faulted.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        if measured > f64::from(cfg.upper_limit) + cfg.pid.overtemp_margin {
+            let e = OvertempError(measured, cfg.upper_limit, cfg.pid.overtemp_margin);
+            return Err(self.fault(e.into(), error_send));
+        }
+        let target = self.hotend_target.load().map(f64::from);
+        match self.hotend_loop.sample(target, measured) {
+            Ok(on) => {
+                self.pi.write_hotend_heat(on)?;
+                if self.hotend_loop.at_target() {
+                    self.notify_hotend_target_reached();
+                }
+                Ok(())
+            }
+            Err(e) => Err(self.fault(e.into(), error_send)),
+        }
     }
 
     #[cfg(feature = "dev_no_pi")]
-    pub fn update_hotend_heat(&mut self) -> Result<()> {
+    pub fn update_hotend_heat(
+        &mut self,
+        _cfg: &Hotend,
+        _error_send: &Sender<ControlComms<Error>>,
+    ) -> Result<()> {
         for notify_send in self.hotend_waiting.drain(..) {
-            notify_send.send(Ok(())).unwrap();
+            // a timed-out waiter drops its receiver, so ignore send failures
+            let _ = notify_send.send(Ok(()));
         }
         Ok(())
     }
 
+    /// Same as [`Self::update_hotend_heat`], but for the bed; also checks
+    /// `bed_min_waiting` against the freshly measured temperature, since
+    /// that pool can resolve before the bed's own target is reached.
     #[cfg(not(feature = "dev_no_pi"))]
-    pub fn update_bed_heat(&mut self) -> Result<()> {
-        // FIXME TODO
-        Ok(())
+    pub fn update_bed_heat(
+        &mut self,
+        cfg: &Bed,
+        error_send: &Sender<ControlComms<Error>>,
+    ) -> Result<()> {
+        let measured = self.bed_filter.sample(self.pi.read_bed_temp());
+        self.bed_measured.store(measured, Ordering::Release);
+        if self.faulted.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        if measured > f64::from(cfg.upper_limit) + cfg.pid.overtemp_margin {
+            let e = OvertempError(measured, cfg.upper_limit, cfg.pid.overtemp_margin);
+            return Err(self.fault(e.into(), error_send));
+        }
+        let target = self.bed_target.load().map(f64::from);
+        match self.bed_loop.sample(target, measured) {
+            Ok(on) => {
+                self.pi.write_bed_heat(on)?;
+                if self.bed_loop.at_target() {
+                    self.notify_bed_waiting();
+                }
+                self.notify_bed_min_reached(measured);
+                Ok(())
+            }
+            Err(e) => Err(self.fault(e.into(), error_send)),
+        }
     }
 
     #[cfg(feature = "dev_no_pi")]
-    pub fn update_bed_heat(&mut self) -> Result<()> {
+    pub fn update_bed_heat(
+        &mut self,
+        _cfg: &Bed,
+        _error_send: &Sender<ControlComms<Error>>,
+    ) -> Result<()> {
         for notify_send in self.bed_waiting.drain(..) {
-            notify_send.send(Ok(())).unwrap();
+            // a timed-out waiter drops its receiver, so ignore send failures
+            let _ = notify_send.send(Ok(()));
         }
         for notify_send in mem::replace(&mut self.bed_min_waiting, BTreeMap::new()).into_values() {
-            notify_send.send(Ok(())).unwrap();
+            let _ = notify_send.send(Ok(()));
         }
         Ok(())
     }
 
+    // FIXME a waiter that already timed out has dropped its receiver, so its
+    // sender just lingers here as a no-op until the next target change; not
+    // worth cleaning up proactively since the pools are small and short-lived
     fn notify_waiting_target_changed<I: IntoIterator<Item = Sender<WaitTempComms>>>(waiting: I) {
         for notify_send in waiting.into_iter() {
-            notify_send.send(Err(WaitTempError::TargetChanged)).unwrap();
+            // ignore send failures; see the FIXME above
+            let _ = notify_send.send(Err(WaitTempError::TargetChanged));
         }
     }
 
+    /// Same as [`Self::notify_waiting_target_changed`], but for a heater
+    /// fault; see [`Self::fault`]
+    fn notify_waiting_fault<I: IntoIterator<Item = Sender<WaitTempComms>>>(waiting: I) {
+        for notify_send in waiting.into_iter() {
+            // ignore send failures; see the FIXME above
+            let _ = notify_send.send(Err(WaitTempError::HeaterFault));
+        }
+    }
+
+    /// Latches `faulted`, e-stops the heaters/fans, clears both targets
+    /// without going through [`Self::set_hotend_target`]/
+    /// [`set_bed_target`][Self::set_bed_target] (which would notify waiters
+    /// with the wrong error), and fails every pending temperature wait with
+    /// [`WaitTempError::HeaterFault`] so a print aborts instead of hanging
+    ///
+    /// Returns `error` unchanged so the caller can propagate it as the
+    /// triggering cause; any error from the e-stop itself is forwarded to
+    /// `error_send` directly instead, same as [`handle_pi_msg`]'s
+    /// `InnerPiComms::EStop` arm.
+    fn fault(&mut self, error: Error, error_send: &Sender<ControlComms<Error>>) -> Error {
+        self.faulted.store(true, Ordering::Release);
+        if let Err(es) = self.estop() {
+            for e in es {
+                error_send.send(ControlComms::Msg(e)).unwrap();
+            }
+        }
+        self.hotend_target.store(None);
+        self.bed_target.store(None);
+        Self::notify_waiting_fault(self.hotend_waiting.drain(..));
+        Self::notify_waiting_fault(self.bed_waiting.drain(..));
+        Self::notify_waiting_fault(
+            mem::replace(&mut self.bed_min_waiting, BTreeMap::new()).into_values(),
+        );
+        error
+    }
+
+    /// Re-arms the heaters after [`Self::fault`] latched them off
+    pub fn clear_fault(&mut self) {
+        self.faulted.store(false, Ordering::Release);
+    }
+
     fn notify_hotend_target_changed(&mut self) {
         Self::notify_waiting_target_changed(self.hotend_waiting.drain(..))
     }
 
+    /// Wakes every thread waiting for the hotend to reach its target, since
+    /// [`HeaterLoop::at_target`] says it has
+    fn notify_hotend_target_reached(&mut self) {
+        for notify_send in self.hotend_waiting.drain(..) {
+            // a timed-out waiter drops its receiver, so ignore send failures
+            let _ = notify_send.send(Ok(()));
+        }
+    }
+
     pub fn set_hotend_target(&mut self, target: Option<u16>) {
         self.hotend_target.store(target);
         self.notify_hotend_target_changed();
@@ -293,6 +686,33 @@ impl PiThreadData {
         self.notify_bed_target_changed();
     }
 
+    /// Wakes every thread waiting for the bed to reach its target, since
+    /// [`HeaterLoop::at_target`] says it has
+    fn notify_bed_waiting(&mut self) {
+        for notify_send in self.bed_waiting.drain(..) {
+            // a timed-out waiter drops its receiver, so ignore send failures
+            let _ = notify_send.send(Ok(()));
+        }
+    }
+
+    /// Wakes every thread waiting for the bed to reach at least some minimum
+    /// temperature that `measured` has now met, leaving the rest waiting
+    fn notify_bed_min_reached(&mut self, measured: f64) {
+        self.bed_min_waiting = mem::replace(&mut self.bed_min_waiting, BTreeMap::new())
+            .into_iter()
+            .filter_map(|(min_temp, notify_send)| {
+                if min_temp.map_or(true, |min| measured >= f64::from(min)) {
+                    // a timed-out waiter drops its receiver, so ignore send
+                    // failures
+                    let _ = notify_send.send(Ok(()));
+                    None
+                } else {
+                    Some((min_temp, notify_send))
+                }
+            })
+            .collect();
+    }
+
     pub fn add_hotend_waiting(&mut self, notify_send: Sender<WaitTempComms>) {
         // TODO check if actual temp is already at target
         self.hotend_waiting.push(notify_send)
@@ -340,60 +760,108 @@ impl PiThreadData {
     }
 }
 
+/// Handles one message off `pi_recv`, returning whether the loop should keep
+/// running (`false` once `ControlComms::Exit` has been handled)
+fn handle_pi_msg(
+    msg: PiComms,
+    data: &mut PiThreadData,
+    error_send: &Sender<ControlComms<Error>>,
+) -> bool {
+    match msg {
+        ControlComms::Msg(msg) => {
+            debug!(target: target::INTERNAL, "received {:?}, executing...", msg);
+            match msg {
+                InnerPiComms::SetHotendTarget(target) => data.set_hotend_target(target),
+                InnerPiComms::SetBedTarget(target) => data.set_bed_target(target),
+                InnerPiComms::WaitHotendTarget(notify_send) => data.add_hotend_waiting(notify_send),
+                InnerPiComms::WaitBedTarget(notify_send) => data.add_bed_waiting(notify_send),
+                InnerPiComms::WaitMinBedTemp(min_temp, notify_send) => {
+                    data.add_bed_min_waiting(min_temp, notify_send)
+                }
+                InnerPiComms::Stop => {
+                    data.set_hotend_target(None);
+                    data.set_bed_target(None);
+                }
+                InnerPiComms::EStop => {
+                    if let Err(es) = data.estop() {
+                        for e in es {
+                            error_send.send(ControlComms::Msg(e)).unwrap();
+                        }
+                    }
+                }
+                InnerPiComms::ClearFault => data.clear_fault(),
+            }
+            true
+        }
+        ControlComms::Exit => {
+            debug!(target: target::INTERNAL, "received exit, exiting...");
+            send_err!(data.exit(), error_send);
+            false
+        }
+    }
+}
+
 fn pi_loop(
     settings: Settings,
     mut data: PiThreadData,
     pi_recv: Receiver<PiComms>,
     error_send: Sender<ControlComms<Error>>,
+    input_event_send: Sender<ControlComms<InputEvent>>,
 ) {
-    loop {
-        match pi_recv.try_recv() {
-            Ok(msg) => {
-                match msg {
-                    ControlComms::Msg(msg) => {
-                        debug!(target: target::INTERNAL, "received {:?}, executing...", msg);
-                        match msg {
-                            InnerPiComms::SetHotendTarget(target) => data.set_hotend_target(target),
-                            InnerPiComms::SetBedTarget(target) => data.set_bed_target(target),
-                            InnerPiComms::WaitHotendTarget(notify_send) => {
-                                data.add_hotend_waiting(notify_send)
-                            }
-                            InnerPiComms::WaitBedTarget(notify_send) => {
-                                data.add_bed_waiting(notify_send)
-                            }
-                            InnerPiComms::WaitMinBedTemp(min_temp, notify_send) => {
-                                data.add_bed_min_waiting(min_temp, notify_send)
-                            }
-                            InnerPiComms::Stop => {
-                                data.set_hotend_target(None);
-                                data.set_bed_target(None);
+    // a tick arm alongside pi_recv rather than try_recv + thread::sleep, so
+    // a command (critically EStop/Stop) is handled the instant it arrives
+    // instead of sitting idle for up to a whole check_interval
+    let ticker = channel::tick(Duration::from_millis(settings.config().pi.check_interval));
+    'outer: loop {
+        select! {
+            recv(pi_recv) -> msg => {
+                let msg = msg.expect("pi channel unexpectedly disconnected");
+                if !handle_pi_msg(msg, &mut data, &error_send) {
+                    break 'outer;
+                }
+                // drain whatever else is already queued so a burst of
+                // commands can't get a heat update interleaved between them
+                loop {
+                    match pi_recv.try_recv() {
+                        Ok(msg) => {
+                            if !handle_pi_msg(msg, &mut data, &error_send) {
+                                break 'outer;
                             }
-                            InnerPiComms::EStop => {
+                        }
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => {
+                            panic!("pi channel unexepectedly disconnected")
+                        }
+                    }
+                }
+            }
+            recv(ticker) -> tick => {
+                tick.expect("pi ticker was unexpectedly disconnected");
+                let cfg = settings.config();
+                send_err!(data.update_hotend_heat(&cfg.hotend, &error_send), error_send);
+                send_err!(data.update_bed_heat(&cfg.bed, &error_send), error_send);
+                data.record_telemetry();
+                let active_high = settings.config().pi.filament_runout_active_high;
+                match data.update_inputs(active_high) {
+                    Ok(events) => {
+                        for event in events {
+                            // cut the heaters immediately rather than waiting
+                            // for the event to be forwarded and reacted to
+                            // elsewhere
+                            if event == InputEvent::EStop {
                                 if let Err(es) = data.estop() {
                                     for e in es {
                                         error_send.send(ControlComms::Msg(e)).unwrap();
                                     }
                                 }
                             }
+                            input_event_send.send(ControlComms::Msg(event)).unwrap();
                         }
                     }
-                    ControlComms::Exit => {
-                        debug!(target: target::INTERNAL, "received exit, exiting...");
-                        send_err!(data.exit(), error_send);
-                        break;
-                    }
+                    Err(e) => error_send.send(ControlComms::Msg(e)).unwrap(),
                 }
-                // continue to see if there are more messages in the channel
-                continue;
             }
-            Err(e) => match e {
-                TryRecvError::Disconnected => panic!("pi channel unexepectedly disconnected"),
-                TryRecvError::Empty => (),
-            },
         }
-        thread::sleep(Duration::from_millis(settings.config().pi.check_interval));
-        send_err!(data.update_hotend_heat(), error_send);
-        send_err!(data.update_bed_heat(), error_send);
     }
 }
 
@@ -416,18 +884,31 @@ impl PiStopper {
         &mut self,
         settings: Settings,
         error_send: Sender<ControlComms<Error>>,
+        input_event_send: Sender<ControlComms<InputEvent>>,
     ) -> Result<PiCtrl> {
         let pi_recv = self
             .unstarted_data
             .take()
             .expect("can't start pi thread twice");
         let pi_send = self.pi_send.clone();
-        let pi_thread_data = PiThreadData::new()?;
+        let pi_thread_data = PiThreadData::new(&settings)?;
         let (hotend_target, bed_target) = pi_thread_data.get_targets();
+        let (hotend_measured, bed_measured) = pi_thread_data.get_measured();
+        let inputs = pi_thread_data.get_inputs();
+        let telemetry = pi_thread_data.get_telemetry();
+        let faulted = pi_thread_data.get_faulted();
         let settings_clone = settings.clone();
         let handle = thread::Builder::new()
             .name(String::from("pi"))
-            .spawn(move || pi_loop(settings_clone, pi_thread_data, pi_recv, error_send))
+            .spawn(move || {
+                pi_loop(
+                    settings_clone,
+                    pi_thread_data,
+                    pi_recv,
+                    error_send,
+                    input_event_send,
+                )
+            })
             .context("Creating the pi thread failed")?;
         Ok(PiCtrl::new(
             settings,
@@ -435,6 +916,11 @@ impl PiStopper {
             pi_send,
             hotend_target,
             bed_target,
+            hotend_measured,
+            bed_measured,
+            inputs,
+            telemetry,
+            faulted,
         ))
     }
 
@@ -463,11 +949,15 @@ impl PiStopper {
 
 pub fn init() -> (
     PiStopper,
-    impl FnOnce(Settings, Sender<ControlComms<Error>>) -> Result<PiCtrl>,
+    impl FnOnce(
+        Settings,
+        Sender<ControlComms<Error>>,
+        Sender<ControlComms<InputEvent>>,
+    ) -> Result<PiCtrl>,
 ) {
     let pi_stopper = PiStopper::init();
     let mut pi_stopper_clone = pi_stopper.clone();
-    (pi_stopper, move |settings, error_send| {
-        pi_stopper_clone.start_pi(settings, error_send)
+    (pi_stopper, move |settings, error_send, input_event_send| {
+        pi_stopper_clone.start_pi(settings, error_send, input_event_send)
     })
 }
@@ -50,6 +50,10 @@ impl RevPi {
         self.inner.get_estop().map_err(|e| e.into())
     }
 
+    pub fn read_filament_runout(&self) -> Result<bool> {
+        self.inner.get_filament_runout().map_err(|e| e.into())
+    }
+
     pub fn read_hotend_temp(&self) -> f64 {
         todo!()
     }
@@ -96,6 +100,10 @@ impl RevPi {
         Ok(false)
     }
 
+    pub fn read_filament_runout(&self) -> Result<bool> {
+        Ok(false)
+    }
+
     pub fn read_hotend_temp(&self) -> f64 {
         42.0
     }
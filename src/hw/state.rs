@@ -1,4 +1,9 @@
-use std::path::PathBuf;
+use crate::api::values::ErrorCode;
+use serde_json::Value;
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -17,6 +22,16 @@ pub enum StateError {
     Stopped,
 }
 
+impl StateError {
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode::State
+    }
+
+    pub fn details(&self) -> Option<Value> {
+        None
+    }
+}
+
 #[derive(Debug)]
 pub enum StateInfo {
     Printing(PathBuf),
@@ -41,6 +56,15 @@ enum InnerState {
 pub struct State {
     state: InnerState,
     printing_state: Option<PrintingState>,
+    // when the printer last became stopped; used to run the configured
+    // `macros.idle` gcode again once the printer's been sitting idle for
+    // `macros.idle_timeout`, separately from the `idle`/`cancel` macro that
+    // already runs the instant a print stops/finishes
+    stopped_since: Option<Instant>,
+    // whether the idle-timeout macro has already run for the current idle
+    // stretch, so `idle_timeout_elapsed` only fires once per stretch rather
+    // than every time it's polled
+    idle_timeout_fired: bool,
 }
 
 impl State {
@@ -48,6 +72,8 @@ impl State {
         Self {
             state: InnerState::Stopped,
             printing_state: None,
+            stopped_since: Some(Instant::now()),
+            idle_timeout_fired: false,
         }
     }
 
@@ -65,13 +91,15 @@ impl State {
         }
     }
 
-    pub fn print(&mut self, path: PathBuf) {
+    pub fn print(&mut self, path: PathBuf) -> Result<(), StateError> {
         match self.state {
-            InnerState::Printing => panic!("can't print, already printing"),
-            InnerState::Paused => panic!("can't print, is paused"),
+            InnerState::Printing => Err(StateError::Printing),
+            InnerState::Paused => Err(StateError::Paused),
             InnerState::Stopped => {
                 self.state = InnerState::Printing;
                 self.printing_state = Some(PrintingState { path });
+                self.stopped_since = None;
+                Ok(())
             }
         }
     }
@@ -79,21 +107,42 @@ impl State {
     pub fn stop(&mut self) {
         self.state = InnerState::Stopped;
         self.printing_state = None;
+        self.stopped_since = Some(Instant::now());
+        self.idle_timeout_fired = false;
     }
 
-    pub fn play(&mut self) {
+    /// Returns `true` the first time the printer's been sitting stopped for
+    /// at least `timeout`; `false` otherwise, including every later call
+    /// for the same idle stretch and any call while printing/paused
+    pub fn idle_timeout_elapsed(&mut self, timeout: Duration) -> bool {
+        match self.stopped_since {
+            Some(since) if !self.idle_timeout_fired && since.elapsed() >= timeout => {
+                self.idle_timeout_fired = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn play(&mut self) -> Result<(), StateError> {
         match self.state {
-            InnerState::Printing => (),
-            InnerState::Paused => self.state = InnerState::Printing,
-            InnerState::Stopped => panic!("can't play, is stopped"),
+            InnerState::Printing => Ok(()),
+            InnerState::Paused => {
+                self.state = InnerState::Printing;
+                Ok(())
+            }
+            InnerState::Stopped => Err(StateError::Stopped),
         }
     }
 
-    pub fn pause(&mut self) {
+    pub fn pause(&mut self) -> Result<(), StateError> {
         match self.state {
-            InnerState::Printing => self.state = InnerState::Paused,
-            InnerState::Paused => (),
-            InnerState::Stopped => panic!("can't pause, is stopped"),
+            InnerState::Printing => {
+                self.state = InnerState::Paused;
+                Ok(())
+            }
+            InnerState::Paused => Ok(()),
+            InnerState::Stopped => Err(StateError::Stopped),
         }
     }
 
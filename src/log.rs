@@ -1,15 +1,26 @@
 use crate::APP_NAME;
 use atty;
-use std::io;
-use tracing::{Level, Metadata};
+use crossbeam::channel::{self, Receiver, Sender};
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Metadata, Subscriber,
+};
 use tracing_subscriber::{
     self, filter,
     fmt::{
         self,
         format::{Format, Pretty},
     },
-    layer::SubscriberExt,
+    layer::{Context, Layer, SubscriberExt},
+    reload,
     util::SubscriberInitExt,
+    Registry,
 };
 
 pub mod target {
@@ -41,21 +52,131 @@ fn format_notty(level: Level) -> Format {
     format_common(fmt::format(), level).with_ansi(false)
 }
 
-pub fn setup(level: Level) {
-    let subscriber = tracing_subscriber::fmt()
-        .with_max_level(level)
-        .with_writer(io::stderr);
+/// Handle to the max-level filter installed by [`setup`], allowing the
+/// level to be changed at runtime, e.g. by [`crate::reload`]
+pub type LevelHandle = reload::Handle<filter::LevelFilter, Registry>;
+
+#[derive(Debug)]
+struct InnerLogBuffer {
+    // formatted lines, oldest first; capped at max_lines, evicting the
+    // oldest once full
+    lines: VecDeque<String>,
+    max_lines: usize,
+    // subscribers registered via LogBuffer::subscribe, fed every freshly
+    // formatted line; pruned lazily whenever we try to send and the
+    // receiver has hung up
+    subscribers: Vec<Sender<String>>,
+}
+
+/// A bounded in-memory record of recently formatted [`target::PUBLIC`]
+/// events, fed by [`PublicLogLayer`] and exposed over the api as `/log`
+/// and `/log/stream`
+///
+/// This mirrors [`crate::api::telemetry::Telemetry`]'s ring-plus-broadcast
+/// shape, but a plain `Mutex<VecDeque<String>>` stands in for the
+/// `Copy`-only [`crate::ring_buffer::RingBuffer`] since a formatted line
+/// isn't `Copy`, and events can arrive from any thread rather than a single
+/// sampler.
+#[derive(Debug, Clone)]
+pub struct LogBuffer(Arc<Mutex<InnerLogBuffer>>);
+
+impl LogBuffer {
+    fn new(max_lines: usize) -> Self {
+        Self(Arc::new(Mutex::new(InnerLogBuffer {
+            lines: VecDeque::new(),
+            max_lines,
+            subscribers: Vec::new(),
+        })))
+    }
+
+    fn push(&self, line: String) {
+        let mut inner = self.0.lock().unwrap();
+        while inner.lines.len() >= inner.max_lines {
+            inner.lines.pop_front();
+        }
+        inner.lines.push_back(line.clone());
+        inner.subscribers.retain(|sub| sub.send(line.clone()).is_ok());
+    }
+
+    /// Registers a new live subscriber for freshly formatted lines
+    ///
+    /// Used by the `/log/stream` SSE route; the returned [`Receiver`]
+    /// yields every line pushed from this point on.
+    pub fn subscribe(&self) -> Receiver<String> {
+        let (sub_send, sub_recv) = channel::unbounded();
+        let mut inner = self.0.lock().unwrap();
+        inner.subscribers.push(sub_send);
+        sub_recv
+    }
+
+    /// The buffered window of the most recent lines, oldest first
+    pub fn history(&self) -> Vec<String> {
+        self.0.lock().unwrap().lines.iter().cloned().collect()
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        }
+    }
+}
+
+/// Formats every [`target::PUBLIC`] event into [`LogBuffer`], so a UI can
+/// retrieve recent print progress and executor/decoder errors without
+/// scraping stdout; installed alongside the existing `fmt::layer()` in
+/// [`setup`], it never affects what's printed to stderr
+struct PublicLogLayer {
+    buffer: LogBuffer,
+}
+
+impl<S: Subscriber> Layer<S> for PublicLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != target::PUBLIC {
+            return;
+        }
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("event somehow occured before epoch")
+            .as_secs();
+        self.buffer
+            .push(format!("{} {} {}", time, event.metadata().level(), visitor.message));
+    }
+}
+
+pub fn setup(level: Level, max_log_lines: usize) -> (LevelHandle, LogBuffer) {
+    let log_buffer = LogBuffer::new(max_log_lines);
+    let (level_filter, level_handle) = reload::Layer::new(filter::LevelFilter::from(level));
+    let registry = tracing_subscriber::registry()
+        .with(level_filter)
+        .with(filter::filter_fn(filter_modules))
+        .with(PublicLogLayer {
+            buffer: log_buffer.clone(),
+        });
     if atty::is(atty::Stream::Stderr) {
-        subscriber
-            .event_format(format_tty(level))
-            .finish()
-            .with(filter::filter_fn(filter_modules))
+        registry
+            .with(
+                fmt::layer()
+                    .event_format(format_tty(level))
+                    .with_writer(io::stderr),
+            )
             .init()
     } else {
-        subscriber
-            .event_format(format_notty(level))
-            .finish()
-            .with(filter::filter_fn(filter_modules))
+        registry
+            .with(
+                fmt::layer()
+                    .event_format(format_notty(level))
+                    .with_writer(io::stderr),
+            )
             .init()
     }
+    (level_handle, log_buffer)
 }
@@ -4,7 +4,10 @@ mod comms;
 mod config;
 mod hw;
 mod log;
+mod reload;
+mod ring_buffer;
 mod settings;
+mod socket;
 mod util;
 
 use crate::{comms::ControlComms, log::target};
@@ -24,8 +27,12 @@ pub const APP_NAME: &'static str = env!("CARGO_BIN_NAME");
 // start pi thread
 // start estop & execute thread
 // start decode thread
+// start command-socket thread
+// start config-reload thread
 // start api
 // wait for api to finish
+// stop config-reload thread
+// stop command-socket thread
 // stop decode thread
 // stop value thread
 // stop pi thread
@@ -49,7 +56,7 @@ pub const APP_NAME: &'static str = env!("CARGO_BIN_NAME");
 fn main() -> Result<()> {
     let args = args::args();
     let config = config::config(&args)?;
-    log::setup(config.log.level);
+    let (level_handle, log_buffer) = log::setup(config.log.level, config.log.max_lines);
     #[cfg(feature = "dev_no_pi")]
     warn!(
         target: target::INTERNAL,
@@ -64,13 +71,37 @@ fn main() -> Result<()> {
     debug!(target: target::INTERNAL, "Config is: {:?}", config);
     let settings = settings::settings(config)?;
     let (error_send, error_recv) = channel::unbounded();
-    let (error_handle, errors) = api::values::start(error_recv)?;
+    let (error_handle, errors) = api::values::start(error_recv, settings.config().errors.clone())?;
     let (pi_handle, estop_handle, hw_ctrl) = hw::start(settings.clone(), error_send.clone())?;
-    api::launch(settings.clone(), errors, hw_ctrl.clone())?;
+    let (socket_handle, socket_send) = socket::start(
+        settings.clone(),
+        hw_ctrl.clone(),
+        errors.clone(),
+        error_send.clone(),
+    )?;
+    let (reload_handle, reload_send) =
+        reload::start(args, settings.clone(), level_handle, hw_ctrl.clone())?;
+    let (telemetry_handle, telemetry_send, telemetry) =
+        api::telemetry::start(hw_ctrl.clone(), settings.config().telemetry.clone())?;
+    let mqtt = match settings.config().mqtt.clone() {
+        Some(mqtt_config) => Some(api::mqtt::start(hw_ctrl.clone(), mqtt_config)?),
+        None => None,
+    };
+    api::launch(settings.clone(), errors, hw_ctrl.clone(), telemetry, log_buffer)?;
     debug!(
         target: target::INTERNAL,
         "api exited gracefully, shutting down..."
     );
+    reload_send.send(ControlComms::Exit).unwrap();
+    reload_handle.join().unwrap();
+    socket_send.send(ControlComms::Exit).unwrap();
+    socket_handle.join().unwrap();
+    telemetry_send.send(ControlComms::Exit).unwrap();
+    telemetry_handle.join().unwrap();
+    if let Some((mqtt_handle, mqtt_send)) = mqtt {
+        mqtt_send.send(ControlComms::Exit).unwrap();
+        mqtt_handle.join().unwrap();
+    }
     hw_ctrl.exit();
     estop_handle.join().unwrap();
     pi_handle.join().unwrap();
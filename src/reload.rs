@@ -0,0 +1,131 @@
+use crate::{
+    args::Args,
+    comms::ControlComms,
+    config,
+    hw::HwCtrl,
+    log::{target, LevelHandle},
+    settings::Settings,
+};
+use anyhow::{Context, Result};
+use crossbeam::channel::{self, Receiver, Sender, TryRecvError};
+use signal_hook::{consts::SIGHUP, iterator::Signals};
+use std::{
+    fs, thread,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+use tracing::{debug, warn};
+use tracing_subscriber::filter::LevelFilter;
+
+// how long to sleep between polls of the config file's mtime
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+fn mtime(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Re-extracts the config from `args.cfg`, swaps in everything that can be
+/// changed live and warns about everything that can't
+fn reload(args: &Args, settings: &Settings, level_handle: &LevelHandle, hw_ctrl: &HwCtrl) {
+    let new = match config::config(args) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!(
+                target: target::PUBLIC,
+                "failed to reload config, keeping the running one: {:#}", e
+            );
+            return;
+        }
+    };
+    let old = settings.config();
+    if new.log.level != old.log.level {
+        level_handle
+            .reload(LevelFilter::from(new.log.level))
+            .expect("the log level filter is never dropped before the process exits");
+        debug!(
+            target: target::PUBLIC,
+            "reloaded log level, now {}", new.log.level
+        );
+    }
+    if new.api.address != old.api.address || new.api.port != old.api.port {
+        warn!(
+            target: target::PUBLIC,
+            "api.address/api.port changed in the config, this requires a restart to take effect"
+        );
+    }
+    if new.socket.path != old.socket.path {
+        warn!(
+            target: target::PUBLIC,
+            "socket.path changed in the config, this requires a restart to take effect"
+        );
+    }
+    if new.general.settings_path != old.general.settings_path {
+        warn!(
+            target: target::PUBLIC,
+            "general.settings_path changed in the config, this requires a restart to take effect"
+        );
+    }
+    // motors/hotend/bed/pi are baked into the motors/pi threads at startup,
+    // so we can't tell in general whether they changed; hand the new config
+    // to the executor anyways so the next print picks it up, but everything
+    // still running (motors/pi threads) keeps using what it was started with
+    hw_ctrl.reload_settings(settings.with_config(new));
+}
+
+fn reload_loop(
+    args: Args,
+    settings: Settings,
+    level_handle: LevelHandle,
+    hw_ctrl: HwCtrl,
+    control_recv: Receiver<ControlComms<()>>,
+) {
+    let sighup = Arc::new(AtomicBool::new(false));
+    let sighup_clone = Arc::clone(&sighup);
+    let mut signals = Signals::new([SIGHUP]).expect("registering the SIGHUP handler failed");
+    let signals_handle = signals.handle();
+    let signal_thread = thread::Builder::new()
+        .name(String::from("reload-sighup"))
+        .spawn(move || {
+            for _ in signals.forever() {
+                sighup_clone.store(true, Ordering::Release);
+            }
+        })
+        .expect("creating the sighup thread failed");
+    let mut last_mtime = mtime(&args.cfg);
+    loop {
+        match control_recv.try_recv() {
+            Ok(ControlComms::Exit) => break,
+            Ok(ControlComms::Msg(())) => (),
+            Err(TryRecvError::Disconnected) => {
+                panic!("reload channel unexpectedly disconnected")
+            }
+            Err(TryRecvError::Empty) => (),
+        }
+        let changed_on_disk = mtime(&args.cfg) != last_mtime;
+        if changed_on_disk || sighup.swap(false, Ordering::AcqRel) {
+            debug!(target: target::INTERNAL, "reloading config");
+            reload(&args, &settings, &level_handle, &hw_ctrl);
+            last_mtime = mtime(&args.cfg);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    signals_handle.close();
+    signal_thread.join().unwrap();
+}
+
+pub fn start(
+    args: Args,
+    settings: Settings,
+    level_handle: LevelHandle,
+    hw_ctrl: HwCtrl,
+) -> Result<(thread::JoinHandle<()>, Sender<ControlComms<()>>)> {
+    let (control_send, control_recv) = channel::unbounded();
+    let handle = thread::Builder::new()
+        .name(String::from("reload"))
+        .spawn(move || reload_loop(args, settings, level_handle, hw_ctrl, control_recv))
+        .context("Creating the reload thread failed")?;
+    Ok((handle, control_send))
+}
@@ -0,0 +1,105 @@
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A fixed-capacity, lock-free ring buffer with a single producer and any
+/// number of reading threads
+///
+/// Unlike a queue, reading it doesn't remove anything -- every reader just
+/// gets a consistent snapshot of whatever is currently buffered, oldest
+/// first, so several clients can independently look at the same recent
+/// history. `push` never blocks or allocates, making it safe to call from a
+/// hot sampling thread.
+pub struct RingBuffer<T> {
+    slots: Box<[UnsafeCell<T>]>,
+    // total number of elements ever pushed; a logical index `i` lives in
+    // `slots[i % slots.len()]`
+    end: AtomicUsize,
+    // oldest logical index still valid; `end - start` never exceeds capacity
+    start: AtomicUsize,
+}
+
+// `slots` is only ever written by the single producer calling `push`, and
+// only ever read after the writing `push` has published `end` with Release,
+// so sharing `&RingBuffer` across threads is sound even though `UnsafeCell`
+// isn't `Sync` on its own.
+unsafe impl<T: Send> Sync for RingBuffer<T> {}
+
+impl<T: Copy + Default> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "a ring buffer needs at least one slot");
+        // one spare physical slot beyond the logical capacity, so the slot
+        // `push` writes into next can never be one a concurrent `snapshot`
+        // still has in its already-loaded `start..end` range; see `push`'s
+        // safety comment
+        let physical_capacity = capacity + 1;
+        Self {
+            slots: (0..physical_capacity)
+                .map(|_| UnsafeCell::new(T::default()))
+                .collect(),
+            end: AtomicUsize::new(0),
+            start: AtomicUsize::new(0),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len() - 1
+    }
+
+    fn physical_capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start.load(Ordering::Acquire) == self.end.load(Ordering::Acquire)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.end.load(Ordering::Acquire) - self.start.load(Ordering::Acquire) == self.capacity()
+    }
+
+    /// Appends `value`, overwriting the oldest entry once the buffer is full
+    ///
+    /// Only ever safe to call from a single producer thread.
+    pub fn push(&self, value: T) {
+        let end = self.end.load(Ordering::Relaxed);
+        let start = self.start.load(Ordering::Relaxed);
+        // safety: only the single producer ever writes. `end - start` never
+        // exceeds the logical `capacity`, so across the `physical_capacity`
+        // (= capacity + 1) physical slots, the one `end` maps to is always
+        // one past the contiguous run `start..end` already occupies rather
+        // than coinciding with any of them -- including `start`'s own slot,
+        // the one about to be retired. That's what makes this safe to write
+        // to without racing a concurrent `snapshot` that already loaded
+        // `start`/`end` and is still reading that range.
+        unsafe { *self.slots[end % self.physical_capacity()].get() = value };
+        if end - start == self.capacity() {
+            self.start.store(start + 1, Ordering::Release);
+        }
+        self.end.store(end + 1, Ordering::Release);
+    }
+
+    /// A snapshot of everything currently buffered, oldest first
+    pub fn snapshot(&self) -> Vec<T> {
+        let start = self.start.load(Ordering::Acquire);
+        let end = self.end.load(Ordering::Acquire);
+        (start..end)
+            // safety: every index in `start..end` was published by a `push`
+            // that already happened-before this load of `end`, and (see
+            // `push`) can't have been overwritten since
+            .map(|i| unsafe { *self.slots[i % self.physical_capacity()].get() })
+            .collect()
+    }
+}
+
+impl<T> fmt::Debug for RingBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RingBuffer")
+            .field("capacity", &self.capacity())
+            .field("start", &self.start.load(Ordering::Relaxed))
+            .field("end", &self.end.load(Ordering::Relaxed))
+            .finish()
+    }
+}
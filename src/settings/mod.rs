@@ -1,14 +1,33 @@
-use crate::config::{self, Config};
+use crate::{
+    api::values::ErrorCode,
+    config::{self, Config},
+    util::ensure_own,
+};
 use anyhow::{Context, Error, Result};
 use serde::{Deserialize, Serialize};
-use serde_json;
+use serde_json::{self, Value};
 use std::{
     fs::File,
     io::{self, Read, Write},
     sync::{Arc, RwLock},
 };
+use thiserror::Error;
 use tracing::warn;
 
+#[derive(Debug, Error)]
+#[error("{} was out of bounds, was {}, must be <= {}", .0, .1, .2)]
+pub struct SettingsError(&'static str, u32, u32);
+
+impl SettingsError {
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode::SettingOutOfBounds
+    }
+
+    pub fn details(&self) -> Option<Value> {
+        None
+    }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(default)]
 pub struct InnerAxisMotorSettings {
@@ -40,7 +59,6 @@ macro_rules! get_motor_setting {
     }};
 }
 
-// FIXME check limits
 macro_rules! set_motor_setting {
     ($self:ident, $setting:ident, $value:expr) => {{
         ($self.fm)(&mut $self.settings.write().unwrap().motors).$setting = Some($value)
@@ -57,24 +75,57 @@ where
         get_motor_setting!(self, reference_speed, default_reference_speed)
     }
 
-    pub fn set_reference_speed(&self, speed: u32) {
-        set_motor_setting!(self, reference_speed, speed)
+    /// Sets the reference speed, rejecting it if it's above the axis's
+    /// configured `speed_limit` rather than persisting a value that would
+    /// just be rejected later, when actually referencing the axis
+    pub fn set_reference_speed(&self, speed: u32) -> Result<(), SettingsError> {
+        let limit = (self.c)(&self.config.motors).speed_limit;
+        ensure_own!(
+            speed <= limit,
+            SettingsError("reference_speed", speed, limit)
+        );
+        set_motor_setting!(self, reference_speed, speed);
+        Ok(())
     }
 
     pub fn get_reference_accel_decel(&self) -> u32 {
         get_motor_setting!(self, reference_accel_decel, default_reference_accel)
     }
 
-    pub fn set_reference_accel_decel(&self, accel: u32) {
-        set_motor_setting!(self, reference_accel_decel, accel)
+    /// Sets the reference acceleration/deceleration, rejecting it if it's
+    /// above the axis's configured `accel_limit`/`decel_limit`
+    pub fn set_reference_accel_decel(&self, accel_decel: u32) -> Result<(), SettingsError> {
+        let cfg = (self.c)(&self.config.motors);
+        ensure_own!(
+            accel_decel <= cfg.accel_limit,
+            SettingsError("reference_accel_decel", accel_decel, cfg.accel_limit)
+        );
+        ensure_own!(
+            accel_decel <= cfg.decel_limit,
+            SettingsError("reference_accel_decel", accel_decel, cfg.decel_limit)
+        );
+        set_motor_setting!(self, reference_accel_decel, accel_decel);
+        Ok(())
     }
 
     pub fn get_reference_jerk(&self) -> u32 {
         get_motor_setting!(self, reference_jerk, default_reference_jerk)
     }
 
-    pub fn set_reference_jerk(&self, jerk: u32) {
-        set_motor_setting!(self, reference_jerk, jerk)
+    /// Sets the reference jerk, rejecting it if it's above the axis's
+    /// configured `accel_jerk_limit`/`decel_jerk_limit`
+    pub fn set_reference_jerk(&self, jerk: u32) -> Result<(), SettingsError> {
+        let cfg = (self.c)(&self.config.motors);
+        ensure_own!(
+            jerk <= cfg.accel_jerk_limit,
+            SettingsError("reference_jerk", jerk, cfg.accel_jerk_limit)
+        );
+        ensure_own!(
+            jerk <= cfg.decel_jerk_limit,
+            SettingsError("reference_jerk", jerk, cfg.decel_jerk_limit)
+        );
+        set_motor_setting!(self, reference_jerk, jerk);
+        Ok(())
     }
 }
 
@@ -190,6 +241,18 @@ impl Settings {
     pub fn config(&self) -> &Config {
         self.config.as_ref()
     }
+
+    /// Returns a copy of these `Settings` using a different [`Config`],
+    /// keeping the same runtime-adjustable settings (e.g. reference speeds)
+    ///
+    /// Used by [`crate::reload`] to push a freshly re-read config into the
+    /// running executor without losing settings set at runtime via the api.
+    pub fn with_config(&self, config: Config) -> Self {
+        Self {
+            config: Arc::new(config),
+            settings: self.settings.clone(),
+        }
+    }
 }
 
 pub fn settings(config: Config) -> Result<Settings> {
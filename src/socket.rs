@@ -0,0 +1,192 @@
+use crate::{
+    api::values::Errors,
+    comms::{Axis, ControlComms, ReferenceRunOptParameters},
+    hw::HwCtrl,
+    log::target,
+    settings::Settings,
+    util::send_err,
+};
+use anyhow::{Context, Error, Result};
+use crossbeam::channel::{self, Receiver, Sender, TryRecvError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs, io,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use tracing::debug;
+
+// how long to sleep between polls of a non-blocking accept() when there's
+// nothing to accept
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum Command {
+    Pause,
+    Resume,
+    Cancel,
+    Estop,
+    ReferenceAll,
+    Status,
+    LastError,
+}
+
+#[derive(Debug, Serialize)]
+struct Reply {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Reply {
+    fn ok() -> Self {
+        Self {
+            ok: true,
+            data: None,
+            error: None,
+        }
+    }
+
+    fn ok_data(data: Value) -> Self {
+        Self {
+            ok: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    fn err<E: ToString>(error: E) -> Self {
+        Self {
+            ok: false,
+            data: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+fn dispatch(cmd: Command, hw_ctrl: &HwCtrl, errors: &Errors) -> Reply {
+    match cmd {
+        Command::Pause => match hw_ctrl.try_pause() {
+            Ok(()) => Reply::ok(),
+            Err(e) => Reply::err(e),
+        },
+        Command::Resume => match hw_ctrl.try_play() {
+            Ok(()) => Reply::ok(),
+            Err(e) => Reply::err(e),
+        },
+        Command::Cancel => {
+            hw_ctrl.stop();
+            Reply::ok()
+        }
+        Command::Estop => {
+            hw_ctrl.estop();
+            Reply::ok()
+        }
+        Command::ReferenceAll => {
+            for axis in [Axis::X, Axis::Y, Axis::Z] {
+                if let Err(e) =
+                    hw_ctrl.try_reference_axis(axis, ReferenceRunOptParameters::default())
+                {
+                    return Reply::err(e);
+                }
+            }
+            Reply::ok()
+        }
+        Command::Status => Reply::ok_data(
+            serde_json::to_value(hw_ctrl.state_info()).expect("StateInfo always serializes"),
+        ),
+        Command::LastError => Reply::ok_data(
+            serde_json::to_value(errors.get_last()).expect("an ApiError always serializes"),
+        ),
+    }
+}
+
+/// Handles a single client connection, dispatching every newline-delimited
+/// JSON command it sends until it disconnects
+fn handle_connection(stream: UnixStream, hw_ctrl: &HwCtrl, errors: &Errors) -> io::Result<()> {
+    let mut writer = &stream;
+    for line in BufReader::new(&stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Command>(&line) {
+            Ok(cmd) => dispatch(cmd, hw_ctrl, errors),
+            Err(e) => Reply::err(format!("invalid command: {}", e)),
+        };
+        let mut out = serde_json::to_vec(&reply).expect("a Reply always serializes");
+        out.push(b'\n');
+        writer.write_all(&out)?;
+    }
+    Ok(())
+}
+
+fn socket_loop(
+    listener: UnixListener,
+    path: PathBuf,
+    control_recv: Receiver<ControlComms<()>>,
+    hw_ctrl: HwCtrl,
+    errors: Errors,
+    error_send: Sender<ControlComms<Error>>,
+) {
+    loop {
+        match control_recv.try_recv() {
+            Ok(ControlComms::Exit) => break,
+            Ok(ControlComms::Msg(())) => (),
+            Err(TryRecvError::Disconnected) => {
+                panic!("command-socket channel unexpectedly disconnected")
+            }
+            Err(TryRecvError::Empty) => (),
+        }
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if let Err(e) = handle_connection(stream, &hw_ctrl, &errors) {
+                    debug!(
+                        target: target::INTERNAL,
+                        "command-socket connection ended with an error: {}", e
+                    );
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_POLL_INTERVAL)
+            }
+            Err(e) => send_err!(Err(Error::from(e)), error_send),
+        }
+    }
+    // best effort, a stale socket file is cleaned up on the next startup anyways
+    let _ = fs::remove_file(path);
+}
+
+pub fn start(
+    settings: Settings,
+    hw_ctrl: HwCtrl,
+    errors: Errors,
+    error_send: Sender<ControlComms<Error>>,
+) -> Result<(JoinHandle<()>, Sender<ControlComms<()>>)> {
+    let path = settings.config().socket.path.clone();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("Failed to create the command-socket's directory")?;
+    }
+    // remove a stale socket file left behind by an unclean shutdown, bind
+    // would otherwise fail with AddrInUse
+    if path.exists() {
+        fs::remove_file(&path).context("Failed to remove the stale command socket")?;
+    }
+    let listener = UnixListener::bind(&path).context("Failed to bind the command socket")?;
+    listener
+        .set_nonblocking(true)
+        .context("Failed to set the command socket to non-blocking")?;
+    let (control_send, control_recv) = channel::unbounded();
+    let handle = thread::Builder::new()
+        .name(String::from("socket"))
+        .spawn(move || socket_loop(listener, path, control_recv, hw_ctrl, errors, error_send))
+        .context("Creating the command-socket thread failed")?;
+    Ok((handle, control_send))
+}